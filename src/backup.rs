@@ -0,0 +1,492 @@
+use anyhow::{Result, anyhow};
+use std::fmt;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::controller::{
+    COLOR_REGION, CalibrationTarget, Controller, ControllerType, FirmwareVersion, OperationContext,
+    SPI_FLASH, WRITABLE_REGIONS, format_mac,
+};
+
+// File layout, little-endian:
+//   magic, format version (u16), MAC, controller type code, firmware major and minor,
+//   Unix time in seconds (u64), region count (u16),
+//   an (offset, length) u32 pair per region, the regions' bytes in the same order,
+//   then a CRC32 of everything before it.
+// An unknown MAC is stored as zeros, an unknown type or firmware as 0.
+const MAGIC: &[u8; 6] = b"RJCBAK";
+const FORMAT_VERSION: u16 = 1;
+const HEADER_LEN: usize = 27;
+
+// Why a file couldn't be loaded as a backup. Returned wrapped in anyhow::Error;
+// NoHeader means it may still be a raw dump, see SpiBackup::load_raw.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BackupFileError {
+    NoHeader,
+    Truncated,
+    BadChecksum,
+    // Written by a newer version of the app, in a layout this one doesn't know
+    FutureVersion(u16),
+}
+
+impl fmt::Display for BackupFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackupFileError::NoHeader => write!(f, "not a rustjoycal backup"),
+            BackupFileError::Truncated => write!(f, "the backup is truncated"),
+            BackupFileError::BadChecksum => {
+                write!(f, "the backup is corrupted (checksum mismatch)")
+            }
+            BackupFileError::FutureVersion(version) => write!(
+                f,
+                "the backup uses format version {}, which needs a newer rustjoycal",
+                version
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BackupFileError {}
+
+// CRC-32 (IEEE 802.3, as used by zip and PNG)
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+// Where automatic backups of a controller go, one directory per MAC
+pub fn backup_dir(mac: &str) -> Option<PathBuf> {
+    Some(
+        dirs::data_dir()?
+            .join("rustjoycal")
+            .join("backups")
+            .join(mac.replace(':', "-")),
+    )
+}
+
+// The most recent automatic backup saved for a controller
+pub fn latest_backup(mac: &str) -> Option<PathBuf> {
+    fs::read_dir(backup_dir(mac)?)
+        .ok()?
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let timestamp = path.file_stem()?.to_str()?.parse::<u64>().ok()?;
+            Some((timestamp, path))
+        })
+        .max_by_key(|(timestamp, _)| *timestamp)
+        .map(|(_, path)| path)
+}
+
+// Where the first-connect snapshots of factory calibration go, one file per MAC
+fn snapshot_dir() -> Option<PathBuf> {
+    Some(dirs::data_dir()?.join("rustjoycal").join("factory"))
+}
+
+pub fn snapshot_path(mac: &str) -> Option<PathBuf> {
+    Some(snapshot_dir()?.join(format!("{}.jcbak", mac.replace(':', "-"))))
+}
+
+// Factory motion and stick calibration and the stick parameters, as shipped
+const FACTORY_SNAPSHOT_REGIONS: [Range<u32>; 3] = [0x6020..0x6038, 0x603D..0x604F, 0x6086..0x60AA];
+
+// Save the factory calibration of a controller the first time it's seen, before
+// anything can be written to it. An existing snapshot is never replaced, so it
+// always holds what the controller first came with; Ok(None) means one was there.
+pub fn save_factory_snapshot(c: &mut Controller) -> Result<Option<PathBuf>> {
+    let mac = format_mac(&c.get_device_info()?.mac);
+    let path =
+        snapshot_path(&mac).ok_or_else(|| anyhow!("No data directory to save snapshots in"))?;
+    if path.exists() {
+        return Ok(None);
+    }
+    let snapshot = SpiBackup::read(c, &FACTORY_SNAPSHOT_REGIONS, &OperationContext::detached())?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|e| anyhow!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    snapshot.save(&path)?;
+    Ok(Some(path))
+}
+
+// Every snapshot on disk as (MAC, capture time), oldest first. Unreadable files are
+// skipped rather than failing the whole list.
+pub fn factory_snapshots() -> Vec<(String, u64)> {
+    let Some(entries) = snapshot_dir().and_then(|dir| fs::read_dir(dir).ok()) else {
+        return Vec::new();
+    };
+    let mut snapshots: Vec<(String, u64)> = entries
+        .filter_map(|entry| {
+            let snapshot = SpiBackup::load(&entry.ok()?.path()).ok()?;
+            Some((snapshot.mac_string()?, snapshot.timestamp))
+        })
+        .collect();
+    snapshots.sort_by_key(|(_, timestamp)| *timestamp);
+    snapshots
+}
+
+// A Unix time as "YYYY-MM-DD HH:MM UTC", without pulling in a date crate
+pub fn format_timestamp(timestamp: u64) -> String {
+    let days = (timestamp / 86400) as i64;
+    let minutes = timestamp % 86400 / 60;
+    // Civil date from days since 1970-01-01, after Howard Hinnant's civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        minutes / 60,
+        minutes % 60
+    )
+}
+
+// Regions a stick calibration write can touch. Whole restorable regions are saved
+// so the backup can be put back with a restore.
+fn pre_write_regions(target: CalibrationTarget) -> Vec<Range<u32>> {
+    let mut regions = vec![0x603D..0x604F, 0x6086..0x60AA];
+    if target == CalibrationTarget::User {
+        regions.push(0x8010..0x8040);
+    }
+    regions
+}
+
+// Save what a calibration write is about to overwrite to a timestamped file in the
+// controller's backup directory. The backup is returned too, to undo the write with.
+pub fn save_before_write(
+    c: &mut Controller,
+    target: CalibrationTarget,
+) -> Result<(SpiBackup, PathBuf)> {
+    save_to_backup_dir(c, &pre_write_regions(target), "")
+}
+
+// The same for a color write. The name prefix keeps these out of latest_backup, so
+// undoing a calibration write never picks up a color backup instead.
+pub fn save_before_color_write(c: &mut Controller) -> Result<PathBuf> {
+    save_to_backup_dir(c, &[COLOR_REGION], "colors-").map(|(_, path)| path)
+}
+
+fn save_to_backup_dir(
+    c: &mut Controller,
+    regions: &[Range<u32>],
+    prefix: &str,
+) -> Result<(SpiBackup, PathBuf)> {
+    let backup = SpiBackup::read(c, regions, &OperationContext::detached())?;
+    let dir = backup_dir(&backup.mac_string().unwrap_or_default())
+        .ok_or_else(|| anyhow!("No data directory to save backups in"))?;
+    fs::create_dir_all(&dir).map_err(|e| anyhow!("Failed to create {}: {}", dir.display(), e))?;
+    let path = dir.join(format!("{}{}.jcbak", prefix, backup.timestamp));
+    backup.save(&path)?;
+    Ok((backup, path))
+}
+
+// A copy of some or all of a controller's SPI flash. Identity fields are None for a
+// raw dump loaded without a header.
+pub struct SpiBackup {
+    pub mac: Option<[u8; 6]>,
+    pub controller_type: Option<ControllerType>,
+    pub firmware: Option<FirmwareVersion>,
+    pub timestamp: u64,
+    pub regions: Vec<(u32, Vec<u8>)>,
+}
+
+impl SpiBackup {
+    // Read `regions` from the controller, reporting the bytes read so far and
+    // stopping after the current chunk once cancelled
+    pub fn read(c: &mut Controller, regions: &[Range<u32>], op: &OperationContext) -> Result<Self> {
+        let info = c.get_device_info()?;
+        let total = regions.iter().map(|r| r.end - r.start).sum();
+        let mut done = 0;
+        let mut read = Vec::new();
+        for range in regions {
+            let data = c.dump_spi(range.clone(), |bytes| {
+                op.report(done + bytes, total, "Reading");
+                !op.cancelled()
+            })?;
+            done += data.len() as u32;
+            read.push((range.start, data));
+        }
+        Ok(Self {
+            mac: Some(info.mac),
+            controller_type: Some(c.get_controller_type()),
+            firmware: Some(info.firmware),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            regions: read,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let bytes =
+            fs::read(path).map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+        Self::parse(&bytes).map_err(|e| anyhow!(e).context(format!("{}: {}", path.display(), e)))
+    }
+
+    fn parse(bytes: &[u8]) -> Result<Self, BackupFileError> {
+        if bytes.len() < MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(BackupFileError::NoHeader);
+        }
+        if bytes.len() < HEADER_LEN + 4 {
+            return Err(BackupFileError::Truncated);
+        }
+        let version = u16::from_le_bytes([bytes[6], bytes[7]]);
+        if version > FORMAT_VERSION {
+            return Err(BackupFileError::FutureVersion(version));
+        }
+        let count = u16::from_le_bytes([bytes[25], bytes[26]]) as usize;
+        let table_end = HEADER_LEN + count * 8;
+        let data_len: usize = bytes
+            .get(HEADER_LEN..table_end)
+            .ok_or(BackupFileError::Truncated)?
+            .chunks_exact(8)
+            .map(|entry| u32::from_le_bytes(entry[4..8].try_into().unwrap()) as usize)
+            .sum();
+        let payload_end = table_end + data_len;
+        if bytes.len() < payload_end + 4 {
+            return Err(BackupFileError::Truncated);
+        }
+        let stored_crc =
+            u32::from_le_bytes(bytes[payload_end..payload_end + 4].try_into().unwrap());
+        if crc32(&bytes[..payload_end]) != stored_crc {
+            return Err(BackupFileError::BadChecksum);
+        }
+
+        let mut data = &bytes[table_end..payload_end];
+        let mut regions = Vec::with_capacity(count);
+        for entry in bytes[HEADER_LEN..table_end].chunks_exact(8) {
+            let offset = u32::from_le_bytes(entry[0..4].try_into().unwrap());
+            let len = u32::from_le_bytes(entry[4..8].try_into().unwrap()) as usize;
+            regions.push((offset, data[..len].to_vec()));
+            data = &data[len..];
+        }
+        let mac: [u8; 6] = bytes[8..14].try_into().unwrap();
+        Ok(Self {
+            mac: (mac != [0; 6]).then_some(mac),
+            controller_type: ControllerType::from_code(bytes[14]),
+            firmware: (bytes[15..17] != [0, 0]).then_some(FirmwareVersion {
+                major: bytes[15],
+                minor: bytes[16],
+            }),
+            timestamp: u64::from_le_bytes(bytes[17..25].try_into().unwrap()),
+            regions,
+        })
+    }
+
+    // A headerless dump of flash starting at `offset`, such as other tools save
+    pub fn load_raw(path: &Path, offset: u32) -> Result<Self> {
+        let data =
+            fs::read(path).map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+        if offset as u64 + data.len() as u64 > SPI_FLASH.end as u64 {
+            return Err(anyhow!(
+                "{} bytes from 0x{:04X} run past the end of the flash",
+                data.len(),
+                offset
+            ));
+        }
+        Ok(Self {
+            mac: None,
+            controller_type: None,
+            firmware: None,
+            timestamp: 0,
+            regions: vec![(offset, data)],
+        })
+    }
+
+    pub fn mac_string(&self) -> Option<String> {
+        self.mac.as_ref().map(format_mac)
+    }
+
+    // Bytes the backup holds for `range`, if one of its regions covers all of it
+    pub fn region(&self, range: &Range<u32>) -> Option<&[u8]> {
+        self.regions.iter().find_map(|(offset, data)| {
+            let end = offset + data.len() as u32;
+            (*offset <= range.start && range.end <= end)
+                .then(|| &data[(range.start - offset) as usize..(range.end - offset) as usize])
+        })
+    }
+
+    // Regions among `regions` where the controller no longer holds what the backup does
+    pub fn differences(
+        &self,
+        c: &mut Controller,
+        regions: &[(Range<u32>, &'static str)],
+        op: &OperationContext,
+    ) -> Result<Vec<(Range<u32>, &'static str)>> {
+        let total = regions.iter().map(|(r, _)| r.end - r.start).sum();
+        let mut done = 0;
+        let mut differing = Vec::new();
+        for (range, name) in regions {
+            let expected = self.region(range).ok_or_else(|| {
+                anyhow!(
+                    "The backup doesn't contain 0x{:04X}-0x{:04X}",
+                    range.start,
+                    range.end
+                )
+            })?;
+            let actual = c.dump_spi(range.clone(), |bytes| {
+                op.report(done + bytes, total, "Comparing");
+                !op.cancelled()
+            })?;
+            done += actual.len() as u32;
+            if actual != expected {
+                differing.push((range.clone(), *name));
+            }
+        }
+        Ok(differing)
+    }
+
+    // Every region in the backup, for restores with the offset guard lifted
+    pub fn all_regions(&self) -> Vec<(Range<u32>, &'static str)> {
+        self.regions
+            .iter()
+            .map(|(offset, data)| (*offset..offset + data.len() as u32, "unchecked region"))
+            .collect()
+    }
+
+    // Whitelisted calibration regions this backup can restore, with their names
+    pub fn restorable(&self) -> Vec<(Range<u32>, &'static str)> {
+        WRITABLE_REGIONS
+            .iter()
+            .filter(|(range, _)| self.region(range).is_some())
+            .cloned()
+            .collect()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.to_bytes())
+            .map_err(|e| anyhow!("Failed to write {}: {}", path.display(), e))
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        out.extend_from_slice(&self.mac.unwrap_or_default());
+        out.push(self.controller_type.map_or(0, ControllerType::code));
+        out.extend_from_slice(&self.firmware.map_or([0, 0], |f| [f.major, f.minor]));
+        out.extend_from_slice(&self.timestamp.to_le_bytes());
+        out.extend_from_slice(&(self.regions.len() as u16).to_le_bytes());
+        for (offset, data) in &self.regions {
+            out.extend_from_slice(&offset.to_le_bytes());
+            out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        }
+        for (_, data) in &self.regions {
+            out.extend_from_slice(data);
+        }
+        out.extend_from_slice(&crc32(&out).to_le_bytes());
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backup() -> SpiBackup {
+        SpiBackup {
+            mac: Some([0x98, 0xB6, 0xE9, 0x12, 0x34, 0x56]),
+            controller_type: Some(ControllerType::ProController),
+            firmware: Some(FirmwareVersion {
+                major: 4,
+                minor: 33,
+            }),
+            timestamp: 1_700_000_000,
+            regions: vec![(0x603D, (0..18).collect()), (0x6086, (100..136).collect())],
+        }
+    }
+
+    fn parse_error(bytes: &[u8]) -> Option<BackupFileError> {
+        SpiBackup::parse(bytes).err()
+    }
+
+    #[test]
+    fn crc32_matches_the_standard_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn saved_backup_parses_back() {
+        let original = backup();
+        let parsed = SpiBackup::parse(&original.to_bytes()).unwrap();
+        assert_eq!(parsed.mac, original.mac);
+        assert_eq!(parsed.controller_type, original.controller_type);
+        assert_eq!(parsed.firmware, original.firmware);
+        assert_eq!(parsed.timestamp, original.timestamp);
+        assert_eq!(parsed.regions, original.regions);
+    }
+
+    #[test]
+    fn unknown_identity_parses_back_as_none() {
+        let original = SpiBackup {
+            mac: None,
+            controller_type: None,
+            firmware: None,
+            ..backup()
+        };
+        let parsed = SpiBackup::parse(&original.to_bytes()).unwrap();
+        assert_eq!(parsed.mac, None);
+        assert_eq!(parsed.controller_type, None);
+        assert_eq!(parsed.firmware, None);
+    }
+
+    #[test]
+    fn changed_bytes_fail_the_checksum() {
+        let bytes = backup().to_bytes();
+        // In the payload, and in the stored CRC itself
+        for index in [HEADER_LEN + 16 + 5, bytes.len() - 1] {
+            let mut corrupted = bytes.clone();
+            corrupted[index] ^= 0x01;
+            assert_eq!(parse_error(&corrupted), Some(BackupFileError::BadChecksum));
+        }
+    }
+
+    #[test]
+    fn truncated_files_are_rejected() {
+        let bytes = backup().to_bytes();
+        // Inside the header, the region table, the payload, and the CRC
+        for len in [20, HEADER_LEN + 8, HEADER_LEN + 16 + 30, bytes.len() - 1] {
+            assert_eq!(
+                parse_error(&bytes[..len]),
+                Some(BackupFileError::Truncated),
+                "{} bytes",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn newer_format_versions_are_refused() {
+        let mut bytes = backup().to_bytes();
+        bytes[6..8].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        assert_eq!(
+            parse_error(&bytes),
+            Some(BackupFileError::FutureVersion(FORMAT_VERSION + 1))
+        );
+    }
+
+    #[test]
+    fn files_without_the_magic_have_no_header() {
+        let bytes = backup().to_bytes();
+        assert_eq!(parse_error(&bytes[1..]), Some(BackupFileError::NoHeader));
+        assert_eq!(parse_error(&[]), Some(BackupFileError::NoHeader));
+    }
+}
@@ -1,20 +1,140 @@
+use crate::backup::SpiBackup;
+use crate::hid_log;
+use crate::replay::Replay;
 use anyhow::{Result, anyhow};
-use hidapi::{HidApi, HidDevice};
+use hidapi::{BusType, HidApi, HidDevice, HidError, HidResult};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::ffi::{CStr, CString};
+use std::fmt;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, OnceLock, Weak};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 const NINTENDO_VID: u16 = 0x057E;
 const JOYCON_L_PID: u16 = 0x2006;
 const JOYCON_R_PID: u16 = 0x2007;
 const PRO_CONTROLLER_PID: u16 = 0x2009;
+const N64_CONTROLLER_PID: u16 = 0x2019;
+const SNES_CONTROLLER_PID: u16 = 0x2017;
+const GENESIS_CONTROLLER_PID: u16 = 0x201E;
+// Switch 2 controllers use a different calibration protocol and aren't supported yet
+const JOYCON2_R_PID: u16 = 0x2066;
+const JOYCON2_L_PID: u16 = 0x2067;
+const PRO_CONTROLLER2_PID: u16 = 0x2069;
+const GAMECUBE2_CONTROLLER_PID: u16 = 0x2073;
+
+// Extra VID/PID treated as a Pro Controller, for clones that speak the same protocol
+static EXTRA_DEVICE: OnceLock<(u16, u16)> = OnceLock::new();
+
+// Set by --dry-run: no controller writes its flash for the rest of the session
+static FORCE_DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+// Controller type byte in the device info reply. NSO NES controllers reuse the
+// Joy-Con product IDs, so this is the only way to tell them apart.
+const DEVICE_TYPE_JOYCON_L: u8 = 0x01;
+const DEVICE_TYPE_JOYCON_R: u8 = 0x02;
+const DEVICE_TYPE_PRO_CONTROLLER: u8 = 0x03;
+const DEVICE_TYPE_NES_L: u8 = 0x09;
+const DEVICE_TYPE_NES_R: u8 = 0x0A;
+
+// Player LED patterns for subcommand 0x30: low nibble solid, high nibble flashing
+pub const PLAYER_LEDS_FIRST: u8 = 0x01;
+pub const PLAYER_LEDS_FIRST_TWO: u8 = 0x03;
+pub const PLAYER_LEDS_FIRST_THREE: u8 = 0x07;
+pub const PLAYER_LEDS_ALL: u8 = 0x0F;
+pub const PLAYER_LEDS_FLASH_ALL: u8 = 0xF0;
+
+// USB-only commands (report 0x80), answered with report 0x81
+const USB_COMMAND_ID: u8 = 0x80;
+const USB_REPLY_ID: u8 = 0x81;
+const USB_CMD_STATUS: u8 = 0x01;
+const USB_CMD_HANDSHAKE: u8 = 0x02;
+const USB_CMD_HIGH_BAUD: u8 = 0x03;
+const USB_CMD_HID_ONLY: u8 = 0x04;
+const USB_CMD_ALLOW_TIMEOUT: u8 = 0x05;
+
+// How long a freshly opened controller gets to answer anything at all
+const LIVENESS_TIMEOUT: Duration = Duration::from_millis(500);
+
+// How long the connect-time capability check waits for an SPI read reply
+const SPI_CHECK_TIMEOUT: Duration = Duration::from_millis(300);
+
+// How long to wait for the reply to a voltage query (subcommand 0x50)
+const VOLTAGE_TIMEOUT: Duration = Duration::from_millis(300);
+
+// Default wait for a subcommand's 0x21 reply
+const SUBCOMMAND_TIMEOUT: Duration = Duration::from_millis(500);
+
+// The ACK byte each subcommand this crate sends gets back in its 0x21 reply: 0x80 for a
+// plain ACK, with the low bits naming the kind of data that follows
+const REPLY_ACKS: [(u8, u8); 10] = [
+    (0x02, 0x82),
+    (0x03, 0x80),
+    (0x10, 0x90),
+    (0x11, 0x80),
+    (0x30, 0x80),
+    (0x38, 0x80),
+    (0x40, 0x80),
+    (0x41, 0x80),
+    (0x48, 0x80),
+    (0x50, 0xD0),
+];
+
+// Neutral rumble state for both motors, sent in bytes 2-9 of every 0x01 output report.
+// Zeros there read as a malformed rumble state and can cause a buzz or a dropped subcommand.
+const NEUTRAL_RUMBLE: [u8; 8] = [0x00, 0x01, 0x40, 0x40, 0x00, 0x01, 0x40, 0x40];
+
+// Reads that error out this many times in a row mean the device is gone
+const MAX_CONSECUTIVE_READ_FAILURES: u32 = 3;
+
+// Report rate is the number of reports parsed over this trailing window
+const REPORT_RATE_WINDOW: Duration = Duration::from_secs(1);
+
+// Reports a subscriber can fall behind by before the oldest are dropped
+const SUBSCRIPTION_CAPACITY: usize = 256;
+
+// Whole SPI flash, and the parts of it that hold calibration: factory (serial,
+// IMU, sticks, colors, stick parameters) and user (sticks, IMU)
+pub const SPI_FLASH: Range<u32> = 0..0x80000;
+pub const CALIBRATION_REGIONS: [Range<u32>; 2] = [0x6000..0x6100, 0x8000..0x8040];
+pub const COLOR_REGION: Range<u32> = 0x6050..0x605C;
+// The only regions SPI writes may touch, and so the only ones a backup is restored
+// to. Pairing keys, serial and firmware are never rewritten.
+pub const WRITABLE_REGIONS: [(Range<u32>, &str); 5] = [
+    (0x6020..0x6038, "factory motion calibration"),
+    (0x603D..0x604F, "factory stick calibration"),
+    (COLOR_REGION, "body, button and grip colors"),
+    (0x6086..0x60AA, "stick parameters"),
+    (0x8010..0x8040, "user stick and motion calibration"),
+];
 
 // SPI memory addresses
+const SERIAL_NUMBER_ADDR: u32 = 0x6000;
+const COLOR_ADDR: u32 = 0x6050;
+// Pairing slots: 0x26 bytes each, starting with a 0x95 magic byte and the host address at +4
+const PAIRING_INFO_ADDR: u32 = 0x2000;
+const PAIRING_SLOT_SIZE: u32 = 0x26;
+const PAIRING_SLOT_COUNT: u32 = 2;
+const PAIRING_MAGIC: u8 = 0x95;
 const LEFT_STICK_CAL_ADDR: u32 = 0x603D;
 const RIGHT_STICK_CAL_ADDR: u32 = 0x6046;
 const LEFT_STICK_PARAMS_ADDR: u32 = 0x6089;
 const RIGHT_STICK_PARAMS_ADDR: u32 = 0x609B;
+const FACTORY_IMU_CAL_ADDR: u32 = 0x6020;
+// User stick calibration: the 0xB2 0xA1 magic, then the same 9-byte layout as the factory slots
+const LEFT_USER_STICK_CAL_ADDR: u32 = 0x8010;
+const RIGHT_USER_STICK_CAL_ADDR: u32 = 0x801B;
+// User 6-axis calibration: a 2-byte magic, then the same 24-byte layout as the factory block
+const USER_IMU_CAL_ADDR: u32 = 0x8026;
+const USER_CAL_MAGIC: [u8; 2] = [0xB2, 0xA1];
 
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct StickCalibration {
     pub xmax: u16,
     pub ymax: u16,
@@ -24,273 +144,3844 @@ pub struct StickCalibration {
     pub ymin: u16,
 }
 
+// Below this, min to max, an axis barely moves the stick at all
+const MIN_STICK_RANGE: u16 = 0x200;
+
+// The range ratio this tool always used to write. The factory's 0xE14 tends to overshoot
+// and 0xFFF to undershoot; this sits in between and suits Pro Controllers and Joy-Cons.
+pub const DEFAULT_RANGE_RATIO: u16 = 0xF80;
+// Only used when the controller's own calibration and range ratio can't be read: a rough
+// mean travel from center, in raw units, for a healthy stick after the default outer
+// deadzone, which then gets DEFAULT_RANGE_RATIO
+const NOMINAL_STICK_TRAVEL: f64 = 1440.0;
+const MIN_RANGE_RATIO: u16 = 0xC00;
+const MAX_RANGE_RATIO: u16 = 0xFFF;
+
+// Why a stick calibration can't be written as it stands, naming the axis
 #[derive(Debug, Clone, Copy, PartialEq)]
-pub enum ControllerType {
-    JoyConL,
-    JoyConR,
-    ProController,
+pub enum CalibrationProblem {
+    InvertedRange(&'static str),
+    CenterOutsideRange(&'static str),
+    RangeTooSmall(&'static str, u16),
+    // The deadzone and the most it could be
+    DeadzoneTooLarge(u16, u16),
 }
 
-pub struct Controller {
-    device: HidDevice,
-    pub controller_type: ControllerType,
-    timing_byte: u8,
+impl fmt::Display for CalibrationProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CalibrationProblem::InvertedRange(axis) => {
+                write!(f, "{} min is above {} max", axis, axis)
+            }
+            CalibrationProblem::CenterOutsideRange(axis) => {
+                write!(f, "{} center is outside the {} range", axis, axis)
+            }
+            CalibrationProblem::RangeTooSmall(axis, range) => write!(
+                f,
+                "{} range is only {} (at least {} needed)",
+                axis, range, MIN_STICK_RANGE
+            ),
+            CalibrationProblem::DeadzoneTooLarge(deadzone, limit) => write!(
+                f,
+                "Deadzone {} is more than half the range ({})",
+                deadzone, limit
+            ),
+        }
+    }
 }
 
-// Helper functions for stick parameter encoding
-fn encode_stick_params(decoded: &[u16; 2]) -> [u8; 3] {
-    let mut encoded = [0u8; 3];
-    encoded[0] = (decoded[0] & 0xFF) as u8;
-    encoded[1] = ((decoded[0] & 0xF00) >> 8) as u8 | ((decoded[1] & 0xF) << 4) as u8;
-    encoded[2] = ((decoded[1] & 0xFF0) >> 4) as u8;
-    encoded
+impl StickCalibration {
+    // Mean of the four distances from center to the ends of the range, in raw units
+    fn mean_travel(&self) -> f64 {
+        [
+            self.xmax.saturating_sub(self.xcenter),
+            self.xcenter.saturating_sub(self.xmin),
+            self.ymax.saturating_sub(self.ycenter),
+            self.ycenter.saturating_sub(self.ymin),
+        ]
+        .iter()
+        .map(|&side| side as f64)
+        .sum::<f64>()
+            / 4.0
+    }
+
+    // The range ratio scaled to this stick's travel against the calibration the controller
+    // already stores and the range ratio stored with it:
+    //   factory ratio * mean travel / mean travel of the factory calibration
+    // clamped to MIN_RANGE_RATIO..=MAX_RANGE_RATIO, so a stick with 20% less travel than
+    // the factory measured gets a ratio 20% smaller. Without a usable reference, a
+    // NOMINAL_STICK_TRAVEL stick with DEFAULT_RANGE_RATIO stands in for it.
+    pub fn range_ratio(&self, reference: Option<(&StickCalibration, u16)>) -> u16 {
+        let (ratio, travel) = usable_ratio_reference(reference)
+            .unwrap_or((DEFAULT_RANGE_RATIO as f64, NOMINAL_STICK_TRAVEL));
+        let scaled = ratio * self.mean_travel() / travel;
+        (scaled.round() as u16).clamp(MIN_RANGE_RATIO, MAX_RANGE_RATIO)
+    }
+
+    // Everything that would make this calibration underflow when encoded or leave a
+    // useless stick; empty when it is safe to write
+    pub fn validate(&self, deadzone: u16) -> Vec<CalibrationProblem> {
+        let mut problems = Vec::new();
+        let axes = [
+            ("X", self.xmin, self.xcenter, self.xmax),
+            ("Y", self.ymin, self.ycenter, self.ymax),
+        ];
+        for (axis, min, center, max) in axes {
+            if min > max {
+                problems.push(CalibrationProblem::InvertedRange(axis));
+            } else if center < min || center > max {
+                problems.push(CalibrationProblem::CenterOutsideRange(axis));
+            } else if max - min < MIN_STICK_RANGE {
+                problems.push(CalibrationProblem::RangeTooSmall(axis, max - min));
+            }
+        }
+        if problems.is_empty() {
+            let limit = (self.xmax - self.xmin).min(self.ymax - self.ymin) / 2;
+            if deadzone > limit {
+                problems.push(CalibrationProblem::DeadzoneTooLarge(deadzone, limit));
+            }
+        }
+        problems
+    }
 }
 
-#[derive(Debug, Clone, Copy)]
-pub struct StickData {
-    pub lx: u16,
-    pub ly: u16,
-    pub rx: u16,
-    pub ry: u16,
+// A stored calibration and range ratio as (ratio, mean travel), if both look like a real
+// stick's rather than erased or damaged flash
+pub fn usable_ratio_reference(reference: Option<(&StickCalibration, u16)>) -> Option<(f64, f64)> {
+    let (cal, ratio) = reference?;
+    let travel = cal.mean_travel();
+    // Erased flash decodes to ranges past the 12-bit stick values
+    let usable = cal.validate(0).is_empty()
+        && cal.xmax <= 0xFFF
+        && cal.ymax <= 0xFFF
+        && (MIN_RANGE_RATIO..=MAX_RANGE_RATIO).contains(&ratio)
+        && travel > 0.0;
+    usable.then_some((ratio as f64, travel))
 }
 
-impl Default for StickData {
-    fn default() -> Self {
-        Self {
-            lx: 0x800,
-            ly: 0x800,
-            rx: 0x800,
-            ry: 0x800,
+// Both sticks store the deadzone first and the range ratio second, 12 bits each, which is
+// how read_stick_parameters reads them back
+fn encode_stick_parameters(stick: &str, parameters: &StickParameters) -> Result<[u8; 3]> {
+    if parameters.deadzone > 0xFFF || parameters.range_ratio > 0xFFF {
+        return Err(anyhow!(
+            "{} stick parameters don't fit in 12 bits: deadzone {}, range ratio 0x{:X}",
+            stick,
+            parameters.deadzone,
+            parameters.range_ratio
+        ));
+    }
+    Ok(encode_stick_params(&[
+        parameters.deadzone,
+        parameters.range_ratio,
+    ]))
+}
+
+// Raw calibration fields are stored as they are, so each must fit the 12 bits it's packed into
+fn check_raw_stick_calibration(stick: &str, cal: &StickCalibration) -> Result<()> {
+    let fields = [
+        ("xmax", cal.xmax),
+        ("ymax", cal.ymax),
+        ("xcenter", cal.xcenter),
+        ("ycenter", cal.ycenter),
+        ("xmin", cal.xmin),
+        ("ymin", cal.ymin),
+    ];
+    let too_big: Vec<String> = fields
+        .iter()
+        .filter(|(_, value)| *value > 0xFFF)
+        .map(|(name, value)| format!("{} 0x{:X}", name, value))
+        .collect();
+    if too_big.is_empty() {
+        return Ok(());
+    }
+    Err(anyhow!(
+        "{} stick raw calibration doesn't fit in 12 bits: {}",
+        stick,
+        too_big.join(", ")
+    ))
+}
+
+// Refuse a stick calibration with problems, listing them all
+fn check_stick_calibration(stick: &str, cal: &StickCalibration, deadzone: u16) -> Result<()> {
+    let problems = cal.validate(deadzone);
+    if problems.is_empty() {
+        return Ok(());
+    }
+    let problems: Vec<String> = problems.iter().map(|p| p.to_string()).collect();
+    Err(anyhow!(
+        "{} stick calibration is invalid, so nothing was written: {}",
+        stick,
+        problems.join("; ")
+    ))
+}
+
+// What a connected controller can do, worked out once at connect time
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ControllerCapabilities {
+    pub has_left_stick: bool,
+    pub has_right_stick: bool,
+    pub has_imu: bool,
+    // Answered the SPI read probe; calibration can be written
+    pub has_spi_flash: bool,
+    pub has_home_led: bool,
+}
+
+// Body and button colors as stored in SPI, each as [r, g, b]. Pro Controllers also
+// store left and right grip colors.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ControllerColors {
+    pub body: [u8; 3],
+    pub buttons: [u8; 3],
+    pub grips: Option<[[u8; 3]; 2]>,
+}
+
+impl ControllerColors {
+    // The whole color region, with 0xFF where a controller has no grip colors
+    fn to_bytes(self) -> [u8; 12] {
+        let mut bytes = [0xFF; 12];
+        bytes[0..3].copy_from_slice(&self.body);
+        bytes[3..6].copy_from_slice(&self.buttons);
+        if let Some([left, right]) = self.grips {
+            bytes[6..9].copy_from_slice(&left);
+            bytes[9..12].copy_from_slice(&right);
         }
+        bytes
     }
 }
 
-impl Controller {
-    pub fn connect() -> Result<Self> {
-        let api = HidApi::new()?;
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ControllerType {
+    JoyConL,
+    JoyConR,
+    ProController,
+    N64,
+    // NSO controllers without analog sticks
+    Nes,
+    Snes,
+    Genesis,
+    // Recognised so we can say so, but not supported; carries the model name
+    Switch2(&'static str),
+}
 
-        if let Ok(device) = api.open(NINTENDO_VID, JOYCON_L_PID) {
-            return Ok(Controller {
-                device,
-                controller_type: ControllerType::JoyConL,
-                timing_byte: 0,
-            });
+impl ControllerType {
+    // From the type byte of the device info reply; None for bytes we don't recognise
+    fn from_device_type(device_type: u8) -> Option<Self> {
+        match device_type {
+            DEVICE_TYPE_JOYCON_L => Some(ControllerType::JoyConL),
+            DEVICE_TYPE_JOYCON_R => Some(ControllerType::JoyConR),
+            DEVICE_TYPE_PRO_CONTROLLER => Some(ControllerType::ProController),
+            DEVICE_TYPE_NES_L | DEVICE_TYPE_NES_R => Some(ControllerType::Nes),
+            _ => None,
         }
+    }
 
-        if let Ok(device) = api.open(NINTENDO_VID, JOYCON_R_PID) {
-            return Ok(Controller {
-                device,
-                controller_type: ControllerType::JoyConR,
-                timing_byte: 0,
-            });
+    // Stable one-byte code for files; 0 is kept for "unknown"
+    pub fn code(self) -> u8 {
+        match self {
+            ControllerType::JoyConL => 1,
+            ControllerType::JoyConR => 2,
+            ControllerType::ProController => 3,
+            ControllerType::N64 => 4,
+            ControllerType::Nes => 5,
+            ControllerType::Snes => 6,
+            ControllerType::Genesis => 7,
+            ControllerType::Switch2(_) => 0,
         }
+    }
 
-        if let Ok(device) = api.open(NINTENDO_VID, PRO_CONTROLLER_PID) {
-            return Ok(Controller {
-                device,
-                controller_type: ControllerType::ProController,
-                timing_byte: 0,
-            });
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            1 => Some(ControllerType::JoyConL),
+            2 => Some(ControllerType::JoyConR),
+            3 => Some(ControllerType::ProController),
+            4 => Some(ControllerType::N64),
+            5 => Some(ControllerType::Nes),
+            6 => Some(ControllerType::Snes),
+            7 => Some(ControllerType::Genesis),
+            _ => None,
         }
+    }
 
-        Err(anyhow!("No supported controller found."))
+    fn from_product_id(pid: u16) -> Option<Self> {
+        match pid {
+            JOYCON_L_PID => Some(ControllerType::JoyConL),
+            JOYCON_R_PID => Some(ControllerType::JoyConR),
+            PRO_CONTROLLER_PID => Some(ControllerType::ProController),
+            N64_CONTROLLER_PID => Some(ControllerType::N64),
+            SNES_CONTROLLER_PID => Some(ControllerType::Snes),
+            GENESIS_CONTROLLER_PID => Some(ControllerType::Genesis),
+            JOYCON2_R_PID => Some(ControllerType::Switch2("Joy-Con 2 (R)")),
+            JOYCON2_L_PID => Some(ControllerType::Switch2("Joy-Con 2 (L)")),
+            PRO_CONTROLLER2_PID => Some(ControllerType::Switch2("Pro Controller 2")),
+            GAMECUBE2_CONTROLLER_PID => Some(ControllerType::Switch2("NSO GameCube Controller")),
+            _ => None,
+        }
     }
 
-    pub fn get_device_info(&self) -> Result<(String, String)> {
-        let mut buf = [0u8; 49];
-        let mut cmd = [0u8; 49];
-        let mut error_reading = 0;
+    // Only the Joy-Con (R) and Pro Controller have a HOME button LED
+    pub fn has_home_led(&self) -> bool {
+        matches!(
+            self,
+            ControllerType::JoyConR | ControllerType::ProController
+        )
+    }
 
-        while error_reading < 20 {
-            cmd[0] = 0x01; // cmd
-            cmd[10] = 0x02; // subcmd
+    // The NSO N64 controller's single stick reports in the left stick slots
+    fn has_left_stick(&self) -> bool {
+        matches!(
+            self,
+            ControllerType::JoyConL | ControllerType::ProController | ControllerType::N64
+        )
+    }
 
-            self.device.write(&cmd)?;
+    fn has_right_stick(&self) -> bool {
+        matches!(
+            self,
+            ControllerType::JoyConR | ControllerType::ProController
+        )
+    }
 
-            let mut retries = 0;
-            while retries < 8 {
-                match self.device.read_timeout(&mut buf, 64) {
-                    Ok(_) => {
-                        if buf[0x0D] == 0x82 && buf[0x0E] == 0x02 {
-                            let firmware = format!("{:X}.{:02X}", buf[0x0F], buf[0x10]);
-                            let mac = format!(
-                                "{:02X}:{:02X}:{:02X}:{:02X}:{:02X}:{:02X}",
-                                buf[0x13], buf[0x14], buf[0x15], buf[0x16], buf[0x17], buf[0x18]
-                            );
-                            return Ok((firmware, mac));
-                        }
-                    }
-                    Err(_) => break,
-                }
-                retries += 1;
-            }
-            error_reading += 1;
+    fn has_imu(&self) -> bool {
+        matches!(
+            self,
+            ControllerType::JoyConL | ControllerType::JoyConR | ControllerType::ProController
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConnectionKind {
+    Usb,
+    Bluetooth,
+}
+
+impl fmt::Display for ConnectionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectionKind::Usb => write!(f, "USB"),
+            ConnectionKind::Bluetooth => write!(f, "Bluetooth"),
         }
+    }
+}
 
-        Err(anyhow!("Failed to get valid device info"))
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FirmwareVersion {
+    pub major: u8,
+    pub minor: u8,
+}
+
+impl fmt::Display for FirmwareVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:X}.{:02X}", self.major, self.minor)
     }
+}
 
-    pub fn get_controller_type(&self) -> ControllerType {
-        self.controller_type
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FirmwareCompatibility {
+    Compatible,
+    // Pre-3.x firmwares interpret the stick parameter block differently
+    LegacyStickParams,
+    // Firmware known to ignore user calibration data
+    IgnoresUserCalibration,
+    // Device info hasn't been read (yet)
+    Unknown,
+}
+
+// Firmwares before this interpret the stick parameter block differently
+const FIRST_MODERN_FIRMWARE: FirmwareVersion = FirmwareVersion { major: 3, minor: 0 };
+
+// Specific firmware versions reported to ignore user calibration. None are
+// confirmed yet; add versions here as they are verified on real hardware.
+const FIRMWARES_IGNORING_USER_CAL: &[FirmwareVersion] = &[];
+
+impl FirmwareVersion {
+    pub fn compatibility(&self) -> FirmwareCompatibility {
+        if FIRMWARES_IGNORING_USER_CAL.contains(self) {
+            FirmwareCompatibility::IgnoresUserCalibration
+        } else if *self < FIRST_MODERN_FIRMWARE {
+            FirmwareCompatibility::LegacyStickParams
+        } else {
+            FirmwareCompatibility::Compatible
+        }
     }
+}
 
-    pub fn enable_standard_input(&mut self) -> Result<()> {
-        let mut cmd = [0u8; 49];
-        cmd[0] = 0x01; // cmd
-        cmd[1] = self.timing_byte & 0xF;
-        self.timing_byte = self.timing_byte.wrapping_add(1);
-        cmd[10] = 0x03; // subcmd
-        cmd[11] = 0x30; // arg
-        self.device.write(&cmd)?;
-        thread::sleep(Duration::from_millis(100));
-        Ok(())
+// Identity from the device info reply (subcommand 0x02)
+#[derive(Debug, Clone)]
+pub struct DeviceInfo {
+    pub firmware: FirmwareVersion,
+    pub mac: [u8; 6],
+    // Type the controller claims to be; None for a type byte we don't recognise
+    pub reported_type: Option<ControllerType>,
+    // Whether the console should show the body and button colors stored in SPI
+    pub colors_in_spi: bool,
+}
+
+impl DeviceInfo {
+    pub fn mac_string(&self) -> String {
+        format_mac(&self.mac)
     }
+}
 
-    pub fn read_stick_data(&self) -> Result<StickData> {
-        let mut last_valid_data: Option<StickData> = None;
-        let mut buf = [0u8; 0x170];
+pub fn format_mac(mac: &[u8; 6]) -> String {
+    mac.iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
 
-        // Loop to drain the buffer and get the latest packet
-        loop {
-            // Use 0ms timeout to just check if data is available
-            match self.device.read_timeout(&mut buf, 0) {
-                Ok(res) if res > 0 => {
-                    if res > 12 {
-                        let lx = ((buf[7] & 0xF) as u16) << 8 | buf[6] as u16;
-                        let ly = (buf[8] as u16) << 4 | ((buf[7] & 0xF0) >> 4) as u16;
-                        let rx = ((buf[10] & 0xF) as u16) << 8 | buf[9] as u16;
-                        let ry = (buf[11] as u16) << 4 | ((buf[10] & 0xF0) >> 4) as u16;
-                        last_valid_data = Some(StickData { lx, ly, rx, ry });
-                    }
-                }
-                _ => break, // No more data or error, stop reading
-            }
-        }
+// Outcome of reading back one region after a calibration write
+#[derive(Debug, Clone)]
+pub struct RegionCheck {
+    pub name: &'static str,
+    pub offset: u32,
+    pub passed: bool,
+}
+
+// A supported controller found during enumeration, not yet opened
+#[derive(Debug, Clone)]
+pub struct ControllerInfo {
+    pub path: CString,
+    pub controller_type: ControllerType,
+    pub serial: Option<String>,
+    pub bus: BusType,
+    // Known without connecting only over Bluetooth, where the HID serial is the MAC
+    pub mac: Option<String>,
+}
 
-        if let Some(data) = last_valid_data {
-            Ok(data)
+impl ControllerInfo {
+    fn from_device_info(info: &hidapi::DeviceInfo) -> Option<Self> {
+        let controller_type = if EXTRA_DEVICE.get() == Some(&(info.vendor_id(), info.product_id()))
+        {
+            ControllerType::ProController
+        } else if info.vendor_id() == NINTENDO_VID {
+            ControllerType::from_product_id(info.product_id())?
         } else {
-            // If we didn't get any new data this frame, try a blocking read for a short time
-            // to ensure we return *something* if the buffer was empty initially.
-            // This keeps the loop running.
-            match self.device.read_timeout(&mut buf, 20) {
-                Ok(res) if res > 12 => {
-                    let lx = ((buf[7] & 0xF) as u16) << 8 | buf[6] as u16;
-                    let ly = (buf[8] as u16) << 4 | ((buf[7] & 0xF0) >> 4) as u16;
-                    let rx = ((buf[10] & 0xF) as u16) << 8 | buf[9] as u16;
-                    let ry = (buf[11] as u16) << 4 | ((buf[10] & 0xF0) >> 4) as u16;
-                    Ok(StickData { lx, ly, rx, ry })
-                }
-                Ok(_) => Err(anyhow!("No data or invalid packet")),
-                Err(e) => Err(anyhow!(e)),
+            return None;
+        };
+        let serial = info
+            .serial_number()
+            .filter(|s| !s.is_empty())
+            .map(str::to_string);
+        let mac = match info.bus_type() {
+            BusType::Bluetooth => serial.as_deref().and_then(mac_from_serial),
+            _ => None,
+        };
+        Some(ControllerInfo {
+            path: info.path().to_owned(),
+            controller_type,
+            serial,
+            bus: info.bus_type(),
+            mac,
+        })
+    }
+}
+
+// Bluetooth stacks report the MAC as the serial, with or without separators and
+// in either case. Normalise it to the AA:BB:CC:DD:EE:FF form get_device_info uses.
+fn mac_from_serial(serial: &str) -> Option<String> {
+    let digits: Vec<char> = serial
+        .chars()
+        .filter(|c| *c != ':' && *c != '-')
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    if digits.len() != 12 || !digits.iter().all(char::is_ascii_hexdigit) {
+        return None;
+    }
+    Some(
+        digits
+            .chunks(2)
+            .map(|pair| pair.iter().collect::<String>())
+            .collect::<Vec<_>>()
+            .join(":"),
+    )
+}
+
+// Errors callers may want to branch on. Returned wrapped in anyhow::Error;
+// use downcast_ref::<ControllerError>() to inspect.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ControllerError {
+    Disconnected,
+    NotNintendoController,
+    // A recognised controller this tool can't calibrate, with its model name
+    UnsupportedModel(&'static str),
+    // Another program (Steam Input, joycond, ...) holds the device or swallows its reports
+    DeviceBusy,
+    // An SPI write, as start and end offsets, outside WRITABLE_REGIONS
+    ForbiddenOffset(u32, u32),
+}
+
+impl fmt::Display for ControllerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ControllerError::Disconnected => write!(f, "Controller disconnected"),
+            ControllerError::NotNintendoController => {
+                write!(f, "Device is not a supported Nintendo controller")
             }
+            ControllerError::UnsupportedModel(model) => {
+                write!(f, "{} is not supported", model)
+            }
+            ControllerError::DeviceBusy => write!(
+                f,
+                "Controller is in use by another program (Steam Input or joycond?)"
+            ),
+            ControllerError::ForbiddenOffset(start, end) => write!(
+                f,
+                "Refusing to write 0x{:04X}-0x{:04X}, outside the calibration regions",
+                start, end
+            ),
         }
     }
+}
 
-    pub fn write_spi_data(&mut self, offset: u32, data: &[u8]) -> Result<()> {
-        const MAX_ATTEMPTS: u32 = 20;
-        const MAX_RETRIES: u32 = 8;
-        let mut buf = [0u8; 49];
+impl std::error::Error for ControllerError {}
 
-        for _ in 0..MAX_ATTEMPTS {
-            buf[0] = 0x01; // cmd
-            buf[1] = self.timing_byte & 0xF;
-            self.timing_byte = self.timing_byte.wrapping_add(1);
-            buf[10] = 0x11; // subcmd for SPI write
-            buf[11..15].copy_from_slice(&offset.to_le_bytes());
-            buf[15] = data.len() as u8;
-            buf[16..16 + data.len()].copy_from_slice(data);
-
-            self.device.write(&buf)?;
-
-            for _ in 0..MAX_RETRIES {
-                let mut resp = [0u8; 49];
-                match self.device.read_timeout(&mut resp, 64) {
-                    Ok(_) => {
-                        if resp[0x0D] == 0x80 && resp[0x0E] == 0x11 {
-                            thread::sleep(Duration::from_millis(100));
-                            return Ok(());
-                        }
-                    }
-                    Err(_) => break,
-                }
+// Why a subcommand got no usable reply. Returned wrapped in anyhow::Error like
+// ControllerError, so retry loops can tell the cases apart.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SubcommandFailure {
+    Nacked(u8),
+    // Only replies to another subcommand (the one given second) arrived in time
+    WrongReply(u8, u8),
+    TimedOut(u8),
+}
+
+impl fmt::Display for SubcommandFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SubcommandFailure::Nacked(subcmd) => {
+                write!(f, "Controller rejected subcommand 0x{:02X}", subcmd)
             }
-            thread::sleep(Duration::from_millis(10));
+            SubcommandFailure::WrongReply(subcmd, other) => write!(
+                f,
+                "Got a reply to subcommand 0x{:02X} while waiting for 0x{:02X}",
+                other, subcmd
+            ),
+            SubcommandFailure::TimedOut(subcmd) => write!(
+                f,
+                "Timed out waiting for the reply to subcommand 0x{:02X}",
+                subcmd
+            ),
         }
-        Err(anyhow!("Failed to write SPI data"))
     }
+}
 
-    pub fn write_calibration_to_device(
-        &mut self,
-        left_cal: StickCalibration,
-        right_cal: StickCalibration,
-        left_deadzone: u16,
-        right_deadzone: u16,
-        _raw_calibration: bool, // Currently unused logic but kept for interface
-    ) -> Result<()> {
-        // Fixed range ratio as in original code
-        // Default value here is 0xE14, but greater than ideal
-        // overshoot, while 0xFFF tends to cause some undershoot.
-        // There's probably a proper way to model this var's effect
-        // and calculate the correct value, but somewhere in the middle
-        // seems to behave fine with both Pro Controllers and Joycons.
-        let range_ratio_l = 0xF80;
-        let range_ratio_r = 0xF80;
-
-        let mut left_params = encode_stick_params(&[left_deadzone, range_ratio_l]);
-        let mut right_params = encode_stick_params(&[range_ratio_r, right_deadzone]);
+impl std::error::Error for SubcommandFailure {}
 
-        let (final_left_cal, final_right_cal) = match self.controller_type {
-            ControllerType::JoyConL => {
-                right_params = left_params;
-                (left_cal, left_cal)
-            }
-            ControllerType::JoyConR => {
-                left_params = right_params;
-                (right_cal, right_cal)
+// An SPI write that failed every attempt, with how each attempt went wrong
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpiWriteError {
+    pub offset: u32,
+    pub attempts: u32,
+    pub nacked: u32,
+    pub wrong_reply: u32,
+    pub timed_out: u32,
+    // Anything else, such as a read error; the last one is kept
+    pub other: u32,
+    pub last_other: Option<String>,
+}
+
+impl fmt::Display for SpiWriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut reasons = Vec::new();
+        for (count, reason) in [
+            (self.timed_out, "timed out"),
+            (self.nacked, "NACKed"),
+            (self.wrong_reply, "answered another subcommand"),
+            (self.other, "failed otherwise"),
+        ] {
+            if count > 0 {
+                reasons.push(format!("{} {}", count, reason));
             }
-            ControllerType::ProController => (left_cal, right_cal),
-        };
+        }
+        write!(
+            f,
+            "Failed to write SPI data at 0x{:04X} after {} attempts ({})",
+            self.offset,
+            self.attempts,
+            reasons.join(", ")
+        )?;
+        if let Some(last) = &self.last_other {
+            write!(f, "; last error: {}", last)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for SpiWriteError {}
+
+// A calibration write that failed after changing some regions, and how putting their
+// old contents back went
+#[derive(Debug)]
+pub struct CalibrationWriteError {
+    pub cause: anyhow::Error,
+    // Regions left holding new or partial data; empty when the rollback succeeded
+    pub not_restored: Vec<&'static str>,
+    pub rollback_error: Option<anyhow::Error>,
+}
 
-        self.write_right_stick_calibration(&final_right_cal)?;
-        self.write_spi_data(RIGHT_STICK_PARAMS_ADDR, &right_params)?;
-        self.write_left_stick_calibration(&final_left_cal)?;
-        self.write_spi_data(LEFT_STICK_PARAMS_ADDR, &left_params)?;
+impl CalibrationWriteError {
+    pub fn rolled_back(&self) -> bool {
+        self.not_restored.is_empty()
+    }
+}
 
+impl fmt::Display for CalibrationWriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.rolled_back() {
+            return write!(
+                f,
+                "{}. Nothing was changed: the regions already written were put back",
+                self.cause
+            );
+        }
+        write!(
+            f,
+            "{}. Rolling back also failed, so {} may hold partial data",
+            self.cause,
+            self.not_restored.join(", ")
+        )?;
+        if let Some(e) = &self.rollback_error {
+            write!(f, " ({})", e)?;
+        }
         Ok(())
     }
+}
+
+impl std::error::Error for CalibrationWriteError {}
+
+// Exponential backoff from 10ms, capped at 320ms, with up to 50% random jitter so
+// retries don't fall into step with the controller's own timing
+fn retry_backoff(attempt: u32) -> Duration {
+    use std::hash::{BuildHasher, RandomState};
+    let base = 10u64 << attempt.min(5);
+    let jitter = RandomState::new().hash_one(attempt) % (base / 2 + 1);
+    Duration::from_millis(base + jitter)
+}
+
+// Where reports are written to and read from: a real device, or a recording played back
+pub trait HidBackend: Send {
+    fn write(&self, data: &[u8]) -> HidResult<usize>;
+    fn read_timeout(&self, buf: &mut [u8], timeout_ms: i32) -> HidResult<usize>;
+}
+
+impl HidBackend for HidDevice {
+    fn write(&self, data: &[u8]) -> HidResult<usize> {
+        HidDevice::write(self, data)
+    }
+
+    fn read_timeout(&self, buf: &mut [u8], timeout_ms: i32) -> HidResult<usize> {
+        HidDevice::read_timeout(self, buf, timeout_ms)
+    }
+}
+
+// How far a long SPI operation has got, in bytes, and what it's doing
+#[derive(Debug, Clone, Copy)]
+pub struct Progress {
+    pub done: u32,
+    pub total: u32,
+    pub phase: &'static str,
+}
+
+// Handed to the work of an Operation to report progress and check for cancellation.
+// Work should check `cancelled` only between chunks, once a chunk is complete.
+pub struct OperationContext {
+    progress: Sender<Progress>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl OperationContext {
+    // For running operation code inline, where nobody watches or cancels it
+    pub fn detached() -> Self {
+        Self {
+            progress: mpsc::channel().0,
+            cancel: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn report(&self, done: u32, total: u32, phase: &'static str) {
+        // Nobody listening just means nobody wants to know
+        let _ = self.progress.send(Progress { done, total, phase });
+    }
+
+    pub fn cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+}
+
+// A dump, restore or verification running on its own thread, so a Bluetooth
+// controller's tens of seconds of SPI traffic never block the UI. The UI polls for
+// progress and the result; cancel() takes effect at the next chunk boundary.
+pub struct Operation<T> {
+    progress: Receiver<Progress>,
+    cancel: Arc<AtomicBool>,
+    latest: Option<Progress>,
+    handle: Option<thread::JoinHandle<Result<T>>>,
+}
+
+impl<T: Send + 'static> Operation<T> {
+    pub fn spawn(work: impl FnOnce(&OperationContext) -> Result<T> + Send + 'static) -> Self {
+        let (sender, progress) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let context = OperationContext {
+            progress: sender,
+            cancel: cancel.clone(),
+        };
+        Self {
+            progress,
+            cancel,
+            latest: None,
+            handle: Some(thread::spawn(move || work(&context))),
+        }
+    }
 
-    fn write_left_stick_calibration(&mut self, left_cal: &StickCalibration) -> Result<()> {
-        let mut data = [0u16; 6];
-        data[0] = left_cal.xmax - left_cal.xcenter;
-        data[1] = left_cal.ymax - left_cal.ycenter;
-        data[2] = left_cal.xcenter;
-        data[3] = left_cal.ycenter;
-        data[4] = left_cal.xcenter - left_cal.xmin;
-        data[5] = left_cal.ycenter - left_cal.ymin;
+    // Drain the progress sent since the last poll, then hand back the result if the
+    // work has finished. Returns None while it's still running.
+    pub fn poll(&mut self) -> Option<Result<T>> {
+        if let Some(progress) = self.progress.try_iter().last() {
+            self.latest = Some(progress);
+        }
+        if !self.handle.as_ref()?.is_finished() {
+            return None;
+        }
+        let result = self.handle.take()?.join();
+        Some(result.unwrap_or_else(|_| Err(anyhow!("The operation panicked"))))
+    }
 
-        let mut stick_cal = [0u8; 9];
-        stick_cal[0..3].copy_from_slice(&encode_stick_params(&[data[0], data[1]]));
-        stick_cal[3..6].copy_from_slice(&encode_stick_params(&[data[2], data[3]]));
-        stick_cal[6..9].copy_from_slice(&encode_stick_params(&[data[4], data[5]]));
+    pub fn latest(&self) -> Option<Progress> {
+        self.latest
+    }
 
-        self.write_spi_data(LEFT_STICK_CAL_ADDR, &stick_cal)
+    // Shared with the UI so its Cancel button can reach the running work
+    pub fn cancel_flag(&self) -> Arc<AtomicBool> {
+        self.cancel.clone()
     }
+}
+
+pub struct Controller {
+    device: Box<dyn HidBackend>,
+    pub controller_type: ControllerType,
+    timing_byte: u8,
+    read_failures: u32,
+    connection: ConnectionKind,
+    capabilities: ControllerCapabilities,
+    shut_down: bool,
+    // Battery from the device info reply, before full input reports are enabled
+    battery: Option<BatteryStatus>,
+    firmware: Option<FirmwareVersion>,
+    last_report: Instant,
+    // When each report in the last REPORT_RATE_WINDOW was parsed
+    report_times: VecDeque<Instant>,
+    // Timer byte of the previous full report; None after reports were skipped unparsed
+    last_timer: Option<u8>,
+    packet_stats: PacketStats,
+    // Newest input report not yet handed out by read_stick_data, including ones that
+    // arrived while a subcommand was waiting for its reply
+    pending_input: Option<StickData>,
+    // Queues of live subscribers; dropped receivers are pruned on the next report
+    subscribers: Vec<Weak<Mutex<VecDeque<InputReport>>>>,
+    // Detected at connect: some macOS Bluetooth stacks deliver reports without the ID byte
+    report_id_missing: bool,
+    // Subcommand whose reply is being waited for, the only one a stripped NACK is taken for
+    awaiting_reply: Option<u8>,
+    // Re-sent along with the report mode, which a reconnect or wake resets
+    imu_enabled: bool,
+    // Needed to turn raw IMU readings into physical units
+    imu_settings: ImuSettings,
+    // In dry-run mode SPI writes are assembled as usual but recorded here, as
+    // (offset, bytes) per packet, instead of being sent
+    dry_run: bool,
+    recorded_writes: Vec<(u32, Vec<u8>)>,
+    // Left and right parameter blocks as last read, so a dry run can tell whether a write
+    // would change them without reading the flash again
+    stored_stick_params: [Option<[u8; 3]>; 2],
+    // Lifts the WRITABLE_REGIONS guard on SPI writes
+    allow_any_offset: bool,
+}
+
+// Encode one motor's HD rumble state into the 4-byte output report format.
+// The high band covers roughly 82-1252 Hz and the low band 41-626 Hz; amplitude is 0.0-1.0.
+// With amplitude 0 this yields the neutral pattern 00 01 40 40 for 320/160 Hz.
+pub fn encode_rumble(high_freq: f32, low_freq: f32, amplitude: f32) -> [u8; 4] {
+    let encode_freq = |freq: f32| ((freq / 10.0).log2() * 32.0).round();
+    let hf = ((encode_freq(high_freq.clamp(81.75, 1252.0)) as i32 - 0x60) * 4) as u16;
+    let lf = (encode_freq(low_freq.clamp(40.875, 626.0)) as i32 - 0x40) as u8;
+
+    let amp = amplitude.clamp(0.0, 1.0);
+    let encoded_amp = if amp <= 0.0 {
+        0
+    } else if amp > 0.23 {
+        ((amp * 8.7).log2() * 32.0).round() as u8
+    } else if amp > 0.12 {
+        ((amp * 17.0).log2() * 16.0).round() as u8
+    } else {
+        ((amp.log2() * 32.0 - 96.0) / (4.0 - 2.0 * amp))
+            .round()
+            .max(0.0) as u8
+    };
+    let hf_amp = encoded_amp as u16 * 2;
+    // Odd steps set the top bit, which lands in bit 7 above the low band's frequency
+    let lf_amp = (encoded_amp as u16 / 2 + 0x40) | ((encoded_amp as u16 & 1) << 15);
+
+    [
+        (hf & 0xFF) as u8,
+        (hf_amp + (hf >> 8)) as u8,
+        lf + (lf_amp >> 8) as u8,
+        (lf_amp & 0xFF) as u8,
+    ]
+}
+
+// Helper functions for stick parameter encoding
+fn encode_stick_params(decoded: &[u16; 2]) -> [u8; 3] {
+    let mut encoded = [0u8; 3];
+    encoded[0] = (decoded[0] & 0xFF) as u8;
+    encoded[1] = ((decoded[0] & 0xF00) >> 8) as u8 | ((decoded[1] & 0xF) << 4) as u8;
+    encoded[2] = ((decoded[1] & 0xFF0) >> 4) as u8;
+    encoded
+}
 
-    fn write_right_stick_calibration(&mut self, right_cal: &StickCalibration) -> Result<()> {
-        let mut data = [0u16; 6];
-        data[0] = right_cal.xcenter;
-        data[1] = right_cal.ycenter;
-        data[2] = right_cal.xcenter - right_cal.xmin;
-        data[3] = right_cal.ycenter - right_cal.ymin;
-        data[4] = right_cal.xmax - right_cal.xcenter;
-        data[5] = right_cal.ymax - right_cal.ycenter;
+// Inverse of encode_stick_params: two 12-bit values packed into three bytes
+fn decode_stick_params(encoded: &[u8; 3]) -> [u16; 2] {
+    [
+        (encoded[1] as u16 & 0xF) << 8 | encoded[0] as u16,
+        (encoded[2] as u16) << 4 | (encoded[1] as u16) >> 4,
+    ]
+}
+
+// Where a stick calibration write goes. User slots are what the console's own calibration
+// uses and leave the factory values intact; Factory overwrites them, for repairing
+// controllers whose factory calibration is already damaged.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CalibrationTarget {
+    Factory,
+    User,
+}
 
-        let mut stick_cal = [0u8; 9];
-        stick_cal[0..3].copy_from_slice(&encode_stick_params(&[data[0], data[1]]));
-        stick_cal[3..6].copy_from_slice(&encode_stick_params(&[data[2], data[3]]));
-        stick_cal[6..9].copy_from_slice(&encode_stick_params(&[data[4], data[5]]));
+// The left stick is stored as [max above center, center, min below center] and the
+// right one as [center, min below center, max above center], each an X/Y pair.
+fn encode_left_stick_cal(cal: &StickCalibration) -> [u8; 9] {
+    let mut encoded = [0u8; 9];
+    encoded[0..3].copy_from_slice(&encode_stick_params(&[
+        cal.xmax - cal.xcenter,
+        cal.ymax - cal.ycenter,
+    ]));
+    encoded[3..6].copy_from_slice(&encode_stick_params(&[cal.xcenter, cal.ycenter]));
+    encoded[6..9].copy_from_slice(&encode_stick_params(&[
+        cal.xcenter - cal.xmin,
+        cal.ycenter - cal.ymin,
+    ]));
+    encoded
+}
 
-        self.write_spi_data(RIGHT_STICK_CAL_ADDR, &stick_cal)
+fn encode_right_stick_cal(cal: &StickCalibration) -> [u8; 9] {
+    let mut encoded = [0u8; 9];
+    encoded[0..3].copy_from_slice(&encode_stick_params(&[cal.xcenter, cal.ycenter]));
+    encoded[3..6].copy_from_slice(&encode_stick_params(&[
+        cal.xcenter - cal.xmin,
+        cal.ycenter - cal.ymin,
+    ]));
+    encoded[6..9].copy_from_slice(&encode_stick_params(&[
+        cal.xmax - cal.xcenter,
+        cal.ymax - cal.ycenter,
+    ]));
+    encoded
+}
+
+// Raw calibration: the fields already hold what is stored, with xmax/ymax the range above
+// center and xmin/ymin the range below it. Same layouts as above, minus the subtraction.
+fn encode_raw_left_stick_cal(cal: &StickCalibration) -> [u8; 9] {
+    let mut encoded = [0u8; 9];
+    encoded[0..3].copy_from_slice(&encode_stick_params(&[cal.xmax, cal.ymax]));
+    encoded[3..6].copy_from_slice(&encode_stick_params(&[cal.xcenter, cal.ycenter]));
+    encoded[6..9].copy_from_slice(&encode_stick_params(&[cal.xmin, cal.ymin]));
+    encoded
+}
+
+fn encode_raw_right_stick_cal(cal: &StickCalibration) -> [u8; 9] {
+    let mut encoded = [0u8; 9];
+    encoded[0..3].copy_from_slice(&encode_stick_params(&[cal.xcenter, cal.ycenter]));
+    encoded[3..6].copy_from_slice(&encode_stick_params(&[cal.xmin, cal.ymin]));
+    encoded[6..9].copy_from_slice(&encode_stick_params(&[cal.xmax, cal.ymax]));
+    encoded
+}
+
+// A raw calibration as absolute min/center/max, for checking it like any other
+fn raw_stick_cal_to_absolute(cal: &StickCalibration) -> StickCalibration {
+    stick_cal_from_parts(
+        [cal.xmax, cal.ymax],
+        [cal.xcenter, cal.ycenter],
+        [cal.xmin, cal.ymin],
+    )
+}
+
+// A user calibration slot: the magic, then the encoded stick calibration
+fn user_cal_block(encoded: &[u8; 9]) -> [u8; 11] {
+    let mut block = [0u8; 11];
+    block[0..2].copy_from_slice(&USER_CAL_MAGIC);
+    block[2..11].copy_from_slice(encoded);
+    block
+}
+
+// Turn stored [above, center, below] pairs back into absolute min/center/max
+fn stick_cal_from_parts(above: [u16; 2], center: [u16; 2], below: [u16; 2]) -> StickCalibration {
+    StickCalibration {
+        xmax: center[0] + above[0],
+        ymax: center[1] + above[1],
+        xcenter: center[0],
+        ycenter: center[1],
+        xmin: center[0].saturating_sub(below[0]),
+        ymin: center[1].saturating_sub(below[1]),
+    }
+}
+
+fn decode_left_stick_cal(encoded: &[u8; 9]) -> StickCalibration {
+    let part = |i: usize| decode_stick_params(&[encoded[i], encoded[i + 1], encoded[i + 2]]);
+    stick_cal_from_parts(part(0), part(3), part(6))
+}
+
+fn decode_right_stick_cal(encoded: &[u8; 9]) -> StickCalibration {
+    let part = |i: usize| decode_stick_params(&[encoded[i], encoded[i + 1], encoded[i + 2]]);
+    stick_cal_from_parts(part(6), part(0), part(3))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum StickSide {
+    Left,
+    Right,
+}
+
+// Deadzone and range ratio as currently stored for one stick
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct StickParameters {
+    pub deadzone: u16,
+    pub range_ratio: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum BatteryLevel {
+    Empty,
+    Critical,
+    Low,
+    Medium,
+    Full,
+}
+
+impl fmt::Display for BatteryLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BatteryLevel::Empty => write!(f, "Empty"),
+            BatteryLevel::Critical => write!(f, "Critical"),
+            BatteryLevel::Low => write!(f, "Low"),
+            BatteryLevel::Medium => write!(f, "Medium"),
+            BatteryLevel::Full => write!(f, "Full"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BatteryStatus {
+    pub level: BatteryLevel,
+    pub charging: bool,
+}
+
+impl BatteryStatus {
+    // High nibble of byte 2 in full and subcommand reply reports:
+    // level in the top three bits, charging in the lowest
+    fn from_report_byte(byte: u8) -> Self {
+        let level = match byte >> 5 {
+            0 => BatteryLevel::Empty,
+            1 => BatteryLevel::Critical,
+            2 => BatteryLevel::Low,
+            3 => BatteryLevel::Medium,
+            _ => BatteryLevel::Full,
+        };
+        BatteryStatus {
+            level,
+            charging: byte & 0x10 != 0,
+        }
+    }
+}
+
+// A 0x21 reply matched to the subcommand that caused it
+pub struct SubcommandReply {
+    report: [u8; 64],
+}
+
+impl SubcommandReply {
+    // Subcommand-specific payload, following the ACK and echoed subcommand ID
+    pub fn data(&self) -> &[u8] {
+        &self.report[0x0F..]
+    }
+
+    pub fn battery(&self) -> BatteryStatus {
+        BatteryStatus::from_report_byte(self.report[2])
+    }
+}
+
+// Gyroscope full-scale range, in degrees per second
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GyroRange {
+    Dps250,
+    Dps500,
+    Dps1000,
+    Dps2000,
+}
+
+impl GyroRange {
+    fn code(self) -> u8 {
+        match self {
+            GyroRange::Dps250 => 0x00,
+            GyroRange::Dps500 => 0x01,
+            GyroRange::Dps1000 => 0x02,
+            GyroRange::Dps2000 => 0x03,
+        }
+    }
+
+    // Nominal range, for labels; the sensor's raw +32767 reads somewhat beyond it
+    pub fn full_scale(self) -> f32 {
+        match self {
+            GyroRange::Dps250 => 250.0,
+            GyroRange::Dps500 => 500.0,
+            GyroRange::Dps1000 => 1000.0,
+            GyroRange::Dps2000 => 2000.0,
+        }
+    }
+
+    // Degrees per second per raw unit, from the LSM6DS3 datasheet (8.75 to 70 mdps/LSB)
+    pub fn sensitivity(self) -> f32 {
+        match self {
+            GyroRange::Dps250 => 0.00875,
+            GyroRange::Dps500 => 0.0175,
+            GyroRange::Dps1000 => 0.035,
+            GyroRange::Dps2000 => 0.070,
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            GyroRange::Dps250 => GyroRange::Dps500,
+            GyroRange::Dps500 => GyroRange::Dps1000,
+            GyroRange::Dps1000 => GyroRange::Dps2000,
+            GyroRange::Dps2000 => GyroRange::Dps250,
+        }
+    }
+}
+
+// Accelerometer full-scale range, in g
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AccelRange {
+    G2,
+    G4,
+    G8,
+    G16,
+}
+
+impl AccelRange {
+    fn code(self) -> u8 {
+        match self {
+            AccelRange::G8 => 0x00,
+            AccelRange::G4 => 0x01,
+            AccelRange::G2 => 0x02,
+            AccelRange::G16 => 0x03,
+        }
+    }
+
+    // Nominal range, for labels
+    pub fn full_scale(self) -> f32 {
+        match self {
+            AccelRange::G2 => 2.0,
+            AccelRange::G4 => 4.0,
+            AccelRange::G8 => 8.0,
+            AccelRange::G16 => 16.0,
+        }
+    }
+
+    // g per raw unit, from the LSM6DS3 datasheet (0.061 to 0.488 mg/LSB)
+    pub fn sensitivity(self) -> f32 {
+        match self {
+            AccelRange::G2 => 0.000061,
+            AccelRange::G4 => 0.000122,
+            AccelRange::G8 => 0.000244,
+            AccelRange::G16 => 0.000488,
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            AccelRange::G2 => AccelRange::G4,
+            AccelRange::G4 => AccelRange::G8,
+            AccelRange::G8 => AccelRange::G16,
+            AccelRange::G16 => AccelRange::G2,
+        }
+    }
+}
+
+// Gyroscope output data rate; 833Hz lowers latency at the cost of power
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GyroRate {
+    Hz208,
+    Hz833,
+}
+
+impl GyroRate {
+    fn code(self) -> u8 {
+        match self {
+            GyroRate::Hz833 => 0x00,
+            GyroRate::Hz208 => 0x01,
+        }
+    }
+
+    pub fn hz(self) -> u16 {
+        match self {
+            GyroRate::Hz208 => 208,
+            GyroRate::Hz833 => 833,
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            GyroRate::Hz208 => GyroRate::Hz833,
+            GyroRate::Hz833 => GyroRate::Hz208,
+        }
+    }
+}
+
+// Accelerometer anti-aliasing filter bandwidth
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AccelFilter {
+    Hz100,
+    Hz200,
+}
+
+impl AccelFilter {
+    fn code(self) -> u8 {
+        match self {
+            AccelFilter::Hz200 => 0x00,
+            AccelFilter::Hz100 => 0x01,
+        }
+    }
+
+    pub fn hz(self) -> u16 {
+        match self {
+            AccelFilter::Hz100 => 100,
+            AccelFilter::Hz200 => 200,
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            AccelFilter::Hz100 => AccelFilter::Hz200,
+            AccelFilter::Hz200 => AccelFilter::Hz100,
+        }
+    }
+}
+
+// IMU configuration as last sent with subcommand 0x41. Defaults match the controller's own.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImuSettings {
+    pub gyro_range: GyroRange,
+    pub accel_range: AccelRange,
+    pub gyro_rate: GyroRate,
+    pub accel_filter: AccelFilter,
+}
+
+impl Default for ImuSettings {
+    fn default() -> Self {
+        Self {
+            gyro_range: GyroRange::Dps2000,
+            accel_range: AccelRange::G8,
+            gyro_rate: GyroRate::Hz208,
+            accel_filter: AccelFilter::Hz100,
+        }
+    }
+}
+
+// 6-axis calibration as stored in SPI, X/Y/Z triplets in raw units at the default
+// ranges (±8g, ±2000dps). Origins are the zero readings; sensitivities the scale.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImuCalibration {
+    pub accel_origin: [i16; 3],
+    pub accel_sensitivity: [i16; 3],
+    pub gyro_origin: [i16; 3],
+    pub gyro_sensitivity: [i16; 3],
+}
+
+impl ImuCalibration {
+    // Nominal sensitivities, for a user calibration that only measures origins
+    pub const DEFAULT_ACCEL_SENSITIVITY: [i16; 3] = [0x4000; 3];
+    pub const DEFAULT_GYRO_SENSITIVITY: [i16; 3] = [0x343B; 3];
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        let value = |i: usize| i16::from_le_bytes([bytes[i * 2], bytes[i * 2 + 1]]);
+        let triplet = |first: usize| [value(first), value(first + 1), value(first + 2)];
+        Self {
+            accel_origin: triplet(0),
+            accel_sensitivity: triplet(3),
+            gyro_origin: triplet(6),
+            gyro_sensitivity: triplet(9),
+        }
+    }
+
+    fn to_bytes(self) -> [u8; 24] {
+        let mut bytes = [0u8; 24];
+        let values = [
+            self.accel_origin,
+            self.accel_sensitivity,
+            self.gyro_origin,
+            self.gyro_sensitivity,
+        ];
+        for (chunk, value) in bytes.chunks_exact_mut(2).zip(values.iter().flatten()) {
+            chunk.copy_from_slice(&value.to_le_bytes());
+        }
+        bytes
+    }
+}
+
+// One 6-axis sample, X/Y/Z: acceleration in g and rotation in degrees per second
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ImuSample {
+    pub accel: [f32; 3],
+    pub gyro: [f32; 3],
+}
+
+// The three samples, 5ms apart, that each full report carries. All zeros until
+// enable_imu is called, and on clones without a working IMU.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ImuData {
+    pub samples: [ImuSample; 3],
+}
+
+// Buttons held in an input report. Byte 3 belongs to the right Joy-Con, byte 5 to the
+// left one and byte 4 is shared, so a single Joy-Con only ever sets its own half.
+// SL/SR exist on both Joy-Cons and are kept apart for that reason.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ButtonState {
+    pub a: bool,
+    pub b: bool,
+    pub x: bool,
+    pub y: bool,
+    pub r: bool,
+    pub zr: bool,
+    pub right_sl: bool,
+    pub right_sr: bool,
+    pub l: bool,
+    pub zl: bool,
+    pub left_sl: bool,
+    pub left_sr: bool,
+    pub up: bool,
+    pub down: bool,
+    pub left: bool,
+    pub right: bool,
+    pub plus: bool,
+    pub minus: bool,
+    pub home: bool,
+    pub capture: bool,
+    pub left_stick: bool,
+    pub right_stick: bool,
+}
+
+impl ButtonState {
+    fn from_report(right: u8, shared: u8, left: u8) -> Self {
+        Self {
+            y: right & 0x01 != 0,
+            x: right & 0x02 != 0,
+            b: right & 0x04 != 0,
+            a: right & 0x08 != 0,
+            right_sr: right & 0x10 != 0,
+            right_sl: right & 0x20 != 0,
+            r: right & 0x40 != 0,
+            zr: right & 0x80 != 0,
+            minus: shared & 0x01 != 0,
+            plus: shared & 0x02 != 0,
+            right_stick: shared & 0x04 != 0,
+            left_stick: shared & 0x08 != 0,
+            home: shared & 0x10 != 0,
+            capture: shared & 0x20 != 0,
+            down: left & 0x01 != 0,
+            up: left & 0x02 != 0,
+            right: left & 0x04 != 0,
+            left: left & 0x08 != 0,
+            left_sr: left & 0x10 != 0,
+            left_sl: left & 0x20 != 0,
+            l: left & 0x40 != 0,
+            zl: left & 0x80 != 0,
+        }
+    }
+
+    // Take one Joy-Con's half from its own report, for a pair read as two devices
+    pub fn merge_half(&mut self, from: &ButtonState, left_half: bool) {
+        if left_half {
+            self.l = from.l;
+            self.zl = from.zl;
+            self.left_sl = from.left_sl;
+            self.left_sr = from.left_sr;
+            self.up = from.up;
+            self.down = from.down;
+            self.left = from.left;
+            self.right = from.right;
+            self.minus = from.minus;
+            self.capture = from.capture;
+            self.left_stick = from.left_stick;
+        } else {
+            self.a = from.a;
+            self.b = from.b;
+            self.x = from.x;
+            self.y = from.y;
+            self.r = from.r;
+            self.zr = from.zr;
+            self.right_sl = from.right_sl;
+            self.right_sr = from.right_sr;
+            self.plus = from.plus;
+            self.home = from.home;
+            self.right_stick = from.right_stick;
+        }
+    }
+
+    // Names of the buttons held, in a fixed order
+    pub fn pressed(&self) -> Vec<&'static str> {
+        [
+            (self.a, "A"),
+            (self.b, "B"),
+            (self.x, "X"),
+            (self.y, "Y"),
+            (self.l, "L"),
+            (self.r, "R"),
+            (self.zl, "ZL"),
+            (self.zr, "ZR"),
+            (self.plus, "+"),
+            (self.minus, "-"),
+            (self.home, "Home"),
+            (self.capture, "Capture"),
+            (self.left_stick, "Left Stick"),
+            (self.right_stick, "Right Stick"),
+            (self.up, "Up"),
+            (self.down, "Down"),
+            (self.left, "Left"),
+            (self.right, "Right"),
+            (self.left_sl, "SL (L)"),
+            (self.left_sr, "SR (L)"),
+            (self.right_sl, "SL (R)"),
+            (self.right_sr, "SR (R)"),
+        ]
+        .into_iter()
+        .filter_map(|(held, name)| held.then_some(name))
+        .collect()
+    }
+}
+
+// Input report continuity, from the timer in byte 1 of each full report
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PacketStats {
+    pub received: u32,
+    // Timer steps skipped between consecutive reports
+    pub dropped: u32,
+    // Reports repeating the previous timer value
+    pub duplicates: u32,
+}
+
+// One decoded input report, as delivered to subscribers
+#[derive(Debug, Clone, Copy)]
+pub struct InputReport {
+    pub received: Instant,
+    pub report_id: u8,
+    // Sticks, buttons, battery and IMU samples
+    pub data: StickData,
+}
+
+// Receiving end of Controller::subscribe. Reports arrive as the controller's reads
+// parse them, so it only fills while something keeps calling read_stick_data.
+pub struct InputReceiver {
+    queue: Arc<Mutex<VecDeque<InputReport>>>,
+}
+
+impl InputReceiver {
+    // Everything received since the last call, oldest first
+    pub fn drain(&self) -> Vec<InputReport> {
+        self.queue.lock().drain(..).collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct StickData {
+    pub lx: u16,
+    pub ly: u16,
+    pub rx: u16,
+    pub ry: u16,
+    pub buttons: ButtonState,
+    // Only full (0x30) and NFC/IR (0x31) reports carry the battery
+    pub battery: Option<BatteryStatus>,
+    pub imu: ImuData,
+}
+
+impl Default for StickData {
+    fn default() -> Self {
+        Self {
+            lx: 0x800,
+            ly: 0x800,
+            rx: 0x800,
+            ry: 0x800,
+            buttons: ButtonState::default(),
+            battery: None,
+            imu: ImuData::default(),
+        }
+    }
+}
+
+// Full reports (0x30, 0x31) hold three accel + gyro samples at bytes 13-48,
+// each axis a little-endian i16 at the configured range's sensitivity. Subcommand
+// replies use those bytes for the reply.
+fn parse_imu(buf: &[u8], settings: ImuSettings) -> ImuData {
+    let mut imu = ImuData::default();
+    if !matches!(buf[0], 0x30 | 0x31) {
+        return imu;
+    }
+    for (i, sample) in imu.samples.iter_mut().enumerate() {
+        let axis = |n: usize, sensitivity: f32| {
+            let at = 13 + i * 12 + n * 2;
+            i16::from_le_bytes([buf[at], buf[at + 1]]) as f32 * sensitivity
+        };
+        let g = settings.accel_range.sensitivity();
+        let dps = settings.gyro_range.sensitivity();
+        sample.accel = [axis(0, g), axis(1, g), axis(2, g)];
+        sample.gyro = [axis(3, dps), axis(4, dps), axis(5, dps)];
+    }
+    imu
+}
+
+// The ID of a report whose ID byte the platform stripped, with the rest shifted back to
+// the documented offsets. A 0x21 reply has its ACK byte and the echoed subcommand at
+// 0x0D-0x0E, where a full report has the first accelerometer sample, so it takes an ACK
+// matching the echoed subcommand to count as a reply. A NACK's zero ACK byte is common in
+// motion data, so a NACK only counts for the subcommand being waited for.
+fn recover_report_id(report: &[u8], awaiting: Option<u8>) -> u8 {
+    if report.len() <= 12 {
+        return 0x3F;
+    }
+    let (Some(&ack), Some(&subcmd)) = (report.get(0x0D), report.get(0x0E)) else {
+        return 0x30;
+    };
+    let acked = REPLY_ACKS.contains(&(subcmd, ack));
+    let nacked = ack == 0x00 && awaiting == Some(subcmd);
+    if acked || nacked { 0x21 } else { 0x30 }
+}
+
+// Standard full (0x30) and NFC/IR (0x31) reports carry the sticks at bytes 6-11.
+// Subcommand replies (0x21) do too, but go to the subcommand waiting for them rather
+// than the input stream. Anything else, like simple HID (0x3F), has another layout.
+fn parse_stick_report(buf: &[u8], imu_settings: ImuSettings) -> Option<StickData> {
+    if !matches!(buf[0], 0x30 | 0x31) {
+        return None;
+    }
+    let lx = ((buf[7] & 0xF) as u16) << 8 | buf[6] as u16;
+    let ly = (buf[8] as u16) << 4 | ((buf[7] & 0xF0) >> 4) as u16;
+    let rx = ((buf[10] & 0xF) as u16) << 8 | buf[9] as u16;
+    let ry = (buf[11] as u16) << 4 | ((buf[10] & 0xF0) >> 4) as u16;
+    Some(StickData {
+        lx,
+        ly,
+        rx,
+        ry,
+        buttons: ButtonState::from_report(buf[3], buf[4], buf[5]),
+        battery: Some(BatteryStatus::from_report_byte(buf[2])),
+        imu: parse_imu(buf, imu_settings),
+    })
+}
+
+// Also enumerate devices with this VID/PID, handled like a Pro Controller.
+// Only the first registration takes effect.
+pub fn register_extra_device(vid: u16, pid: u16) {
+    let _ = EXTRA_DEVICE.set((vid, pid));
+}
+
+// Put every controller in dry-run mode, whatever set_dry_run says
+pub fn force_dry_run() {
+    FORCE_DRY_RUN.store(true, Ordering::Relaxed);
+}
+
+pub fn dry_run_forced() -> bool {
+    FORCE_DRY_RUN.load(Ordering::Relaxed)
+}
+
+impl Controller {
+    // Enumerate every connected supported controller
+    pub fn list() -> Result<Vec<ControllerInfo>> {
+        let api = HidApi::new()?;
+        let mut controllers: Vec<ControllerInfo> = Vec::new();
+
+        for info in api.device_list() {
+            let Some(info) = ControllerInfo::from_device_info(info) else {
+                continue;
+            };
+            // Some backends report the same device once per usage; keep one entry per path
+            if controllers.iter().any(|c| c.path == info.path) {
+                continue;
+            }
+            controllers.push(info);
+        }
+
+        Ok(controllers)
+    }
+
+    // Open one specific device, e.g. the second of two identical Joy-Cons.
+    // The type comes from the device's own product ID, never from the caller.
+    pub fn open_path(path: &CStr) -> Result<Self> {
+        let api = HidApi::new()?;
+        let info = api
+            .device_list()
+            .find(|info| info.path() == path)
+            .ok_or_else(|| anyhow!("No HID device at {}", path.to_string_lossy()))?;
+        let info = ControllerInfo::from_device_info(info)
+            .ok_or_else(|| anyhow!(ControllerError::NotNintendoController))?;
+        Self::open(&info)
+    }
+
+    pub fn open(info: &ControllerInfo) -> Result<Self> {
+        if let ControllerType::Switch2(model) = info.controller_type {
+            return Err(anyhow!(ControllerError::UnsupportedModel(model)));
+        }
+        let api = HidApi::new()?;
+        // hidapi already opens devices shared on Windows, so there is no less exclusive
+        // mode to fall back to; a device that still can't be opened is held by someone else.
+        let device = api.open_path(&info.path).map_err(|e| {
+            if looks_busy(&e) {
+                anyhow!(ControllerError::DeviceBusy)
+            } else {
+                anyhow!(e)
+            }
+        })?;
+        Self::init(Box::new(device), info.controller_type, info.bus)
+    }
+
+    // Play back a --hid-log recording as a controller, for reproducing bugs and working
+    // on the UI without hardware. The type comes from the recorded device info reply.
+    pub fn from_recording(path: &Path) -> Result<Self> {
+        let replay = Replay::load(path)?;
+        let controller_type = replay
+            .device_type()
+            .and_then(ControllerType::from_device_type)
+            .unwrap_or(ControllerType::ProController);
+        let bus = if replay.is_usb() {
+            BusType::Usb
+        } else {
+            BusType::Bluetooth
+        };
+        Self::init(Box::new(replay), controller_type, bus)
+    }
+
+    // Handshake, liveness and capability checks shared by every way of opening
+    fn init(
+        device: Box<dyn HidBackend>,
+        controller_type: ControllerType,
+        bus: BusType,
+    ) -> Result<Self> {
+        let mut controller = Self::new(device, controller_type);
+
+        match bus {
+            BusType::Usb => {
+                controller.usb_handshake()?;
+                controller.connection = ConnectionKind::Usb;
+            }
+            BusType::Bluetooth => {}
+            // Some backends can't tell us the bus; only a wired controller answers the handshake
+            _ => {
+                if controller.usb_handshake().is_ok() {
+                    controller.connection = ConnectionKind::Usb;
+                }
+            }
+        }
+
+        // joycond and Steam can leave the device openable but silent to us
+        if !controller.is_alive() {
+            return Err(anyhow!(ControllerError::DeviceBusy));
+        }
+
+        // A harmless read of the factory stick calibration; no reply means no usable flash
+        let has_spi_flash = controller
+            .try_read_spi(LEFT_STICK_CAL_ADDR, 9, SPI_CHECK_TIMEOUT)
+            .is_ok();
+        controller.update_capabilities(has_spi_flash);
+
+        Ok(controller)
+    }
+
+    // A controller on `device` before any handshake or check, assumed to be on Bluetooth
+    fn new(device: Box<dyn HidBackend>, controller_type: ControllerType) -> Self {
+        Controller {
+            device,
+            controller_type,
+            timing_byte: 0,
+            read_failures: 0,
+            connection: ConnectionKind::Bluetooth,
+            capabilities: ControllerCapabilities::default(),
+            shut_down: false,
+            battery: None,
+            firmware: None,
+            last_report: Instant::now(),
+            report_times: VecDeque::new(),
+            last_timer: None,
+            packet_stats: PacketStats::default(),
+            pending_input: None,
+            subscribers: Vec::new(),
+            report_id_missing: false,
+            awaiting_reply: None,
+            imu_enabled: false,
+            imu_settings: ImuSettings::default(),
+            dry_run: false,
+            recorded_writes: Vec::new(),
+            stored_stick_params: [None; 2],
+            allow_any_offset: false,
+        }
+    }
+
+    // Ask for device info and accept any report at all as a sign of life. The
+    // reply also tells us whether this platform strips the report ID byte.
+    fn is_alive(&mut self) -> bool {
+        if self.write_subcommand(0x02, &[]).is_err() {
+            return false;
+        }
+        let mut buf = [0u8; 64];
+        let mut alive = false;
+        let deadline = Instant::now() + LIVENESS_TIMEOUT;
+        while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+            match self.read_device(&mut buf, remaining.as_millis().max(1) as i32) {
+                Ok(n) if n > 0 => {
+                    alive = true;
+                    if buf[0x0D] == 0x82 && buf[0x0E] == 0x02 {
+                        self.report_id_missing = false;
+                        return true;
+                    }
+                    // Same ACK one byte early: reports arrive without their ID (macOS Bluetooth)
+                    if buf[0x0C] == 0x82 && buf[0x0D] == 0x02 {
+                        self.report_id_missing = true;
+                        return true;
+                    }
+                }
+                Ok(_) => {}
+                Err(_) => return false,
+            }
+        }
+        alive
+    }
+
+    // All device traffic goes through these two, so --hid-log sees every report
+    fn write_device(&self, data: &[u8]) -> HidResult<usize> {
+        hid_log::outgoing(data);
+        self.device.write(data)
+    }
+
+    fn read_device(&self, buf: &mut [u8], timeout_ms: i32) -> HidResult<usize> {
+        let n = self.device.read_timeout(buf, timeout_ms)?;
+        if n > 0 {
+            hid_log::incoming(&buf[..n]);
+        }
+        Ok(n)
+    }
+
+    // Read one input report, putting back the report ID if the platform stripped it,
+    // so every parser can use the documented byte offsets and dispatch on the ID
+    fn read_report(&self, buf: &mut [u8], timeout_ms: i32) -> HidResult<usize> {
+        if !self.report_id_missing {
+            return self.read_device(buf, timeout_ms);
+        }
+        let n = self.read_device(&mut buf[1..], timeout_ms)?;
+        if n == 0 {
+            return Ok(0);
+        }
+        buf[0] = recover_report_id(&buf[..n + 1], self.awaiting_reply);
+        Ok(n + 1)
+    }
+
+    // Over USB the controller ignores subcommands until it has been handshaken
+    // and told to stop timing out back to its proprietary USB mode.
+    fn usb_handshake(&self) -> Result<()> {
+        self.send_usb_command(USB_CMD_STATUS, true)?;
+        self.send_usb_command(USB_CMD_HANDSHAKE, true)?;
+        self.send_usb_command(USB_CMD_HID_ONLY, false)?;
+        Ok(())
+    }
+
+    // Switch the USB link to the 3Mbit baud rate, which roughly doubles the input report
+    // rate. Needs a fresh handshake afterwards; if anything fails the link is handshaken
+    // again at the default rate so the controller stays usable.
+    pub fn enable_fast_usb(&mut self) -> Result<()> {
+        if self.connection != ConnectionKind::Usb {
+            return Err(anyhow!("Fast polling is only available over USB"));
+        }
+        let switched = self
+            .send_usb_command(USB_CMD_HIGH_BAUD, true)
+            .and_then(|_| self.send_usb_command(USB_CMD_HANDSHAKE, true))
+            .and_then(|_| self.send_usb_command(USB_CMD_HID_ONLY, false));
+        if let Err(e) = switched {
+            self.usb_handshake()?;
+            return Err(e.context("Controller stayed at the default USB rate"));
+        }
+        Ok(())
+    }
+
+    fn send_usb_command(&self, command: u8, wait_for_reply: bool) -> Result<()> {
+        const MAX_RETRIES: u32 = 8;
+        let mut buf = [0u8; 64];
+
+        self.write_device(&[USB_COMMAND_ID, command])?;
+        if !wait_for_reply {
+            return Ok(());
+        }
+
+        for _ in 0..MAX_RETRIES {
+            match self.read_device(&mut buf, 64) {
+                Ok(_) if buf[0] == USB_REPLY_ID && buf[1] == command => return Ok(()),
+                Ok(_) => {}
+                Err(e) => return Err(anyhow!(e)),
+            }
+        }
+        Err(anyhow!(
+            "No reply to USB command 0x{:02X} 0x{:02X}",
+            USB_COMMAND_ID,
+            command
+        ))
+    }
+
+    pub fn get_device_info(&mut self) -> Result<DeviceInfo> {
+        const MAX_ATTEMPTS: u32 = 20;
+
+        for _ in 0..MAX_ATTEMPTS {
+            let reply = match self.send_subcommand(0x02, &[]) {
+                Ok(reply) => reply,
+                Err(e) if e.downcast_ref::<ControllerError>().is_some() => return Err(e),
+                Err(_) => continue,
+            };
+            // Firmware, type byte, one unknown byte, the MAC, one unknown byte, then
+            // whether the colors in SPI are used
+            let data = reply.data();
+            if matches!(data[2], DEVICE_TYPE_NES_L | DEVICE_TYPE_NES_R) {
+                self.controller_type = ControllerType::Nes;
+                self.update_capabilities(self.capabilities.has_spi_flash);
+            }
+            self.battery = Some(reply.battery());
+            let firmware = FirmwareVersion {
+                major: data[0],
+                minor: data[1],
+            };
+            self.firmware = Some(firmware);
+            return Ok(DeviceInfo {
+                firmware,
+                mac: data[4..10].try_into()?,
+                reported_type: ControllerType::from_device_type(data[2]),
+                colors_in_spi: data[11] == 0x01,
+            });
+        }
+
+        Err(anyhow!("Failed to get valid device info"))
+    }
+
+    pub fn get_controller_type(&self) -> ControllerType {
+        self.controller_type
+    }
+
+    // A sleeping controller stays connected but stops sending reports
+    pub fn time_since_last_report(&self) -> Duration {
+        self.last_report.elapsed()
+    }
+
+    // Based on the firmware version from the last device info reply
+    pub fn firmware_compatibility(&self) -> FirmwareCompatibility {
+        self.firmware
+            .map_or(FirmwareCompatibility::Unknown, |fw| fw.compatibility())
+    }
+
+    // Battery as of the last device info reply; StickData carries live updates
+    pub fn battery(&self) -> Option<BatteryStatus> {
+        self.battery
+    }
+
+    pub fn connection_kind(&self) -> ConnectionKind {
+        self.connection
+    }
+
+    pub fn enable_standard_input(&mut self) -> Result<()> {
+        // Report mode 0x30: full input reports at 60/120Hz
+        self.send_subcommand(0x03, &[0x30])?;
+        if self.imu_enabled {
+            self.enable_imu()?;
+        }
+        // Silence before full reports were enabled doesn't count
+        self.last_report = Instant::now();
+        Ok(())
+    }
+
+    // Turn on the 6-axis sensor so full reports carry motion data (subcommand 0x40).
+    // Does nothing on controllers without one.
+    pub fn enable_imu(&mut self) -> Result<()> {
+        if !self.capabilities.has_imu {
+            return Ok(());
+        }
+        self.send_subcommand(0x40, &[0x01])?;
+        self.imu_enabled = true;
+        let s = self.imu_settings;
+        self.set_imu_sensitivity(s.gyro_range, s.accel_range, s.gyro_rate, s.accel_filter)
+    }
+
+    // Configure the 6-axis sensor (subcommand 0x41). Later readings are scaled to match.
+    pub fn set_imu_sensitivity(
+        &mut self,
+        gyro_range: GyroRange,
+        accel_range: AccelRange,
+        sample_rate: GyroRate,
+        filter: AccelFilter,
+    ) -> Result<()> {
+        if !self.capabilities.has_imu {
+            return Ok(());
+        }
+        self.send_subcommand(
+            0x41,
+            &[
+                gyro_range.code(),
+                accel_range.code(),
+                sample_rate.code(),
+                filter.code(),
+            ],
+        )?;
+        self.imu_settings = ImuSettings {
+            gyro_range,
+            accel_range,
+            gyro_rate: sample_rate,
+            accel_filter: filter,
+        };
+        Ok(())
+    }
+
+    pub fn imu_settings(&self) -> ImuSettings {
+        self.imu_settings
+    }
+
+    fn read_failed(&mut self, e: HidError) -> anyhow::Error {
+        self.read_failures += 1;
+        if self.read_failures >= MAX_CONSECUTIVE_READ_FAILURES {
+            ControllerError::Disconnected.into()
+        } else {
+            anyhow!(e)
+        }
+    }
+
+    pub fn read_stick_data(&mut self) -> Result<StickData> {
+        let mut buf = [0u8; 0x170];
+
+        // Loop to drain the buffer and get the latest packet
+        loop {
+            // Use 0ms timeout to just check if data is available
+            match self.read_report(&mut buf, 0) {
+                Ok(res) if res > 12 => {
+                    self.route_report(&buf);
+                }
+                Ok(res) if res > 0 => {}
+                _ => break, // No more data or error, stop reading
+            }
+        }
+
+        if self.pending_input.is_none() {
+            // If we didn't get any new data this frame, try a blocking read for a short time
+            // to ensure we return *something* if the buffer was empty initially.
+            // This keeps the loop running.
+            match self.read_report(&mut buf, 20) {
+                Ok(res) if res > 12 => {
+                    self.route_report(&buf);
+                }
+                Ok(_) => {}
+                Err(e) => return Err(self.read_failed(e)),
+            }
+        }
+
+        let data = self
+            .pending_input
+            .take()
+            .ok_or_else(|| anyhow!("No data or invalid packet"))?;
+        self.read_failures = 0;
+        self.last_report = Instant::now();
+        Ok(data)
+    }
+
+    // Dispatch one report by its ID, as sent or as read_report recovered it from the
+    // content: input reports become the pending stick data and true is returned for
+    // subcommand replies, which are the caller's to match or drop
+    fn route_report(&mut self, buf: &[u8]) -> bool {
+        if buf[0] == 0x21 {
+            return true;
+        }
+        if let Some(data) = self.parse_report(buf) {
+            self.pending_input = Some(data);
+        }
+        false
+    }
+
+    // Parse an input report, counting it towards the report rate when it carries stick data
+    fn parse_report(&mut self, buf: &[u8]) -> Option<StickData> {
+        let data = parse_stick_report(buf, self.imu_settings)?;
+        self.count_packet(buf[1]);
+        let now = Instant::now();
+        self.publish(InputReport {
+            received: now,
+            report_id: buf[0],
+            data,
+        });
+        self.report_times.push_back(now);
+        while self
+            .report_times
+            .front()
+            .is_some_and(|&t| now.duration_since(t) > REPORT_RATE_WINDOW)
+        {
+            self.report_times.pop_front();
+        }
+        Some(data)
+    }
+
+    // Every input report parsed from now on, not just the latest one read_stick_data
+    // returns. Any number of receivers can be live at once. Each holds at most
+    // SUBSCRIPTION_CAPACITY reports and drops the oldest when full, so a slow consumer
+    // can't grow memory without bound.
+    pub fn subscribe(&mut self) -> InputReceiver {
+        let queue = Arc::new(Mutex::new(VecDeque::new()));
+        self.subscribers.push(Arc::downgrade(&queue));
+        InputReceiver { queue }
+    }
+
+    fn publish(&mut self, report: InputReport) {
+        self.subscribers.retain(|queue| {
+            let Some(queue) = queue.upgrade() else {
+                return false;
+            };
+            let mut queue = queue.lock();
+            if queue.len() == SUBSCRIPTION_CAPACITY {
+                queue.pop_front();
+            }
+            queue.push_back(report);
+            true
+        });
+    }
+
+    // The timer goes up by one per full report and wraps at 0xFF
+    fn count_packet(&mut self, timer: u8) {
+        match self.last_timer.map(|last| timer.wrapping_sub(last)) {
+            Some(0) => self.packet_stats.duplicates += 1,
+            Some(step) => self.packet_stats.dropped += step as u32 - 1,
+            None => {}
+        }
+        self.last_timer = Some(timer);
+        self.packet_stats.received += 1;
+    }
+
+    pub fn packet_stats(&self) -> PacketStats {
+        self.packet_stats
+    }
+
+    pub fn reset_packet_stats(&mut self) {
+        self.packet_stats = PacketStats::default();
+        self.last_timer = None;
+    }
+
+    // Input reports parsed per second over the last second. Around 66Hz over
+    // Bluetooth and 120Hz over USB when the link is healthy.
+    pub fn report_rate_hz(&self) -> f32 {
+        let now = Instant::now();
+        let recent = self
+            .report_times
+            .iter()
+            .filter(|&&t| now.duration_since(t) <= REPORT_RATE_WINDOW)
+            .count();
+        recent as f32 / REPORT_RATE_WINDOW.as_secs_f32()
+    }
+
+    // Read a span of SPI flash in reply-sized chunks. After each chunk `progress` gets
+    // the bytes read so far and returns false to stop.
+    pub fn dump_spi(
+        &mut self,
+        range: Range<u32>,
+        mut progress: impl FnMut(u32) -> bool,
+    ) -> Result<Vec<u8>> {
+        let mut data = Vec::with_capacity((range.end - range.start) as usize);
+        let mut offset = range.start;
+        while offset < range.end {
+            let len = (range.end - offset).min(0x1D) as u8;
+            data.extend(self.read_spi_data(offset, len)?);
+            offset += len as u32;
+            if !progress(offset - range.start) {
+                return Err(anyhow!("Cancelled"));
+            }
+        }
+        Ok(data)
+    }
+
+    pub fn read_spi_data(&mut self, offset: u32, len: u8) -> Result<Vec<u8>> {
+        const MAX_ATTEMPTS: u32 = 5;
+        const ATTEMPT_TIMEOUT: Duration = Duration::from_millis(500);
+
+        for _ in 0..MAX_ATTEMPTS {
+            if let Ok(data) = self.try_read_spi(offset, len, ATTEMPT_TIMEOUT) {
+                return Ok(data);
+            }
+        }
+        Err(anyhow!("Failed to read SPI data"))
+    }
+
+    // One SPI read request, waiting at most `timeout` for the matching reply
+    fn try_read_spi(&mut self, offset: u32, len: u8, timeout: Duration) -> Result<Vec<u8>> {
+        // Replies carry at most 0x1D bytes of SPI data
+        let len = len.min(0x1D);
+        let mut args = [0u8; 5];
+        args[0..4].copy_from_slice(&offset.to_le_bytes());
+        args[4] = len;
+        let packet = self.subcommand_packet(0x10, &args);
+        self.discard_pending_reports();
+        self.write_device(&packet)?;
+
+        self.awaiting_reply = Some(0x10);
+        let data = self.await_spi_data(offset, len, timeout);
+        self.awaiting_reply = None;
+        data
+    }
+
+    fn await_spi_data(&mut self, offset: u32, len: u8, timeout: Duration) -> Result<Vec<u8>> {
+        let deadline = Instant::now() + timeout;
+        while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+            let mut resp = [0u8; 64];
+            if self.read_report(&mut resp, remaining.as_millis().max(1) as i32)? == 0 {
+                continue;
+            }
+            if !self.route_report(&resp) {
+                continue;
+            }
+            if resp[0x0D] == 0x90 && resp[0x0E] == 0x10 && resp[0x0F..0x13] == offset.to_le_bytes()
+            {
+                return Ok(resp[0x14..0x14 + len as usize].to_vec());
+            }
+        }
+        Err(anyhow!("Timed out reading SPI data"))
+    }
+
+    // Regulated battery voltage in millivolts (subcommand 0x50). The reply holds a
+    // little-endian raw value in units of 2.5mV, roughly 0x528 (3.3V) to 0x690 (4.2V).
+    pub fn get_voltage(&mut self) -> Result<u16> {
+        let reply = self.send_subcommand_timeout(0x50, &[], VOLTAGE_TIMEOUT)?;
+        let raw = u16::from_le_bytes([reply.data()[0], reply.data()[1]]);
+        Ok((raw as u32 * 5 / 2) as u16)
+    }
+
+    // Hand the controller back to the OS in simple HID mode, leaving it powered on.
+    // Drop does this too; calling it explicitly just doesn't depend on drop order.
+    pub fn close(&mut self) -> Result<()> {
+        self.shutdown(false)
+    }
+
+    // Put the controller back the way we found it: simple HID report mode (0x3F),
+    // optionally followed by an HCI sleep so a Bluetooth controller powers down.
+    // Safe to call more than once and on a device that has already gone away.
+    pub fn shutdown(&mut self, hci_sleep: bool) -> Result<()> {
+        if self.shut_down {
+            return Ok(());
+        }
+        self.shut_down = true;
+
+        let mode = self.write_subcommand(0x03, &[0x3F]);
+        if hci_sleep && self.connection == ConnectionKind::Bluetooth {
+            self.write_subcommand(0x06, &[0x00])?;
+        }
+        if self.connection == ConnectionKind::Usb {
+            // Let the controller return to its normal USB behaviour
+            self.write_device(&[USB_COMMAND_ID, USB_CMD_ALLOW_TIMEOUT])?;
+        }
+        mode
+    }
+
+    pub fn set_player_leds(&mut self, pattern: u8) -> Result<()> {
+        self.write_subcommand(0x30, &[pattern])
+    }
+
+    // Short vibration on both motors so the user can feel which controller this is
+    pub fn rumble_pulse(&mut self, duration_ms: u64) -> Result<()> {
+        self.write_subcommand(0x48, &[0x01])?;
+        self.write_rumble(encode_rumble(320.0, 160.0, 0.6))?;
+        thread::sleep(Duration::from_millis(duration_ms));
+        let silenced = self.write_rumble(encode_rumble(320.0, 160.0, 0.0));
+        self.write_subcommand(0x48, &[0x00])?;
+        silenced
+    }
+
+    // Rumble-only output report (0x10), same data for the left and right motor
+    fn write_rumble(&mut self, motor: [u8; 4]) -> Result<()> {
+        let mut report = [0u8; 10];
+        report[0] = 0x10;
+        report[1] = self.timing_byte & 0xF;
+        self.timing_byte = self.timing_byte.wrapping_add(1);
+        report[2..6].copy_from_slice(&motor);
+        report[6..10].copy_from_slice(&motor);
+        self.write_device(&report)?;
+        Ok(())
+    }
+
+    // Fade the HOME LED to `brightness` (0-15) over `fade` (0-15) base-duration steps
+    // and hold it there. Does nothing on controllers without a HOME LED.
+    pub fn set_home_led(&mut self, brightness: u8, fade: u8) -> Result<()> {
+        if !self.capabilities.has_home_led {
+            return Ok(());
+        }
+        let brightness = brightness.min(0xF);
+        let fade = fade.min(0xF);
+        // One mini cycle at the shortest base duration, repeated forever
+        self.write_subcommand(0x38, &[0x11, 0x00, brightness << 4, fade << 4 | 0xF, 0x00])
+    }
+
+    // Blink the HOME LED `times` times, leaving it off. Does nothing without a HOME LED.
+    pub fn blink_home_led(&mut self, times: u8) -> Result<()> {
+        if !self.capabilities.has_home_led {
+            return Ok(());
+        }
+        // Two mini cycles (full on, off) of 0x8 base duration, `times` full cycles
+        self.write_subcommand(0x38, &[0x28, times.min(0xF), 0xF0, 0x01, 0x01])
+    }
+
+    // Output report 0x01: timing byte, neutral rumble, then the subcommand and its arguments
+    fn subcommand_packet(&mut self, subcmd: u8, args: &[u8]) -> [u8; 49] {
+        let mut packet = [0u8; 49];
+        packet[0] = 0x01;
+        packet[1] = self.timing_byte & 0xF;
+        self.timing_byte = self.timing_byte.wrapping_add(1);
+        packet[2..10].copy_from_slice(&NEUTRAL_RUMBLE);
+        packet[10] = subcmd;
+        packet[11..11 + args.len()].copy_from_slice(args);
+        packet
+    }
+
+    pub fn send_subcommand(&mut self, subcmd: u8, args: &[u8]) -> Result<SubcommandReply> {
+        self.send_subcommand_timeout(subcmd, args, SUBCOMMAND_TIMEOUT)
+    }
+
+    // Send a subcommand and wait for the 0x21 reply acknowledging it. Input reports that
+    // arrive in the meantime are kept for read_stick_data; replies to other subcommands
+    // are dropped.
+    pub fn send_subcommand_timeout(
+        &mut self,
+        subcmd: u8,
+        args: &[u8],
+        timeout: Duration,
+    ) -> Result<SubcommandReply> {
+        let packet = self.subcommand_packet(subcmd, args);
+        self.discard_pending_reports();
+        // A write only fails once the device node is gone
+        self.write_device(&packet)
+            .map_err(|_| ControllerError::Disconnected)?;
+
+        self.awaiting_reply = Some(subcmd);
+        let reply = self.await_reply(subcmd, timeout);
+        self.awaiting_reply = None;
+        reply
+    }
+
+    fn await_reply(&mut self, subcmd: u8, timeout: Duration) -> Result<SubcommandReply> {
+        let deadline = Instant::now() + timeout;
+        let mut other_reply = None;
+        while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+            let mut report = [0u8; 64];
+            if self.read_report(&mut report, remaining.as_millis().max(1) as i32)? == 0 {
+                continue;
+            }
+            if !self.route_report(&report) {
+                continue;
+            }
+            if report[0x0E] != subcmd {
+                other_reply = Some(report[0x0E]);
+                continue;
+            }
+            // The top bit of the ACK byte is clear on a NACK
+            if report[0x0D] & 0x80 == 0 {
+                return Err(anyhow!(SubcommandFailure::Nacked(subcmd)));
+            }
+            return Ok(SubcommandReply { report });
+        }
+        Err(anyhow!(match other_reply {
+            Some(other) => SubcommandFailure::WrongReply(subcmd, other),
+            None => SubcommandFailure::TimedOut(subcmd),
+        }))
+    }
+
+    // Replies don't echo the packet counter, so the only way to tell a late ACK for an
+    // earlier command from the one we're about to send is that it is already queued.
+    // Queued input reports are kept for read_stick_data.
+    fn discard_pending_reports(&mut self) {
+        let mut buf = [0u8; 64];
+        while let Ok(n) = self.read_report(&mut buf, 0) {
+            if n == 0 {
+                break;
+            }
+            self.route_report(&buf);
+        }
+    }
+
+    // Fire-and-forget variant of send_subcommand, for commands whose reply doesn't matter
+    fn write_subcommand(&mut self, subcmd: u8, args: &[u8]) -> Result<()> {
+        let packet = self.subcommand_packet(subcmd, args);
+        self.write_device(&packet)?;
+        Ok(())
+    }
+
+    // Factory serial number, or None if the region is blank (common on clones)
+    pub fn get_serial(&mut self) -> Result<Option<String>> {
+        let data = self.read_spi_data(SERIAL_NUMBER_ADDR, 16)?;
+        // A first byte >= 0x80 means no serial was ever written
+        if data.first().is_none_or(|&b| b >= 0x80) {
+            return Ok(None);
+        }
+        let serial: String = data
+            .iter()
+            .take_while(|&&b| b != 0xFF && b != 0x00)
+            .map(|&b| b as char)
+            .collect();
+        Ok(Some(serial).filter(|s| !s.trim().is_empty()))
+    }
+
+    // Body/button colors, or None if the region is 0xFF-filled (grey clones)
+    pub fn get_colors(&mut self) -> Result<Option<ControllerColors>> {
+        let data = self.read_spi_data(COLOR_ADDR, 12)?;
+        if data.len() < 12 || data[0..6].iter().all(|&b| b == 0xFF) {
+            return Ok(None);
+        }
+        let grips = (self.controller_type == ControllerType::ProController
+            && data[6..12].iter().any(|&b| b != 0xFF))
+        .then(|| [[data[6], data[7], data[8]], [data[9], data[10], data[11]]]);
+        Ok(Some(ControllerColors {
+            body: [data[0], data[1], data[2]],
+            buttons: [data[3], data[4], data[5]],
+            grips,
+        }))
+    }
+
+    // Rewrite the color region and read it back. Controllers whose device info says
+    // the console ignores SPI colors are refused, as the write would do nothing.
+    pub fn write_colors(&mut self, colors: ControllerColors) -> Result<()> {
+        if !self.supports_spi_write() {
+            return Err(anyhow!(
+                "This controller does not support on-device calibration"
+            ));
+        }
+        if !self.get_device_info()?.colors_in_spi {
+            return Err(anyhow!(
+                "This controller doesn't take its colors from SPI, so the console would ignore them"
+            ));
+        }
+        let bytes = colors.to_bytes();
+        self.write_spi_data(COLOR_ADDR, &bytes)?;
+        if self.is_dry_run() {
+            return Ok(());
+        }
+        if self.read_spi_data(COLOR_ADDR, bytes.len() as u8)? != bytes {
+            return Err(anyhow!("Colors did not read back as written"));
+        }
+        Ok(())
+    }
+
+    // What the controller uses now, so the user can compare before overwriting it
+    pub fn read_stick_parameters(&mut self, side: StickSide) -> Result<StickParameters> {
+        let addr = match side {
+            StickSide::Left => LEFT_STICK_PARAMS_ADDR,
+            StickSide::Right => RIGHT_STICK_PARAMS_ADDR,
+        };
+        let data = self.read_spi_data(addr, 3)?;
+        let data = [data[0], data[1], data[2]];
+        self.stored_stick_params[side as usize] = Some(data);
+        let [deadzone, range_ratio] = decode_stick_params(&data);
+        Ok(StickParameters {
+            deadzone,
+            range_ratio,
+        })
+    }
+
+    // The center and ranges the controller currently applies to one stick
+    pub fn read_factory_stick_calibration(&mut self, side: StickSide) -> Result<StickCalibration> {
+        let addr = match side {
+            StickSide::Left => LEFT_STICK_CAL_ADDR,
+            StickSide::Right => RIGHT_STICK_CAL_ADDR,
+        };
+        let data: [u8; 9] = self
+            .read_spi_data(addr, 9)?
+            .try_into()
+            .map_err(|_| anyhow!("Short SPI read"))?;
+        Ok(match side {
+            StickSide::Left => decode_left_stick_cal(&data),
+            StickSide::Right => decode_right_stick_cal(&data),
+        })
+    }
+
+    // User calibration the console wrote for one stick, or None if the slot is blank or
+    // lacks the magic. When present, the Switch uses it instead of the factory values.
+    pub fn read_user_stick_calibration(
+        &mut self,
+        side: StickSide,
+    ) -> Result<Option<StickCalibration>> {
+        let addr = match side {
+            StickSide::Left => LEFT_USER_STICK_CAL_ADDR,
+            StickSide::Right => RIGHT_USER_STICK_CAL_ADDR,
+        };
+        let data = self.read_spi_data(addr, 11)?;
+        if data.len() < 11 || data[0..2] != USER_CAL_MAGIC {
+            return Ok(None);
+        }
+        let cal: [u8; 9] = data[2..11].try_into()?;
+        Ok(Some(match side {
+            StickSide::Left => decode_left_stick_cal(&cal),
+            StickSide::Right => decode_right_stick_cal(&cal),
+        }))
+    }
+
+    // Blank the user slot so the console falls back to the factory calibration
+    pub fn clear_user_stick_calibration(&mut self, side: StickSide) -> Result<()> {
+        if !self.supports_spi_write() {
+            return Err(anyhow!(
+                "This controller does not support on-device calibration"
+            ));
+        }
+        let addr = match side {
+            StickSide::Left => LEFT_USER_STICK_CAL_ADDR,
+            StickSide::Right => RIGHT_USER_STICK_CAL_ADDR,
+        };
+        self.write_spi_data(addr, &[0xFF; 11])?;
+        if self.is_dry_run() {
+            return Ok(());
+        }
+
+        let erased = self.read_spi_data(addr, 11)?;
+        if erased.len() < 11 || erased.iter().any(|&b| b != 0xFF) {
+            return Err(anyhow!("User calibration is still present after erasing"));
+        }
+        Ok(())
+    }
+
+    // 6-axis calibration from the factory, or None if the region is blank
+    pub fn read_imu_factory_calibration(&mut self) -> Result<Option<ImuCalibration>> {
+        let data = self.read_spi_data(FACTORY_IMU_CAL_ADDR, 24)?;
+        if data.iter().all(|&b| b == 0xFF) {
+            return Ok(None);
+        }
+        Ok(Some(ImuCalibration::from_bytes(&data)))
+    }
+
+    // Bluetooth addresses of the hosts this controller is paired with. Read-only:
+    // nothing in this crate ever writes the pairing region.
+    pub fn get_pairing_info(&mut self) -> Result<Vec<String>> {
+        let mut hosts = Vec::new();
+        for slot in 0..PAIRING_SLOT_COUNT {
+            let data = self.read_spi_data(PAIRING_INFO_ADDR + slot * PAIRING_SLOT_SIZE, 10)?;
+            if data[0] != PAIRING_MAGIC {
+                continue;
+            }
+            // The host address at slot offset 4 is stored big-endian, as it's printed
+            hosts.push(
+                data[4..10]
+                    .iter()
+                    .map(|byte| format!("{byte:02X}"))
+                    .collect::<Vec<_>>()
+                    .join(":"),
+            );
+        }
+        Ok(hosts)
+    }
+
+    pub fn capabilities(&self) -> ControllerCapabilities {
+        self.capabilities
+    }
+
+    fn update_capabilities(&mut self, has_spi_flash: bool) {
+        let controller_type = self.controller_type;
+        self.capabilities = ControllerCapabilities {
+            has_left_stick: controller_type.has_left_stick(),
+            has_right_stick: controller_type.has_right_stick(),
+            has_imu: controller_type.has_imu(),
+            has_spi_flash,
+            has_home_led: controller_type.has_home_led(),
+        };
+    }
+
+    // Whether the connect-time SPI check passed. Licensed and clone controllers
+    // may accept subcommands but have no flash behind them, and hang on writes.
+    pub fn supports_spi_write(&self) -> bool {
+        self.capabilities.has_spi_flash
+    }
+
+    // Let SPI writes go anywhere in flash, pairing and firmware included. Only for
+    // power users restoring a backup they know the contents of.
+    pub fn unsafe_allow_any_offset(&mut self) {
+        self.allow_any_offset = true;
+    }
+
+    // A write must lie entirely inside one whitelisted region
+    fn offset_allowed(&self, range: &Range<u32>) -> bool {
+        self.allow_any_offset
+            || WRITABLE_REGIONS
+                .iter()
+                .any(|(allowed, _)| allowed.start <= range.start && range.end <= allowed.end)
+    }
+
+    pub fn set_dry_run(&mut self, enabled: bool) {
+        self.dry_run = enabled;
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run || dry_run_forced()
+    }
+
+    // Writes recorded in dry-run mode since the last call
+    pub fn take_recorded_writes(&mut self) -> Vec<(u32, Vec<u8>)> {
+        std::mem::take(&mut self.recorded_writes)
+    }
+
+    // Run a calibration write in dry-run mode and return the packets it would send
+    pub fn preview_calibration_writes(
+        &mut self,
+        left_cal: StickCalibration,
+        right_cal: StickCalibration,
+        left_parameters: StickParameters,
+        right_parameters: StickParameters,
+        target: CalibrationTarget,
+    ) -> Result<Vec<(u32, Vec<u8>)>> {
+        let was_dry_run = self.dry_run;
+        self.set_dry_run(true);
+        self.recorded_writes.clear();
+        let result = self.write_calibration_to_device(
+            left_cal,
+            right_cal,
+            left_parameters,
+            right_parameters,
+            false,
+            target,
+        );
+        self.set_dry_run(was_dry_run);
+        let writes = self.take_recorded_writes();
+        result.map(|_| writes)
+    }
+
+    // Write any length of data, one reply-sized chunk after another
+    pub fn write_spi_data(&mut self, offset: u32, data: &[u8]) -> Result<()> {
+        let count = data.len().div_ceil(0x1D);
+        for (i, chunk) in data.chunks(0x1D).enumerate() {
+            let chunk_offset = offset + (i * 0x1D) as u32;
+            // Context rather than a new error, so a disconnect can still be downcast
+            self.write_spi_chunk(chunk_offset, chunk).map_err(|e| {
+                let message = format!(
+                    "Chunk {} of {} at 0x{:04X}: {}",
+                    i + 1,
+                    count,
+                    chunk_offset,
+                    e
+                );
+                e.context(message)
+            })?;
+        }
+        Ok(())
+    }
+
+    fn write_spi_chunk(&mut self, offset: u32, data: &[u8]) -> Result<()> {
+        const MAX_ATTEMPTS: u32 = 20;
+        // Address, length, then at most 0x1D bytes of data
+        let mut args = [0u8; 5 + 0x1D];
+        args[0..4].copy_from_slice(&offset.to_le_bytes());
+        args[4] = data.len() as u8;
+        args[5..5 + data.len()].copy_from_slice(data);
+
+        let range = offset..offset + data.len() as u32;
+        if !self.offset_allowed(&range) {
+            return Err(anyhow!(ControllerError::ForbiddenOffset(
+                range.start,
+                range.end
+            )));
+        }
+        if !self.is_dry_run() {
+            for (stored, addr) in self
+                .stored_stick_params
+                .iter_mut()
+                .zip([LEFT_STICK_PARAMS_ADDR, RIGHT_STICK_PARAMS_ADDR])
+            {
+                if range.start < addr + 3 && addr < range.end {
+                    *stored = None;
+                }
+            }
+        }
+        if self.is_dry_run() {
+            let len = args[4] as usize;
+            self.recorded_writes
+                .push((offset, args[5..5 + len].to_vec()));
+            return Ok(());
+        }
+
+        let mut failure = SpiWriteError {
+            offset,
+            attempts: MAX_ATTEMPTS,
+            nacked: 0,
+            wrong_reply: 0,
+            timed_out: 0,
+            other: 0,
+            last_other: None,
+        };
+        for attempt in 0..MAX_ATTEMPTS {
+            match self.send_subcommand(0x11, &args[..5 + data.len()]) {
+                // Status 0x01 means the region is write protected
+                Ok(reply) if reply.data()[0] != 0x00 => {
+                    return Err(anyhow!(
+                        "Controller refused the write to 0x{:04X} (status 0x{:02X})",
+                        offset,
+                        reply.data()[0]
+                    ));
+                }
+                Ok(_) => {
+                    thread::sleep(Duration::from_millis(100));
+                    return Ok(());
+                }
+                Err(e) if e.downcast_ref::<ControllerError>().is_some() => return Err(e),
+                Err(e) => {
+                    match e.downcast_ref::<SubcommandFailure>() {
+                        Some(SubcommandFailure::Nacked(_)) => failure.nacked += 1,
+                        Some(SubcommandFailure::WrongReply(..)) => failure.wrong_reply += 1,
+                        Some(SubcommandFailure::TimedOut(_)) => failure.timed_out += 1,
+                        None => {
+                            failure.other += 1;
+                            failure.last_other = Some(e.to_string());
+                        }
+                    }
+                    thread::sleep(retry_backoff(attempt));
+                }
+            }
+        }
+        Err(anyhow!(failure))
+    }
+
+    // Write a user 6-axis calibration and read it back, so a write the controller
+    // silently dropped isn't reported as success
+    pub fn write_imu_calibration(&mut self, cal: ImuCalibration) -> Result<()> {
+        if !self.supports_spi_write() {
+            return Err(anyhow!(
+                "This controller does not support on-device calibration"
+            ));
+        }
+        let mut block = USER_CAL_MAGIC.to_vec();
+        block.extend_from_slice(&cal.to_bytes());
+        self.write_spi_data(USER_IMU_CAL_ADDR, &block)?;
+        if self.is_dry_run() {
+            return Ok(());
+        }
+
+        let written = self.read_spi_data(USER_IMU_CAL_ADDR, block.len() as u8)?;
+        if written != block {
+            return Err(anyhow!("Motion calibration did not read back as written"));
+        }
+        Ok(())
+    }
+
+    // Write regions of a backup back to flash, checking each chunk by reading it back.
+    // Only whitelisted calibration regions are accepted, and only onto the controller
+    // the backup came from unless `allow_other_mac` is set. A cancelled restore stops
+    // after a chunk that has been verified, never between a write and its check.
+    pub fn restore_spi(
+        &mut self,
+        backup: &SpiBackup,
+        regions: &[Range<u32>],
+        allow_other_mac: bool,
+        op: &OperationContext,
+    ) -> Result<()> {
+        if !self.supports_spi_write() {
+            return Err(anyhow!(
+                "This controller does not support on-device calibration"
+            ));
+        }
+        // Checked up front as well, so a restore fails before its first write
+        if let Some(range) = regions.iter().find(|r| !self.offset_allowed(r)) {
+            return Err(anyhow!(ControllerError::ForbiddenOffset(
+                range.start,
+                range.end
+            )));
+        }
+        let mac = self.get_device_info()?.mac;
+        if backup.mac != Some(mac) && !allow_other_mac {
+            return Err(anyhow!(
+                "The backup is from {}, not this controller ({})",
+                backup
+                    .mac_string()
+                    .unwrap_or_else(|| "an unknown controller".to_string()),
+                format_mac(&mac)
+            ));
+        }
+
+        let total = regions.iter().map(|r| r.end - r.start).sum();
+        let mut done = 0;
+        for range in regions {
+            let data = backup.region(range).ok_or_else(|| {
+                anyhow!(
+                    "The backup doesn't contain 0x{:04X}-0x{:04X}",
+                    range.start,
+                    range.end
+                )
+            })?;
+            for (i, chunk) in data.chunks(0x1D).enumerate() {
+                let offset = range.start + (i * 0x1D) as u32;
+                self.write_spi_data(offset, chunk)?;
+                if !self.is_dry_run() && self.read_spi_data(offset, chunk.len() as u8)? != chunk {
+                    return Err(anyhow!("0x{:04X} did not read back as written", offset));
+                }
+                done += chunk.len() as u32;
+                op.report(done, total, "Writing and verifying");
+                if op.cancelled() {
+                    return Err(anyhow!(
+                        "Cancelled; everything before 0x{:04X} was written and verified",
+                        offset + chunk.len() as u32
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn write_calibration_to_device(
+        &mut self,
+        left_cal: StickCalibration,
+        right_cal: StickCalibration,
+        left_parameters: StickParameters,
+        right_parameters: StickParameters,
+        // The calibrations already hold the stored above/center/below values; see
+        // encode_raw_left_stick_cal
+        raw_calibration: bool,
+        target: CalibrationTarget,
+    ) -> Result<Vec<RegionCheck>> {
+        // Fail fast instead of grinding through write retries on a controller without flash
+        if !self.supports_spi_write() {
+            return Err(anyhow!(
+                "This controller does not support on-device calibration"
+            ));
+        }
+
+        let (left_deadzone, right_deadzone) = (left_parameters.deadzone, right_parameters.deadzone);
+
+        let check = |stick: &str, cal: &StickCalibration, deadzone: u16| {
+            let cal = if raw_calibration {
+                check_raw_stick_calibration(stick, cal)?;
+                raw_stick_cal_to_absolute(cal)
+            } else {
+                *cal
+            };
+            check_stick_calibration(stick, &cal, deadzone)
+        };
+
+        let mut left_params = encode_stick_parameters("Left", &left_parameters)?;
+        let mut right_params = encode_stick_parameters("Right", &right_parameters)?;
+
+        let (final_left_cal, final_right_cal) = match self.controller_type {
+            ControllerType::JoyConL => {
+                check("Left", &left_cal, left_deadzone)?;
+                right_params = left_params;
+                (left_cal, left_cal)
+            }
+            ControllerType::JoyConR => {
+                check("Right", &right_cal, right_deadzone)?;
+                left_params = right_params;
+                (right_cal, right_cal)
+            }
+            ControllerType::ProController => {
+                check("Left", &left_cal, left_deadzone)?;
+                check("Right", &right_cal, right_deadzone)?;
+                (left_cal, right_cal)
+            }
+            ControllerType::N64 => {
+                // Single stick stored in the left stick slots; leave the right slots alone
+                check("Left", &left_cal, left_deadzone)?;
+                let mut writes = vec![left_stick_calibration_write(
+                    &left_cal,
+                    raw_calibration,
+                    target,
+                )];
+                writes.extend(self.parameters_write(StickSide::Left, left_params, target)?);
+                return self.write_verified(&writes, &[(StickSide::Left, left_parameters)]);
+            }
+            ControllerType::Nes | ControllerType::Snes | ControllerType::Genesis => {
+                return Err(anyhow!("This controller has no analog sticks to calibrate"));
+            }
+            ControllerType::Switch2(model) => {
+                return Err(anyhow!(ControllerError::UnsupportedModel(model)));
+            }
+        };
+
+        // A single Joy-Con's parameters go in both blocks
+        let written_parameters = match self.controller_type {
+            ControllerType::JoyConL => [left_parameters; 2],
+            ControllerType::JoyConR => [right_parameters; 2],
+            _ => [left_parameters, right_parameters],
+        };
+
+        // Deadzone and range ratio only exist in the factory area, whatever the target
+        let mut writes = vec![right_stick_calibration_write(
+            &final_right_cal,
+            raw_calibration,
+            target,
+        )];
+        writes.extend(self.parameters_write(StickSide::Right, right_params, target)?);
+        writes.push(left_stick_calibration_write(
+            &final_left_cal,
+            raw_calibration,
+            target,
+        ));
+        writes.extend(self.parameters_write(StickSide::Left, left_params, target)?);
+        let [left_written, right_written] = written_parameters;
+        self.write_verified(
+            &writes,
+            &[
+                (StickSide::Left, left_written),
+                (StickSide::Right, right_written),
+            ],
+        )
+    }
+
+    // A parameter block write, if it's needed. With the user target these blocks are the
+    // only factory data a write would touch, so one that already holds the values is left alone.
+    // A dry run goes by the values read at connect instead of reading the flash, since the
+    // write preview is built on every render; unknown values are assumed to change.
+    fn parameters_write(
+        &mut self,
+        side: StickSide,
+        params: [u8; 3],
+        target: CalibrationTarget,
+    ) -> Result<Option<(&'static str, u32, Vec<u8>)>> {
+        let (name, addr) = match side {
+            StickSide::Left => ("Left stick parameters", LEFT_STICK_PARAMS_ADDR),
+            StickSide::Right => ("Right stick parameters", RIGHT_STICK_PARAMS_ADDR),
+        };
+        if target == CalibrationTarget::User {
+            if !self.is_dry_run() {
+                self.read_stick_parameters(side)?;
+            }
+            if self.stored_stick_params[side as usize] == Some(params) {
+                return Ok(None);
+            }
+        }
+        Ok(Some((name, addr, params.to_vec())))
+    }
+
+    // Decode what a parameter block now holds, the way it is read at connect, and compare
+    // it with what was meant to be written. write_verified only compares the flash with
+    // the encoded bytes, so it would pass a deadzone and range ratio stored swapped.
+    fn check_stick_parameters(&mut self, side: StickSide, expected: StickParameters) -> Result<()> {
+        if self.is_dry_run() {
+            return Ok(());
+        }
+        let stored = self.read_stick_parameters(side)?;
+        if stored != expected {
+            return Err(anyhow!(
+                "{:?} stick parameters read back as deadzone {}, range ratio 0x{:03X} instead of {}, 0x{:03X}",
+                side,
+                stored.deadzone,
+                stored.range_ratio,
+                expected.deadzone,
+                expected.range_ratio
+            ));
+        }
+        Ok(())
+    }
+
+    // Write the regions as one unit: their current contents are read first, and if any
+    // write or check fails, the regions already touched get those contents back. The
+    // stick parameters are decoded and checked too, so a bad read-back undoes the write.
+    fn write_verified(
+        &mut self,
+        writes: &[(&'static str, u32, Vec<u8>)],
+        parameters: &[(StickSide, StickParameters)],
+    ) -> Result<Vec<RegionCheck>> {
+        // Nothing reaches the flash, so there is nothing to verify or undo
+        if self.is_dry_run() {
+            for (_, offset, data) in writes {
+                self.write_spi_data(*offset, data)?;
+            }
+            return Ok(Vec::new());
+        }
+        let mut previous = Vec::new();
+        for (_, offset, data) in writes {
+            previous.push(self.read_spi_data(*offset, data.len() as u8)?);
+        }
+        let mut touched = 0;
+        let result = self.write_and_check(writes, parameters, &mut touched);
+        let Err(cause) = result else {
+            return result;
+        };
+        // A controller that went away can't be rolled back; writing again once it's
+        // back replaces everything anyway
+        if cause.downcast_ref::<ControllerError>() == Some(&ControllerError::Disconnected) {
+            return Err(cause);
+        }
+
+        let mut not_restored = Vec::new();
+        let mut rollback_error = None;
+        for ((name, offset, _), data) in writes.iter().zip(&previous).take(touched).rev() {
+            let restored = self
+                .write_spi_data(*offset, data)
+                .and_then(|_| self.compare_spi(*offset, data));
+            match restored {
+                Ok(mismatches) if mismatches.is_empty() => {}
+                Ok(_) => not_restored.push(*name),
+                Err(e) => {
+                    not_restored.push(*name);
+                    rollback_error.get_or_insert(e);
+                }
+            }
+        }
+        Err(anyhow!(CalibrationWriteError {
+            cause,
+            not_restored,
+            rollback_error,
+        }))
+    }
+
+    // Write each region, then read them all back. An ACK doesn't guarantee the flash
+    // changed, so a region that differs is written once more before giving up.
+    // `touched` counts the regions, in order, whose flash may have been changed.
+    fn write_and_check(
+        &mut self,
+        writes: &[(&'static str, u32, Vec<u8>)],
+        parameters: &[(StickSide, StickParameters)],
+        touched: &mut usize,
+    ) -> Result<Vec<RegionCheck>> {
+        for (_, offset, data) in writes {
+            *touched += 1;
+            self.write_spi_data(*offset, data)?;
+        }
+        let mut checks = Vec::new();
+        let mut differing = Vec::new();
+        for (name, offset, data) in writes {
+            let mut mismatches = self.compare_spi(*offset, data)?;
+            if !mismatches.is_empty() {
+                self.write_spi_data(*offset, data)?;
+                mismatches = self.compare_spi(*offset, data)?;
+            }
+            checks.push(RegionCheck {
+                name,
+                offset: *offset,
+                passed: mismatches.is_empty(),
+            });
+            differing.extend(mismatches);
+        }
+        if !differing.is_empty() {
+            return Err(anyhow!(
+                "Verification failed: {} read back differently at {}",
+                checks
+                    .iter()
+                    .filter(|c| !c.passed)
+                    .map(|c| c.name)
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                differing
+                    .iter()
+                    .map(|offset| format!("0x{:04X}", offset))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+        for &(side, expected) in parameters {
+            self.check_stick_parameters(side, expected)?;
+        }
+        Ok(checks)
+    }
+
+    // Addresses in flash whose contents differ from `expected`
+    fn compare_spi(&mut self, offset: u32, expected: &[u8]) -> Result<Vec<u32>> {
+        let actual = self.read_spi_data(offset, expected.len() as u8)?;
+        Ok(expected
+            .iter()
+            .enumerate()
+            .filter(|&(i, byte)| actual.get(i) != Some(byte))
+            .map(|(i, _)| offset + i as u32)
+            .collect())
+    }
+}
+
+fn left_stick_calibration_write(
+    cal: &StickCalibration,
+    raw: bool,
+    target: CalibrationTarget,
+) -> (&'static str, u32, Vec<u8>) {
+    let encoded = if raw {
+        encode_raw_left_stick_cal(cal)
+    } else {
+        encode_left_stick_cal(cal)
+    };
+    match target {
+        CalibrationTarget::Factory => (
+            "Left stick calibration",
+            LEFT_STICK_CAL_ADDR,
+            encoded.to_vec(),
+        ),
+        CalibrationTarget::User => (
+            "Left stick user calibration",
+            LEFT_USER_STICK_CAL_ADDR,
+            user_cal_block(&encoded).to_vec(),
+        ),
+    }
+}
+
+fn right_stick_calibration_write(
+    cal: &StickCalibration,
+    raw: bool,
+    target: CalibrationTarget,
+) -> (&'static str, u32, Vec<u8>) {
+    let encoded = if raw {
+        encode_raw_right_stick_cal(cal)
+    } else {
+        encode_right_stick_cal(cal)
+    };
+    match target {
+        CalibrationTarget::Factory => (
+            "Right stick calibration",
+            RIGHT_STICK_CAL_ADDR,
+            encoded.to_vec(),
+        ),
+        CalibrationTarget::User => (
+            "Right stick user calibration",
+            RIGHT_USER_STICK_CAL_ADDR,
+            user_cal_block(&encoded).to_vec(),
+        ),
+    }
+}
+
+// Error text for a device another process has opened exclusively differs per platform
+fn looks_busy(e: &HidError) -> bool {
+    let message = e.to_string().to_lowercase();
+    ["busy", "access", "exclusive", "sharing violation"]
+        .iter()
+        .any(|hint| message.contains(hint))
+}
+
+impl Drop for Controller {
+    fn drop(&mut self) {
+        // The device may already be unplugged, so a failed write is fine
+        let _ = self.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A full report as Linux delivers it: sticks at 0x81F/0x7A3 and 0x75C/0x7B8, then
+    // three IMU samples of a controller lying flat
+    const LINUX_FULL_REPORT: [u8; 49] = [
+        0x30, 0x5A, 0x8E, 0x00, 0x00, 0x00, 0x1F, 0x38, 0x7A, 0x5C, 0x87, 0x7B, 0x0C, 0xB4, 0xFF,
+        0x28, 0x00, 0xD2, 0x0F, 0x03, 0x00, 0xFE, 0xFF, 0x01, 0x00, 0xB6, 0xFF, 0x27, 0x00, 0xD4,
+        0x0F, 0x02, 0x00, 0xFE, 0xFF, 0x02, 0x00, 0xB3, 0xFF, 0x29, 0x00, 0xD1, 0x0F, 0x03, 0x00,
+        0xFF, 0xFF, 0x01, 0x00,
+    ];
+
+    // Bytes 3-5 of the stick parameter block in the example factory dump from the
+    // published SPI flash notes, where 0x6086 and 0x6098 both hold
+    // 0F 30 61 96 30 F3 D4 14 54 41 15 54 C7 79 9C 33 36 63: deadzone 0x096, range ratio 0xF33
+    const FACTORY_STICK_PARAMETERS: [u8; 3] = [0x96, 0x30, 0xF3];
+
+    // Scripted reads, in order. Each write also queues the next entry of `answers`, the
+    // way the controller answers a subcommand; with none left, a device with `flash`
+    // answers SPI reads and writes from it and acknowledges everything else.
+    #[derive(Default)]
+    struct MockState {
+        reads: VecDeque<Vec<u8>>,
+        answers: VecDeque<Vec<Vec<u8>>>,
+        writes: Vec<Vec<u8>>,
+        flash: Option<Vec<u8>>,
+    }
+
+    #[derive(Clone, Default)]
+    struct MockDevice(Arc<Mutex<MockState>>);
+
+    impl MockDevice {
+        fn answer(&self, reports: Vec<Vec<u8>>) {
+            self.0.lock().answers.push_back(reports);
+        }
+
+        fn with_flash() -> Self {
+            let device = Self::default();
+            device.0.lock().flash = Some(vec![0xFF; SPI_FLASH.end as usize]);
+            device
+        }
+
+        fn flash(&self, range: Range<u32>) -> Vec<u8> {
+            self.0.lock().flash.as_ref().unwrap()[range.start as usize..range.end as usize].to_vec()
+        }
+
+        fn set_flash(&self, offset: u32, data: &[u8]) {
+            let mut state = self.0.lock();
+            let flash = state.flash.as_mut().unwrap();
+            flash[offset as usize..offset as usize + data.len()].copy_from_slice(data);
+        }
+
+        // SPI writes sent, as (offset, length)
+        fn spi_writes(&self) -> Vec<(u32, u8)> {
+            self.0
+                .lock()
+                .writes
+                .iter()
+                .filter(|packet| packet[0] == 0x01 && packet[10] == 0x11)
+                .map(|packet| {
+                    let offset = u32::from_le_bytes(packet[11..15].try_into().unwrap());
+                    (offset, packet[15])
+                })
+                .collect()
+        }
+    }
+
+    // How the controller answers a subcommand packet, given its flash
+    fn flash_reply(flash: &mut [u8], packet: &[u8]) -> Vec<u8> {
+        let (subcmd, args) = (packet[10], &packet[11..]);
+        let offset = u32::from_le_bytes(args[0..4].try_into().unwrap()) as usize;
+        let len = args[4] as usize;
+        match subcmd {
+            0x10 => {
+                let mut data = args[0..5].to_vec();
+                data.extend_from_slice(&flash[offset..offset + len]);
+                reply(0x10, 0x90, &data)
+            }
+            0x11 => {
+                flash[offset..offset + len].copy_from_slice(&args[5..5 + len]);
+                reply(0x11, 0x80, &[])
+            }
+            _ => reply(subcmd, 0x80, &[]),
+        }
+    }
+
+    impl HidBackend for MockDevice {
+        fn write(&self, data: &[u8]) -> HidResult<usize> {
+            let mut state = self.0.lock();
+            state.writes.push(data.to_vec());
+            if let Some(reports) = state.answers.pop_front() {
+                state.reads.extend(reports);
+            } else if data[0] == 0x01 {
+                let state = &mut *state;
+                if let Some(flash) = &mut state.flash {
+                    state.reads.push_back(flash_reply(flash, data));
+                }
+            }
+            Ok(data.len())
+        }
+
+        fn read_timeout(&self, buf: &mut [u8], _timeout_ms: i32) -> HidResult<usize> {
+            let Some(report) = self.0.lock().reads.pop_front() else {
+                return Ok(0);
+            };
+            let n = report.len().min(buf.len());
+            buf[..n].copy_from_slice(&report[..n]);
+            Ok(n)
+        }
+    }
+
+    fn mock_controller(report_id_missing: bool) -> (Controller, MockDevice) {
+        let device = MockDevice::default();
+        let mut controller =
+            Controller::new(Box::new(device.clone()), ControllerType::ProController);
+        controller.report_id_missing = report_id_missing;
+        (controller, device)
+    }
+
+    // A full report with the given timer and stick positions, the rest as above
+    fn full_report(timer: u8, left: [u16; 2], right: [u16; 2]) -> Vec<u8> {
+        let mut report = LINUX_FULL_REPORT.to_vec();
+        report[1] = timer;
+        report[6..9].copy_from_slice(&encode_stick_params(&left));
+        report[9..12].copy_from_slice(&encode_stick_params(&right));
+        report
+    }
+
+    // A subcommand reply with centered sticks
+    fn reply(subcmd: u8, ack: u8, data: &[u8]) -> Vec<u8> {
+        let mut report = full_report(0, [0x800; 2], [0x800; 2]);
+        report[0] = 0x21;
+        report[0x0D] = ack;
+        report[0x0E] = subcmd;
+        report[0x0F..].fill(0);
+        report[0x0F..0x0F + data.len()].copy_from_slice(data);
+        report
+    }
+
+    // As macOS Bluetooth delivers it
+    fn stripped(report: &[u8]) -> Vec<u8> {
+        report[1..].to_vec()
+    }
+
+    #[test]
+    fn stripped_full_report_decodes_like_linux() {
+        let (mut linux, linux_device) = mock_controller(false);
+        linux_device
+            .0
+            .lock()
+            .reads
+            .push_back(LINUX_FULL_REPORT.to_vec());
+        let (mut macos, macos_device) = mock_controller(true);
+        macos_device
+            .0
+            .lock()
+            .reads
+            .push_back(stripped(&LINUX_FULL_REPORT));
+
+        let linux = linux.read_stick_data().unwrap();
+        let macos = macos.read_stick_data().unwrap();
+        for data in [linux, macos] {
+            assert_eq!((data.lx, data.ly), (0x81F, 0x7A3));
+            assert_eq!((data.rx, data.ry), (0x75C, 0x7B8));
+        }
+        assert_eq!(linux.imu.samples[0].accel, macos.imu.samples[0].accel);
+        assert_eq!(linux.imu.samples[2].gyro, macos.imu.samples[2].gyro);
+    }
+
+    #[test]
+    fn stripped_subcommand_reply_is_matched() {
+        let (mut controller, device) = mock_controller(true);
+        device.answer(vec![stripped(&reply(0x03, 0x80, &[]))]);
+        controller.send_subcommand(0x03, &[0x30]).unwrap();
+        // Not parsed as motion data or counted as an input report
+        assert_eq!(controller.packet_stats().received, 0);
+
+        device.answer(vec![stripped(&reply(
+            0x10,
+            0x90,
+            &[0x3D, 0x60, 0x00, 0x00, 0x02, 0xAB, 0xCD],
+        ))]);
+        assert_eq!(controller.read_spi_data(0x603D, 2).unwrap(), [0xAB, 0xCD]);
+    }
+
+    #[test]
+    fn stripped_report_id_comes_from_the_content() {
+        let mut report = LINUX_FULL_REPORT.to_vec();
+        // First accelerometer sample 0x0300, which reads as a NACK to subcommand 0x03
+        report[0x0D] = 0x00;
+        report[0x0E] = 0x03;
+        assert_eq!(recover_report_id(&report, None), 0x30);
+        assert_eq!(recover_report_id(&report, Some(0x03)), 0x21);
+        assert_eq!(recover_report_id(&reply(0x02, 0x82, &[]), None), 0x21);
+        assert_eq!(recover_report_id(&LINUX_FULL_REPORT, Some(0x02)), 0x30);
+        assert_eq!(recover_report_id(&LINUX_FULL_REPORT[..12], None), 0x3F);
+    }
+
+    #[test]
+    fn replies_interleaved_with_input_reach_the_subcommand() {
+        let (mut controller, device) = mock_controller(true);
+        let input = controller.subscribe();
+        let sticks = |i: u16| [0x700 + i, 0x800 - i];
+        device.answer(vec![
+            stripped(&full_report(1, sticks(1), sticks(1))),
+            stripped(&full_report(2, sticks(2), sticks(2))),
+            // A late reply to an earlier LED command
+            stripped(&reply(0x30, 0x80, &[])),
+            stripped(&full_report(3, sticks(3), sticks(3))),
+            stripped(&reply(0x03, 0x80, &[])),
+            stripped(&full_report(4, sticks(4), sticks(4))),
+        ]);
+        controller.send_subcommand(0x03, &[0x30]).unwrap();
+        controller.read_stick_data().unwrap();
+
+        let reports = input.drain();
+        assert_eq!(reports.len(), 4);
+        for (i, report) in reports.iter().enumerate() {
+            assert_eq!(report.report_id, 0x30);
+            let [lx, ly] = sticks(i as u16 + 1);
+            assert_eq!((report.data.lx, report.data.ly), (lx, ly));
+        }
+        let stats = controller.packet_stats();
+        assert_eq!((stats.received, stats.dropped), (4, 0));
+    }
+
+    fn flash_controller(controller_type: ControllerType) -> (Controller, MockDevice) {
+        let device = MockDevice::with_flash();
+        let mut controller = Controller::new(Box::new(device.clone()), controller_type);
+        controller.update_capabilities(true);
+        (controller, device)
+    }
+
+    // A healthy stick around the middle of the raw range
+    fn factory_like_calibration() -> StickCalibration {
+        StickCalibration {
+            xmax: 0xE00,
+            ymax: 0xDF0,
+            xcenter: 0x800,
+            ycenter: 0x7F0,
+            xmin: 0x200,
+            ymin: 0x210,
+        }
+    }
+
+    #[test]
+    fn stick_parameters_encode_like_the_factory() {
+        let factory = StickParameters {
+            deadzone: 0x096,
+            range_ratio: 0xF33,
+        };
+        for stick in ["Left", "Right"] {
+            assert_eq!(
+                encode_stick_parameters(stick, &factory).unwrap(),
+                FACTORY_STICK_PARAMETERS
+            );
+        }
+        let (mut controller, device) = flash_controller(ControllerType::ProController);
+        device.set_flash(LEFT_STICK_PARAMS_ADDR, &FACTORY_STICK_PARAMETERS);
+        device.set_flash(RIGHT_STICK_PARAMS_ADDR, &FACTORY_STICK_PARAMETERS);
+        for side in [StickSide::Left, StickSide::Right] {
+            assert_eq!(controller.read_stick_parameters(side).unwrap(), factory);
+        }
+        assert!(
+            encode_stick_parameters(
+                "Left",
+                &StickParameters {
+                    deadzone: 0x1000,
+                    range_ratio: 0xF33,
+                }
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn written_stick_parameters_read_back() {
+        let (mut controller, device) = flash_controller(ControllerType::ProController);
+        let left = StickParameters {
+            deadzone: 0x0AE,
+            range_ratio: 0xE14,
+        };
+        let right = StickParameters {
+            deadzone: 0x0B4,
+            range_ratio: 0xF80,
+        };
+        let cal = factory_like_calibration();
+        controller
+            .write_calibration_to_device(cal, cal, left, right, false, CalibrationTarget::Factory)
+            .unwrap();
+        assert_eq!(device.flash(0x6089..0x608C), [0xAE, 0x40, 0xE1]);
+        assert_eq!(device.flash(0x609B..0x609E), [0xB4, 0x00, 0xF8]);
+        assert_eq!(
+            controller.read_stick_parameters(StickSide::Left).unwrap(),
+            left
+        );
+        assert_eq!(
+            controller.read_stick_parameters(StickSide::Right).unwrap(),
+            right
+        );
+    }
+
+    #[test]
+    fn user_target_leaves_unchanged_parameters_alone() {
+        let (mut controller, device) = flash_controller(ControllerType::ProController);
+        device.set_flash(LEFT_STICK_PARAMS_ADDR, &FACTORY_STICK_PARAMETERS);
+        device.set_flash(RIGHT_STICK_PARAMS_ADDR, &FACTORY_STICK_PARAMETERS);
+        let factory = StickParameters {
+            deadzone: 0x096,
+            range_ratio: 0xF33,
+        };
+        let cal = factory_like_calibration();
+        controller
+            .write_calibration_to_device(cal, cal, factory, factory, false, CalibrationTarget::User)
+            .unwrap();
+        let factory_writes = |device: &MockDevice| -> Vec<u32> {
+            device
+                .spi_writes()
+                .into_iter()
+                .map(|(offset, _)| offset)
+                .filter(|offset| CALIBRATION_REGIONS[0].contains(offset))
+                .collect()
+        };
+        assert!(factory_writes(&device).is_empty());
+        assert!(!device.spi_writes().is_empty());
+
+        // Only the block that changes is written
+        let wider = StickParameters {
+            deadzone: 0x0AE,
+            ..factory
+        };
+        controller
+            .write_calibration_to_device(cal, cal, wider, factory, false, CalibrationTarget::User)
+            .unwrap();
+        assert_eq!(factory_writes(&device), [LEFT_STICK_PARAMS_ADDR]);
+        assert_eq!(device.flash(0x609B..0x609E), FACTORY_STICK_PARAMETERS);
+
+        // The factory target writes them regardless
+        let writes = controller
+            .preview_calibration_writes(cal, cal, wider, factory, CalibrationTarget::Factory)
+            .unwrap();
+        let offsets: Vec<u32> = writes.iter().map(|(offset, _)| *offset).collect();
+        assert!(offsets.contains(&LEFT_STICK_PARAMS_ADDR));
+        assert!(offsets.contains(&RIGHT_STICK_PARAMS_ADDR));
+    }
+
+    #[test]
+    fn bad_parameter_read_back_is_rolled_back() {
+        let (mut controller, device) = flash_controller(ControllerType::ProController);
+        device.set_flash(LEFT_STICK_PARAMS_ADDR, &FACTORY_STICK_PARAMETERS);
+        let meant = StickParameters {
+            deadzone: 0x0AE,
+            range_ratio: 0xE14,
+        };
+        // The bytes verify, but decode to the deadzone and ratio swapped
+        let swapped = encode_stick_params(&[meant.range_ratio, meant.deadzone]);
+        let error = controller
+            .write_verified(
+                &[(
+                    "Left stick parameters",
+                    LEFT_STICK_PARAMS_ADDR,
+                    swapped.to_vec(),
+                )],
+                &[(StickSide::Left, meant)],
+            )
+            .unwrap_err();
+        let failed = error.downcast_ref::<CalibrationWriteError>().unwrap();
+        assert!(failed.rolled_back());
+        assert!(failed.cause.to_string().contains("read back as deadzone"));
+        assert_eq!(
+            device.flash(0x6089..0x608C),
+            FACTORY_STICK_PARAMETERS.to_vec()
+        );
+    }
+
+    #[test]
+    fn write_preview_does_not_read_the_flash() {
+        let (mut controller, device) = flash_controller(ControllerType::ProController);
+        device.set_flash(LEFT_STICK_PARAMS_ADDR, &FACTORY_STICK_PARAMETERS);
+        device.set_flash(RIGHT_STICK_PARAMS_ADDR, &FACTORY_STICK_PARAMETERS);
+        let cal = factory_like_calibration();
+        let factory = StickParameters {
+            deadzone: 0x096,
+            range_ratio: 0xF33,
+        };
+        let preview = |controller: &mut Controller| -> Vec<u32> {
+            let sent = device.0.lock().writes.len();
+            let writes = controller
+                .preview_calibration_writes(cal, cal, factory, factory, CalibrationTarget::User)
+                .unwrap();
+            assert_eq!(device.0.lock().writes.len(), sent);
+            writes.iter().map(|(offset, _)| *offset).collect()
+        };
+        // Before the blocks were read they are assumed to change
+        let offsets = preview(&mut controller);
+        assert!(offsets.contains(&LEFT_STICK_PARAMS_ADDR));
+        assert!(offsets.contains(&RIGHT_STICK_PARAMS_ADDR));
+
+        // Once read, as at connect, unchanged ones are left out
+        for side in [StickSide::Left, StickSide::Right] {
+            controller.read_stick_parameters(side).unwrap();
+        }
+        let offsets = preview(&mut controller);
+        assert!(!offsets.contains(&LEFT_STICK_PARAMS_ADDR));
+        assert!(!offsets.contains(&RIGHT_STICK_PARAMS_ADDR));
+
+        // Writing over a block forgets it, since it may no longer hold what was read
+        controller.write_spi_data(0x6086, &[0; 8]).unwrap();
+        let offsets = preview(&mut controller);
+        assert!(offsets.contains(&LEFT_STICK_PARAMS_ADDR));
+        assert!(!offsets.contains(&RIGHT_STICK_PARAMS_ADDR));
+    }
+
+    #[test]
+    fn raw_imu_readings_use_the_datasheet_sensitivity() {
+        // 0x4000 on every axis of every sample
+        let mut report = LINUX_FULL_REPORT;
+        for pair in report[13..49].chunks_mut(2) {
+            pair.copy_from_slice(&0x4000i16.to_le_bytes());
+        }
+        let gyro = [
+            (GyroRange::Dps250, 143.36),
+            (GyroRange::Dps500, 286.72),
+            (GyroRange::Dps1000, 573.44),
+            (GyroRange::Dps2000, 1146.88),
+        ];
+        let accel = [
+            (AccelRange::G2, 0.999424),
+            (AccelRange::G4, 1.998848),
+            (AccelRange::G8, 3.997696),
+            (AccelRange::G16, 7.995392),
+        ];
+        for ((gyro_range, dps), (accel_range, g)) in gyro.into_iter().zip(accel) {
+            let settings = ImuSettings {
+                gyro_range,
+                accel_range,
+                ..ImuSettings::default()
+            };
+            for sample in parse_imu(&report, settings).samples {
+                for axis in 0..3 {
+                    assert!((sample.gyro[axis] - dps).abs() < 1e-3, "{:?}", gyro_range);
+                    assert!((sample.accel[axis] - g).abs() < 1e-5, "{:?}", accel_range);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pairing_host_reads_in_stored_order() {
+        let (mut controller, device) = flash_controller(ControllerType::ProController);
+        // Magic, pairing type, then the host address; the second slot is erased
+        device.set_flash(
+            PAIRING_INFO_ADDR,
+            &[0x95, 0x22, 0x00, 0x00, 0x98, 0xB6, 0xE9, 0x12, 0x34, 0x56],
+        );
+        assert_eq!(
+            controller.get_pairing_info().unwrap(),
+            ["98:B6:E9:12:34:56"]
+        );
+    }
+
+    #[test]
+    fn raw_calibration_skips_the_delta_conversion() {
+        let cal = factory_like_calibration();
+        let written = |raw| {
+            (
+                left_stick_calibration_write(&cal, raw, CalibrationTarget::Factory).2,
+                right_stick_calibration_write(&cal, raw, CalibrationTarget::Factory).2,
+            )
+        };
+        // Ranges of 0x600 above and 0x600/0x5E0 below the center
+        let (left, right) = written(false);
+        assert_eq!(left, [0x00, 0x06, 0x60, 0x00, 0x08, 0x7F, 0x00, 0x06, 0x5E]);
+        assert_eq!(
+            right,
+            [0x00, 0x08, 0x7F, 0x00, 0x06, 0x5E, 0x00, 0x06, 0x60]
+        );
+        // The min and max fields as they are
+        let (left, right) = written(true);
+        assert_eq!(left, [0x00, 0x0E, 0xDF, 0x00, 0x08, 0x7F, 0x00, 0x02, 0x21]);
+        assert_eq!(
+            right,
+            [0x00, 0x08, 0x7F, 0x00, 0x02, 0x21, 0x00, 0x0E, 0xDF]
+        );
+    }
+
+    #[test]
+    fn raw_calibration_writes_precomputed_ranges_verbatim() {
+        let cal = factory_like_calibration();
+        let ranges = StickCalibration {
+            xmax: cal.xmax - cal.xcenter,
+            ymax: cal.ymax - cal.ycenter,
+            xmin: cal.xcenter - cal.xmin,
+            ymin: cal.ycenter - cal.ymin,
+            ..cal
+        };
+        assert_eq!(
+            encode_raw_left_stick_cal(&ranges),
+            encode_left_stick_cal(&cal)
+        );
+        assert_eq!(
+            encode_raw_right_stick_cal(&ranges),
+            encode_right_stick_cal(&cal)
+        );
+        // The user slots hold the same nine bytes behind their magic
+        let user = left_stick_calibration_write(&ranges, true, CalibrationTarget::User).2;
+        assert_eq!(user[2..], encode_left_stick_cal(&cal));
+    }
+
+    #[test]
+    fn raw_calibration_write_reads_back_as_absolute() {
+        let (mut controller, device) = flash_controller(ControllerType::ProController);
+        let cal = factory_like_calibration();
+        let ranges = StickCalibration {
+            xmax: cal.xmax - cal.xcenter,
+            ymax: cal.ymax - cal.ycenter,
+            xmin: cal.xcenter - cal.xmin,
+            ymin: cal.ycenter - cal.ymin,
+            ..cal
+        };
+        let parameters = StickParameters {
+            deadzone: 0x096,
+            range_ratio: 0xF33,
+        };
+        controller
+            .write_calibration_to_device(
+                ranges,
+                ranges,
+                parameters,
+                parameters,
+                true,
+                CalibrationTarget::Factory,
+            )
+            .unwrap();
+        assert_eq!(device.flash(0x603D..0x6046), encode_left_stick_cal(&cal));
+        assert_eq!(device.flash(0x6046..0x604F), encode_right_stick_cal(&cal));
+        for side in [StickSide::Left, StickSide::Right] {
+            assert_eq!(
+                controller.read_factory_stick_calibration(side).unwrap(),
+                cal
+            );
+        }
+    }
+
+    #[test]
+    fn raw_calibration_over_12_bits_is_rejected() {
+        let (mut controller, device) = flash_controller(ControllerType::ProController);
+        let ranges = StickCalibration {
+            xmax: 0x1000,
+            ymax: 0x600,
+            xcenter: 0x800,
+            ycenter: 0x7F0,
+            xmin: 0x600,
+            ymin: 0xFFFF,
+        };
+        let parameters = StickParameters {
+            deadzone: 0x096,
+            range_ratio: 0xF33,
+        };
+        let error = controller
+            .write_calibration_to_device(
+                ranges,
+                ranges,
+                parameters,
+                parameters,
+                true,
+                CalibrationTarget::Factory,
+            )
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Left stick raw calibration doesn't fit in 12 bits: xmax 0x1000, ymin 0xFFFF"
+        );
+        assert!(device.spi_writes().is_empty());
+    }
+
+    #[test]
+    fn factory_like_calibration_is_valid() {
+        let cal = factory_like_calibration();
+        assert_eq!(cal.validate(0x096), []);
+        assert!(check_stick_calibration("Left", &cal, 0x096).is_ok());
+    }
+
+    #[test]
+    fn inverted_range_is_rejected() {
+        let cal = StickCalibration {
+            xmin: 0xE00,
+            xmax: 0x200,
+            ..factory_like_calibration()
+        };
+        assert_eq!(
+            cal.validate(0x096),
+            [CalibrationProblem::InvertedRange("X")]
+        );
+    }
+
+    #[test]
+    fn center_outside_range_is_rejected() {
+        let cal = StickCalibration {
+            ycenter: 0xE80,
+            ..factory_like_calibration()
+        };
+        assert_eq!(
+            cal.validate(0x096),
+            [CalibrationProblem::CenterOutsideRange("Y")]
+        );
+    }
+
+    #[test]
+    fn too_small_range_is_rejected() {
+        let cal = StickCalibration {
+            xmin: 0x780,
+            xmax: 0x900,
+            ..factory_like_calibration()
+        };
+        assert_eq!(
+            cal.validate(0x096),
+            [CalibrationProblem::RangeTooSmall("X", 0x180)]
+        );
+    }
+
+    #[test]
+    fn deadzone_over_half_the_range_is_rejected() {
+        // The Y axis is the shorter at 0xBE0
+        let cal = factory_like_calibration();
+        assert_eq!(cal.validate(0x5F0), []);
+        assert_eq!(
+            cal.validate(0x5F1),
+            [CalibrationProblem::DeadzoneTooLarge(0x5F1, 0x5F0)]
+        );
+    }
+
+    #[test]
+    fn invalid_calibration_writes_nothing() {
+        let (mut controller, device) = flash_controller(ControllerType::ProController);
+        let cal = factory_like_calibration();
+        let inverted = StickCalibration { ymin: 0xF00, ..cal };
+        let parameters = StickParameters {
+            deadzone: 0x096,
+            range_ratio: 0xF33,
+        };
+        let result = controller.write_calibration_to_device(
+            cal,
+            inverted,
+            parameters,
+            parameters,
+            false,
+            CalibrationTarget::Factory,
+        );
+        assert!(result.unwrap_err().to_string().contains("Right stick"));
+        assert!(device.0.lock().writes.is_empty());
+    }
+
+    #[test]
+    fn writes_must_stay_inside_a_region() {
+        let (mut controller, _) = mock_controller(false);
+        // The stick parameters region, 0x6086..0x60AA
+        assert!(controller.offset_allowed(&(0x6086..0x60AA)));
+        assert!(controller.offset_allowed(&(0x609B..0x60AA)));
+        assert!(!controller.offset_allowed(&(0x609B..0x60AB)));
+        assert!(!controller.offset_allowed(&(0x6085..0x6089)));
+        // Touching two regions is not inside either
+        assert!(!controller.offset_allowed(&(0x6030..0x6040)));
+        assert!(!controller.offset_allowed(&(0x2000..0x2010)));
+        controller.unsafe_allow_any_offset();
+        assert!(controller.offset_allowed(&(0x2000..0x2010)));
+    }
+
+    #[test]
+    fn forbidden_write_sends_nothing() {
+        let (mut controller, device) = flash_controller(ControllerType::ProController);
+        let error = controller.write_spi_data(0x60A8, &[0; 3]).unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<ControllerError>(),
+            Some(&ControllerError::ForbiddenOffset(0x60A8, 0x60AB))
+        );
+        assert!(device.0.lock().writes.is_empty());
+    }
+
+    #[test]
+    fn long_writes_are_split_into_chunks() {
+        let (mut controller, device) = flash_controller(ControllerType::ProController);
+        let data: Vec<u8> = (0..40).collect();
+        controller.write_spi_data(0x8010, &data).unwrap();
+        assert_eq!(device.spi_writes(), [(0x8010, 0x1D), (0x802D, 11)]);
+        assert_eq!(device.flash(0x8010..0x8038), data);
+    }
+
+    #[test]
+    fn failed_chunk_is_named() {
+        let (mut controller, device) = flash_controller(ControllerType::ProController);
+        // The second chunk runs past the end of the user calibration region
+        let error = controller.write_spi_data(0x8020, &[0; 40]).unwrap_err();
+        assert!(error.to_string().starts_with("Chunk 2 of 2 at 0x803D"));
+        assert!(error.downcast_ref::<ControllerError>().is_some());
+        assert_eq!(device.spi_writes(), [(0x8020, 0x1D)]);
+    }
+
+    #[test]
+    fn stale_reply_before_the_ack_is_skipped() {
+        let (mut controller, device) = mock_controller(false);
+        // A late answer to an earlier SPI read arrives first
+        device.answer(vec![
+            reply(0x10, 0x90, &[0x10, 0x80, 0x00, 0x00, 0x02, 0xFF, 0xFF]),
+            reply(0x11, 0x80, &[0x00]),
+        ]);
+        controller.write_spi_data(0x8010, &[0xB2, 0xA1]).unwrap();
+        assert_eq!(device.spi_writes(), [(0x8010, 2)]);
+    }
+
+    // Expected bytes follow the published HD rumble tables: high band 0x0004 at 81.75 Hz to
+    // 0x01FC at 1252 Hz, low band 0x01 at 40.875 Hz to 0x7F at 626 Hz, and amplitude step
+    // n as 2n in the high band and n / 2 + 0x40, with 0x8000 on odd steps, in the low band
+    #[test]
+    fn rumble_encodes_like_the_tables() {
+        assert_eq!(encode_rumble(320.0, 160.0, 0.0), [0x00, 0x01, 0x40, 0x40]);
+        assert_eq!(encode_rumble(81.75, 40.875, 0.0), [0x04, 0x00, 0x01, 0x40]);
+        assert_eq!(encode_rumble(1252.0, 626.0, 0.0), [0xFC, 0x01, 0x7F, 0x40]);
+        // Out of range frequencies clamp to the ends of the bands
+        assert_eq!(encode_rumble(2000.0, 20.0, 0.0), [0xFC, 0x01, 0x01, 0x40]);
+        // Step 100 (0xC8 / 0x0072), the table's 1.003
+        assert_eq!(encode_rumble(320.0, 160.0, 1.0), [0x00, 0xC9, 0x40, 0x72]);
+        // Step 68 (0x88 / 0x0062)
+        assert_eq!(encode_rumble(320.0, 160.0, 0.5), [0x00, 0x89, 0x40, 0x62]);
+        // Step 51 (0x66 / 0x8059)
+        assert_eq!(encode_rumble(320.0, 160.0, 0.35), [0x00, 0x67, 0xC0, 0x59]);
+    }
+
+    #[test]
+    fn sticks_are_parsed_only_from_reports_that_carry_them() {
+        let settings = ImuSettings::default();
+        let sticks = |report: &[u8]| {
+            parse_stick_report(report, settings).map(|data| (data.lx, data.ly, data.rx, data.ry))
+        };
+        let expected = Some((0x81F, 0x7A3, 0x75C, 0x7B8));
+        assert_eq!(sticks(&LINUX_FULL_REPORT), expected);
+        // NFC/IR mode reports share the layout
+        let mut nfc = LINUX_FULL_REPORT;
+        nfc[0] = 0x31;
+        assert_eq!(sticks(&nfc), expected);
+        // Replies are routed to the waiting subcommand, not read as stick samples
+        assert_eq!(sticks(&reply(0x10, 0x90, &[0x3D, 0x60])), None);
+        // Simple HID mode: buttons, hat, then 16-bit sticks from byte 4
+        let simple_hid = [
+            0x3F, 0x00, 0x00, 0x08, 0x00, 0x80, 0x00, 0x80, 0x00, 0x80, 0x00, 0x80,
+        ];
+        assert_eq!(sticks(&simple_hid), None);
+        for unknown in [0x00, 0x23, 0x81] {
+            let mut report = LINUX_FULL_REPORT;
+            report[0] = unknown;
+            assert_eq!(sticks(&report), None);
+        }
+    }
+
+    #[test]
+    fn subcommand_packets_carry_neutral_rumble() {
+        let (mut controller, _) = mock_controller(false);
+        // An SPI read of 9 bytes at 0x603D
+        let packet = controller.subcommand_packet(0x10, &[0x3D, 0x60, 0x00, 0x00, 0x09]);
+        let mut expected = [0u8; 49];
+        expected[..16].copy_from_slice(&[
+            0x01, 0x00, 0x00, 0x01, 0x40, 0x40, 0x00, 0x01, 0x40, 0x40, 0x10, 0x3D, 0x60, 0x00,
+            0x00, 0x09,
+        ]);
+        assert_eq!(packet, expected);
+        // The timing byte counts up and wraps at 0xF
+        let timers: Vec<u8> = (0..17)
+            .map(|_| controller.subcommand_packet(0x02, &[])[1])
+            .collect();
+        assert_eq!(timers[..3], [0x01, 0x02, 0x03]);
+        assert_eq!(timers[14..], [0x0F, 0x00, 0x01]);
+    }
+
+    #[test]
+    fn factory_imu_calibration_decodes_little_endian() {
+        let (mut controller, device) = flash_controller(ControllerType::ProController);
+        assert_eq!(controller.read_imu_factory_calibration().unwrap(), None);
+        // Laid out like a factory block: small signed origins and the usual sensitivities
+        let bytes = [
+            0xD3, 0xFF, 0xD5, 0xFF, 0x55, 0x01, 0x00, 0x40, 0x00, 0x40, 0x00, 0x40, 0x19, 0x00,
+            0xDD, 0xFF, 0xDC, 0xFF, 0x3B, 0x34, 0x3B, 0x34, 0x3B, 0x34,
+        ];
+        device.set_flash(FACTORY_IMU_CAL_ADDR, &bytes);
+        let cal = controller.read_imu_factory_calibration().unwrap().unwrap();
+        assert_eq!(cal.accel_origin, [-45, -43, 341]);
+        assert_eq!(
+            cal.accel_sensitivity,
+            ImuCalibration::DEFAULT_ACCEL_SENSITIVITY
+        );
+        assert_eq!(cal.gyro_origin, [25, -35, -36]);
+        assert_eq!(
+            cal.gyro_sensitivity,
+            ImuCalibration::DEFAULT_GYRO_SENSITIVITY
+        );
+        assert_eq!(cal.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn stick_params_decode_what_they_encode() {
+        let seconds = (0..0x1000).step_by(13).chain([0xFFF]);
+        for second in seconds {
+            for first in 0..0x1000 {
+                let encoded = encode_stick_params(&[first, second]);
+                assert_eq!(decode_stick_params(&encoded), [first, second]);
+            }
+        }
+        // Every three bytes are some pair of 12-bit values
+        for bytes in [[0x00; 3], [0xFF; 3], [0x12, 0x34, 0x56], [0xA5, 0x5A, 0xC3]] {
+            assert_eq!(encode_stick_params(&decode_stick_params(&bytes)), bytes);
+        }
+    }
+
+    #[test]
+    fn stick_calibration_decodes_what_it_encodes() {
+        // Every field different, so a mixed up axis or part shows
+        let cal = StickCalibration {
+            xmax: 0xE12,
+            ymax: 0xD9A,
+            xcenter: 0x7E5,
+            ycenter: 0x83C,
+            xmin: 0x1F4,
+            ymin: 0x2A7,
+        };
+        for cal in [cal, factory_like_calibration()] {
+            assert_eq!(decode_left_stick_cal(&encode_left_stick_cal(&cal)), cal);
+            assert_eq!(decode_right_stick_cal(&encode_right_stick_cal(&cal)), cal);
+        }
+        assert_ne!(encode_left_stick_cal(&cal), encode_right_stick_cal(&cal));
+
+        let (mut controller, device) = flash_controller(ControllerType::ProController);
+        device.set_flash(LEFT_STICK_CAL_ADDR, &encode_left_stick_cal(&cal));
+        device.set_flash(RIGHT_STICK_CAL_ADDR, &encode_right_stick_cal(&cal));
+        for side in [StickSide::Left, StickSide::Right] {
+            assert_eq!(
+                controller.read_factory_stick_calibration(side).unwrap(),
+                cal
+            );
+        }
+    }
+
+    #[test]
+    fn buttons_decode_from_their_report_bytes() {
+        // Bytes 3, 4 and 5 as each controller sends them, and the buttons that means
+        let cases: [(&str, [u8; 3], &[&str]); 7] = [
+            ("Pro, nothing", [0x00, 0x00, 0x00], &[]),
+            ("Pro, A and ZL", [0x08, 0x00, 0x80], &["A", "ZL"]),
+            (
+                "Pro, Home and Capture",
+                [0x00, 0x30, 0x00],
+                &["Home", "Capture"],
+            ),
+            (
+                "Pro, face buttons and d-pad",
+                [0x0F, 0x00, 0x0F],
+                &["A", "B", "X", "Y", "Up", "Down", "Left", "Right"],
+            ),
+            // A Joy-Con only sets its own byte and its half of the shared one
+            (
+                "Joy-Con L, SL, minus and stick",
+                [0x00, 0x09, 0x20],
+                &["-", "Left Stick", "SL (L)"],
+            ),
+            (
+                "Joy-Con R, SR, plus and stick",
+                [0x10, 0x06, 0x00],
+                &["+", "Right Stick", "SR (R)"],
+            ),
+            ("Joy-Con R, R and ZR", [0xC0, 0x00, 0x00], &["R", "ZR"]),
+        ];
+        for (name, bytes, expected) in cases {
+            let mut report = LINUX_FULL_REPORT;
+            report[3..6].copy_from_slice(&bytes);
+            let data = parse_stick_report(&report, ImuSettings::default()).unwrap();
+            assert_eq!(data.buttons.pressed(), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn joycon_halves_merge_into_one_state() {
+        let left = ButtonState::from_report(0x00, 0x01, 0x42);
+        let right = ButtonState::from_report(0x08, 0x10, 0x00);
+        let mut merged = ButtonState::default();
+        merged.merge_half(&left, true);
+        merged.merge_half(&right, false);
+        assert_eq!(merged.pressed(), ["A", "L", "-", "Home", "Up"]);
+    }
+
+    // A calibration `travel` raw units from center in every direction
+    fn calibration_with_travel(travel: u16) -> StickCalibration {
+        StickCalibration {
+            xmax: 0x800 + travel,
+            ymax: 0x800 + travel,
+            xcenter: 0x800,
+            ycenter: 0x800,
+            xmin: 0x800 - travel,
+            ymin: 0x800 - travel,
+        }
+    }
+
+    #[test]
+    fn range_ratio_scales_with_travel_against_the_stored_calibration() {
+        // Stored with the example dump's 0xF33
+        let factory = factory_like_calibration();
+        assert_eq!(factory.range_ratio(Some((&factory, 0xF33))), 0xF33);
+        let stored = calibration_with_travel(1500);
+        let reference = Some((&stored, 0xF33));
+        // 20% less travel, 20% smaller ratio: 0xF33 * 0.8 = 3112.8
+        let reduced = calibration_with_travel(1200);
+        assert_eq!(reduced.range_ratio(reference), 0xC29);
+        // Far more travel clamps rather than overflowing 12 bits
+        assert_eq!(calibration_with_travel(0x7FF).range_ratio(reference), 0xFFF);
+    }
+
+    #[test]
+    fn range_ratio_falls_back_without_a_usable_reference() {
+        let healthy = calibration_with_travel(1440);
+        assert_eq!(healthy.range_ratio(None), DEFAULT_RANGE_RATIO);
+        let reduced = calibration_with_travel(1152);
+        assert_eq!(reduced.range_ratio(None), 0xC66);
+        // Erased flash, and a ratio no controller would store
+        let erased = decode_left_stick_cal(&[0xFF; 9]);
+        let factory = factory_like_calibration();
+        for reference in [(&erased, 0xF33), (&factory, 0x800)] {
+            assert_eq!(usable_ratio_reference(Some(reference)), None);
+            assert_eq!(healthy.range_ratio(Some(reference)), DEFAULT_RANGE_RATIO);
+        }
     }
 }
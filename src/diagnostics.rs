@@ -0,0 +1,501 @@
+use crate::controller::{ImuSettings, InputReport, StickCalibration, StickSide};
+use crate::remap::remap_calibrated_axis;
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+
+// Each full report carries three IMU samples taken this far apart, in seconds
+const IMU_SAMPLE_INTERVAL: f64 = 0.005;
+
+// Gyro readings accumulated while the controller lies still. Anything it measures
+// is bias, since a controller at rest should read zero on every axis.
+#[derive(Default)]
+pub struct DriftMeasurement {
+    angle: [f64; 3],
+    samples: u32,
+    first_report: Option<Instant>,
+    last_report: Option<Instant>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DriftResult {
+    // Mean reading at rest, in degrees per second and in raw units at ±2000dps
+    pub bias_dps: [f64; 3],
+    pub bias_raw: [i16; 3],
+    // Heading error a game would accumulate per minute without recentering
+    pub drift_deg_per_min: [f64; 3],
+    pub seconds: f64,
+    // Share of the test's reports that arrived, from the samples against the time taken
+    pub coverage: f64,
+}
+
+impl DriftMeasurement {
+    pub fn add(&mut self, report: &InputReport) {
+        // Only full reports carry motion data
+        if !matches!(report.report_id, 0x30 | 0x31) {
+            return;
+        }
+        self.first_report.get_or_insert(report.received);
+        self.last_report = Some(report.received);
+        for sample in &report.data.imu.samples {
+            // Reports from before the IMU came on have no gravity at all
+            if sample.accel == [0.0; 3] {
+                continue;
+            }
+            for axis in 0..3 {
+                self.angle[axis] += sample.gyro[axis] as f64 * IMU_SAMPLE_INTERVAL;
+            }
+            self.samples += 1;
+        }
+    }
+
+    pub fn result(&self) -> Option<DriftResult> {
+        if self.samples == 0 {
+            return None;
+        }
+        let seconds = self.samples as f64 * IMU_SAMPLE_INTERVAL;
+        let sensitivity = ImuSettings::default().gyro_range.sensitivity() as f64;
+        let bias_dps = self.angle.map(|angle| angle / seconds);
+        // The last report's own samples span one report interval past its arrival
+        let span = match (self.first_report, self.last_report) {
+            (Some(first), Some(last)) => {
+                last.duration_since(first).as_secs_f64() + 3.0 * IMU_SAMPLE_INTERVAL
+            }
+            _ => seconds,
+        };
+        Some(DriftResult {
+            bias_dps,
+            bias_raw: bias_dps.map(|dps| (dps / sensitivity).round() as i16),
+            drift_deg_per_min: self.angle.map(|angle| angle / seconds * 60.0),
+            seconds,
+            coverage: (seconds / span).min(1.0),
+        })
+    }
+}
+
+// Output beyond the deadzone, in percent of full deflection, that games will show as the
+// stick moving on its own
+const VISIBLE_STICK_DRIFT_PCT: f64 = 1.0;
+
+// How far a stick left untouched strays from the center the controller applies, and what
+// that looks like to a game once the on-device deadzone has had its say
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct StickDrift {
+    // Distance of the mean resting position from the calibrated center, in raw units
+    pub mean_offset: f64,
+    // Furthest single sample from the calibrated center, in raw units
+    pub peak_offset: f64,
+    // Largest output a game would see, in percent of full deflection
+    pub peak_output_pct: f64,
+    pub samples: usize,
+}
+
+impl StickDrift {
+    pub fn visible(&self) -> bool {
+        self.peak_output_pct > VISIBLE_STICK_DRIFT_PCT
+    }
+}
+
+// Measure drift over resting samples, against a stick's calibration and deadzone as the
+// controller stores them; None without samples
+pub fn stick_drift(
+    samples: &[(u16, u16)],
+    cal: &StickCalibration,
+    deadzone: u16,
+) -> Option<StickDrift> {
+    if samples.is_empty() {
+        return None;
+    }
+    let offset =
+        |&(x, y): &(u16, u16)| (x as f64 - cal.xcenter as f64, y as f64 - cal.ycenter as f64);
+    let count = samples.len() as f64;
+    let (sum_x, sum_y) = samples
+        .iter()
+        .map(offset)
+        .fold((0.0, 0.0), |(sx, sy), (dx, dy)| (sx + dx, sy + dy));
+    let peak_offset = samples
+        .iter()
+        .map(|sample| {
+            let (dx, dy) = offset(sample);
+            dx.hypot(dy)
+        })
+        .fold(0.0, f64::max);
+    // Through the same mapping the calibrated preview uses, 0.5 being centered
+    let fraction = |value: u16| value as f32 / 4095.0;
+    let output = |&(x, y): &(u16, u16)| {
+        let deadzone = fraction(deadzone);
+        let ox = remap_calibrated_axis(
+            fraction(x),
+            fraction(cal.xmin),
+            fraction(cal.xcenter),
+            fraction(cal.xmax),
+            deadzone,
+        );
+        let oy = remap_calibrated_axis(
+            fraction(y),
+            fraction(cal.ymin),
+            fraction(cal.ycenter),
+            fraction(cal.ymax),
+            deadzone,
+        );
+        ((ox - 0.5) as f64).hypot((oy - 0.5) as f64) * 2.0 * 100.0
+    };
+    Some(StickDrift {
+        mean_offset: (sum_x / count).hypot(sum_y / count),
+        peak_offset,
+        peak_output_pct: samples.iter().map(output).fold(0.0, f64::max),
+        samples: samples.len(),
+    })
+}
+
+// A stick drift run as saved to disk, for comparing before and after a calibration
+#[derive(Serialize, Deserialize)]
+pub struct StickDriftReport {
+    pub mac: String,
+    // Seconds since the Unix epoch when the run finished
+    pub finished: u64,
+    pub seconds: f64,
+    pub sticks: Vec<(StickSide, StickDrift)>,
+}
+
+impl StickDriftReport {
+    pub fn save(&self, path: &Path) -> Result<()> {
+        save_json(self, path)
+    }
+}
+
+// A gyro drift run as saved to disk
+#[derive(Serialize, Deserialize)]
+pub struct GyroDriftReport {
+    pub mac: String,
+    // Seconds since the Unix epoch when the run finished
+    pub finished: u64,
+    pub result: DriftResult,
+}
+
+impl GyroDriftReport {
+    pub fn save(&self, path: &Path) -> Result<()> {
+        save_json(self, path)
+    }
+}
+
+fn save_json(report: &impl Serialize, path: &Path) -> Result<()> {
+    let json = serde_json::to_string_pretty(report)?;
+    fs::write(path, json).map_err(|e| anyhow!("Failed to write {}: {}", path.display(), e))
+}
+
+// A flick has to reach at least this far from center, in raw units, to be measured
+const MIN_FLICK_RADIUS: f64 = 1024.0;
+// Once inside this share of the flick's furthest reach, the stick counts as released
+const RELEASE_SHARE: f64 = 0.5;
+// Within this many raw units of center, a released stick counts as settled
+const SETTLED_RADIUS: f64 = 100.0;
+// How long after release a flick is followed, and how much before it the trace shows
+pub const SNAPBACK_WINDOW_MS: f64 = 300.0;
+const SNAPBACK_LEAD_MS: f64 = 50.0;
+
+// One stick reading, as an offset from center, at a time in milliseconds
+#[derive(Clone, Copy)]
+pub struct TimedOffset {
+    pub ms: f64,
+    pub dx: f64,
+    pub dy: f64,
+}
+
+impl TimedOffset {
+    fn radius(&self) -> f64 {
+        self.dx.hypot(self.dy)
+    }
+}
+
+// How a stick behaved after being flicked one way and let go
+pub struct Snapback {
+    // Direction of the flick, in radians from +X
+    pub angle: f64,
+    // Furthest the stick went past center on the opposite side, in raw units
+    pub overshoot: f64,
+    // From release until it stayed near center; None if it hadn't by the window's end
+    pub settle_ms: Option<f64>,
+    // Position along the flick direction against time from release, for plotting
+    pub trace: Vec<(f64, f64)>,
+}
+
+// Where a flick was let go: the first reading after its furthest point that falls
+// within RELEASE_SHARE of it. None until there has been a full flick and release.
+pub fn release_index(samples: &[TimedOffset]) -> Option<usize> {
+    let (peak, reach) = samples
+        .iter()
+        .enumerate()
+        .map(|(i, sample)| (i, sample.radius()))
+        .fold(
+            (0, 0.0),
+            |best, next| if next.1 > best.1 { next } else { best },
+        );
+    if reach < MIN_FLICK_RADIUS {
+        return None;
+    }
+    samples[peak..]
+        .iter()
+        .position(|sample| sample.radius() < reach * RELEASE_SHARE)
+        .map(|i| peak + i)
+}
+
+// Measure the overshoot and settling of a flick from readings that run at least
+// SNAPBACK_WINDOW_MS past its release
+pub fn snapback(samples: &[TimedOffset]) -> Option<Snapback> {
+    let release = release_index(samples)?;
+    let peak = samples[..release]
+        .iter()
+        .max_by(|a, b| a.radius().total_cmp(&b.radius()))?;
+    let (ux, uy) = (peak.dx / peak.radius(), peak.dy / peak.radius());
+    let along = |sample: &TimedOffset| sample.dx * ux + sample.dy * uy;
+    let released = samples[release].ms;
+    let window: Vec<&TimedOffset> = samples[release..]
+        .iter()
+        .take_while(|sample| sample.ms - released <= SNAPBACK_WINDOW_MS)
+        .collect();
+    let overshoot = window
+        .iter()
+        .map(|sample| -along(sample))
+        .fold(0.0, f64::max);
+    // Settled from the reading after the last one that was still away from center
+    let settle_ms = match window
+        .iter()
+        .rposition(|sample| sample.radius() > SETTLED_RADIUS)
+    {
+        None => Some(0.0),
+        Some(last) => window.get(last + 1).map(|sample| sample.ms - released),
+    };
+    let trace = samples
+        .iter()
+        .filter(|sample| {
+            let t = sample.ms - released;
+            (-SNAPBACK_LEAD_MS..=SNAPBACK_WINDOW_MS).contains(&t)
+        })
+        .map(|sample| (sample.ms - released, along(sample)))
+        .collect();
+    Some(Snapback {
+        angle: peak.dy.atan2(peak.dx).rem_euclid(std::f64::consts::TAU),
+        overshoot,
+        settle_ms,
+        trace,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controller::{ImuData, ImuSample, StickCalibration, StickData};
+    use std::time::Duration;
+
+    // A full report every 15ms, each with three identical samples
+    fn still_reports(count: u32, gyro: [f32; 3], accel: [f32; 3]) -> Vec<InputReport> {
+        let start = Instant::now();
+        (0..count)
+            .map(|i| InputReport {
+                received: start + Duration::from_millis(15) * i,
+                report_id: 0x30,
+                data: StickData {
+                    imu: ImuData {
+                        samples: [ImuSample { accel, gyro }; 3],
+                    },
+                    ..Default::default()
+                },
+            })
+            .collect()
+    }
+
+    fn assert_close(actual: [f64; 3], expected: [f64; 3]) {
+        for (a, e) in actual.into_iter().zip(expected) {
+            assert!((a - e).abs() < 1e-9, "{actual:?} is not {expected:?}");
+        }
+    }
+
+    #[test]
+    fn constant_bias_gives_steady_drift() {
+        let mut drift = DriftMeasurement::default();
+        assert!(drift.result().is_none());
+        for report in still_reports(2000, [0.5, -0.25, 0.0], [0.0, 0.0, 1.0]) {
+            drift.add(&report);
+        }
+        let result = drift.result().unwrap();
+        assert!((result.seconds - 30.0).abs() < 1e-9);
+        assert_close(result.bias_dps, [0.5, -0.25, 0.0]);
+        assert_close(result.drift_deg_per_min, [30.0, -15.0, 0.0]);
+        // 70 mdps per unit at ±2000dps
+        assert_eq!(result.bias_raw, [7, -4, 0]);
+        assert!((result.coverage - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn gyro_drift_report_saves_and_loads() {
+        let mut drift = DriftMeasurement::default();
+        for report in still_reports(200, [0.5, -0.25, 0.0], [0.0, 0.0, 1.0]) {
+            drift.add(&report);
+        }
+        let report = GyroDriftReport {
+            mac: "98:B6:E9:12:34:56".to_string(),
+            finished: 1_700_000_000,
+            result: drift.result().unwrap(),
+        };
+        let path = std::env::temp_dir().join(format!("gyro-drift-{}.json", std::process::id()));
+        report.save(&path).unwrap();
+        let loaded: GyroDriftReport =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.mac, report.mac);
+        assert_eq!(loaded.finished, report.finished);
+        assert_eq!(loaded.result.bias_raw, report.result.bias_raw);
+        assert_close(loaded.result.drift_deg_per_min, [30.0, -15.0, 0.0]);
+    }
+
+    #[test]
+    fn samples_without_motion_data_are_skipped() {
+        let mut drift = DriftMeasurement::default();
+        // Before the IMU is on, and replies, which carry no motion data
+        for report in still_reports(10, [9.0; 3], [0.0; 3]) {
+            drift.add(&report);
+        }
+        for mut report in still_reports(10, [9.0; 3], [0.0, 0.0, 1.0]) {
+            report.report_id = 0x21;
+            drift.add(&report);
+        }
+        assert!(drift.result().is_none());
+        for report in still_reports(100, [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]) {
+            drift.add(&report);
+        }
+        assert_close(drift.result().unwrap().bias_dps, [1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn missing_reports_lower_the_coverage() {
+        let mut drift = DriftMeasurement::default();
+        for report in still_reports(2000, [0.0; 3], [0.0, 0.0, 1.0])
+            .iter()
+            .step_by(2)
+        {
+            drift.add(report);
+        }
+        let result = drift.result().unwrap();
+        assert!((result.coverage - 0.5).abs() < 0.01, "{}", result.coverage);
+    }
+
+    // Centered at 0x800 with 0x600 of travel each way
+    const CAL: StickCalibration = StickCalibration {
+        xmax: 0xE00,
+        ymax: 0xE00,
+        xcenter: 0x800,
+        ycenter: 0x800,
+        xmin: 0x200,
+        ymin: 0x200,
+    };
+    const DEADZONE: u16 = 150;
+
+    #[test]
+    fn resting_stick_does_not_drift() {
+        assert!(stick_drift(&[], &CAL, DEADZONE).is_none());
+        let drift = stick_drift(&[(0x800, 0x800); 50], &CAL, DEADZONE).unwrap();
+        assert_eq!(drift.mean_offset, 0.0);
+        assert_eq!(drift.peak_offset, 0.0);
+        assert_eq!(drift.peak_output_pct, 0.0);
+        assert_eq!(drift.samples, 50);
+        assert!(!drift.visible());
+    }
+
+    #[test]
+    fn drift_inside_the_deadzone_is_invisible() {
+        // Wobbling 100 either side averages out, but the peak is still seen
+        let drift = stick_drift(&[(0x864, 0x800), (0x79C, 0x800)], &CAL, DEADZONE).unwrap();
+        assert_eq!(drift.mean_offset, 0.0);
+        assert_eq!(drift.peak_offset, 100.0);
+        assert_eq!(drift.peak_output_pct, 0.0);
+        assert!(!drift.visible());
+    }
+
+    #[test]
+    fn drift_past_the_deadzone_is_visible() {
+        // 60 past the deadzone, of the 1386 left before the end of the range
+        let samples = [(0x800 + 210, 0x800), (0x800 + 200, 0x800)];
+        let drift = stick_drift(&samples, &CAL, DEADZONE).unwrap();
+        assert_eq!(drift.mean_offset, 205.0);
+        assert_eq!(drift.peak_offset, 210.0);
+        let expected = 60.0 / 1386.0 * 100.0;
+        assert!(
+            (drift.peak_output_pct - expected).abs() < 0.01,
+            "{}",
+            drift.peak_output_pct
+        );
+        assert!(drift.visible());
+        // Off center diagonally, both axes count
+        let diagonal = stick_drift(&[(0x800 + 210, 0x800 - 210)], &CAL, DEADZONE).unwrap();
+        assert!((diagonal.peak_output_pct - expected * 2f64.sqrt()).abs() < 0.01);
+    }
+
+    // A flick to 1500 along `direction` (radians), let go at 100ms, then `after` for each
+    // 5ms reading from 105ms on, as offsets along the same direction
+    fn flick(direction: f64, after: &[f64]) -> Vec<TimedOffset> {
+        let along = |ms: f64, distance: f64| TimedOffset {
+            ms,
+            dx: distance * direction.cos(),
+            dy: distance * direction.sin(),
+        };
+        let mut samples: Vec<TimedOffset> = (0..20)
+            .map(|i| along(i as f64 * 5.0, (i as f64 * 250.0).min(1500.0)))
+            .collect();
+        samples.push(along(100.0, 600.0));
+        for (i, &distance) in after.iter().enumerate() {
+            samples.push(along(105.0 + i as f64 * 5.0, distance));
+        }
+        samples
+    }
+
+    // Overshoot to 200 past center, then settled from 115ms on
+    fn settling(ringing: usize) -> Vec<f64> {
+        let mut after = vec![-200.0, -150.0];
+        after.extend((0..ringing).map(|i| if i % 2 == 0 { 120.0 } else { -120.0 }));
+        after.push(-50.0);
+        after.resize(after.len() + 70, 0.0);
+        after
+    }
+
+    #[test]
+    fn release_is_the_first_reading_well_inside_the_peak() {
+        let samples = flick(0.0, &settling(0));
+        assert_eq!(release_index(&samples), Some(20));
+        // Never far enough out, or never let go
+        assert_eq!(release_index(&flick(0.0, &[])[..4]), None);
+        assert_eq!(release_index(&flick(0.0, &[])[..20]), None);
+    }
+
+    #[test]
+    fn snapback_measures_overshoot_and_settling() {
+        let snapback = snapback(&flick(0.0, &settling(0))).unwrap();
+        assert_eq!(snapback.angle, 0.0);
+        assert_eq!(snapback.overshoot, 200.0);
+        // The last reading away from center was at 110ms, so settled at 115ms
+        assert_eq!(snapback.settle_ms, Some(15.0));
+        // 50ms before release to the end of the window
+        assert_eq!(snapback.trace.first(), Some(&(-50.0, 1500.0)));
+        assert_eq!(snapback.trace.last().map(|p| p.0), Some(SNAPBACK_WINDOW_MS));
+    }
+
+    #[test]
+    fn snapback_follows_the_flick_direction() {
+        let up = std::f64::consts::FRAC_PI_2;
+        let snapback = snapback(&flick(up, &settling(4))).unwrap();
+        assert!((snapback.angle - up).abs() < 1e-9);
+        assert!((snapback.overshoot - 200.0).abs() < 1e-9);
+        // Ringing until 130ms keeps it from settling until 135ms
+        assert_eq!(snapback.settle_ms, Some(35.0));
+    }
+
+    #[test]
+    fn ringing_to_the_end_never_settles() {
+        let ringing: Vec<f64> = (0..80)
+            .map(|i| if i % 2 == 0 { 150.0 } else { -150.0 })
+            .collect();
+        assert_eq!(snapback(&flick(0.0, &ringing)).unwrap().settle_ms, None);
+    }
+}
@@ -0,0 +1,56 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::OnceLock;
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::Instant;
+
+// Raw HID traffic for bug reports, one line per report:
+// `<seconds since start> <OUT|IN> <hex bytes>`. Lines go to a writer thread over a
+// channel, so logging never blocks the read path.
+static LOG: OnceLock<(Instant, Sender<String>)> = OnceLock::new();
+
+// Start logging to `path`, replacing the file. Only the first call takes effect.
+pub fn start(path: &Path) -> std::io::Result<()> {
+    let file = File::create(path)?;
+    let (tx, rx) = mpsc::channel::<String>();
+    thread::spawn(move || {
+        let mut out = BufWriter::new(file);
+        while let Ok(line) = rx.recv() {
+            let _ = writeln!(out, "{}", line);
+            // Write out whatever is queued, then flush so a crash loses little
+            for line in rx.try_iter() {
+                let _ = writeln!(out, "{}", line);
+            }
+            let _ = out.flush();
+        }
+    });
+    let _ = LOG.set((Instant::now(), tx));
+    Ok(())
+}
+
+pub fn outgoing(bytes: &[u8]) {
+    record("OUT", bytes);
+}
+
+pub fn incoming(bytes: &[u8]) {
+    record("IN", bytes);
+}
+
+fn record(direction: &str, bytes: &[u8]) {
+    let Some((start, tx)) = LOG.get() else {
+        return;
+    };
+    let hex = bytes
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let _ = tx.send(format!(
+        "{:.6} {} {}",
+        start.elapsed().as_secs_f64(),
+        direction,
+        hex
+    ));
+}
@@ -0,0 +1,174 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::controller::{Controller, ControllerType, StickCalibration, StickParameters, StickSide};
+
+// Bumped when a field changes meaning, so older readers can refuse the file
+const FORMAT_VERSION: u32 = 1;
+
+// A controller's stick calibration as decoded values, saved as JSON (.joycal). Meant to
+// be readable when shared for help, and to be imported onto a controller later.
+#[derive(Serialize, Deserialize)]
+pub struct JoycalFile {
+    pub format: u32,
+    pub mac: String,
+    #[serde(with = "type_name")]
+    pub controller_type: ControllerType,
+    pub sticks: Vec<StickRecord>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct StickRecord {
+    pub side: StickSide,
+    pub factory: StickCalibration,
+    // None when the user slot is blank
+    pub user: Option<StickCalibration>,
+    pub parameters: StickParameters,
+}
+
+// Controller types by name, so the file reads "JoyConL" rather than a number
+mod type_name {
+    use crate::controller::ControllerType;
+    use serde::{Deserialize, Deserializer, Serializer, de::Error};
+
+    const NAMES: [(ControllerType, &str); 7] = [
+        (ControllerType::JoyConL, "JoyConL"),
+        (ControllerType::JoyConR, "JoyConR"),
+        (ControllerType::ProController, "ProController"),
+        (ControllerType::N64, "N64"),
+        (ControllerType::Nes, "Nes"),
+        (ControllerType::Snes, "Snes"),
+        (ControllerType::Genesis, "Genesis"),
+    ];
+
+    pub fn serialize<S: Serializer>(t: &ControllerType, s: S) -> Result<S::Ok, S::Error> {
+        let name = NAMES
+            .iter()
+            .find(|(known, _)| known == t)
+            .map(|(_, name)| *name);
+        s.serialize_str(name.unwrap_or("Unsupported"))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<ControllerType, D::Error> {
+        let name = String::deserialize(d)?;
+        NAMES
+            .iter()
+            .find(|(_, known)| *known == name)
+            .map(|(t, _)| *t)
+            .ok_or_else(|| D::Error::custom(format!("unknown controller type {}", name)))
+    }
+}
+
+impl StickRecord {
+    // What the controller actually uses: the user calibration when there is one
+    pub fn effective(&self) -> StickCalibration {
+        self.user.unwrap_or(self.factory)
+    }
+}
+
+// Values are 12-bit, and the center must lie strictly inside the range on both axes
+fn validate_calibration(side: StickSide, which: &str, cal: &StickCalibration) -> Result<()> {
+    let values = [
+        cal.xmin,
+        cal.xcenter,
+        cal.xmax,
+        cal.ymin,
+        cal.ycenter,
+        cal.ymax,
+    ];
+    if values.iter().any(|&v| v > 0xFFF) {
+        return Err(anyhow!(
+            "{:?} stick {} calibration has values above 0xFFF",
+            side,
+            which
+        ));
+    }
+    let inside = |min, center, max| min < center && center < max;
+    if !inside(cal.xmin, cal.xcenter, cal.xmax) || !inside(cal.ymin, cal.ycenter, cal.ymax) {
+        return Err(anyhow!(
+            "{:?} stick {} calibration has its center outside its range",
+            side,
+            which
+        ));
+    }
+    Ok(())
+}
+
+impl JoycalFile {
+    // Read the factory and user calibration and the parameters of every stick
+    pub fn read_from(c: &mut Controller) -> Result<Self> {
+        let mac = c.get_device_info()?.mac_string();
+        let capabilities = c.capabilities();
+        let mut sticks = Vec::new();
+        for (side, present) in [
+            (StickSide::Left, capabilities.has_left_stick),
+            (StickSide::Right, capabilities.has_right_stick),
+        ] {
+            if !present {
+                continue;
+            }
+            sticks.push(StickRecord {
+                side,
+                factory: c.read_factory_stick_calibration(side)?,
+                user: c.read_user_stick_calibration(side)?,
+                parameters: c.read_stick_parameters(side)?,
+            });
+        }
+        if sticks.is_empty() {
+            return Err(anyhow!("This controller has no analog sticks to export"));
+        }
+        Ok(Self {
+            format: FORMAT_VERSION,
+            mac,
+            controller_type: c.get_controller_type(),
+            sticks,
+        })
+    }
+
+    // Load a file and reject anything that couldn't be written as it stands
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+        let file: Self = serde_json::from_str(&json)
+            .map_err(|e| anyhow!("{} is not a valid .joycal file: {}", path.display(), e))?;
+        if file.format > FORMAT_VERSION {
+            return Err(anyhow!(
+                "{} uses format version {}, which needs a newer rustjoycal",
+                path.display(),
+                file.format
+            ));
+        }
+        file.validate()?;
+        Ok(file)
+    }
+
+    fn validate(&self) -> Result<()> {
+        for (i, stick) in self.sticks.iter().enumerate() {
+            if self.sticks[..i].iter().any(|s| s.side == stick.side) {
+                return Err(anyhow!("The {:?} stick appears twice", stick.side));
+            }
+            validate_calibration(stick.side, "factory", &stick.factory)?;
+            if let Some(user) = &stick.user {
+                validate_calibration(stick.side, "user", user)?;
+            }
+            if stick.parameters.deadzone > 0xFFF || stick.parameters.range_ratio > 0xFFF {
+                return Err(anyhow!(
+                    "{:?} stick parameters are out of range (at most 0xFFF)",
+                    stick.side
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn stick(&self, side: StickSide) -> Option<&StickRecord> {
+        self.sticks.iter().find(|s| s.side == side)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(path, json).map_err(|e| anyhow!("Failed to write {}: {}", path.display(), e))
+    }
+}
@@ -1,29 +1,460 @@
+mod backup;
 mod controller;
+mod diagnostics;
+mod hid_log;
+mod joycal;
+mod remap;
+mod replay;
+mod state;
 
-use controller::{Controller, StickCalibration, StickData};
+use controller::{Controller, ControllerInfo, StickCalibration, StickData};
 use gpui::prelude::*;
 use gpui::*;
 use parking_lot::Mutex;
+use std::ffi::{CStr, CString};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use crate::controller::ControllerType;
+use crate::backup::{BackupFileError, SpiBackup};
+
+use crate::controller::{
+    BatteryLevel, BatteryStatus, ButtonState, CALIBRATION_REGIONS, CalibrationTarget,
+    CalibrationWriteError, ConnectionKind, ControllerColors, ControllerError, ControllerType,
+    DEFAULT_RANGE_RATIO, DeviceInfo, FirmwareCompatibility, FirmwareVersion, ImuCalibration,
+    ImuData, ImuSettings, InputReceiver, Operation, OperationContext, PLAYER_LEDS_ALL,
+    PLAYER_LEDS_FIRST, PLAYER_LEDS_FIRST_THREE, PLAYER_LEDS_FIRST_TWO, PLAYER_LEDS_FLASH_ALL,
+    Progress, RegionCheck, SPI_FLASH, SpiWriteError, StickParameters, StickSide,
+    usable_ratio_reference,
+};
+use crate::diagnostics::{
+    DriftMeasurement, DriftResult, GyroDriftReport, SNAPBACK_WINDOW_MS, Snapback, StickDrift,
+    StickDriftReport, TimedOffset,
+};
+use crate::joycal::{JoycalFile, StickRecord};
+use crate::remap::remap_calibrated_axis;
+use crate::state::SavedState;
+
+// Length of the Identify (rumble) vibration
+const RUMBLE_PULSE_MS: u64 = 200;
+
+// Samples per phase after which the HOME LED goes from dim to solid
+const ENOUGH_SAMPLES: u32 = 200;
+
+// Samples each present stick needs before a step can finish. Less than this and the
+// result would still carry the 0xFFF/0 starting values.
+const MIN_SAMPLES: u32 = 100;
+
+// The range step splits the directions around the center into this many sectors, and
+// the stick must have reached MIN_RIM_RADIUS in each before the step can finish
+const COVERAGE_SECTORS: usize = 32;
+const MIN_RIM_RADIUS: f64 = 1024.0;
+// Outer deadzone padding, in raw units from each end of the measured range. The default
+// is for sticks whose rim sweeps weren't measured.
+const DEFAULT_OUTER_PADDING: u16 = 0x050;
+const MAX_OUTER_PADDING: u16 = 0x100;
+const PADDING_SLIDER_STEP: u16 = 0x10;
+// Measured padding never goes below this, to absorb noise the sweeps missed
+const MIN_MEASURED_PADDING: u16 = 0x10;
+// Samples at least this share of a sector's furthest reach count as sweeps along the rim
+const RIM_SHARE: f64 = 0.9;
+
+// Where a resting stick can plausibly report during the center step
+const CENTER_WINDOW: Range<u16> = 0x100..0xF00;
+// How far a stick may move between two polls during the range step before the
+// sample is taken for a glitch; `--max-stick-jump` overrides it
+const DEFAULT_MAX_STICK_JUMP: u16 = 0x300;
+
+// HOME LED brightness (0-15) for each stage of data collection
+const HOME_LED_OFF: u8 = 0x0;
+const HOME_LED_COLLECTING: u8 = 0x3;
+const HOME_LED_ENOUGH: u8 = 0xF;
+const HOME_LED_FADE: u8 = 0x4;
+
+// How long the Identify button keeps the player LEDs flashing
+const IDENTIFY_DURATION: Duration = Duration::from_secs(2);
+
+// Silence in full report mode after which the controller is assumed asleep
+const SLEEP_TIMEOUT: Duration = Duration::from_secs(3);
+
+// Below this the battery is nearly flat, whatever the bar level says
+const LOW_VOLTAGE_MV: u16 = 3300;
+
+// How often the Connect screen re-enumerates devices while waiting for a controller
+const SCAN_INTERVAL: Duration = Duration::from_millis(1500);
+
+// How long the controller has to sit still while motion calibration averages its IMU
+const MOTION_COLLECT_DURATION: Duration = Duration::from_secs(3);
+
+// Choices for how long the gyro drift test runs
+const DRIFT_TEST_DURATIONS: [Duration; 4] = [
+    Duration::from_secs(10),
+    Duration::from_secs(30),
+    Duration::from_secs(60),
+    Duration::from_secs(120),
+];
+const DEFAULT_DRIFT_TEST_DURATION: Duration = Duration::from_secs(30);
+
+// Choices for how long the stick drift test runs
+const STICK_DRIFT_TEST_DURATIONS: [Duration; 4] = [
+    Duration::from_secs(10),
+    Duration::from_secs(20),
+    Duration::from_secs(60),
+    Duration::from_secs(120),
+];
+const DEFAULT_STICK_DRIFT_TEST_DURATION: Duration = Duration::from_secs(20);
+
+// How much of each new reading the smoothed stick dot takes on; lower is steadier but lags
+const SMOOTHING_ALPHAS: [f32; 4] = [0.1, 0.2, 0.3, 0.5];
+const DEFAULT_SMOOTHING_ALPHA: f32 = 0.3;
+
+// Readings kept while waiting for a flick; a hold longer than this only loses its start
+const SNAPBACK_BUFFER_MS: f64 = 2000.0;
+
+// More dropped reports than this during the range sweep may have left gaps in it
+const DROPPED_PACKETS_NOTE: u32 = 10;
+
+// Below this many input reports a second, sweeps start to miss the stick's real extremes
+const LOW_REPORT_RATE_HZ: f32 = 30.0;
+
+// How long the confirm button must be held to write from the controller itself
+const WRITE_HOLD_DURATION: Duration = Duration::from_secs(1);
 
 // App State
 struct CalibrationApp {
-    controller: Option<Arc<Mutex<Controller>>>,
-    device_info: Option<(String, String)>, // Firmware, MAC
+    // Set by --device; pins the app to one hidapi path instead of scanning
+    device_path: Option<CString>,
+    // `--replay <path>`: play back a HID log instead of opening a controller
+    replay: Option<PathBuf>,
+    available_controllers: Vec<ControllerInfo>,
+    selected_controller: Option<usize>,
+    scan_task: Option<Task<()>>,
+    reconnect_task: Option<Task<()>>,
+    identify_task: Option<Task<()>>,
+    connect_task: Option<Task<()>>,
+    // Stage of the connect under way, shown on the Connect screen with a Cancel button
+    connect_progress: Option<&'static str>,
+    // While in the Disconnected step: which controller went away and where to go back to
+    lost_controller: Option<ControllerType>,
+    resume_step: CalibrationStep,
+    asleep: bool,
+    // One handle, or two when a left and right Joy-Con are calibrated together
+    controllers: Vec<Arc<Mutex<Controller>>>,
+    device_info: Vec<DeviceDetails>, // One per connected controller that answered
     controller_type: Option<ControllerType>,
+    paired: bool,
+    // Controller failed the SPI capability check; writing is disabled
+    read_only: bool,
+    connection_kind: Option<ConnectionKind>,
+    // USB link switched to the high baud rate; lasts until the controller is reopened
+    fast_usb: bool,
     has_left: bool,
     has_right: bool,
+    has_imu: bool,
     stick_data: StickData,
+    // Buttons from the previous report, so a held button only acts once
+    prev_buttons: ButtonState,
+    // Confirm went down on a step that writes; the write fires once it's held long enough
+    confirm_held_since: Option<Instant>,
+    motion_data: MotionCalibrationData,
+    motion_started: Option<Instant>,
+    motion_result: Option<ImuCalibration>,
+    drift_duration: Duration,
+    drift_measurement: DriftMeasurement,
+    // Every report during a drift test, rather than the latest one per poll
+    drift_reports: Option<InputReceiver>,
+    // Set while a drift test is running
+    drift_started: Option<Instant>,
+    drift_result: Option<GyroDriftReport>,
+    stick_drift_duration: Duration,
+    // Set while a stick drift test is running
+    stick_drift_started: Option<Instant>,
+    // Resting positions per stick, left then right
+    stick_drift_samples: [Vec<(u16, u16)>; 2],
+    // What each stick's controller applied when the test started, read from SPI, with
+    // its deadzone
+    stick_drift_calibration: Vec<(StickSide, StickCalibration, u16)>,
+    stick_drift_result: Option<StickDriftReport>,
+    // The run before the latest one this session, to compare against after calibrating
+    stick_drift_previous: Option<StickDriftReport>,
+    // Snapback test: the stick being flicked and its stored center
+    snapback_stick: StickSide,
+    snapback_center: (u16, u16),
+    // Every report from the controller with that stick, read from snapback_origin on
+    snapback_reports: Option<InputReceiver>,
+    snapback_origin: Instant,
+    snapback_samples: Vec<TimedOffset>,
+    // Latest flick per direction (see DIRECTION_NAMES), and which one came last
+    snapback_results: [Option<Snapback>; 8],
+    snapback_latest: Option<usize>,
+    // Latest battery report from each connected controller
+    battery: Vec<(ControllerType, BatteryStatus)>,
     calibration_step: CalibrationStep,
     calibration_data: CalibrationData,
+    // Directions reached during the range step, left then right
+    range_coverage: [RangeCoverage; 2],
+    // Padding that still reaches full deflection on each stick's weakest rim sweep, worked
+    // out once the range step ends; None until then, or when the rim wasn't covered
+    measured_padding: [Option<u16>; 2],
     left_result: StickCalibration,
     right_result: StickCalibration,
     left_deadzone: u16,
     right_deadzone: u16,
-    outer_deadzone: bool,
+    // What the center step found; None before it and for imported values
+    center_result: Option<CenterResult>,
+    deadzone_strategy: DeadzoneStrategy,
+    // Outer deadzone set by hand, in raw units; None uses each stick's measured padding
+    padding_override: Option<u16>,
+    // The override as typed, and the focus that routes keys to it
+    padding_text: String,
+    padding_focus: FocusHandle,
+    // Take the final range from the bounding box of all samples, as older versions did
+    legacy_range: bool,
+    // With the outer deadzone, also pull the range in by the stick's circularity error
+    circularity_shrink: bool,
+    // Lopsided axes were cut down to their shorter side on the Review screen
+    symmetric_range: bool,
+    // Write the long-standing fixed range ratio instead of one scaled to each stick's travel
+    pin_range_ratio: bool,
+    // User slots by default so the factory calibration stays as a fallback
+    calibration_target: CalibrationTarget,
+    // Reset to factory calibration was clicked and is waiting for confirmation
+    confirm_factory_reset: bool,
+    // Display-only; kept for the whole session and never applied to written values
+    orientation: Orientation,
+    // Draw the stick dot through a moving average, with the raw reading faint behind it.
+    // Display-only like orientation: collection always takes raw samples.
+    smoothing: bool,
+    smoothing_alpha: f32,
+    // Smoothed left and right stick positions in raw units, unturned
+    smoothed_sticks: [Option<(f32, f32)>; 2],
     error_message: Option<String>,
+    // Last connect failed because another program holds the device; offer a retry
+    device_busy: bool,
+    show_details: bool,
+    show_motion_details: bool,
+    // Last calibrated controller and the auto-connect preference, persisted between runs
+    saved_state: SavedState,
+    // Back up the whole flash rather than just the calibration regions
+    full_backup: bool,
+    // Backup, restore or comparison running on its own thread
+    operation: Option<OperationView>,
+    backup_status: Option<String>,
+    restore: Option<PendingRestore>,
+    // A headerless file picked for restoring, waiting for its start offset
+    raw_import: Option<PathBuf>,
+    // Read-back result of each region the last stick calibration write touched
+    write_checks: Vec<RegionCheck>,
+    // Files holding what the last stick calibration write overwrote
+    write_backups: Vec<PathBuf>,
+    // The same contents kept in memory, one per controller, for undoing the write
+    undo: Vec<SpiBackup>,
+    confirm_undo: bool,
+    undone: bool,
+    // Review's "Preview writes" expander
+    show_write_preview: bool,
+    // Packets recorded by the last write when --dry-run is in effect
+    dry_run_writes: Vec<(u32, Vec<u8>)>,
+    // --allow-any-spi-offset: restores offer every region of a backup
+    allow_any_offset: bool,
+    // --max-stick-jump: the range step's glitch threshold
+    max_stick_jump: u16,
+    color_editor: Option<ColorEditor>,
+    // The last write failed and left some regions changed, so Review offers Undo
+    write_partial: bool,
+    // Freshly read for the Calibration details page
+    calibration_details: Vec<StickRecord>,
+    // The connected controller's first-connect factory snapshot, if there is one
+    factory_snapshot: Option<PathBuf>,
+    // Snapshot list as (MAC, capture time), loaded when the list is opened
+    snapshot_list: Option<Vec<(String, u64)>>,
+    // Set when Review shows values imported from a .joycal file: where they came from,
+    // and a warning if the file belongs to another controller
+    imported_from: Option<PathBuf>,
+    import_warning: Option<String>,
+}
+
+// A long SPI operation under way, shown on the Working step. The Operation itself is
+// owned by `_task`, which polls it and copies its progress here.
+struct OperationView {
+    title: &'static str,
+    progress: Option<Progress>,
+    cancel: Arc<AtomicBool>,
+    _task: Task<()>,
+}
+
+// The color editor's fields, edited as hex text; a field only counts once it holds
+// all six digits
+struct ColorEditor {
+    fields: Vec<(&'static str, String)>,
+    active: usize,
+    focus: FocusHandle,
+}
+
+impl ColorEditor {
+    fn new(colors: ControllerColors, cx: &mut App) -> Self {
+        let hex = |color: [u8; 3]| format!("{:06X}", rgb_hex(color));
+        let mut fields = vec![("Body", hex(colors.body)), ("Buttons", hex(colors.buttons))];
+        if let Some([left, right]) = colors.grips {
+            fields.push(("Left grip", hex(left)));
+            fields.push(("Right grip", hex(right)));
+        }
+        Self {
+            fields,
+            active: 0,
+            focus: cx.focus_handle(),
+        }
+    }
+
+    fn color(&self, index: usize) -> Option<[u8; 3]> {
+        let text = &self.fields.get(index)?.1;
+        if text.len() != 6 {
+            return None;
+        }
+        let value = u32::from_str_radix(text, 16).ok()?;
+        Some([(value >> 16) as u8, (value >> 8) as u8, value as u8])
+    }
+
+    // All fields complete, as colors to write
+    fn colors(&self) -> Option<ControllerColors> {
+        let grips = match self.fields.len() {
+            4 => Some([self.color(2)?, self.color(3)?]),
+            _ => None,
+        };
+        Some(ControllerColors {
+            body: self.color(0)?,
+            buttons: self.color(1)?,
+            grips,
+        })
+    }
+
+    fn key(&mut self, key: &str) {
+        let text = &mut self.fields[self.active].1;
+        match key {
+            "backspace" => {
+                text.pop();
+            }
+            "tab" => self.active = (self.active + 1) % self.fields.len(),
+            _ if key.len() == 1 && key.chars().all(|c| c.is_ascii_hexdigit()) && text.len() < 6 => {
+                text.push_str(&key.to_uppercase())
+            }
+            _ => {}
+        }
+    }
+}
+
+// A backup file picked for restoring, waiting for the user to confirm the summary
+struct PendingRestore {
+    path: PathBuf,
+    // Shared with the thread comparing or restoring it
+    backup: Arc<SpiBackup>,
+    regions: Vec<(Range<u32>, &'static str)>,
+    // The user accepted restoring a backup taken from a different controller
+    allow_other_mac: bool,
+    // Names of the regions that differ from the controller, once compared
+    differences: Option<Vec<&'static str>>,
+}
+
+// Identity of a connected controller, shown on the Connected screen
+struct DeviceDetails {
+    controller_type: ControllerType,
+    // What the device info reply claims; a different answer than the product ID
+    // suggests a clone imitating another controller
+    reported_type: Option<ControllerType>,
+    firmware: FirmwareVersion,
+    mac: String,
+    colors_in_spi: bool,
+    serial: Option<String>,
+    colors: Option<ControllerColors>,
+    paired_hosts: Vec<String>,
+    firmware_compatibility: FirmwareCompatibility,
+    voltage_mv: Option<u16>,
+    imu_factory: Option<ImuCalibration>,
+    // Deadzone and range ratio already on the device, per stick it has
+    stick_params: Vec<(StickSide, StickParameters)>,
+    // Factory calibration slots, per stick it has
+    stick_cal: Vec<(StickSide, StickCalibration)>,
+    // User calibration the console wrote, which overrides the factory slots when present
+    user_stick_cal: Vec<(StickSide, StickCalibration)>,
+}
+
+impl DeviceDetails {
+    // Which stick calibration the Switch will apply, if any was readable
+    fn calibration_in_use(&self) -> Option<&'static str> {
+        match (self.user_stick_cal.is_empty(), self.stick_cal.is_empty()) {
+            (false, false) => {
+                Some("Factory and user calibration present; the Switch uses the user one.")
+            }
+            (false, true) => Some("User calibration only."),
+            (true, false) => Some("Factory calibration only."),
+            (true, true) => None,
+        }
+    }
+
+    fn read(c: &mut Controller, info: DeviceInfo) -> Self {
+        // Don't sit through SPI read retries on a controller that failed the capability check
+        let (serial, colors, paired_hosts) = if c.capabilities().has_spi_flash {
+            (
+                c.get_serial().ok().flatten(),
+                c.get_colors().ok().flatten(),
+                c.get_pairing_info().unwrap_or_default(),
+            )
+        } else {
+            (None, None, Vec::new())
+        };
+        let imu_factory = if c.capabilities().has_spi_flash && c.capabilities().has_imu {
+            c.read_imu_factory_calibration().ok().flatten()
+        } else {
+            None
+        };
+        let mut stick_params = Vec::new();
+        let mut stick_cal = Vec::new();
+        let mut user_stick_cal = Vec::new();
+        if c.capabilities().has_spi_flash {
+            for (side, present) in [
+                (StickSide::Left, c.capabilities().has_left_stick),
+                (StickSide::Right, c.capabilities().has_right_stick),
+            ] {
+                if !present {
+                    continue;
+                }
+                if let Ok(params) = c.read_stick_parameters(side) {
+                    stick_params.push((side, params));
+                }
+                if let Ok(cal) = c.read_factory_stick_calibration(side) {
+                    stick_cal.push((side, cal));
+                }
+                if let Ok(Some(cal)) = c.read_user_stick_calibration(side) {
+                    user_stick_cal.push((side, cal));
+                }
+            }
+        }
+        Self {
+            controller_type: c.get_controller_type(),
+            reported_type: info.reported_type,
+            firmware: info.firmware,
+            mac: info.mac_string(),
+            colors_in_spi: info.colors_in_spi,
+            serial,
+            colors,
+            paired_hosts,
+            firmware_compatibility: c.firmware_compatibility(),
+            voltage_mv: c.get_voltage().ok(),
+            imu_factory,
+            stick_params,
+            stick_cal,
+            user_stick_cal,
+        }
+    }
+}
+
+// How a single Joy-Con is held, for drawing the stick visuals the way the user sees them
+#[derive(Clone, Copy, PartialEq)]
+enum Orientation {
+    Upright,
+    Sideways,
 }
 
 #[derive(Clone, Copy, PartialEq)]
@@ -34,7 +465,48 @@ enum CalibrationStep {
     CalibrateRange,
     OuterDeadzoneChoice,
     Review,
+    // Motion calibration: average the IMU while the controller lies flat, then review
+    MotionCollect,
+    MotionReview,
+    // Measure gyro bias with the controller at rest, without writing anything
+    GyroDrift,
+    // Measure how far untouched sticks stray from their stored center
+    StickDrift,
+    // Measure how far sticks overshoot center after a flick, and how fast they settle
+    Snapback,
     Done,
+    // A controller vanished mid-session; waiting for it to come back
+    Disconnected,
+    // A long SPI operation (backup, restore or comparison) with its progress bar
+    Working,
+    // Editing the body, button and grip colors stored in SPI
+    Colors,
+    // Factory and user stick calibration side by side
+    CalibrationDetails,
+}
+
+impl CalibrationStep {
+    // One more LED for each stick step, flashing while the result waits to be written
+    fn player_leds(self) -> u8 {
+        match self {
+            CalibrationStep::CalibrateCenter => PLAYER_LEDS_FIRST,
+            CalibrationStep::CalibrateRange => PLAYER_LEDS_FIRST_TWO,
+            CalibrationStep::OuterDeadzoneChoice => PLAYER_LEDS_FIRST_THREE,
+            CalibrationStep::Review | CalibrationStep::MotionReview => PLAYER_LEDS_FLASH_ALL,
+            _ => PLAYER_LEDS_ALL,
+        }
+    }
+}
+
+// Which glitch filter CalibrationData applies
+#[derive(Default, Clone, Copy, PartialEq)]
+enum CollectionPhase {
+    #[default]
+    Center,
+    // Samples moving further than `max_jump` on an axis since the last report are dropped
+    Range {
+        max_jump: u16,
+    },
 }
 
 #[derive(Default, Clone)]
@@ -51,8 +523,36 @@ struct CalibrationData {
     center_ly: u16,
     center_rx: u16,
     center_ry: u16,
-    deadzone_l: u16,
-    deadzone_r: u16,
+    slack_l: CenterSlack,
+    slack_r: CenterSlack,
+    // Spread of the kept samples, left then right, updated as each one arrives
+    noise: [CenterNoise; 2],
+    samples: u32,
+    phase: CollectionPhase,
+    // Samples dropped as glitches, over both sticks
+    rejected: u32,
+    // The last report per stick, kept or not, for the range step's jump check
+    previous: [Option<(u16, u16)>; 2],
+    // Samples kept per stick (left, right), and running totals per axis (lx, ly, rx,
+    // ry) for the mean center
+    accepted: [u32; 2],
+    sums: [u64; 4],
+    // The furthest resting positions per direction, left then right, since the deadzone
+    // is measured against the latest center
+    extremes: [SlackExtremes; 2],
+}
+
+fn controller_type_name(controller_type: ControllerType) -> &'static str {
+    match controller_type {
+        ControllerType::JoyConL => "Switch Joy-Con (L)",
+        ControllerType::JoyConR => "Switch Joy-Con (R)",
+        ControllerType::ProController => "Switch Pro Controller",
+        ControllerType::N64 => "NSO N64 Controller",
+        ControllerType::Nes => "NSO NES Controller",
+        ControllerType::Snes => "NSO SNES Controller",
+        ControllerType::Genesis => "NSO Genesis Controller",
+        ControllerType::Switch2(model) => model,
+    }
 }
 
 fn euclidean_distance(p1_x: f64, p1_y: f64, p2_x: f64, p2_y: f64) -> f64 {
@@ -76,285 +576,4763 @@ impl CalibrationData {
             center_ly: 0,
             center_rx: 0,
             center_ry: 0,
-            deadzone_l: 0,
-            deadzone_r: 0,
-        }
-    }
-
-    fn update(&mut self, data: &StickData) {
-        self.min_lx = self.min_lx.min(data.lx);
-        self.max_lx = self.max_lx.max(data.lx);
-        self.min_ly = self.min_ly.min(data.ly);
-        self.max_ly = self.max_ly.max(data.ly);
-        self.min_rx = self.min_rx.min(data.rx);
-        self.max_rx = self.max_rx.max(data.rx);
-        self.min_ry = self.min_ry.min(data.ry);
-        self.max_ry = self.max_ry.max(data.ry);
-        self.center_lx = (self.max_lx + self.min_lx) / 2;
-        self.center_ly = (self.max_ly + self.min_ly) / 2;
-        self.center_rx = (self.max_rx + self.min_rx) / 2;
-        self.center_ry = (self.max_ry + self.min_ry) / 2;
-        self.deadzone_l = {
-            (euclidean_distance(
-                self.min_lx as f64,
-                self.min_ly as f64,
-                self.max_lx as f64,
-                self.max_ly as f64,
-            ) / 2.0) as u16
-        };
-        self.deadzone_r = {
-            (euclidean_distance(
-                self.min_rx as f64,
-                self.min_ry as f64,
-                self.max_rx as f64,
-                self.max_ry as f64,
-            ) / 2.0) as u16
-        };
+            slack_l: CenterSlack::default(),
+            slack_r: CenterSlack::default(),
+            noise: Default::default(),
+            samples: 0,
+            phase: CollectionPhase::Center,
+            rejected: 0,
+            previous: [None; 2],
+            accepted: [0; 2],
+            sums: [0; 4],
+            extremes: Default::default(),
+        }
     }
-}
 
-impl CalibrationApp {
-    fn new(_cx: &mut Context<Self>) -> Self {
+    // Every present stick has enough kept samples to derive a calibration from
+    fn has_enough_samples(&self, present: [bool; 2]) -> bool {
+        present
+            .iter()
+            .zip(self.accepted)
+            .all(|(&present, accepted)| !present || accepted >= MIN_SAMPLES)
+    }
+
+    // Collection for the range step, which filters glitches by jump size instead
+    fn for_range(max_jump: u16) -> Self {
         Self {
-            controller: None,
-            device_info: None,
-            controller_type: None,
-            has_left: false,
-            has_right: false,
-            stick_data: StickData::default(),
-            calibration_step: CalibrationStep::Connect,
-            calibration_data: CalibrationData::new(),
-            left_result: StickCalibration::default(),
-            right_result: StickCalibration::default(),
-            left_deadzone: 0,
-            right_deadzone: 0,
-            outer_deadzone: false,
-            error_message: None,
+            phase: CollectionPhase::Range { max_jump },
+            ..Self::new()
         }
     }
 
-    fn connect(&mut self, _cx: &mut Context<Self>) {
-        match Controller::connect() {
-            Ok(c) => {
-                let info = c.get_device_info().ok();
-                self.controller_type = Some(c.get_controller_type());
-                self.controller = Some(Arc::new(Mutex::new(c)));
-                self.device_info = info;
-                self.calibration_step = CalibrationStep::Connected;
-
-                if self.controller_type == Some(ControllerType::JoyConL) {
-                    self.has_left = true;
-                } else if self.controller_type == Some(ControllerType::JoyConR) {
-                    self.has_right = true;
-                } else if self.controller_type == Some(ControllerType::ProController) {
-                    self.has_left = true;
-                    self.has_right = true;
-                }
-
-                self.error_message = None;
+    // `present` says which of the left and right sticks exist; absent ones read as zero.
+    // Returns which sticks' samples were kept.
+    fn update(&mut self, data: &StickData, present: [bool; 2]) -> [bool; 2] {
+        self.samples += 1;
+        let kept = [
+            present[0] && self.plausible(0, (data.lx, data.ly)),
+            present[1] && self.plausible(1, (data.rx, data.ry)),
+        ];
+        if kept[0] {
+            self.min_lx = self.min_lx.min(data.lx);
+            self.max_lx = self.max_lx.max(data.lx);
+            self.min_ly = self.min_ly.min(data.ly);
+            self.max_ly = self.max_ly.max(data.ly);
+            (self.center_lx, self.center_ly) = self.add_to_mean(0, (data.lx, data.ly));
+            // Slack and noise describe the resting stick, so only the center step has them
+            if self.phase == CollectionPhase::Center {
+                self.extremes[0].add((data.lx, data.ly));
+                self.slack_l =
+                    CenterSlack::measure(self.extremes[0].points(), self.center_lx, self.center_ly);
+                self.noise[0].add(data.lx, data.ly);
             }
-            Err(e) => {
-                self.error_message = Some(format!("Failed to connect: {}", e));
+        }
+        if kept[1] {
+            self.min_rx = self.min_rx.min(data.rx);
+            self.max_rx = self.max_rx.max(data.rx);
+            self.min_ry = self.min_ry.min(data.ry);
+            self.max_ry = self.max_ry.max(data.ry);
+            (self.center_rx, self.center_ry) = self.add_to_mean(1, (data.rx, data.ry));
+            if self.phase == CollectionPhase::Center {
+                self.extremes[1].add((data.rx, data.ry));
+                self.slack_r =
+                    CenterSlack::measure(self.extremes[1].points(), self.center_rx, self.center_ry);
+                self.noise[1].add(data.rx, data.ry);
             }
         }
+        kept
     }
 
-    fn start_calibration(&mut self, _cx: &mut Context<Self>) {
-        if let Some(c) = &self.controller {
-            if let Err(e) = c.lock().enable_standard_input() {
-                self.error_message = Some(format!("Failed to enable input: {}", e));
-                return;
-            }
+    // Min and max never recover from a corrupted report decoding to 0x000 or 0xFFF, so
+    // those are dropped: outside the window while centered, or too far from the last
+    // report while spinning. The last report counts whether or not it was kept, so a
+    // genuinely fast move only loses one sample.
+    fn plausible(&mut self, stick: usize, (x, y): (u16, u16)) -> bool {
+        let plausible = match self.phase {
+            CollectionPhase::Center => CENTER_WINDOW.contains(&x) && CENTER_WINDOW.contains(&y),
+            CollectionPhase::Range { max_jump } => self.previous[stick]
+                .is_none_or(|(px, py)| x.abs_diff(px) <= max_jump && y.abs_diff(py) <= max_jump),
+        };
+        self.previous[stick] = Some((x, y));
+        if !plausible {
+            self.rejected += 1;
         }
-        self.calibration_step = CalibrationStep::CalibrateCenter;
-        self.calibration_data = CalibrationData::new(); // Reset collected data
+        plausible
     }
 
-    fn next_step(&mut self, _cx: &mut Context<Self>) {
-        match self.calibration_step {
-            CalibrationStep::CalibrateCenter => {
-                // Calculate Centers and Deadzones
-                let data = &self.calibration_data;
+    // The mean of every kept sample, so one noisy report barely moves the center; the
+    // midpoint of the extremes would shift by half its error
+    fn add_to_mean(&mut self, stick: usize, (x, y): (u16, u16)) -> (u16, u16) {
+        self.accepted[stick] += 1;
+        self.sums[stick * 2] += x as u64;
+        self.sums[stick * 2 + 1] += y as u64;
+        let mean = |sum: u64| (sum as f64 / self.accepted[stick] as f64).round() as u16;
+        (mean(self.sums[stick * 2]), mean(self.sums[stick * 2 + 1]))
+    }
+}
+
+// How far the stick got from center in each direction during the range step. Min and
+// max alone can't tell that a quick spin cut the diagonals short.
+#[derive(Default, Clone)]
+struct RangeCoverage {
+    center: (u16, u16),
+    reach: [f64; COVERAGE_SECTORS],
+    // Every sample's distance from center, by sector, for how consistent the sweeps were
+    radii: [Vec<f64>; COVERAGE_SECTORS],
+}
 
-                self.left_result.xcenter = (data.min_lx + data.max_lx) / 2;
-                self.left_result.ycenter = (data.min_ly + data.max_ly) / 2;
-                self.right_result.xcenter = (data.min_rx + data.max_rx) / 2;
-                self.right_result.ycenter = (data.min_ry + data.max_ry) / 2;
+impl RangeCoverage {
+    fn new(center: (u16, u16)) -> Self {
+        Self {
+            center,
+            reach: [0.0; COVERAGE_SECTORS],
+            radii: Default::default(),
+        }
+    }
 
-                self.left_deadzone = (data.max_lx - data.min_lx) / 2;
-                self.right_deadzone = (data.max_rx - data.min_rx) / 2;
+    fn add(&mut self, x: u16, y: u16) {
+        let dx = x as f64 - self.center.0 as f64;
+        let dy = y as f64 - self.center.1 as f64;
+        let turns = (dy.atan2(dx) / std::f64::consts::TAU).rem_euclid(1.0);
+        let sector = (turns * COVERAGE_SECTORS as f64) as usize % COVERAGE_SECTORS;
+        self.reach[sector] = self.reach[sector].max(dx.hypot(dy));
+        self.radii[sector].push(dx.hypot(dy));
+    }
 
-                self.calibration_step = CalibrationStep::CalibrateRange;
-                self.calibration_data = CalibrationData::new(); // Reset for range
-            }
-            CalibrationStep::CalibrateRange => {
-                self.calibration_step = CalibrationStep::OuterDeadzoneChoice;
-            }
-            _ => {}
+    // How inconsistent the sweeps along the rim were: in the worst sector, the gap between
+    // the furthest sample and the 5th percentile of the samples near the rim
+    fn rim_spread(&self) -> Option<f64> {
+        if self.missing() != 0 {
+            return None;
         }
+        let spread = |sector: usize| {
+            let reach = self.reach[sector];
+            let mut rim: Vec<f64> = self.radii[sector]
+                .iter()
+                .copied()
+                .filter(|&radius| radius >= reach * RIM_SHARE)
+                .collect();
+            rim.sort_by(f64::total_cmp);
+            reach - rim[(rim.len() - 1) * 5 / 100]
+        };
+        Some((0..COVERAGE_SECTORS).map(spread).fold(0.0, f64::max))
     }
 
-    fn set_outer_deadzone(&mut self, enable: bool, _cx: &mut Context<Self>) {
-        self.outer_deadzone = enable;
+    fn covered(&self, sector: usize) -> bool {
+        self.reach[sector] >= MIN_RIM_RADIUS
+    }
 
-        // Calculate final ranges
-        let padding = if enable { 0x050 } else { 0x000 };
-        let data = &self.calibration_data; // This is the data from CalibrateRange
+    fn missing(&self) -> usize {
+        (0..COVERAGE_SECTORS).filter(|&s| !self.covered(s)).count()
+    }
+
+    // Only meaningful once the stick has reached the rim all the way round
+    fn circularity_error(&self) -> Option<f64> {
+        (self.missing() == 0).then(|| circularity_error(&self.reach))
+    }
+
+    // How far the stick reached along each axis direction: right, up, left, down. Each
+    // direction lies on the boundary of two sectors, so it takes the further of the pair.
+    fn cardinal_reach(&self) -> Option<[f64; 4]> {
+        (self.missing() == 0).then(|| {
+            std::array::from_fn(|k| {
+                let sector = k * COVERAGE_SECTORS / 4;
+                let before = (sector + COVERAGE_SECTORS - 1) % COVERAGE_SECTORS;
+                self.reach[sector].max(self.reach[before])
+            })
+        })
+    }
 
-        self.left_result.xmin = data.min_lx.saturating_add(padding).min(0xFFF);
-        self.left_result.ymin = data.min_ly.saturating_add(padding).min(0xFFF);
-        self.left_result.xmax = data.max_lx.saturating_sub(padding).max(0);
-        self.left_result.ymax = data.max_ly.saturating_sub(padding).max(0);
+    fn corners(&self) -> Option<[GateCorner; 8]> {
+        (self.missing() == 0).then(|| octagon_corners(&self.reach))
+    }
 
-        self.right_result.xmin = data.min_rx.saturating_add(padding).min(0xFFF);
-        self.right_result.ymin = data.min_ry.saturating_add(padding).min(0xFFF);
-        self.right_result.xmax = data.max_rx.saturating_sub(padding).max(0);
-        self.right_result.ymax = data.max_ry.saturating_sub(padding).max(0);
+    // Where a corner sits, in raw stick units
+    fn corner_point(&self, corner: GateCorner) -> (u16, u16) {
+        let at = |center: u16, offset: f64| (center as f64 + offset).clamp(0.0, 4095.0) as u16;
+        (
+            at(self.center.0, corner.radius * corner.angle.cos()),
+            at(self.center.1, corner.radius * corner.angle.sin()),
+        )
+    }
 
-        self.calibration_step = CalibrationStep::Review;
+    // The middle of a sector at the required radius, in raw stick units
+    fn marker(&self, sector: usize) -> (u16, u16) {
+        let angle = (sector as f64 + 0.5) / COVERAGE_SECTORS as f64 * std::f64::consts::TAU;
+        let at = |center: u16, offset: f64| (center as f64 + offset).clamp(0.0, 4095.0) as u16;
+        (
+            at(self.center.0, MIN_RIM_RADIUS * angle.cos()),
+            at(self.center.1, MIN_RIM_RADIUS * angle.sin()),
+        )
     }
+}
 
-    fn write_calibration(&mut self, _cx: &mut Context<Self>) {
-        if let Some(c) = &self.controller {
-            let mut c = c.lock();
-            match c.write_calibration_to_device(
-                self.left_result,
-                self.right_result,
-                self.left_deadzone,
-                self.right_deadzone,
-                false,
-            ) {
-                Ok(_) => self.calibration_step = CalibrationStep::Done,
-                Err(e) => self.error_message = Some(format!("Failed to write: {}", e)),
+// One corner of an octagonal gate: its direction in radians from +X, and its radius
+#[derive(Clone, Copy)]
+struct GateCorner {
+    angle: f64,
+    radius: f64,
+}
+
+// Corners whose radius differs from the opposite corner's by more than this share
+// are reported as lopsided, the usual sign of a collapsed gate corner
+const CORNER_MISMATCH: f64 = 0.10;
+
+// The eight corners of an octagonal gate from per-sector rim radii. The corners are a
+// quarter of the sectors apart, so first find the phase whose every fourth sector
+// reaches furthest overall, then take the furthest sector in each window around those.
+// Ties go to the lower sector, which keeps the result deterministic.
+fn octagon_corners(reach: &[f64; COVERAGE_SECTORS]) -> [GateCorner; 8] {
+    const SPACING: usize = COVERAGE_SECTORS / 8;
+    let sector = |i: usize| i % COVERAGE_SECTORS;
+    let phase_reach = |phase: usize| {
+        (0..8)
+            .map(|k| reach[sector(phase + k * SPACING)])
+            .sum::<f64>()
+    };
+    let mut phase = 0;
+    for candidate in 1..SPACING {
+        if phase_reach(candidate) > phase_reach(phase) {
+            phase = candidate;
+        }
+    }
+    std::array::from_fn(|k| {
+        // A window of SPACING sectors, starting one before the expected corner
+        let start = phase + k * SPACING + COVERAGE_SECTORS - 1;
+        let mut best = sector(start);
+        for i in start + 1..start + SPACING {
+            if reach[sector(i)] > reach[best] {
+                best = sector(i);
             }
         }
+        GateCorner {
+            angle: (best as f64 + 0.5) / COVERAGE_SECTORS as f64 * std::f64::consts::TAU,
+            radius: reach[best],
+        }
+    })
+}
+
+// Indices of corners that reach noticeably less than the corner opposite them
+fn short_corners(corners: &[GateCorner; 8]) -> Vec<usize> {
+    (0..8)
+        .filter(|&k| {
+            let (own, opposite) = (corners[k].radius, corners[(k + 4) % 8].radius);
+            opposite > 0.0 && (opposite - own) / opposite > CORNER_MISMATCH
+        })
+        .collect()
+}
+
+// The eight compass directions, counterclockwise from +X
+const DIRECTION_NAMES: [&str; 8] = [
+    "right",
+    "up-right",
+    "up",
+    "up-left",
+    "left",
+    "down-left",
+    "down",
+    "down-right",
+];
+
+// Nearest of the eight directions to an angle, as an index into DIRECTION_NAMES
+fn direction_index(angle: f64) -> usize {
+    (angle / std::f64::consts::TAU * 8.0).round() as usize % 8
+}
+
+// Rough direction of an angle, for naming corners
+fn direction_name(angle: f64) -> &'static str {
+    DIRECTION_NAMES[direction_index(angle)]
+}
+
+// How far from round a stick's travel is: the spread between the furthest and
+// nearest rim radius, as a share of the mean radius. 0 for a perfect circle.
+fn circularity_error(radii: &[f64]) -> f64 {
+    let max = radii.iter().copied().fold(f64::MIN, f64::max);
+    let min = radii.iter().copied().fold(f64::MAX, f64::min);
+    let mean = radii.iter().sum::<f64>() / radii.len() as f64;
+    if mean > 0.0 { (max - min) / mean } else { 0.0 }
+}
+
+// How far a stick travelled from `center`: right, up, left, down. The bounding box of all
+// samples, `bounds` as (min, max), overstates any direction the stick only got near by
+// way of a neighbouring corner, so by default each direction ends where the stick
+// actually reached. The bounding box is used in legacy mode, and until every sector has
+// been reached. `shrink` pulls the reach in by the circularity error.
+fn range_extents(
+    coverage: &RangeCoverage,
+    ((min_x, min_y), (max_x, max_y)): ((u16, u16), (u16, u16)),
+    center: (u16, u16),
+    legacy: bool,
+    shrink: bool,
+) -> [u16; 4] {
+    let Some(reach) = coverage.cardinal_reach().filter(|_| !legacy) else {
+        return [
+            max_x.saturating_sub(center.0),
+            max_y.saturating_sub(center.1),
+            center.0.saturating_sub(min_x),
+            center.1.saturating_sub(min_y),
+        ];
+    };
+    let scale = match coverage.circularity_error() {
+        Some(error) if shrink => 1.0 - error,
+        _ => 1.0,
+    };
+    reach.map(|radius| (radius * scale).round().clamp(0.0, 4095.0) as u16)
+}
+
+// A calibration around the center step's center, reaching `extents` (right, up, left,
+// down) from it less the outer deadzone padding
+fn compose_calibration(
+    (cx, cy): (u16, u16),
+    [right, up, left, down]: [u16; 4],
+    padding: u16,
+) -> StickCalibration {
+    StickCalibration {
+        xcenter: cx,
+        ycenter: cy,
+        xmin: cx.saturating_sub(left).saturating_add(padding).min(0xFFF),
+        ymin: cy.saturating_sub(down).saturating_add(padding).min(0xFFF),
+        xmax: (cx + right).min(0xFFF).saturating_sub(padding),
+        ymax: (cy + up).min(0xFFF).saturating_sub(padding),
     }
+}
 
-    fn update_stick_data(&mut self, cx: &mut Context<Self>) {
-        if let Some(c) = &self.controller {
-            // Non-blocking read (or very fast)
-            // We modified Controller::read_stick_data to timeout 20ms, let's assume it's fine for now
-            // or I should update controller.rs to 0ms.
-            // But I'll leave as is for now, 20ms might be slightly noticeable but OK.
-            let res = c.lock().read_stick_data();
-            if let Ok(data) = res {
-                self.stick_data = data;
-
-                if self.calibration_step == CalibrationStep::CalibrateCenter
-                    || self.calibration_step == CalibrationStep::CalibrateRange
-                {
-                    self.calibration_data.update(&data);
-                    cx.notify();
-                } else if self.calibration_step == CalibrationStep::Connected
-                    || self.calibration_step == CalibrationStep::Review
-                    || self.calibration_step == CalibrationStep::Done
-                {
-                    cx.notify();
-                }
-            }
+// What the center step found, kept apart from the range step's samples so the final
+// calibration is always composed around it
+#[derive(Clone)]
+struct CenterResult {
+    // Mean resting position, slack and noise, left then right
+    centers: [(u16, u16); 2],
+    slack: [CenterSlack; 2],
+    noise: [CenterNoise; 2],
+}
+
+impl CenterResult {
+    fn from_center_step(data: &CalibrationData) -> Self {
+        CenterResult {
+            centers: [
+                (data.center_lx, data.center_ly),
+                (data.center_rx, data.center_ry),
+            ],
+            slack: [data.slack_l, data.slack_r],
+            noise: data.noise.clone(),
         }
     }
 }
 
-// Visual components
-fn stick_deadzone_visual(
-    _cx: &Context<CalibrationApp>,
+// How far a stick rested from center during the center step, along each axis and in
+// any direction
+#[derive(Default, Clone, Copy)]
+struct CenterSlack {
     x: u16,
     y: u16,
-    min_x: u16,
-    max_x: u16,
-    min_y: u16,
-    max_y: u16,
-    center_x: u16,
-    center_y: u16,
-    deadzone: u16,
-    label: &str,
-) -> impl IntoElement {
-    let size = 255.0;
-    let raw_x_pct = x as f32 / 4095.0;
-    let raw_y_pct = 1.0 - (y as f32 / 4095.0);
-    let min_x_pct = min_x as f32 / 4095.0;
-    let max_x_pct = max_x as f32 / 4095.0;
-    let min_y_pct = min_y as f32 / 4095.0;
-    let max_y_pct = max_y as f32 / 4095.0;
-    let dz_pct = (deadzone as f32 / 4095.0) * 2.0;
-    let cx_pct = center_x as f32 / 4095.0;
-    let cy_pct = center_y as f32 / 4095.0;
+    // The furthest from center in any direction. Half the bounding box diagonal
+    // overshoots this whenever the slack isn't a square.
+    radial: u16,
+}
 
-    div()
-        .flex()
-        .flex_col()
-        .items_center()
-        .child(label.to_string())
-        .child(
-            div()
-                .size(px(255.0))
-                .bg(rgb(0x222222))
-                .border_0()
-                .relative()
-                // Deadzone viz
-                .child(
-                    div()
-                        .absolute()
-                        .size(px(dz_pct) * size)
-                        .left(px((cx_pct) * size) - px(dz_pct * size / 2.0))
-                        .top(px((1.0 - cy_pct) * size) - px(dz_pct * size / 2.0))
-                        .rounded_full()
-                        .bg(rgba(0xFF00FF88)),
-                )
-                // min/max viz
-                .child(
-                    div()
-                        .bg(rgba(0x0000FF88))
-                        .absolute()
-                        .w(px((max_x_pct - min_x_pct) * size))
-                        .h(px((max_y_pct - min_y_pct) * size))
-                        .left(px((cx_pct - (max_x_pct - min_x_pct) / 2.0) * size))
-                        .top(px((1.0 - cy_pct - (max_y_pct - min_y_pct) / 2.0) * size)),
-                )
-                // Stick Dot
-                .child(
-                    div()
-                        .absolute()
-                        .size(px(2.0))
-                        .bg(rgb(0x00FF00))
-                        .rounded_full()
-                        .left(px(raw_x_pct) * size - px(1.0))
-                        .top(px(raw_y_pct) * size - px(1.0)),
-                ),
-        )
-        .child(format!("X: {:.3}\nY: {:.3}", raw_x_pct, raw_y_pct))
+impl CenterSlack {
+    fn measure(points: impl IntoIterator<Item = (u16, u16)>, center_x: u16, center_y: u16) -> Self {
+        let mut slack = Self::default();
+        let mut radial: f64 = 0.0;
+        for (x, y) in points {
+            slack.x = slack.x.max(x.abs_diff(center_x));
+            slack.y = slack.y.max(y.abs_diff(center_y));
+            radial = radial.max(euclidean_distance(
+                center_x as f64,
+                center_y as f64,
+                x as f64,
+                y as f64,
+            ));
+        }
+        slack.radial = radial.ceil() as u16;
+        slack
+    }
 }
 
-// Visualize stick X Y range
-fn stick_range_visual(
-    _cx: &Context<CalibrationApp>,
-    x: u16,
-    y: u16,
-    min_x: u16,
-    max_x: u16,
-    min_y: u16,
-    max_y: u16,
-    label: &str,
-) -> impl IntoElement {
-    let size = 255.0;
-    let raw_x_pct = x as f32 / 4095.0;
-    let raw_y_pct = 1.0 - (y as f32 / 4095.0);
-    let raw_min_x = min_x as f32 / 4095.0;
-    let raw_min_y = min_y as f32 / 4095.0;
-    let raw_max_x = max_x as f32 / 4095.0;
-    let raw_max_y = max_y as f32 / 4095.0;
+// Slack beyond this many standard deviations of the resting samples is more than sensor
+// noise would explain, so it comes from mechanical play
+const NOISE_SLACK_SIGMAS: f64 = 4.0;
 
-    div()
-        .flex()
-        .flex_col()
-        .items_center()
-        .child(label.to_string())
-        .child(
-            div()
+// Running spread of a stick's resting samples. Variance uses Welford's algorithm, so
+// nothing is summed again per sample. Radial excursions are binned by whole raw units
+// against the mean at the time each sample arrived, which settles within a few samples.
+#[derive(Default, Clone)]
+struct CenterNoise {
+    count: u32,
+    mean: [f64; 2],
+    // Sums of squared differences from the mean, per axis
+    m2: [f64; 2],
+    radial_bins: Vec<u32>,
+}
+
+impl CenterNoise {
+    fn add(&mut self, x: u16, y: u16) {
+        self.count += 1;
+        for (axis, value) in [x, y].into_iter().enumerate() {
+            let value = value as f64;
+            let delta = value - self.mean[axis];
+            self.mean[axis] += delta / self.count as f64;
+            self.m2[axis] += delta * (value - self.mean[axis]);
+        }
+        let radius = (x as f64 - self.mean[0])
+            .hypot(y as f64 - self.mean[1])
+            .round() as usize;
+        if self.radial_bins.len() <= radius {
+            self.radial_bins.resize(radius + 1, 0);
+        }
+        self.radial_bins[radius] += 1;
+    }
+
+    // Population standard deviation of X and Y, in raw units
+    fn std_dev(&self) -> (f64, f64) {
+        if self.count == 0 {
+            return (0.0, 0.0);
+        }
+        let sd = |m2: f64| (m2 / self.count as f64).sqrt();
+        (sd(self.m2[0]), sd(self.m2[1]))
+    }
+
+    // Radius from the mean that 95% of samples stayed within
+    fn radial_p95(&self) -> u16 {
+        let needed = (self.count as f64 * 0.95).ceil() as u32;
+        let mut seen = 0;
+        for (radius, &count) in self.radial_bins.iter().enumerate() {
+            seen += count;
+            if seen >= needed {
+                return radius as u16;
+            }
+        }
+        0
+    }
+
+    // Whether sensor noise alone accounts for the slack
+    fn explains(&self, slack: CenterSlack) -> bool {
+        let (sx, sy) = self.std_dev();
+        slack.radial as f64 <= NOISE_SLACK_SIGMAS * sx.hypot(sy)
+    }
+}
+
+// Directions SlackExtremes keeps a furthest position in
+const SLACK_DIRECTIONS: usize = 32;
+
+// The furthest resting position seen along each of SLACK_DIRECTIONS evenly spread
+// directions, kept up as samples arrive. The sample furthest from any center lies within
+// half a direction step of one of them, so measuring against these alone finds the radial
+// slack to within 0.5% without keeping every sample. Per-axis slack is exact, since the
+// axis directions are among them.
+#[derive(Default, Clone)]
+struct SlackExtremes {
+    points: [Option<(u16, u16)>; SLACK_DIRECTIONS],
+}
+
+impl SlackExtremes {
+    fn add(&mut self, point: (u16, u16)) {
+        for (k, best) in self.points.iter_mut().enumerate() {
+            let angle = k as f64 / SLACK_DIRECTIONS as f64 * std::f64::consts::TAU;
+            let along = |(x, y): (u16, u16)| x as f64 * angle.cos() + y as f64 * angle.sin();
+            if best.is_none_or(|best| along(point) > along(best)) {
+                *best = Some(point);
+            }
+        }
+    }
+
+    fn points(&self) -> impl Iterator<Item = (u16, u16)> + '_ {
+        self.points.iter().flatten().copied()
+    }
+}
+
+// How a stick's slack becomes the single radial deadzone the stick parameters hold
+#[derive(Clone, Copy, PartialEq)]
+enum DeadzoneStrategy {
+    // Covers every position seen, diagonals included
+    Radial,
+    // Covers the worse axis; diagonal slack beyond it may still drift
+    WorseAxis,
+    // Root mean square of both axes, smallest and least certain to cover everything
+    Rms,
+    // Covers 95% of resting positions, leaving out the rarest excursions
+    Percentile,
+}
+
+impl DeadzoneStrategy {
+    fn deadzone(self, slack: CenterSlack, noise: &CenterNoise) -> u16 {
+        match self {
+            DeadzoneStrategy::Radial => slack.radial,
+            DeadzoneStrategy::WorseAxis => slack.x.max(slack.y),
+            DeadzoneStrategy::Rms => (((slack.x as f64).powi(2) + (slack.y as f64).powi(2)) / 2.0)
+                .sqrt()
+                .ceil() as u16,
+            DeadzoneStrategy::Percentile => noise.radial_p95(),
+        }
+    }
+
+    fn next(self) -> Self {
+        match self {
+            DeadzoneStrategy::Radial => DeadzoneStrategy::WorseAxis,
+            DeadzoneStrategy::WorseAxis => DeadzoneStrategy::Rms,
+            DeadzoneStrategy::Rms => DeadzoneStrategy::Percentile,
+            DeadzoneStrategy::Percentile => DeadzoneStrategy::Radial,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            DeadzoneStrategy::Radial => "radial",
+            DeadzoneStrategy::WorseAxis => "fit to worse axis",
+            DeadzoneStrategy::Rms => "fit to RMS of both axes",
+            DeadzoneStrategy::Percentile => "fit to 95% of resting positions",
+        }
+    }
+}
+
+// Running sums of IMU readings while the controller lies flat and still. Kept apart
+// from CalibrationData so motion and stick calibration never affect each other.
+#[derive(Default)]
+struct MotionCalibrationData {
+    accel: [f64; 3],
+    gyro: [f64; 3],
+    samples: u32,
+}
+
+impl MotionCalibrationData {
+    fn update(&mut self, imu: &ImuData) {
+        for sample in &imu.samples {
+            // Reports from before the IMU came on have no gravity at all
+            if sample.accel == [0.0; 3] {
+                continue;
+            }
+            for axis in 0..3 {
+                self.accel[axis] += sample.accel[axis] as f64;
+                self.gyro[axis] += sample.gyro[axis] as f64;
+            }
+            self.samples += 1;
+        }
+    }
+
+    // Averages as origins in raw units at the default ranges. Lying flat, Z reads +1g,
+    // which is gravity rather than offset.
+    fn result(&self) -> Option<ImuCalibration> {
+        if self.samples == 0 {
+            return None;
+        }
+        let ranges = ImuSettings::default();
+        let g = ranges.accel_range.sensitivity() as f64;
+        let dps = ranges.gyro_range.sensitivity() as f64;
+        let raw =
+            |sum: f64, sensitivity: f64| (sum / self.samples as f64 / sensitivity).round() as i16;
+        Some(ImuCalibration {
+            accel_origin: [
+                raw(self.accel[0], g),
+                raw(self.accel[1], g),
+                raw(self.accel[2] - self.samples as f64, g),
+            ],
+            accel_sensitivity: ImuCalibration::DEFAULT_ACCEL_SENSITIVITY,
+            gyro_origin: [
+                raw(self.gyro[0], dps),
+                raw(self.gyro[1], dps),
+                raw(self.gyro[2], dps),
+            ],
+            gyro_sensitivity: ImuCalibration::DEFAULT_GYRO_SENSITIVITY,
+        })
+    }
+}
+
+impl CalibrationApp {
+    fn new(
+        device_path: Option<CString>,
+        replay: Option<PathBuf>,
+        allow_any_offset: bool,
+        max_stick_jump: u16,
+        cx: &mut Context<Self>,
+    ) -> Self {
+        let mut app = Self {
+            device_path,
+            replay,
+            available_controllers: Vec::new(),
+            selected_controller: None,
+            scan_task: None,
+            reconnect_task: None,
+            identify_task: None,
+            connect_task: None,
+            connect_progress: None,
+            lost_controller: None,
+            resume_step: CalibrationStep::Connect,
+            asleep: false,
+            controllers: Vec::new(),
+            device_info: Vec::new(),
+            controller_type: None,
+            paired: false,
+            read_only: false,
+            connection_kind: None,
+            fast_usb: false,
+            has_left: false,
+            has_right: false,
+            has_imu: false,
+            stick_data: StickData::default(),
+            prev_buttons: ButtonState::default(),
+            confirm_held_since: None,
+            motion_data: MotionCalibrationData::default(),
+            motion_started: None,
+            motion_result: None,
+            drift_duration: DEFAULT_DRIFT_TEST_DURATION,
+            drift_measurement: DriftMeasurement::default(),
+            drift_reports: None,
+            drift_started: None,
+            drift_result: None,
+            stick_drift_duration: DEFAULT_STICK_DRIFT_TEST_DURATION,
+            stick_drift_started: None,
+            stick_drift_samples: Default::default(),
+            stick_drift_calibration: Vec::new(),
+            stick_drift_result: None,
+            stick_drift_previous: None,
+            snapback_stick: StickSide::Left,
+            snapback_center: (0x800, 0x800),
+            snapback_reports: None,
+            snapback_origin: Instant::now(),
+            snapback_samples: Vec::new(),
+            snapback_results: Default::default(),
+            snapback_latest: None,
+            battery: Vec::new(),
+            calibration_step: CalibrationStep::Connect,
+            calibration_data: CalibrationData::new(),
+            range_coverage: Default::default(),
+            measured_padding: [None; 2],
+            left_result: StickCalibration::default(),
+            right_result: StickCalibration::default(),
+            left_deadzone: 0,
+            right_deadzone: 0,
+            center_result: None,
+            deadzone_strategy: DeadzoneStrategy::Radial,
+            padding_override: None,
+            padding_text: String::new(),
+            padding_focus: cx.focus_handle(),
+            legacy_range: false,
+            circularity_shrink: false,
+            symmetric_range: false,
+            pin_range_ratio: false,
+            calibration_target: CalibrationTarget::User,
+            confirm_factory_reset: false,
+            orientation: Orientation::Upright,
+            smoothing: true,
+            smoothing_alpha: DEFAULT_SMOOTHING_ALPHA,
+            smoothed_sticks: [None; 2],
+            error_message: None,
+            device_busy: false,
+            show_details: false,
+            show_motion_details: false,
+            saved_state: state::load(),
+            full_backup: false,
+            operation: None,
+            backup_status: None,
+            restore: None,
+            raw_import: None,
+            write_checks: Vec::new(),
+            write_backups: Vec::new(),
+            undo: Vec::new(),
+            confirm_undo: false,
+            undone: false,
+            show_write_preview: false,
+            dry_run_writes: Vec::new(),
+            allow_any_offset,
+            max_stick_jump,
+            imported_from: None,
+            import_warning: None,
+            color_editor: None,
+            write_partial: false,
+            calibration_details: Vec::new(),
+            factory_snapshot: None,
+            snapshot_list: None,
+        };
+        cx.on_app_quit(|this, _| {
+            this.shutdown_controllers(this.saved_state.sleep_on_exit);
+            async {}
+        })
+        .detach();
+        if app.device_path.is_some() || app.replay.is_some() {
+            app.connect(cx);
+        } else {
+            app.rescan();
+            if app.saved_state.auto_connect && app.remembered_controller().is_some() {
+                app.connect(cx);
+            }
+            app.start_scanning(cx);
+        }
+        app
+    }
+
+    fn rescan(&mut self) {
+        match Controller::list() {
+            Ok(list) => {
+                self.set_available_controllers(list);
+                self.error_message = None;
+            }
+            Err(e) => {
+                self.set_available_controllers(Vec::new());
+                self.error_message = Some(format!("Failed to enumerate controllers: {}", e));
+            }
+        }
+    }
+
+    fn set_available_controllers(&mut self, list: Vec<ControllerInfo>) {
+        // Keep the same physical device selected across rescans if it's still there
+        let previous = self
+            .selected_controller
+            .and_then(|i| self.available_controllers.get(i))
+            .map(|info| info.path.clone());
+        // Otherwise prefer the controller calibrated last time
+        let remembered = self.saved_state.last_mac.as_deref().and_then(|mac| {
+            list.iter()
+                .position(|info| info.mac.as_deref() == Some(mac))
+        });
+        self.selected_controller = previous
+            .and_then(|path| list.iter().position(|info| info.path == path))
+            .or(remembered)
+            .or(if list.is_empty() { None } else { Some(0) });
+        self.available_controllers = list;
+    }
+
+    // Index of the last calibrated controller, if it is currently listed and selected
+    fn remembered_controller(&self) -> Option<usize> {
+        let mac = self.saved_state.last_mac.as_deref()?;
+        self.selected_controller.filter(|&i| {
+            self.available_controllers
+                .get(i)
+                .is_some_and(|info| info.mac.as_deref() == Some(mac))
+        })
+    }
+
+    fn toggle_auto_connect(&mut self, cx: &mut Context<Self>) {
+        self.saved_state.auto_connect = !self.saved_state.auto_connect;
+        if let Err(e) = state::save(&self.saved_state) {
+            self.error_message = Some(format!("Failed to save settings: {}", e));
+        }
+        cx.notify();
+    }
+
+    fn toggle_calibration_target(&mut self, cx: &mut Context<Self>) {
+        self.calibration_target = match self.calibration_target {
+            CalibrationTarget::User => CalibrationTarget::Factory,
+            CalibrationTarget::Factory => CalibrationTarget::User,
+        };
+        cx.notify();
+    }
+
+    fn toggle_sleep_on_exit(&mut self, cx: &mut Context<Self>) {
+        self.saved_state.sleep_on_exit = !self.saved_state.sleep_on_exit;
+        if let Err(e) = state::save(&self.saved_state) {
+            self.error_message = Some(format!("Failed to save settings: {}", e));
+        }
+        cx.notify();
+    }
+
+    // Poll for newly attached controllers until we leave the Connect step.
+    // Enumeration runs on the background executor so it never stalls a frame.
+    fn start_scanning(&mut self, cx: &mut Context<Self>) {
+        self.scan_task = Some(cx.spawn(async move |this, cx| {
+            loop {
+                cx.background_executor().timer(SCAN_INTERVAL).await;
+                let list = cx
+                    .background_executor()
+                    .spawn(async { Controller::list() })
+                    .await;
+                let keep_scanning = this
+                    .update(cx, |this, cx| this.handle_scan_result(list, cx))
+                    .unwrap_or(false);
+                if !keep_scanning {
+                    break;
+                }
+            }
+        }));
+    }
+
+    fn handle_scan_result(
+        &mut self,
+        list: anyhow::Result<Vec<ControllerInfo>>,
+        cx: &mut Context<Self>,
+    ) -> bool {
+        if self.calibration_step != CalibrationStep::Connect {
+            return false;
+        }
+        // Enumeration can fail transiently while a device is (dis)appearing; try again next tick
+        let Ok(list) = list else {
+            return true;
+        };
+
+        let appeared = list
+            .iter()
+            .find(|info| {
+                !self
+                    .available_controllers
+                    .iter()
+                    .any(|known| known.path == info.path)
+            })
+            .cloned();
+        self.set_available_controllers(list);
+
+        // Pick up a newly attached controller, unless a connect is already under way
+        if let Some(info) = appeared.filter(|_| self.connect_progress.is_none()) {
+            let target = info.clone();
+            self.start_connect(
+                move || Controller::open(&target),
+                move |this, e| {
+                    // Retrying won't help; keep it listed and say why
+                    if unsupported_model(&e).is_some() {
+                        this.error_message = Some(connect_error_message(&e));
+                        return;
+                    }
+                    // Needs the user to close the other program; select it for the Retry button
+                    if is_device_busy(&e) {
+                        this.selected_controller = this
+                            .available_controllers
+                            .iter()
+                            .position(|known| known.path == info.path);
+                        this.device_busy = true;
+                        this.error_message = Some(connect_error_message(&e));
+                        return;
+                    }
+                    // A half-paired controller often refuses to open or answer. Forget it so the
+                    // next scan sees it as new again and retries, rather than reporting an error.
+                    let remaining = this
+                        .available_controllers
+                        .iter()
+                        .filter(|known| known.path != info.path)
+                        .cloned()
+                        .collect();
+                    this.set_available_controllers(remaining);
+                },
+                cx,
+            );
+        }
+
+        cx.notify();
+        true
+    }
+
+    fn select_controller(&mut self, index: usize, cx: &mut Context<Self>) {
+        self.selected_controller = Some(index);
+        cx.notify();
+    }
+
+    fn connect(&mut self, cx: &mut Context<Self>) {
+        if let Some(recording) = self.replay.clone() {
+            self.start_connect(
+                move || Controller::from_recording(&recording),
+                |this, e| this.error_message = Some(format!("Failed to replay recording: {}", e)),
+                cx,
+            );
+            return;
+        }
+        if let Some(path) = self.device_path.clone() {
+            let target = path.clone();
+            self.start_connect(
+                move || Controller::open_path(&target),
+                move |this, e| {
+                    this.device_busy = is_device_busy(&e);
+                    this.error_message = if unsupported_model(&e).is_some() || is_device_busy(&e) {
+                        Some(connect_error_message(&e))
+                    } else {
+                        Some(format!("Failed to open {}: {}", path.to_string_lossy(), e))
+                    };
+                },
+                cx,
+            );
+            return;
+        }
+
+        let Some(info) = self
+            .selected_controller
+            .and_then(|i| self.available_controllers.get(i))
+            .cloned()
+        else {
+            self.error_message = Some("No controller selected.".to_string());
+            return;
+        };
+
+        self.start_connect(
+            move || Controller::open(&info),
+            |this, e| {
+                this.device_busy = is_device_busy(&e);
+                this.error_message = Some(connect_error_message(&e));
+            },
+            cx,
+        );
+    }
+
+    // Opening and querying a controller can take seconds of retries, so it runs on the
+    // background executor. Each stage is reported through connect_progress, and dropping
+    // connect_task cancels the attempt along with the half-opened Controller it owns.
+    fn start_connect(
+        &mut self,
+        open: impl FnOnce() -> anyhow::Result<Controller> + Send + 'static,
+        on_error: impl FnOnce(&mut Self, anyhow::Error) + 'static,
+        cx: &mut Context<Self>,
+    ) {
+        self.connect_progress = Some("Opening device…");
+        self.error_message = None;
+        // A replay's flash contents aren't a controller's own, so never snapshot them
+        let snapshot = self.replay.is_none();
+        self.connect_task = Some(cx.spawn(async move |this, cx| {
+            let opened = cx.background_executor().spawn(async move { open() }).await;
+            let mut c = match opened {
+                Ok(c) => c,
+                Err(e) => {
+                    let _ = this.update(cx, |this, cx| {
+                        this.connect_progress = None;
+                        on_error(this, e);
+                        cx.notify();
+                    });
+                    return;
+                }
+            };
+
+            let _ = this.update(cx, |this, cx| {
+                this.connect_progress = Some("Querying device info…");
+                cx.notify();
+            });
+            let (mut c, info) = cx
+                .background_executor()
+                .spawn(async move {
+                    let info = c.get_device_info().ok();
+                    (c, info)
+                })
+                .await;
+
+            let _ = this.update(cx, |this, cx| {
+                this.connect_progress = Some("Reading serial, colors and pairings…");
+                cx.notify();
+            });
+            let (c, details) = cx
+                .background_executor()
+                .spawn(async move {
+                    let details = info.map(|info| DeviceDetails::read(&mut c, info));
+                    if snapshot && c.capabilities().has_spi_flash {
+                        let _ = backup::save_factory_snapshot(&mut c);
+                    }
+                    (c, details)
+                })
+                .await;
+
+            let _ = this.update(cx, |this, cx| {
+                this.connect_progress = None;
+                this.set_controller(c, details);
+                cx.notify();
+            });
+        }));
+    }
+
+    // Abandon a connect in progress; the Controller is dropped with the task
+    fn cancel_connect(&mut self, cx: &mut Context<Self>) {
+        self.connect_task = None;
+        self.connect_progress = None;
+        cx.notify();
+    }
+
+    fn set_controller(&mut self, mut c: Controller, details: Option<DeviceDetails>) {
+        self.device_info = details.into_iter().collect();
+        let capabilities = c.capabilities();
+        self.read_only = !capabilities.has_spi_flash;
+        self.has_left = capabilities.has_left_stick;
+        self.has_right = capabilities.has_right_stick;
+        self.has_imu = capabilities.has_imu;
+        self.controller_type = Some(c.get_controller_type());
+        self.connection_kind = Some(c.connection_kind());
+        self.fast_usb = false;
+        self.battery = c
+            .battery()
+            .map(|status| (c.get_controller_type(), status))
+            .into_iter()
+            .collect();
+        // Light up the unit we grabbed so it's obvious which one is being calibrated
+        let _ = c.set_player_leds(PLAYER_LEDS_ALL);
+        self.controllers = vec![Arc::new(Mutex::new(c))];
+        self.device_busy = false;
+        self.paired = false;
+        self.asleep = false;
+        self.factory_snapshot = self
+            .device_info
+            .first()
+            .and_then(|d| backup::snapshot_path(&d.mac))
+            .filter(|path| path.exists());
+        self.stick_data = StickData::default();
+        self.calibration_step = CalibrationStep::Connected;
+        self.error_message = None;
+    }
+
+    // The opposite Joy-Con to the one connected, if a pair can be formed
+    fn pair_partner_type(&self) -> Option<ControllerType> {
+        if self.paired {
+            return None;
+        }
+        match self.controller_type {
+            Some(ControllerType::JoyConL) => Some(ControllerType::JoyConR),
+            Some(ControllerType::JoyConR) => Some(ControllerType::JoyConL),
+            _ => None,
+        }
+    }
+
+    // Open the other half of a Joy-Con pair so both sticks are calibrated in one session
+    fn pair_joycons(&mut self, _cx: &mut Context<Self>) {
+        let Some(wanted) = self.pair_partner_type() else {
+            return;
+        };
+        let info = match Controller::list() {
+            Ok(list) => list.into_iter().find(|info| info.controller_type == wanted),
+            Err(e) => {
+                self.error_message = Some(format!("Failed to enumerate controllers: {}", e));
+                return;
+            }
+        };
+        let Some(info) = info else {
+            self.error_message = Some(format!("No {} found.", controller_type_name(wanted)));
+            return;
+        };
+
+        match Controller::open(&info) {
+            Ok(mut c) => {
+                if let Ok(info) = c.get_device_info() {
+                    self.device_info.push(DeviceDetails::read(&mut c, info));
+                }
+                if self.replay.is_none() && c.capabilities().has_spi_flash {
+                    let _ = backup::save_factory_snapshot(&mut c);
+                }
+                let capabilities = c.capabilities();
+                self.read_only |= !capabilities.has_spi_flash;
+                self.has_left |= capabilities.has_left_stick;
+                self.has_right |= capabilities.has_right_stick;
+                self.has_imu |= capabilities.has_imu;
+                if let Some(status) = c.battery() {
+                    self.battery.push((c.get_controller_type(), status));
+                }
+                let _ = c.set_player_leds(PLAYER_LEDS_ALL);
+                self.controllers.push(Arc::new(Mutex::new(c)));
+                self.paired = true;
+                self.error_message = None;
+            }
+            Err(e) => self.error_message = Some(format!("Failed to connect: {}", e)),
+        }
+    }
+
+    fn device_summary(&self) -> String {
+        if self.device_info.is_empty() {
+            return "Unknown Device".to_string();
+        }
+        let type_name = if self.paired {
+            "Joy-Con Pair"
+        } else {
+            self.controller_type
+                .map(controller_type_name)
+                .unwrap_or("Unknown Controller Type")
+        };
+        let devices = self
+            .device_info
+            .iter()
+            .map(|d| {
+                format!(
+                    "Firmware: {} | MAC: {} | Serial: {}",
+                    d.firmware,
+                    d.mac,
+                    d.serial.as_deref().unwrap_or("None")
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let connection = self
+            .connection_kind
+            .map(|kind| kind.to_string())
+            .unwrap_or_else(|| "Unknown".to_string());
+        format!(
+            "Type: {}\n{} | Connection: {}",
+            type_name, devices, connection
+        )
+    }
+
+    // Non-blocking notes about firmwares with known calibration quirks, and about
+    // controllers that identify as something other than their product ID
+    fn firmware_warnings(&self) -> Div {
+        div()
+            .flex()
+            .flex_col()
+            .items_center()
+            .text_color(rgb(0xFFA500))
+            .children(self.device_info.iter().filter_map(|d| {
+                let warning = match d.firmware_compatibility {
+                    FirmwareCompatibility::LegacyStickParams => {
+                        "uses an old firmware that reads stick parameters differently; update it on a Switch before calibrating"
+                    }
+                    FirmwareCompatibility::IgnoresUserCalibration => {
+                        "runs a firmware known to ignore user calibration"
+                    }
+                    FirmwareCompatibility::Compatible | FirmwareCompatibility::Unknown => {
+                        return None;
+                    }
+                };
+                Some(div().child(format!(
+                    "Warning: this {} (firmware {}) {}.",
+                    controller_type_name(d.controller_type),
+                    d.firmware,
+                    warning
+                )))
+            }))
+            .children(self.device_info.iter().filter_map(|d| {
+                let reported = d.reported_type.filter(|&t| t != d.controller_type)?;
+                Some(div().child(format!(
+                    "Warning: this {} identifies itself as a {}. It may be a clone.",
+                    controller_type_name(d.controller_type),
+                    controller_type_name(reported)
+                )))
+            }))
+    }
+
+    fn toggle_details(&mut self, cx: &mut Context<Self>) {
+        self.show_details = !self.show_details;
+        cx.notify();
+    }
+
+    fn toggle_motion_details(&mut self, cx: &mut Context<Self>) {
+        self.show_motion_details = !self.show_motion_details;
+        cx.notify();
+    }
+
+    // Expandable extra information about each connected controller
+    fn details_section(&self, cx: &Context<Self>) -> Div {
+        let toggle = div()
+            .id("details_toggle")
+            .cursor_pointer()
+            .text_color(rgb(0xAAAAAA))
+            .child(if self.show_details {
+                "Details ▾"
+            } else {
+                "Details ▸"
+            })
+            .on_click(cx.listener(|this, _, _, cx| this.toggle_details(cx)));
+
+        div()
+            .flex()
+            .flex_col()
+            .items_center()
+            .child(toggle)
+            .when(self.show_details, |el| {
+                el.children(self.device_info.iter().map(|d| {
+                    let hosts = if d.paired_hosts.is_empty() {
+                        "none".to_string()
+                    } else {
+                        d.paired_hosts.join(", ")
+                    };
+                    let voltage = d.voltage_mv.map_or("unknown".to_string(), |mv| {
+                        format!("{:.2}V", mv as f32 / 1000.0)
+                    });
+                    div()
+                        .flex()
+                        .flex_col()
+                        .items_center()
+                        .child(format!(
+                            "{} paired with: {}",
+                            controller_type_name(d.controller_type),
+                            hosts
+                        ))
+                        .child(format!("Battery voltage: {}", voltage))
+                        .child(if d.colors_in_spi {
+                            "Colors: shown by the console"
+                        } else {
+                            "Colors: ignored by the console (default gray)"
+                        })
+                }))
+            })
+            .when(
+                self.show_details && self.device_info.iter().any(|d| d.imu_factory.is_some()),
+                |el| el.child(self.motion_details(cx)),
+            )
+    }
+
+    fn request_factory_reset(&mut self, confirm: bool, cx: &mut Context<Self>) {
+        self.confirm_factory_reset = confirm;
+        cx.notify();
+    }
+
+    // Erase the user stick calibration on every connected controller, undoing a bad run
+    fn reset_to_factory_calibration(&mut self, cx: &mut Context<Self>) {
+        self.confirm_factory_reset = false;
+        for c in &self.controllers {
+            let mut c = c.lock();
+            let capabilities = c.capabilities();
+            for (side, present) in [
+                (StickSide::Left, capabilities.has_left_stick),
+                (StickSide::Right, capabilities.has_right_stick),
+            ] {
+                if !present {
+                    continue;
+                }
+                if let Err(e) = c.clear_user_stick_calibration(side) {
+                    self.error_message = Some(format!("Reset failed: {}", e));
+                    cx.notify();
+                    return;
+                }
+            }
+        }
+        for d in &mut self.device_info {
+            d.user_stick_cal.clear();
+        }
+        self.error_message = None;
+        cx.notify();
+    }
+
+    // Reset button, or the confirmation that replaces it once clicked
+    fn factory_reset_section(&self, cx: &Context<Self>) -> Div {
+        if !self.confirm_factory_reset {
+            return div().child(
+                div()
+                    .id("factory_reset_btn")
+                    .p_2()
+                    .bg(rgb(0x555555))
+                    .rounded_md()
+                    .text_color(rgb(0xFFFFFF))
+                    .cursor_pointer()
+                    .child("Reset to factory calibration")
+                    .on_click(cx.listener(|this, _, _, cx| this.request_factory_reset(true, cx))),
+            );
+        }
+        div()
+            .flex()
+            .flex_col()
+            .items_center()
+            .gap_2()
+            .child("Erase the user stick calibration and go back to the factory one?")
+            .child(
+                div()
+                    .flex()
+                    .gap_2()
+                    .child(
+                        div()
+                            .id("factory_reset_confirm_btn")
+                            .p_2()
+                            .bg(rgb(0xE53935))
+                            .rounded_md()
+                            .text_color(rgb(0xFFFFFF))
+                            .cursor_pointer()
+                            .child("Reset")
+                            .on_click(
+                                cx.listener(|this, _, _, cx| this.reset_to_factory_calibration(cx)),
+                            ),
+                    )
+                    .child(
+                        div()
+                            .id("factory_reset_cancel_btn")
+                            .p_2()
+                            .bg(rgb(0x555555))
+                            .rounded_md()
+                            .text_color(rgb(0xFFFFFF))
+                            .cursor_pointer()
+                            .child("Cancel")
+                            .on_click(
+                                cx.listener(|this, _, _, cx| this.request_factory_reset(false, cx)),
+                            ),
+                    ),
+            )
+    }
+
+    // Deadzones stored on the device now, as raw values and a share of the stick's half travel
+    fn current_deadzones(&self) -> Div {
+        div()
+            .flex()
+            .flex_col()
+            .items_center()
+            .text_color(rgb(0xAAAAAA))
+            .children(self.device_info.iter().flat_map(|d| {
+                d.stick_params.iter().map(|(side, params)| {
+                    let stick = match side {
+                        StickSide::Left => "Left",
+                        StickSide::Right => "Right",
+                    };
+                    format!(
+                        "{} stick current deadzone: {} ({:.1}%)",
+                        stick,
+                        params.deadzone,
+                        params.deadzone as f32 / 2048.0 * 100.0
+                    )
+                })
+            }))
+    }
+
+    // What each stick is calibrated to before the wizard changes anything, and which
+    // of the factory and user slots the Switch will actually use
+    fn current_stick_calibration(&self) -> Div {
+        let describe = |kind: &str, side: &StickSide, cal: &StickCalibration| {
+            let stick = match side {
+                StickSide::Left => "Left",
+                StickSide::Right => "Right",
+            };
+            format!(
+                "{} stick {} calibration: center ({}, {}), X {}-{}, Y {}-{}",
+                stick, kind, cal.xcenter, cal.ycenter, cal.xmin, cal.xmax, cal.ymin, cal.ymax
+            )
+        };
+        div()
+            .flex()
+            .flex_col()
+            .items_center()
+            .text_color(rgb(0xAAAAAA))
+            .children(self.device_info.iter().filter_map(|d| {
+                let in_use = d.calibration_in_use()?;
+                Some(
+                    div()
+                        .flex()
+                        .flex_col()
+                        .items_center()
+                        .child(in_use)
+                        .children(
+                            d.stick_cal
+                                .iter()
+                                .map(|(side, cal)| describe("factory", side, cal)),
+                        )
+                        .children(
+                            d.user_stick_cal
+                                .iter()
+                                .map(|(side, cal)| describe("user", side, cal)),
+                        ),
+                )
+            }))
+    }
+
+    // Read both calibration slots again, since the Switch may have written the user one
+    // since connecting
+    fn open_calibration_details(&mut self, cx: &mut Context<Self>) {
+        let Some(controller) = self.controllers.first().cloned() else {
+            return;
+        };
+        match JoycalFile::read_from(&mut controller.lock()) {
+            Ok(file) => {
+                self.calibration_details = file.sticks;
+                self.error_message = None;
+                self.set_step(CalibrationStep::CalibrationDetails);
+            }
+            Err(e) => self.error_message = Some(format!("Couldn't read the calibration: {}", e)),
+        }
+        cx.notify();
+    }
+
+    fn close_calibration_details(&mut self, cx: &mut Context<Self>) {
+        self.calibration_details.clear();
+        self.set_step(CalibrationStep::Connected);
+        cx.notify();
+    }
+
+    fn calibration_details_page(&self, cx: &Context<Self>) -> Div {
+        div()
+            .flex()
+            .flex_col()
+            .items_center()
+            .gap_4()
+            .child("Calibration Details")
+            .children(self.calibration_details.iter().map(calibration_table))
+            .child(
+                div()
+                    .id("close_details_btn")
+                    .p_2()
+                    .bg(rgb(0x555555))
+                    .rounded_md()
+                    .text_color(rgb(0xFFFFFF))
+                    .cursor_pointer()
+                    .child("Back")
+                    .on_click(cx.listener(|this, _, _, cx| this.close_calibration_details(cx))),
+            )
+    }
+
+    // Factory 6-axis calibration, nested under Details since few people need it
+    fn motion_details(&self, cx: &Context<Self>) -> Div {
+        let toggle = div()
+            .id("motion_details_toggle")
+            .cursor_pointer()
+            .text_color(rgb(0xAAAAAA))
+            .child(if self.show_motion_details {
+                "Motion calibration ▾"
+            } else {
+                "Motion calibration ▸"
+            })
+            .on_click(cx.listener(|this, _, _, cx| this.toggle_motion_details(cx)));
+
+        div()
+            .flex()
+            .flex_col()
+            .items_center()
+            .child(toggle)
+            .when(self.show_motion_details, |el| {
+                el.children(self.device_info.iter().filter_map(|d| {
+                    let cal = d.imu_factory?;
+                    let triplet = |[x, y, z]: [i16; 3]| format!("{} {} {}", x, y, z);
+                    Some(
+                        div()
+                            .flex()
+                            .flex_col()
+                            .items_center()
+                            .child(format!(
+                                "{} factory values:",
+                                controller_type_name(d.controller_type)
+                            ))
+                            .child(format!(
+                                "Accel origin: {}  sensitivity: {}",
+                                triplet(cal.accel_origin),
+                                triplet(cal.accel_sensitivity)
+                            ))
+                            .child(format!(
+                                "Gyro origin: {}  sensitivity: {}",
+                                triplet(cal.gyro_origin),
+                                triplet(cal.gyro_sensitivity)
+                            )),
+                    )
+                }))
+            })
+    }
+
+    // Background for a stick visual, tinted with the body color of the controller it belongs to
+    fn stick_background(&self, right: bool) -> u32 {
+        let details = if self.paired {
+            self.device_info
+                .iter()
+                .find(|d| (d.controller_type == ControllerType::JoyConR) == right)
+        } else {
+            self.device_info.first()
+        };
+        details
+            .and_then(|d| d.colors)
+            .map(|colors| tinted_background(colors.body))
+            .unwrap_or(NEUTRAL_STICK_BACKGROUND)
+    }
+
+    // Strip across the top in each connected controller's own colors
+    fn colors_header(&self) -> Div {
+        div()
+            .flex()
+            .w_full()
+            .children(self.device_info.iter().map(|d| {
+                let (body, buttons) = d
+                    .colors
+                    .map(|colors| (rgb_hex(colors.body), rgb_hex(colors.buttons)))
+                    .unwrap_or((NEUTRAL_HEADER_BACKGROUND, 0xFFFFFF));
+                div()
+                    .flex_1()
+                    .p_2()
+                    .bg(rgb(body))
+                    .text_color(rgb(buttons))
+                    .child(controller_type_name(d.controller_type))
+            }))
+    }
+
+    // Drop the dead handle and poll until a controller answers again.
+    // Collected calibration data, the current step and any paired Joy-Con are left untouched.
+    fn start_reconnect(&mut self, index: usize, cx: &mut Context<Self>) {
+        let lost = self.controllers.remove(index);
+        self.lost_controller = Some(lost.lock().get_controller_type());
+        drop(lost);
+        if self.calibration_step != CalibrationStep::Disconnected {
+            self.resume_step = self.calibration_step;
+            self.calibration_step = CalibrationStep::Disconnected;
+        }
+        self.asleep = false;
+        self.error_message = None;
+        self.reconnect(cx);
+    }
+
+    // (Re)start looking for the lost controller, trying straight away
+    fn reconnect(&mut self, cx: &mut Context<Self>) {
+        // With a pair, only the missing half should be picked up again
+        let wanted = self.lost_controller.filter(|_| self.paired);
+        let device_path = self.device_path.clone();
+        self.reconnect_task = Some(cx.spawn(async move |this, cx| {
+            loop {
+                let device_path = device_path.clone();
+                let found = cx
+                    .background_executor()
+                    .spawn(
+                        async move { find_responding_controller(device_path.as_deref(), wanted) },
+                    )
+                    .await;
+                if let Some((controller, info)) = found {
+                    let done = this
+                        .update(cx, |this, cx| this.handle_reconnected(controller, info, cx))
+                        .unwrap_or(true);
+                    if done {
+                        break;
+                    }
+                }
+                cx.background_executor().timer(SCAN_INTERVAL).await;
+            }
+        }));
+    }
+
+    fn handle_reconnected(
+        &mut self,
+        mut controller: Controller,
+        info: DeviceInfo,
+        cx: &mut Context<Self>,
+    ) -> bool {
+        // Never carry one controller's measurements over to another
+        let mac = info.mac_string();
+        if !self.device_info.iter().any(|d| d.mac == mac) {
+            self.error_message = Some(format!(
+                "A different controller ({}) answered. Reconnect the original one or restart.",
+                mac
+            ));
+            cx.notify();
+            return false;
+        }
+
+        // The report mode resets when the controller reconnects
+        if self.resume_step != CalibrationStep::Connected
+            && controller
+                .enable_standard_input()
+                .and_then(|_| controller.enable_imu())
+                .is_err()
+        {
+            return false;
+        }
+        let _ = controller.set_player_leds(self.resume_step.player_leds());
+        self.controllers.push(Arc::new(Mutex::new(controller)));
+        self.lost_controller = None;
+        self.fast_usb = false;
+        self.error_message = None;
+        self.calibration_step = self.resume_step;
+        cx.notify();
+        true
+    }
+
+    fn enable_fast_usb(&mut self, cx: &mut Context<Self>) {
+        for c in &self.controllers {
+            if let Err(e) = c.lock().enable_fast_usb() {
+                self.error_message = Some(format!("Fast USB polling failed: {:#}", e));
+                cx.notify();
+                return;
+            }
+        }
+        self.fast_usb = true;
+        self.error_message = None;
+        cx.notify();
+    }
+
+    fn has_sticks(&self) -> bool {
+        self.has_left || self.has_right
+    }
+
+    fn start_calibration(&mut self, _cx: &mut Context<Self>) {
+        if !self.has_sticks() {
+            self.error_message =
+                Some("This controller has no analog sticks to calibrate.".to_string());
+            return;
+        }
+        for c in &self.controllers {
+            let mut c = c.lock();
+            if let Err(e) = c.enable_standard_input().and_then(|_| c.enable_imu()) {
+                self.error_message = Some(format!("Failed to enable input: {}", e));
+                return;
+            }
+        }
+        self.imported_from = None;
+        self.import_warning = None;
+        self.set_step(CalibrationStep::CalibrateCenter);
+        self.calibration_data = CalibrationData::new(); // Reset collected data
+        self.set_home_leds(HOME_LED_COLLECTING);
+    }
+
+    // Move to another step, mirroring wizard progress on the player LEDs
+    fn set_step(&mut self, step: CalibrationStep) {
+        self.calibration_step = step;
+        for c in &self.controllers {
+            // Not waited on, so a slow ACK never holds up the step change
+            let _ = c.lock().set_player_leds(step.player_leds());
+        }
+    }
+
+    // HOME LED step feedback, on every connected controller that has one
+    fn set_home_leds(&self, brightness: u8) {
+        for c in &self.controllers {
+            let _ = c.lock().set_home_led(brightness, HOME_LED_FADE);
+        }
+    }
+
+    // Fresh range data, with coverage measured around the center just found
+    fn start_range_collection(&mut self) {
+        self.calibration_data = CalibrationData::for_range(self.max_stick_jump);
+        let centers = self
+            .center_result
+            .as_ref()
+            .map_or_else(Default::default, |c| c.centers);
+        self.range_coverage = centers.map(RangeCoverage::new);
+        self.measured_padding = [None; 2];
+    }
+
+    // Every present stick has reached the rim in every direction
+    fn range_covered(&self) -> bool {
+        [self.has_left, self.has_right]
+            .iter()
+            .zip(&self.range_coverage)
+            .all(|(&present, coverage)| !present || coverage.missing() == 0)
+    }
+
+    // One stick's fitted gate corners as drawn, each flagged when it falls short of its
+    // opposite; empty until the stick has been all the way round
+    fn gate_octagon(&self, stick: usize) -> Vec<((u16, u16), bool)> {
+        let coverage = &self.range_coverage[stick];
+        let Some(corners) = coverage.corners() else {
+            return Vec::new();
+        };
+        let short = short_corners(&corners);
+        corners
+            .iter()
+            .enumerate()
+            .map(|(k, &corner)| {
+                let (x, y) = coverage.corner_point(corner);
+                (self.display_point(x, y), short.contains(&k))
+            })
+            .collect()
+    }
+
+    // Sector markers of one stick's coverage ring, as drawn, and whether each is reached
+    fn coverage_ring(&self, stick: usize) -> Vec<((u16, u16), bool)> {
+        let coverage = &self.range_coverage[stick];
+        (0..COVERAGE_SECTORS)
+            .map(|sector| {
+                let (x, y) = coverage.marker(sector);
+                (self.display_point(x, y), coverage.covered(sector))
+            })
+            .collect()
+    }
+
+    fn next_step(&mut self, _cx: &mut Context<Self>) {
+        let collecting = matches!(
+            self.calibration_step,
+            CalibrationStep::CalibrateCenter | CalibrationStep::CalibrateRange
+        );
+        if collecting
+            && !self
+                .calibration_data
+                .has_enough_samples([self.has_left, self.has_right])
+        {
+            return;
+        }
+        if self.calibration_step == CalibrationStep::CalibrateRange && !self.range_covered() {
+            return;
+        }
+        match self.calibration_step {
+            CalibrationStep::CalibrateCenter => {
+                // Calculate Centers and Deadzones
+                let center = CenterResult::from_center_step(&self.calibration_data);
+
+                (self.left_result.xcenter, self.left_result.ycenter) = center.centers[0];
+                (self.right_result.xcenter, self.right_result.ycenter) = center.centers[1];
+
+                self.center_result = Some(center);
+                self.apply_deadzone_strategy();
+
+                self.set_step(CalibrationStep::CalibrateRange);
+                self.start_range_collection();
+                self.reset_packet_stats();
+                self.set_home_leds(HOME_LED_COLLECTING);
+            }
+            CalibrationStep::CalibrateRange => {
+                self.choose_outer_deadzone();
+                self.set_home_leds(HOME_LED_OFF);
+            }
+            _ => {}
+        }
+    }
+
+    fn apply_deadzone_strategy(&mut self) {
+        if let Some(CenterResult {
+            slack: [left, right],
+            noise: [left_noise, right_noise],
+            ..
+        }) = &self.center_result
+        {
+            self.left_deadzone = self.deadzone_strategy.deadzone(*left, left_noise);
+            self.right_deadzone = self.deadzone_strategy.deadzone(*right, right_noise);
+        }
+    }
+
+    fn cycle_deadzone_strategy(&mut self, cx: &mut Context<Self>) {
+        self.deadzone_strategy = self.deadzone_strategy.next();
+        self.apply_deadzone_strategy();
+        cx.notify();
+    }
+
+    // Both axes' slack next to the deadzone chosen from them, for each stick
+    fn deadzone_summary(&self, cx: &Context<Self>) -> Div {
+        let Some(CenterResult {
+            slack: [left, right],
+            noise: [left_noise, right_noise],
+            ..
+        }) = &self.center_result
+        else {
+            return div();
+        };
+        let line = |stick: &str, slack: CenterSlack, noise: &CenterNoise, deadzone: u16| {
+            format!(
+                "{} stick deadzone: {} (slack X {}, Y {}, radial {}; {})",
+                stick,
+                deadzone,
+                slack.x,
+                slack.y,
+                slack.radial,
+                if noise.explains(slack) {
+                    "mostly sensor noise"
+                } else {
+                    "mostly mechanical play"
+                }
+            )
+        };
+        div()
+            .flex()
+            .flex_col()
+            .items_center()
+            .child(
+                div()
+                    .id("deadzone_strategy_toggle")
+                    .cursor_pointer()
+                    .child(format!("Deadzone: {} ▸", self.deadzone_strategy.name()))
+                    .on_click(cx.listener(|this, _, _, cx| this.cycle_deadzone_strategy(cx))),
+            )
+            .when(self.has_left, |el| {
+                el.child(line("Left", *left, left_noise, self.left_deadzone))
+            })
+            .when(self.has_right, |el| {
+                el.child(line("Right", *right, right_noise, self.right_deadzone))
+            })
+    }
+
+    // How round each stick's travel was in the range step, when it was measured
+    fn range_summary(&self) -> Div {
+        let sticks = [
+            ("Left stick", self.has_left),
+            ("Right stick", self.has_right),
+        ];
+        div().flex().flex_col().items_center().children(
+            sticks
+                .into_iter()
+                .zip(&self.range_coverage)
+                .filter(|((_, present), coverage)| {
+                    *present && coverage.circularity_error().is_some()
+                })
+                .map(|((stick, _), coverage)| {
+                    div()
+                        .flex()
+                        .flex_col()
+                        .items_center()
+                        .child(stick)
+                        .child(circularity_readout(coverage))
+                        .child(short_corner_warning(coverage))
+                }),
+        )
+    }
+
+    // The range step is over; start from the current padding and preview it live
+    fn choose_outer_deadzone(&mut self) {
+        self.symmetric_range = false;
+        self.measured_padding = [0, 1].map(|stick| self.measure_padding(stick));
+        self.apply_range();
+        self.set_step(CalibrationStep::OuterDeadzoneChoice);
+    }
+
+    // The padding that still reaches full deflection on a stick's weakest rim sweep
+    fn measure_padding(&self, stick: usize) -> Option<u16> {
+        let spread = self.range_coverage[stick].rim_spread()?;
+        Some((spread.round() as u16).clamp(MIN_MEASURED_PADDING, MAX_OUTER_PADDING))
+    }
+
+    // How far in from the measured range each axis of a stick ends, in raw units
+    fn outer_padding(&self, stick: usize) -> u16 {
+        self.padding_override
+            .or(self.measured_padding[stick])
+            .unwrap_or(DEFAULT_OUTER_PADDING)
+    }
+
+    // What the slider shows: the override, or else the larger present stick's padding
+    fn shown_padding(&self) -> u16 {
+        [self.has_left, self.has_right]
+            .iter()
+            .enumerate()
+            .filter(|&(_, &present)| present)
+            .map(|(stick, _)| self.outer_padding(stick))
+            .max()
+            .unwrap_or(DEFAULT_OUTER_PADDING)
+    }
+
+    fn set_outer_padding(&mut self, padding: u16) {
+        let padding = padding.min(MAX_OUTER_PADDING);
+        self.padding_override = Some(padding);
+        self.padding_text = padding.to_string();
+        self.apply_range();
+    }
+
+    fn use_measured_padding(&mut self, cx: &mut Context<Self>) {
+        self.padding_override = None;
+        self.padding_text.clear();
+        self.apply_range();
+        cx.notify();
+    }
+
+    fn pick_outer_padding(&mut self, padding: u16, cx: &mut Context<Self>) {
+        self.set_outer_padding(padding);
+        cx.notify();
+    }
+
+    fn focus_padding_field(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        window.focus(&self.padding_focus);
+        cx.notify();
+    }
+
+    // Digits edit the padding field, the arrows nudge it by one
+    fn padding_key(&mut self, event: &KeyDownEvent, cx: &mut Context<Self>) {
+        let key = event.keystroke.key.as_str();
+        match key {
+            "up" => self.set_outer_padding(self.shown_padding().saturating_add(1)),
+            "down" => self.set_outer_padding(self.shown_padding().saturating_sub(1)),
+            "backspace" => {
+                self.padding_text.pop();
+            }
+            _ if key.len() == 1 && key.chars().all(|c| c.is_ascii_digit()) => {
+                self.padding_text.push_str(key);
+            }
+            _ => return,
+        }
+        // Typed values apply as soon as they make sense; anything over the limit is capped
+        if let Ok(padding) = self.padding_text.parse::<u32>() {
+            let capped = padding.min(MAX_OUTER_PADDING as u32) as u16;
+            self.padding_override = Some(capped);
+            if padding > MAX_OUTER_PADDING as u32 {
+                self.padding_text = capped.to_string();
+            }
+            self.apply_range();
+        }
+        cx.notify();
+    }
+
+    fn finish_outer_deadzone(&mut self, cx: &mut Context<Self>) {
+        self.apply_range();
+        self.set_step(CalibrationStep::Review);
+        cx.notify();
+    }
+
+    // How far one stick travelled from its center-step center in the range step, before
+    // any padding: right, up, left, down
+    fn measured_extents(&self, stick: usize, center: (u16, u16)) -> [u16; 4] {
+        let data = &self.calibration_data; // This is the data from CalibrateRange
+        let bounds = match stick {
+            0 => ((data.min_lx, data.min_ly), (data.max_lx, data.max_ly)),
+            _ => ((data.min_rx, data.min_ry), (data.max_rx, data.max_ry)),
+        };
+        range_extents(
+            &self.range_coverage[stick],
+            bounds,
+            center,
+            self.legacy_range,
+            self.outer_padding(stick) > 0 && self.circularity_shrink,
+        )
+    }
+
+    // Final calibration: the center step's center, with the range step's extents in each
+    // direction pulled in by the outer deadzone padding
+    fn apply_range(&mut self) {
+        let Some(centers) = self.center_result.as_ref().map(|c| c.centers) else {
+            return;
+        };
+        for (stick, center) in centers.into_iter().enumerate() {
+            let padding = self.outer_padding(stick);
+            let extents = self.measured_extents(stick, center);
+            let result = match stick {
+                0 => &mut self.left_result,
+                _ => &mut self.right_result,
+            };
+            *result = compose_calibration(center, extents, padding);
+        }
+        if self.symmetric_range {
+            self.symmetrize_results();
+        }
+    }
+
+    // Everything about the present sticks' results that would block writing them
+    fn calibration_problems(&self) -> Vec<String> {
+        let sticks = [
+            ("Left", self.has_left, self.left_result, self.left_deadzone),
+            (
+                "Right",
+                self.has_right,
+                self.right_result,
+                self.right_deadzone,
+            ),
+        ];
+        sticks
+            .into_iter()
+            .filter(|(_, present, _, _)| *present)
+            .flat_map(|(stick, _, result, deadzone)| {
+                result
+                    .validate(deadzone)
+                    .into_iter()
+                    .map(move |problem| format!("{} stick: {}", stick, problem))
+            })
+            .collect()
+    }
+
+    // Cut the longer side of every lopsided axis down to the shorter one
+    fn symmetrize_results(&mut self) {
+        for result in [&mut self.left_result, &mut self.right_result] {
+            if axis_asymmetry(result.xmin, result.xcenter, result.xmax) > ASYMMETRY_LIMIT {
+                (result.xmin, result.xmax) =
+                    symmetrize_axis(result.xmin, result.xcenter, result.xmax);
+            }
+            if axis_asymmetry(result.ymin, result.ycenter, result.ymax) > ASYMMETRY_LIMIT {
+                (result.ymin, result.ymax) =
+                    symmetrize_axis(result.ymin, result.ycenter, result.ymax);
+            }
+        }
+    }
+
+    fn symmetrize(&mut self, cx: &mut Context<Self>) {
+        self.symmetric_range = true;
+        self.symmetrize_results();
+        cx.notify();
+    }
+
+    // Everything this session measured about a stick; None for imported values, which
+    // weren't measured here
+    fn session_measures(&self, stick: usize) -> Option<SessionMeasures> {
+        let center = self.center_result.as_ref()?;
+        let coverage = &self.range_coverage[stick];
+        let result = match stick {
+            0 => self.left_result,
+            _ => self.right_result,
+        };
+        Some(SessionMeasures::measure(center, coverage, result, stick))
+    }
+
+    // A 0-100 score per stick, what cost it points, and what to do about it
+    fn quality_summary(&self) -> Div {
+        let sticks = [("Left", self.has_left), ("Right", self.has_right)];
+        let lines: Vec<_> = sticks
+            .into_iter()
+            .enumerate()
+            .filter(|(_, (_, present))| *present)
+            .filter_map(|(i, (stick, _))| {
+                let (score, penalties) = quality_score(&self.session_measures(i)?);
+                let (color, verdict) = if score >= GOOD_QUALITY_SCORE {
+                    (0x00AA00, "good to write")
+                } else if penalties
+                    .iter()
+                    .any(|&(_, _, cause)| cause == QualityCause::Sweep)
+                {
+                    (0xFFA500, "redo the range step, sweeping slowly all the way round")
+                } else {
+                    (
+                        0xFFA500,
+                        "the stick module is likely worn; this is about as good as calibration gets",
+                    )
+                };
+                let factors: Vec<_> = penalties
+                    .iter()
+                    .map(|(factor, cost, _)| format!("{} (−{})", factor, cost))
+                    .collect();
+                Some(
+                    div()
+                        .flex()
+                        .flex_col()
+                        .items_center()
+                        .text_color(rgb(color))
+                        .child(format!("{} stick quality: {}/100, {}", stick, score, verdict))
+                        .when(!factors.is_empty(), |el| el.child(factors.join(", "))),
+                )
+            })
+            .collect();
+        div().flex().flex_col().items_center().children(lines)
+    }
+
+    // How much longer one side of each axis is than the other, with a fix when it's a lot
+    fn asymmetry_summary(&self, cx: &Context<Self>) -> Div {
+        let sticks = [
+            ("Left", self.has_left, self.left_result),
+            ("Right", self.has_right, self.right_result),
+        ];
+        let axis = |name: &str, asymmetry: f64| {
+            div()
+                .when(asymmetry > ASYMMETRY_LIMIT, |el| {
+                    el.text_color(rgb(0xFFA500))
+                })
+                .child(format!("{} {:.1}%", name, asymmetry * 100.0))
+        };
+        let mut lopsided = false;
+        let lines: Vec<_> = sticks
+            .into_iter()
+            .filter(|(_, present, _)| *present)
+            .map(|(stick, _, result)| {
+                let x = axis_asymmetry(result.xmin, result.xcenter, result.xmax);
+                let y = axis_asymmetry(result.ymin, result.ycenter, result.ymax);
+                lopsided |= x.max(y) > ASYMMETRY_LIMIT;
+                div()
+                    .flex()
+                    .gap_2()
+                    .child(format!("{} stick asymmetry:", stick))
+                    .child(axis("X", x))
+                    .child(axis("Y", y))
+            })
+            .collect();
+        div()
+            .flex()
+            .flex_col()
+            .items_center()
+            .children(lines)
+            .when(lopsided, |el| {
+                el.child(
+                    div()
+                        .text_color(rgb(0xFFA500))
+                        .child("A lopsided axis feels faster one way; sweep the stick fully or check the module."),
+                )
+                .child(
+                    div()
+                        .id("symmetrize_btn")
+                        .p_2()
+                        .bg(rgb(0x555555))
+                        .rounded_md()
+                        .text_color(rgb(0xFFFFFF))
+                        .cursor_pointer()
+                        .child("Symmetrize (shrink the longer side)")
+                        .on_click(cx.listener(|this, _, _, cx| this.symmetrize(cx))),
+                )
+            })
+    }
+
+    // Each stick's padding, where it came from, and its share of each axis's travel from
+    // center, since the same padding takes more from a worn stick
+    fn padding_share(&self) -> Div {
+        let share = |padding: u16, one_way: u16, other_way: u16| {
+            let half = ((one_way as f64 + other_way as f64) / 2.0).max(1.0);
+            padding as f64 / half * 100.0
+        };
+        let centers = self
+            .center_result
+            .as_ref()
+            .map_or_else(Default::default, |c| c.centers);
+        let source = |stick: usize| match (self.padding_override, self.measured_padding[stick]) {
+            (Some(_), _) => "set by hand",
+            (None, Some(_)) => "measured",
+            (None, None) => "default, not measured",
+        };
+        let sticks = [("Left", self.has_left), ("Right", self.has_right)];
+        div().flex().flex_col().items_center().children(
+            sticks
+                .into_iter()
+                .enumerate()
+                .filter(|(_, (_, present))| *present)
+                .map(|(index, (stick, _))| {
+                    let [right, up, left, down] = self.measured_extents(index, centers[index]);
+                    let padding = self.outer_padding(index);
+                    format!(
+                        "{} stick: {} ({}), {:.1}% of X travel, {:.1}% of Y travel",
+                        stick,
+                        padding,
+                        source(index),
+                        share(padding, right, left),
+                        share(padding, up, down)
+                    )
+                }),
+        )
+    }
+
+    // A row of clickable steps from no padding to the maximum, filled up to the current value
+    fn padding_slider(&self, cx: &Context<Self>) -> Div {
+        let steps = (0..=MAX_OUTER_PADDING).step_by(PADDING_SLIDER_STEP as usize);
+        let shown = self.shown_padding();
+        div()
+            .flex()
+            .items_center()
+            .gap(px(2.0))
+            .children(steps.map(|padding| {
+                div()
+                    .id(("padding_step", padding as usize))
+                    .w(px(14.0))
+                    .h(px(20.0))
+                    .rounded_sm()
+                    .cursor_pointer()
+                    .bg(rgb(if padding <= shown { 0x007ACC } else { 0x444444 }))
+                    .on_click(
+                        cx.listener(move |this, _, _, cx| this.pick_outer_padding(padding, cx)),
+                    )
+            }))
+    }
+
+    fn padding_field(&self, cx: &Context<Self>) -> Stateful<Div> {
+        div()
+            .id("padding_field")
+            .p_1()
+            .rounded_md()
+            .cursor_pointer()
+            .border_1()
+            .border_color(rgb(0x007ACC))
+            .child(match self.padding_override {
+                Some(padding) => format!("Padding: {} (0x{:03X})", self.padding_text, padding),
+                None => "Padding: measured per stick".to_string(),
+            })
+            .on_click(cx.listener(|this, _, window, cx| this.focus_padding_field(window, cx)))
+    }
+
+    // Each present stick's calibrated output with the current results
+    fn calibrated_visuals(&self, cx: &Context<Self>) -> Div {
+        let stick = self.display_stick_data();
+        let left_result = self.display_result(self.left_result);
+        let right_result = self.display_result(self.right_result);
+        let sticks = div()
+            .flex()
+            .gap_8()
+            .when(self.has_left, |el| {
+                el.child(calibrated_visual(
+                    cx,
+                    stick.lx,
+                    stick.ly,
+                    self.display_smoothed(0),
+                    left_result.xmin,
+                    left_result.xmax,
+                    left_result.ymin,
+                    left_result.ymax,
+                    left_result.xcenter,
+                    left_result.ycenter,
+                    self.left_deadzone,
+                    &self.visual_label("Left Calibrated"),
+                    self.stick_background(false),
+                ))
+            })
+            .when(self.has_right, |el| {
+                el.child(calibrated_visual(
+                    cx,
+                    stick.rx,
+                    stick.ry,
+                    self.display_smoothed(1),
+                    right_result.xmin,
+                    right_result.xmax,
+                    right_result.ymin,
+                    right_result.ymax,
+                    right_result.xcenter,
+                    right_result.ycenter,
+                    self.right_deadzone,
+                    &self.visual_label("Right Calibrated"),
+                    self.stick_background(true),
+                ))
+            });
+        div()
+            .flex()
+            .flex_col()
+            .items_center()
+            .gap_2()
+            .child(sticks)
+            .child(self.smoothing_controls(cx))
+    }
+
+    fn toggle_legacy_range(&mut self, cx: &mut Context<Self>) {
+        self.legacy_range = !self.legacy_range;
+        self.apply_range();
+        cx.notify();
+    }
+
+    fn toggle_circularity_shrink(&mut self, cx: &mut Context<Self>) {
+        self.circularity_shrink = !self.circularity_shrink;
+        self.apply_range();
+        cx.notify();
+    }
+
+    // The calibration and range ratio a stick's connected controller stores, which a
+    // measured range ratio is scaled against
+    fn ratio_reference(&self, side: StickSide) -> Option<(StickCalibration, u16)> {
+        self.device_info.iter().find_map(|d| {
+            let (_, cal) = d.stick_cal.iter().find(|(s, _)| *s == side)?;
+            let (_, params) = d.stick_params.iter().find(|(s, _)| *s == side)?;
+            Some((*cal, params.range_ratio))
+        })
+    }
+
+    // The deadzone and range ratio written for each stick, left then right
+    fn stick_parameters(&self) -> [StickParameters; 2] {
+        let range_ratio = |result: StickCalibration, side| {
+            if self.pin_range_ratio {
+                DEFAULT_RANGE_RATIO
+            } else {
+                let reference = self.ratio_reference(side);
+                result.range_ratio(reference.as_ref().map(|(cal, ratio)| (cal, *ratio)))
+            }
+        };
+        [
+            StickParameters {
+                deadzone: self.left_deadzone,
+                range_ratio: range_ratio(self.left_result, StickSide::Left),
+            },
+            StickParameters {
+                deadzone: self.right_deadzone,
+                range_ratio: range_ratio(self.right_result, StickSide::Right),
+            },
+        ]
+    }
+
+    fn toggle_smoothing(&mut self, cx: &mut Context<Self>) {
+        self.smoothing = !self.smoothing;
+        self.smoothed_sticks = [None; 2];
+        cx.notify();
+    }
+
+    fn cycle_smoothing_alpha(&mut self, cx: &mut Context<Self>) {
+        let next = SMOOTHING_ALPHAS
+            .iter()
+            .position(|&alpha| alpha == self.smoothing_alpha)
+            .map_or(0, |i| (i + 1) % SMOOTHING_ALPHAS.len());
+        self.smoothing_alpha = SMOOTHING_ALPHAS[next];
+        cx.notify();
+    }
+
+    // Move each stick's smoothed position toward its latest reading
+    fn smooth_sticks(&mut self) {
+        if !self.smoothing {
+            return;
+        }
+        let StickData { lx, ly, rx, ry, .. } = self.stick_data;
+        let alpha = self.smoothing_alpha;
+        for (smoothed, (x, y)) in self.smoothed_sticks.iter_mut().zip([(lx, ly), (rx, ry)]) {
+            let (x, y) = (x as f32, y as f32);
+            *smoothed = Some(match *smoothed {
+                Some((sx, sy)) => (sx + alpha * (x - sx), sy + alpha * (y - sy)),
+                None => (x, y),
+            });
+        }
+    }
+
+    // A stick's smoothed position, turned like the rest of the display; None when
+    // smoothing is off
+    fn display_smoothed(&self, stick: usize) -> Option<(u16, u16)> {
+        if !self.smoothing {
+            return None;
+        }
+        let (x, y) = self.smoothed_sticks[stick]?;
+        Some(self.display_point(x.round() as u16, y.round() as u16))
+    }
+
+    fn smoothing_controls(&self, cx: &Context<Self>) -> Div {
+        div()
+            .flex()
+            .gap_4()
+            .text_color(rgb(0xAAAAAA))
+            .child(
+                div()
+                    .id("smoothing_toggle")
+                    .cursor_pointer()
+                    .child(if self.smoothing {
+                        "[x] Smooth stick dot"
+                    } else {
+                        "[ ] Smooth stick dot"
+                    })
+                    .on_click(cx.listener(|this, _, _, cx| this.toggle_smoothing(cx))),
+            )
+            .when(self.smoothing, |el| {
+                el.child(
+                    div()
+                        .id("smoothing_alpha_toggle")
+                        .cursor_pointer()
+                        .child(format!("Smoothing α {:.1} ▸", self.smoothing_alpha))
+                        .on_click(cx.listener(|this, _, _, cx| this.cycle_smoothing_alpha(cx))),
+                )
+            })
+    }
+
+    fn toggle_pin_range_ratio(&mut self, cx: &mut Context<Self>) {
+        self.pin_range_ratio = !self.pin_range_ratio;
+        cx.notify();
+    }
+
+    // The range ratio each stick will get, with the option to keep the fixed one
+    fn range_ratio_summary(&self, cx: &Context<Self>) -> Div {
+        let [left, right] = self.stick_parameters();
+        let sticks = [
+            ("Left", StickSide::Left, self.has_left, left),
+            ("Right", StickSide::Right, self.has_right, right),
+        ];
+        div()
+            .flex()
+            .flex_col()
+            .items_center()
+            .children(
+                sticks
+                    .into_iter()
+                    .filter(|(_, _, present, _)| *present)
+                    .map(|(stick, side, _, parameters)| {
+                        let reference = self.ratio_reference(side);
+                        let usable = usable_ratio_reference(
+                            reference.as_ref().map(|(cal, ratio)| (cal, *ratio)),
+                        );
+                        let source = match usable {
+                            _ if self.pin_range_ratio => String::new(),
+                            Some((ratio, _)) => {
+                                format!(" (scaled from the stored 0x{:03X})", ratio as u16)
+                            }
+                            None => {
+                                " (stored values unreadable, scaled from a typical stick)".into()
+                            }
+                        };
+                        format!(
+                            "{} stick range ratio: 0x{:03X}{}",
+                            stick, parameters.range_ratio, source
+                        )
+                    }),
+            )
+            .child(
+                div()
+                    .id("pin_range_ratio_toggle")
+                    .cursor_pointer()
+                    .child(if self.pin_range_ratio {
+                        format!("[x] Pin range ratio to 0x{:03X}", DEFAULT_RANGE_RATIO)
+                    } else {
+                        format!(
+                            "[ ] Pin range ratio to 0x{:03X} (now scaled to each stick's travel)",
+                            DEFAULT_RANGE_RATIO
+                        )
+                    })
+                    .on_click(cx.listener(|this, _, _, cx| this.toggle_pin_range_ratio(cx))),
+            )
+    }
+
+    // How the final range is derived; only offered when the range step measured it
+    fn range_mode(&self, cx: &Context<Self>) -> Div {
+        let measured = [self.has_left, self.has_right]
+            .iter()
+            .zip(&self.range_coverage)
+            .any(|(&present, coverage)| present && coverage.cardinal_reach().is_some());
+        if !measured {
+            return div();
+        }
+        div()
+            .flex()
+            .flex_col()
+            .items_center()
+            .child(
+                div()
+                    .id("legacy_range_toggle")
+                    .cursor_pointer()
+                    .child(if self.legacy_range {
+                        "[x] Legacy range mode (bounding box of all samples)"
+                    } else {
+                        "[ ] Legacy range mode (bounding box of all samples)"
+                    })
+                    .on_click(cx.listener(|this, _, _, cx| this.toggle_legacy_range(cx))),
+            )
+            .when(self.shown_padding() > 0 && !self.legacy_range, |el| {
+                el.child(
+                    div()
+                        .id("circularity_shrink_toggle")
+                        .cursor_pointer()
+                        .child(if self.circularity_shrink {
+                            "[x] Shrink range by circularity error"
+                        } else {
+                            "[ ] Shrink range by circularity error"
+                        })
+                        .on_click(cx.listener(|this, _, _, cx| this.toggle_circularity_shrink(cx))),
+                )
+            })
+    }
+
+    // A controller that powers off mid-write can be left with garbage calibration
+    fn battery_critical(&self) -> bool {
+        let charging = |controller_type| {
+            self.battery
+                .iter()
+                .any(|(t, status)| *t == controller_type && status.charging)
+        };
+        // The voltage is a better sign of an aging battery than the coarse bars
+        let low_voltage = self.device_info.iter().any(|d| {
+            d.voltage_mv.is_some_and(|mv| mv < LOW_VOLTAGE_MV) && !charging(d.controller_type)
+        });
+        low_voltage
+            || self
+                .battery
+                .iter()
+                .any(|(_, status)| status.level <= BatteryLevel::Critical && !status.charging)
+    }
+
+    // Shared checks before any SPI write; sets error_message when the write must not happen
+    fn can_write(&mut self, ignore_low_battery: bool) -> bool {
+        // Never write to only half of a pair
+        if self.calibration_step == CalibrationStep::Disconnected || self.controllers.is_empty() {
+            return false;
+        }
+        if self.read_only {
+            self.error_message =
+                Some("This controller does not support on-device calibration.".to_string());
+            return false;
+        }
+        if self.battery_critical() && !ignore_low_battery {
+            self.error_message = Some(
+                "Battery is critically low. Charge the controller before writing calibration."
+                    .to_string(),
+            );
+            return false;
+        }
+        true
+    }
+
+    fn write_calibration(&mut self, ignore_low_battery: bool, cx: &mut Context<Self>) {
+        if !self.can_write(ignore_low_battery) {
+            return;
+        }
+        let problems = self.calibration_problems();
+        if !problems.is_empty() {
+            self.error_message = Some(format!(
+                "The calibration has problems, so nothing was written: {}",
+                problems.join("; ")
+            ));
+            return;
+        }
+        // Never write without a copy of what is being overwritten
+        self.write_partial = false;
+        self.write_backups.clear();
+        self.undo.clear();
+        self.undone = false;
+        for c in &self.controllers {
+            match backup::save_before_write(&mut c.lock(), self.calibration_target) {
+                Ok((backup, path)) => {
+                    self.undo.push(backup);
+                    self.write_backups.push(path);
+                }
+                Err(e) => {
+                    self.error_message = Some(format!(
+                        "Couldn't back up the current calibration, so nothing was written: {}",
+                        e
+                    ));
+                    return;
+                }
+            }
+        }
+        // Each Joy-Con of a pair only takes the stick that is physically on it
+        self.write_checks.clear();
+        for (i, c) in self.controllers.iter().enumerate() {
+            let [left_parameters, right_parameters] = self.stick_parameters();
+            let result = c.lock().write_calibration_to_device(
+                self.left_result,
+                self.right_result,
+                left_parameters,
+                right_parameters,
+                false,
+                self.calibration_target,
+            );
+            match result {
+                Err(e)
+                    if e.downcast_ref::<ControllerError>()
+                        == Some(&ControllerError::Disconnected) =>
+                {
+                    // Resumes on Review, so the write can simply be repeated
+                    self.start_reconnect(i, cx);
+                    return;
+                }
+                Err(e) => {
+                    let mut message = write_error_message(&e);
+                    let left_partial = e
+                        .downcast_ref::<CalibrationWriteError>()
+                        .is_some_and(|failed| !failed.rolled_back());
+                    if let Some(path) = self.write_backups.get(i)
+                        && left_partial
+                    {
+                        message.push_str(&format!(" The backup is at {}.", path.display()));
+                    }
+                    // The other half of a pair was written before this one failed
+                    if i > 0 {
+                        message.push_str(" The other Joy-Con was already written.");
+                    }
+                    self.write_partial = left_partial || i > 0;
+                    self.error_message = Some(message);
+                    return;
+                }
+                Ok(checks) => self.write_checks.extend(checks),
+            }
+        }
+        self.dry_run_writes = self
+            .controllers
+            .iter()
+            .flat_map(|c| c.lock().take_recorded_writes())
+            .collect();
+        for c in &self.controllers {
+            let _ = c.lock().blink_home_led(3);
+        }
+        // Remembering is a convenience; a failure here shouldn't spoil a successful write
+        if let Some(details) = self.device_info.first() {
+            self.saved_state.last_mac = Some(details.mac.clone());
+            let _ = state::save(&self.saved_state);
+        }
+        self.set_step(CalibrationStep::Done);
+    }
+
+    fn start_motion_calibration(&mut self, _cx: &mut Context<Self>) {
+        for c in &self.controllers {
+            let mut c = c.lock();
+            if let Err(e) = c.enable_standard_input().and_then(|_| c.enable_imu()) {
+                self.error_message = Some(format!("Failed to enable motion sensor: {}", e));
+                return;
+            }
+        }
+        self.motion_data = MotionCalibrationData::default();
+        self.motion_started = Some(Instant::now());
+        self.motion_result = None;
+        self.error_message = None;
+        self.set_step(CalibrationStep::MotionCollect);
+    }
+
+    // Stage the first-connect snapshot as a restore, so it gets the usual summary and
+    // confirmation before anything is written
+    fn restore_factory_snapshot(&mut self, cx: &mut Context<Self>) {
+        let Some(path) = self.factory_snapshot.clone() else {
+            return;
+        };
+        self.backup_status = None;
+        match SpiBackup::load(&path) {
+            Ok(snapshot) => self.stage_restore(path, snapshot),
+            Err(e) => self.error_message = Some(format!("Restore failed: {}", e)),
+        }
+        cx.notify();
+    }
+
+    fn toggle_snapshot_list(&mut self, cx: &mut Context<Self>) {
+        self.snapshot_list = match self.snapshot_list {
+            Some(_) => None,
+            None => Some(backup::factory_snapshots()),
+        };
+        cx.notify();
+    }
+
+    // Controllers with a factory snapshot on disk and when each was taken
+    fn snapshot_section(&self, cx: &Context<Self>) -> Div {
+        let toggle = div()
+            .id("snapshot_list_toggle")
+            .cursor_pointer()
+            .text_color(rgb(0xAAAAAA))
+            .child(if self.snapshot_list.is_some() {
+                "Factory snapshots ▾"
+            } else {
+                "Factory snapshots ▸"
+            })
+            .on_click(cx.listener(|this, _, _, cx| this.toggle_snapshot_list(cx)));
+
+        div()
+            .flex()
+            .flex_col()
+            .items_center()
+            .child(toggle)
+            .when_some(self.snapshot_list.as_ref(), |el, snapshots| {
+                el.when(snapshots.is_empty(), |el| {
+                    el.child(div().text_color(rgb(0xAAAAAA)).child("No snapshots yet."))
+                })
+                .children(snapshots.iter().map(|(mac, timestamp)| {
+                    div().text_color(rgb(0xAAAAAA)).child(format!(
+                        "{}  taken {}",
+                        mac,
+                        backup::format_timestamp(*timestamp)
+                    ))
+                }))
+            })
+    }
+
+    fn toggle_full_backup(&mut self, cx: &mut Context<Self>) {
+        self.full_backup = !self.full_backup;
+        cx.notify();
+    }
+
+    // Ask where to save, then copy the flash there in the background
+    fn start_backup(&mut self, cx: &mut Context<Self>) {
+        let Some(controller) = self.controllers.first().cloned() else {
+            return;
+        };
+        let name = self
+            .device_info
+            .first()
+            .map_or("controller".to_string(), |d| d.mac.replace(':', "-"));
+        let dir = dirs::home_dir().unwrap_or_default();
+        let path = cx.prompt_for_new_path(&dir, Some(&format!("{}.jcbak", name)));
+        let regions: Vec<Range<u32>> = if self.full_backup {
+            vec![SPI_FLASH]
+        } else {
+            CALIBRATION_REGIONS.to_vec()
+        };
+        self.backup_status = None;
+        cx.spawn(async move |this, cx| {
+            let Ok(Ok(Some(path))) = path.await else {
+                return;
+            };
+            let _ = this.update(cx, |this, cx| {
+                this.run_operation(
+                    "Backing up controller flash…",
+                    move |op| {
+                        let mut c = controller.lock();
+                        SpiBackup::read(&mut c, &regions, op)?.save(&path)?;
+                        Ok(path)
+                    },
+                    |this, result, cancelled, _| match result {
+                        Ok(path) => {
+                            this.backup_status = Some(format!("Backup saved to {}", path.display()))
+                        }
+                        Err(_) if cancelled => {}
+                        Err(e) => this.error_message = Some(format!("Backup failed: {}", e)),
+                    },
+                    cx,
+                )
+            });
+        })
+        .detach();
+    }
+
+    // Run `work` on its own thread behind the progress screen. Once it ends, go back to
+    // Connected and hand its result to `finish`, with whether the user cancelled it.
+    fn run_operation<T: Send + 'static>(
+        &mut self,
+        title: &'static str,
+        work: impl FnOnce(&OperationContext) -> anyhow::Result<T> + Send + 'static,
+        finish: impl FnOnce(&mut Self, anyhow::Result<T>, bool, &mut Context<Self>) + 'static,
+        cx: &mut Context<Self>,
+    ) {
+        let mut operation = Operation::spawn(work);
+        let cancel = operation.cancel_flag();
+        let task = cx.spawn(async move |this, cx| {
+            let result = loop {
+                cx.background_executor()
+                    .timer(Duration::from_millis(50))
+                    .await;
+                let result = operation.poll();
+                let progress = operation.latest();
+                let _ = this.update(cx, |this, cx| {
+                    if let Some(view) = &mut this.operation {
+                        view.progress = progress;
+                    }
+                    cx.notify();
+                });
+                if let Some(result) = result {
+                    break result;
+                }
+            };
+            let _ = this.update(cx, |this, cx| {
+                let cancelled = this
+                    .operation
+                    .take()
+                    .is_some_and(|view| view.cancel.load(Ordering::Relaxed));
+                this.set_step(CalibrationStep::Connected);
+                finish(this, result, cancelled, cx);
+                cx.notify();
+            });
+        });
+        self.error_message = None;
+        self.operation = Some(OperationView {
+            title,
+            progress: None,
+            cancel,
+            _task: task,
+        });
+        self.set_step(CalibrationStep::Working);
+        cx.notify();
+    }
+
+    fn cancel_operation(&mut self, cx: &mut Context<Self>) {
+        if let Some(view) = &self.operation {
+            view.cancel.store(true, Ordering::Relaxed);
+        }
+        cx.notify();
+    }
+
+    // Backup and restore buttons, or the summary of a restore waiting to be confirmed
+    fn backup_section(&self, cx: &Context<Self>) -> Div {
+        if let Some(restore) = &self.restore {
+            return self.restore_summary(restore, cx);
+        }
+        if let Some(path) = &self.raw_import {
+            return self.raw_import_section(path, cx);
+        }
+        div()
+            .flex()
+            .flex_col()
+            .items_center()
+            .gap_2()
+            .child(
+                div()
+                    .id("backup_btn")
+                    .p_2()
+                    .bg(rgb(0x555555))
+                    .rounded_md()
+                    .text_color(rgb(0xFFFFFF))
+                    .cursor_pointer()
+                    .child("Backup Controller")
+                    .on_click(cx.listener(|this, _, _, cx| this.start_backup(cx))),
+            )
+            .child(
+                div()
+                    .id("full_backup_toggle")
+                    .cursor_pointer()
+                    .child(format!(
+                        "[{}] Whole flash (512 KiB, several minutes over Bluetooth)",
+                        if self.full_backup { "x" } else { " " }
+                    ))
+                    .on_click(cx.listener(|this, _, _, cx| this.toggle_full_backup(cx))),
+            )
+            .child(
+                div()
+                    .id("restore_btn")
+                    .p_2()
+                    .bg(rgb(0x555555))
+                    .rounded_md()
+                    .text_color(rgb(0xFFFFFF))
+                    .cursor_pointer()
+                    .child("Restore from backup…")
+                    .on_click(cx.listener(|this, _, _, cx| this.choose_restore_file(cx))),
+            )
+            .when(self.factory_snapshot.is_some(), |el| {
+                el.child(
+                    div()
+                        .id("restore_factory_btn")
+                        .p_2()
+                        .bg(rgb(0x555555))
+                        .rounded_md()
+                        .text_color(rgb(0xFFFFFF))
+                        .cursor_pointer()
+                        .child("Restore original factory calibration")
+                        .on_click(cx.listener(|this, _, _, cx| this.restore_factory_snapshot(cx))),
+                )
+            })
+            .child(self.snapshot_section(cx))
+            .when(self.has_sticks(), |el| {
+                el.child(
+                    div()
+                        .id("export_btn")
+                        .p_2()
+                        .bg(rgb(0x555555))
+                        .rounded_md()
+                        .text_color(rgb(0xFFFFFF))
+                        .cursor_pointer()
+                        .child("Export current calibration")
+                        .on_click(cx.listener(|this, _, _, cx| this.export_calibration(cx))),
+                )
+                .child(
+                    div()
+                        .id("import_btn")
+                        .p_2()
+                        .bg(rgb(0x555555))
+                        .rounded_md()
+                        .text_color(rgb(0xFFFFFF))
+                        .cursor_pointer()
+                        .child("Import calibration…")
+                        .on_click(cx.listener(|this, _, _, cx| this.choose_import_file(cx))),
+                )
+            })
+            .child(self.edit_colors_button(cx))
+            .when_some(self.backup_status.as_ref(), |el, status| {
+                el.child(div().text_color(rgb(0xAAAAAA)).child(status.clone()))
+            })
+    }
+
+    fn toggle_write_preview(&mut self, cx: &mut Context<Self>) {
+        self.show_write_preview = !self.show_write_preview;
+        cx.notify();
+    }
+
+    // The packets a write would send right now, through the same code as a real write
+    fn write_preview(&self) -> Div {
+        let mut writes = Vec::new();
+        let [left_parameters, right_parameters] = self.stick_parameters();
+        for c in &self.controllers {
+            match c.lock().preview_calibration_writes(
+                self.left_result,
+                self.right_result,
+                left_parameters,
+                right_parameters,
+                self.calibration_target,
+            ) {
+                Ok(recorded) => writes.extend(recorded),
+                Err(e) => return div().child(format!("Can't preview the write: {}", e)),
+            }
+        }
+        // Even the user target changes factory data when the deadzone or range ratio does
+        let factory = writes
+            .iter()
+            .any(|(offset, _)| CALIBRATION_REGIONS[0].contains(offset));
+        div()
+            .flex()
+            .flex_col()
+            .when(factory, |el| {
+                el.child(div().text_color(rgb(0xFFA500)).child(
+                    match self.calibration_target {
+                        CalibrationTarget::User => {
+                            "Factory data will be changed: the deadzone and range ratio are only stored there."
+                        }
+                        CalibrationTarget::Factory => {
+                            "Factory data will be changed: the factory calibration is overwritten."
+                        }
+                    },
+                ))
+            })
+            .child(spi_write_list(&writes))
+    }
+
+    fn request_undo(&mut self, confirm: bool, cx: &mut Context<Self>) {
+        self.confirm_undo = confirm;
+        cx.notify();
+    }
+
+    // Write back what the last calibration write replaced. Without a write this
+    // session, the newest automatic backup of each controller stands in for it.
+    fn undo_last_write(&mut self, cx: &mut Context<Self>) {
+        self.confirm_undo = false;
+        if self.undo.is_empty() {
+            for d in &self.device_info {
+                let loaded = backup::latest_backup(&d.mac)
+                    .ok_or_else(|| anyhow::anyhow!("No earlier calibration saved for {}", d.mac))
+                    .and_then(|path| SpiBackup::load(&path));
+                match loaded {
+                    Ok(backup) => self.undo.push(backup),
+                    Err(e) => {
+                        self.undo.clear();
+                        self.error_message = Some(format!("Undo failed: {}", e));
+                        cx.notify();
+                        return;
+                    }
+                }
+            }
+        }
+        for (i, (c, previous)) in self.controllers.iter().zip(&self.undo).enumerate() {
+            let mut c = c.lock();
+            let ranges: Vec<Range<u32>> =
+                previous.restorable().into_iter().map(|(r, _)| r).collect();
+            if let Err(e) = c.restore_spi(previous, &ranges, false, &OperationContext::detached()) {
+                self.error_message = Some(format!("Undo failed: {}", e));
+                cx.notify();
+                return;
+            }
+            if let Ok(info) = c.get_device_info()
+                && let Some(details) = self.device_info.get_mut(i)
+            {
+                *details = DeviceDetails::read(&mut c, info);
+            }
+        }
+        self.undone = true;
+        self.error_message = None;
+        cx.notify();
+    }
+
+    // Undo button, its confirmation, or a note once the undo went through
+    fn undo_section(&self, cx: &Context<Self>) -> Div {
+        if self.undone {
+            return div()
+                .text_color(rgb(0xAAAAAA))
+                .child("The previous calibration was written back.");
+        }
+        if !self.confirm_undo {
+            return div().child(
+                div()
+                    .id("undo_btn")
+                    .p_2()
+                    .bg(rgb(0x555555))
+                    .rounded_md()
+                    .text_color(rgb(0xFFFFFF))
+                    .cursor_pointer()
+                    .child("Undo last write")
+                    .on_click(cx.listener(|this, _, _, cx| this.request_undo(true, cx))),
+            );
+        }
+        div()
+            .flex()
+            .flex_col()
+            .items_center()
+            .gap_2()
+            .child("Write the calibration from before the last write back to the controller?")
+            .child(
+                div()
+                    .flex()
+                    .gap_2()
+                    .child(
+                        div()
+                            .id("undo_confirm_btn")
+                            .p_2()
+                            .bg(rgb(0xE53935))
+                            .rounded_md()
+                            .text_color(rgb(0xFFFFFF))
+                            .cursor_pointer()
+                            .child("Undo")
+                            .on_click(cx.listener(|this, _, _, cx| this.undo_last_write(cx))),
+                    )
+                    .child(
+                        div()
+                            .id("undo_cancel_btn")
+                            .p_2()
+                            .bg(rgb(0x555555))
+                            .rounded_md()
+                            .text_color(rgb(0xFFFFFF))
+                            .cursor_pointer()
+                            .child("Cancel")
+                            .on_click(cx.listener(|this, _, _, cx| this.request_undo(false, cx))),
+                    ),
+            )
+    }
+
+    // Save the stick calibration as it is on the controller now, as readable JSON
+    fn export_calibration(&mut self, cx: &mut Context<Self>) {
+        let Some(controller) = self.controllers.first().cloned() else {
+            return;
+        };
+        let name = self
+            .device_info
+            .first()
+            .map_or("controller".to_string(), |d| d.mac.replace(':', "-"));
+        let dir = dirs::home_dir().unwrap_or_default();
+        let path = cx.prompt_for_new_path(&dir, Some(&format!("{}.joycal", name)));
+        cx.spawn(async move |this, cx| {
+            let Ok(Ok(Some(path))) = path.await else {
+                return;
+            };
+            let _ = this.update(cx, |this, cx| {
+                let result =
+                    JoycalFile::read_from(&mut controller.lock()).and_then(|file| file.save(&path));
+                match result {
+                    Ok(()) => {
+                        this.backup_status =
+                            Some(format!("Calibration exported to {}", path.display()))
+                    }
+                    Err(e) => this.error_message = Some(format!("Export failed: {}", e)),
+                }
+                cx.notify();
+            });
+        })
+        .detach();
+    }
+
+    fn open_color_editor(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        let Some(colors) = self.device_info.first().and_then(|d| d.colors) else {
+            return;
+        };
+        let editor = ColorEditor::new(colors, cx);
+        window.focus(&editor.focus);
+        self.color_editor = Some(editor);
+        self.error_message = None;
+        self.set_step(CalibrationStep::Colors);
+        cx.notify();
+    }
+
+    fn close_color_editor(&mut self, cx: &mut Context<Self>) {
+        self.color_editor = None;
+        self.set_step(CalibrationStep::Connected);
+        cx.notify();
+    }
+
+    fn select_color_field(&mut self, index: usize, window: &mut Window, cx: &mut Context<Self>) {
+        if let Some(editor) = &mut self.color_editor {
+            editor.active = index;
+            window.focus(&editor.focus);
+        }
+        cx.notify();
+    }
+
+    fn color_key(&mut self, event: &KeyDownEvent, cx: &mut Context<Self>) {
+        if let Some(editor) = &mut self.color_editor {
+            editor.key(&event.keystroke.key);
+        }
+        cx.notify();
+    }
+
+    // Back up the color region, then write the edited colors and read them back
+    fn write_colors(&mut self, cx: &mut Context<Self>) {
+        let Some(colors) = self.color_editor.as_ref().and_then(|e| e.colors()) else {
+            return;
+        };
+        if !self.can_write(false) {
+            return;
+        }
+        let mut c = self.controllers[0].lock();
+        let result = backup::save_before_color_write(&mut c)
+            .map_err(|e| {
+                anyhow::anyhow!(
+                    "Couldn't back up the current colors, so nothing was written: {}",
+                    e
+                )
+            })
+            .and_then(|path| c.write_colors(colors).map(|_| path));
+        drop(c);
+        match result {
+            Ok(path) => {
+                if let Some(d) = self.device_info.first_mut() {
+                    d.colors = Some(colors);
+                }
+                self.backup_status = Some(format!(
+                    "Colors written. The old ones were saved to {}",
+                    path.display()
+                ));
+                self.close_color_editor(cx);
+            }
+            Err(e) => {
+                self.error_message = Some(write_error_message(&e));
+                cx.notify();
+            }
+        }
+    }
+
+    fn color_editor_page(&self, editor: &ColorEditor, cx: &Context<Self>) -> Div {
+        let swatch = |color: Option<[u8; 3]>| {
+            div()
+                .w(px(24.0))
+                .h(px(24.0))
+                .rounded_md()
+                .border_1()
+                .border_color(rgb(0x888888))
+                .bg(rgb(color.map_or(NEUTRAL_HEADER_BACKGROUND, rgb_hex)))
+        };
+        let fields = editor.fields.iter().enumerate().map(|(i, (label, text))| {
+            div()
+                .id(("color_field", i))
+                .flex()
+                .items_center()
+                .gap_2()
+                .p_1()
+                .rounded_md()
+                .cursor_pointer()
+                .when(i == editor.active, |el| {
+                    el.border_1().border_color(rgb(0x007ACC))
+                })
+                .child(swatch(editor.color(i)))
+                .child(format!("{}: #{}", label, text))
+                .on_click(
+                    cx.listener(move |this, _, window, cx| this.select_color_field(i, window, cx)),
+                )
+        });
+        // A rough controller: grips either side of the body, with four face buttons
+        let button = || {
+            div()
+                .w(px(14.0))
+                .h(px(14.0))
+                .rounded_full()
+                .bg(rgb(editor.color(1).map_or(0xFFFFFF, rgb_hex)))
+        };
+        let grip = |index| {
+            div().w(px(30.0)).h(px(90.0)).rounded_md().bg(rgb(editor
+                .color(index)
+                .or(editor.color(0))
+                .map_or(NEUTRAL_HEADER_BACKGROUND, rgb_hex)))
+        };
+        let preview = div()
+            .flex()
+            .items_center()
+            .when(editor.fields.len() == 4, |el| el.child(grip(2)))
+            .child(
+                div()
+                    .w(px(140.0))
+                    .h(px(70.0))
+                    .rounded_md()
+                    .bg(rgb(editor
+                        .color(0)
+                        .map_or(NEUTRAL_HEADER_BACKGROUND, rgb_hex)))
+                    .flex()
+                    .items_center()
+                    .justify_center()
+                    .gap_2()
+                    .children((0..4).map(|_| button())),
+            )
+            .when(editor.fields.len() == 4, |el| el.child(grip(3)));
+        let ready = editor.colors().is_some();
+
+        div()
+            .track_focus(&editor.focus)
+            .on_key_down(cx.listener(|this, event: &KeyDownEvent, _, cx| this.color_key(event, cx)))
+            .flex()
+            .flex_col()
+            .items_center()
+            .gap_4()
+            .child("Controller Colors")
+            .child("Click a field and type a hex color. Tab moves to the next field.")
+            .child(preview)
+            .child(div().flex().flex_col().gap_1().children(fields))
+            .child(
+                div()
+                    .flex()
+                    .gap_2()
+                    .child(
+                        div()
+                            .id("write_colors_btn")
+                            .p_2()
+                            .bg(rgb(if ready { 0xE53935 } else { 0x333333 }))
+                            .rounded_md()
+                            .text_color(rgb(0xFFFFFF))
+                            .when(ready, |el| {
+                                el.cursor_pointer()
+                                    .on_click(cx.listener(|this, _, _, cx| this.write_colors(cx)))
+                            })
+                            .child("Write Colors"),
+                    )
+                    .child(
+                        div()
+                            .id("close_colors_btn")
+                            .p_2()
+                            .bg(rgb(0x555555))
+                            .rounded_md()
+                            .text_color(rgb(0xFFFFFF))
+                            .cursor_pointer()
+                            .child("Back")
+                            .on_click(cx.listener(|this, _, _, cx| this.close_color_editor(cx))),
+                    ),
+            )
+    }
+
+    // Only offered when there are colors to start from and the console shows them
+    fn edit_colors_button(&self, cx: &Context<Self>) -> Div {
+        match self.device_info.first() {
+            Some(d) if d.colors.is_some() && d.colors_in_spi => div().child(
+                div()
+                    .id("edit_colors_btn")
+                    .p_2()
+                    .bg(rgb(0x555555))
+                    .rounded_md()
+                    .text_color(rgb(0xFFFFFF))
+                    .cursor_pointer()
+                    .child("Edit colors…")
+                    .on_click(
+                        cx.listener(|this, _, window, cx| this.open_color_editor(window, cx)),
+                    ),
+            ),
+            Some(d) if d.colors.is_some() => div()
+                .text_color(rgb(0xAAAAAA))
+                .child("Colors can't be edited: the console ignores this controller's SPI colors"),
+            _ => div(),
+        }
+    }
+
+    fn choose_import_file(&mut self, cx: &mut Context<Self>) {
+        let paths = cx.prompt_for_paths(PathPromptOptions {
+            files: true,
+            directories: false,
+            multiple: false,
+            prompt: Some("Import".into()),
+        });
+        cx.spawn(async move |this, cx| {
+            let Ok(Ok(Some(paths))) = paths.await else {
+                return;
+            };
+            let Some(path) = paths.into_iter().next() else {
+                return;
+            };
+            let _ = this.update(cx, |this, cx| {
+                if let Err(e) = this.import_calibration(path) {
+                    this.error_message = Some(format!("Import failed: {}", e));
+                }
+                cx.notify();
+            });
+        })
+        .detach();
+    }
+
+    // Put a .joycal file's values up for review. Writing them then goes through the
+    // same confirm, backup and verify path as a fresh calibration.
+    fn import_calibration(&mut self, path: PathBuf) -> anyhow::Result<()> {
+        let file = JoycalFile::load(&path)?;
+        let (Some(controller), Some(details)) =
+            (self.controllers.first(), self.device_info.first())
+        else {
+            return Err(anyhow::anyhow!("No controller connected"));
+        };
+        if file.controller_type != details.controller_type {
+            return Err(anyhow::anyhow!(
+                "The file is for a {}, but a {} is connected",
+                controller_type_name(file.controller_type),
+                controller_type_name(details.controller_type)
+            ));
+        }
+        let capabilities = controller.lock().capabilities();
+        for (side, present) in [
+            (StickSide::Left, capabilities.has_left_stick),
+            (StickSide::Right, capabilities.has_right_stick),
+        ] {
+            if present && file.stick(side).is_none() {
+                return Err(anyhow::anyhow!(
+                    "The file has no {:?} stick calibration",
+                    side
+                ));
+            }
+        }
+        {
+            let mut c = controller.lock();
+            c.enable_standard_input().and_then(|_| c.enable_imu())?;
+        }
+
+        // The file's deadzones are used as they are; there's no slack to derive them from
+        self.center_result = None;
+        self.range_coverage = Default::default();
+        self.measured_padding = [None; 2];
+        self.symmetric_range = false;
+        if let Some(stick) = file.stick(StickSide::Left) {
+            self.left_result = stick.effective();
+            self.left_deadzone = stick.parameters.deadzone;
+        }
+        if let Some(stick) = file.stick(StickSide::Right) {
+            self.right_result = stick.effective();
+            self.right_deadzone = stick.parameters.deadzone;
+        }
+        self.import_warning = (file.mac != details.mac).then(|| {
+            format!(
+                "This file was exported from another controller ({}). Only write it if both sticks are the same model.",
+                file.mac
+            )
+        });
+        self.imported_from = Some(path);
+        self.error_message = None;
+        self.set_step(CalibrationStep::Review);
+        Ok(())
+    }
+
+    fn choose_restore_file(&mut self, cx: &mut Context<Self>) {
+        let paths = cx.prompt_for_paths(PathPromptOptions {
+            files: true,
+            directories: false,
+            multiple: false,
+            prompt: Some("Restore".into()),
+        });
+        cx.spawn(async move |this, cx| {
+            let Ok(Ok(Some(paths))) = paths.await else {
+                return;
+            };
+            let Some(path) = paths.into_iter().next() else {
+                return;
+            };
+            let _ = this.update(cx, |this, cx| {
+                this.backup_status = None;
+                match SpiBackup::load(&path) {
+                    Ok(backup) => this.stage_restore(path, backup),
+                    // Possibly a raw dump from another tool; ask where it starts
+                    Err(e)
+                        if e.downcast_ref::<BackupFileError>()
+                            == Some(&BackupFileError::NoHeader) =>
+                    {
+                        this.raw_import = Some(path)
+                    }
+                    Err(e) => this.error_message = Some(format!("Restore failed: {}", e)),
+                }
+                cx.notify();
+            });
+        })
+        .detach();
+    }
+
+    fn stage_restore(&mut self, path: PathBuf, backup: SpiBackup) {
+        self.restore = Some(PendingRestore {
+            regions: if self.allow_any_offset {
+                backup.all_regions()
+            } else {
+                backup.restorable()
+            },
+            path,
+            backup: Arc::new(backup),
+            allow_other_mac: false,
+            differences: None,
+        });
+    }
+
+    // Load the headerless file picked for restoring as a dump starting at `offset`
+    fn import_raw(&mut self, offset: u32, cx: &mut Context<Self>) {
+        let Some(path) = self.raw_import.take() else {
+            return;
+        };
+        match SpiBackup::load_raw(&path, offset) {
+            Ok(backup) => self.stage_restore(path, backup),
+            Err(e) => self.error_message = Some(format!("Restore failed: {}", e)),
+        }
+        cx.notify();
+    }
+
+    fn cancel_restore(&mut self, cx: &mut Context<Self>) {
+        self.restore = None;
+        self.raw_import = None;
+        cx.notify();
+    }
+
+    // Where a headerless dump starts in flash, from the offsets such dumps usually have
+    fn raw_import_section(&self, path: &Path, cx: &Context<Self>) -> Div {
+        let offset_button = |id: &'static str, label: &'static str, offset: u32| {
+            div()
+                .id(id)
+                .p_2()
+                .bg(rgb(0x555555))
+                .rounded_md()
+                .text_color(rgb(0xFFFFFF))
+                .cursor_pointer()
+                .child(label)
+                .on_click(cx.listener(move |this, _, _, cx| this.import_raw(offset, cx)))
+        };
+        div()
+            .flex()
+            .flex_col()
+            .items_center()
+            .gap_2()
+            .child(format!(
+                "{} has no backup header. Which flash offset does the dump start at?",
+                path.display()
+            ))
+            .child(
+                div()
+                    .flex()
+                    .gap_2()
+                    .child(offset_button(
+                        "raw_offset_0",
+                        "0x0000 (whole flash)",
+                        0x0000,
+                    ))
+                    .child(offset_button(
+                        "raw_offset_6000",
+                        "0x6000 (factory config)",
+                        0x6000,
+                    ))
+                    .child(offset_button(
+                        "raw_offset_8000",
+                        "0x8000 (user calibration)",
+                        0x8000,
+                    ))
+                    .child(
+                        div()
+                            .id("raw_import_cancel_btn")
+                            .p_2()
+                            .bg(rgb(0x555555))
+                            .rounded_md()
+                            .text_color(rgb(0xFFFFFF))
+                            .cursor_pointer()
+                            .child("Cancel")
+                            .on_click(cx.listener(|this, _, _, cx| this.cancel_restore(cx))),
+                    ),
+            )
+    }
+
+    fn toggle_restore_other_mac(&mut self, cx: &mut Context<Self>) {
+        if let Some(restore) = &mut self.restore {
+            restore.allow_other_mac = !restore.allow_other_mac;
+        }
+        cx.notify();
+    }
+
+    fn restore_backup(&mut self, cx: &mut Context<Self>) {
+        let Some(restore) = self.restore.take() else {
+            return;
+        };
+        let Some(controller) = self.controllers.first().cloned() else {
+            return;
+        };
+        let allow_any_offset = self.allow_any_offset;
+        let ranges: Vec<Range<u32>> = restore.regions.iter().map(|(r, _)| r.clone()).collect();
+        let (backup, allow_other_mac) = (restore.backup.clone(), restore.allow_other_mac);
+        let worker = controller.clone();
+        self.run_operation(
+            "Restoring backup…",
+            move |op| {
+                let mut c = worker.lock();
+                if allow_any_offset {
+                    c.unsafe_allow_any_offset();
+                }
+                c.restore_spi(&backup, &ranges, allow_other_mac, op)
+            },
+            move |this, result, cancelled, _| {
+                // Show what the controller holds now, even after a partial restore
+                let mut c = controller.lock();
+                if let Ok(info) = c.get_device_info()
+                    && let Some(details) = this.device_info.first_mut()
+                {
+                    *details = DeviceDetails::read(&mut c, info);
+                }
+                match result {
+                    Ok(()) => {
+                        this.backup_status =
+                            Some(format!("Restored from {}", restore.path.display()))
+                    }
+                    Err(e) if cancelled => this.backup_status = Some(e.to_string()),
+                    Err(e) => this.error_message = Some(format!("Restore failed: {}", e)),
+                }
+            },
+            cx,
+        );
+    }
+
+    // Read the regions a restore would write and note which already match the backup
+    fn compare_restore(&mut self, cx: &mut Context<Self>) {
+        let Some(restore) = &self.restore else {
+            return;
+        };
+        let Some(controller) = self.controllers.first().cloned() else {
+            return;
+        };
+        let (backup, regions) = (restore.backup.clone(), restore.regions.clone());
+        self.run_operation(
+            "Comparing the backup with the controller…",
+            move |op| backup.differences(&mut controller.lock(), &regions, op),
+            |this, result, cancelled, _| match (result, &mut this.restore) {
+                (Ok(differing), Some(restore)) => {
+                    restore.differences =
+                        Some(differing.into_iter().map(|(_, name)| name).collect())
+                }
+                (Err(_), _) if cancelled => {}
+                (Err(e), _) => this.error_message = Some(format!("Comparison failed: {}", e)),
+                (Ok(_), None) => {}
+            },
+            cx,
+        );
+    }
+
+    // Exactly what a restore will write, with a confirmation before anything is sent
+    fn restore_summary(&self, restore: &PendingRestore, cx: &Context<Self>) -> Div {
+        let backup_mac = restore.backup.mac_string();
+        let other_controller = self
+            .device_info
+            .first()
+            .is_some_and(|d| backup_mac.as_ref() != Some(&d.mac));
+        let mut origin = vec![backup_mac.unwrap_or_else(|| "an unknown controller".to_string())];
+        if let Some(controller_type) = restore.backup.controller_type {
+            origin.push(controller_type_name(controller_type).to_string());
+        }
+        if let Some(firmware) = restore.backup.firmware {
+            origin.push(format!("firmware {}", firmware));
+        }
+        let ready = !restore.regions.is_empty() && (!other_controller || restore.allow_other_mac);
+        div()
+            .flex()
+            .flex_col()
+            .items_center()
+            .gap_2()
+            .child(format!(
+                "Restore {} (from {})",
+                restore.path.display(),
+                origin.join(", ")
+            ))
+            .when(restore.regions.is_empty(), |el| {
+                el.child("This backup doesn't contain any calibration regions.")
+            })
+            .when(!restore.regions.is_empty(), |el| {
+                el.child("These offsets will be written:")
+            })
+            .children(restore.regions.iter().map(|(range, name)| {
+                div().text_color(rgb(0xAAAAAA)).child(format!(
+                    "0x{:04X}-0x{:04X}  {} ({} bytes)",
+                    range.start,
+                    range.end - 1,
+                    name,
+                    range.end - range.start
+                ))
+            }))
+            .when(other_controller, |el| {
+                el.child(
+                    div()
+                        .text_color(rgb(0xFFB300))
+                        .child("This backup was taken from a different controller."),
+                )
+                .child(
+                    div()
+                        .id("restore_other_mac_toggle")
+                        .cursor_pointer()
+                        .child(format!(
+                            "[{}] Restore it to this controller anyway",
+                            if restore.allow_other_mac { "x" } else { " " }
+                        ))
+                        .on_click(cx.listener(|this, _, _, cx| this.toggle_restore_other_mac(cx))),
+                )
+            })
+            .when_some(restore.differences.as_ref(), |el, differing| {
+                el.child(
+                    div()
+                        .text_color(rgb(0xAAAAAA))
+                        .child(if differing.is_empty() {
+                            "The controller already matches this backup.".to_string()
+                        } else {
+                            format!("Differs from the controller in: {}", differing.join(", "))
+                        }),
+                )
+            })
+            .child(
+                div()
+                    .flex()
+                    .gap_2()
+                    .child(
+                        div()
+                            .id("restore_compare_btn")
+                            .p_2()
+                            .bg(rgb(0x555555))
+                            .rounded_md()
+                            .text_color(rgb(0xFFFFFF))
+                            .cursor_pointer()
+                            .child("Compare with controller")
+                            .on_click(cx.listener(|this, _, _, cx| this.compare_restore(cx))),
+                    )
+                    .child(
+                        div()
+                            .id("restore_confirm_btn")
+                            .p_2()
+                            .bg(rgb(if ready { 0xE53935 } else { 0x333333 }))
+                            .rounded_md()
+                            .text_color(rgb(0xFFFFFF))
+                            .when(ready, |el| {
+                                el.cursor_pointer()
+                                    .on_click(cx.listener(|this, _, _, cx| this.restore_backup(cx)))
+                            })
+                            .child("Restore"),
+                    )
+                    .child(
+                        div()
+                            .id("restore_cancel_btn")
+                            .p_2()
+                            .bg(rgb(0x555555))
+                            .rounded_md()
+                            .text_color(rgb(0xFFFFFF))
+                            .cursor_pointer()
+                            .child("Cancel")
+                            .on_click(cx.listener(|this, _, _, cx| this.cancel_restore(cx))),
+                    ),
+            )
+    }
+
+    fn open_drift_test(&mut self, _cx: &mut Context<Self>) {
+        for c in &self.controllers {
+            let mut c = c.lock();
+            if let Err(e) = c.enable_standard_input().and_then(|_| c.enable_imu()) {
+                self.error_message = Some(format!("Failed to enable motion sensor: {}", e));
+                return;
+            }
+        }
+        self.drift_started = None;
+        self.drift_result = None;
+        self.error_message = None;
+        self.set_step(CalibrationStep::GyroDrift);
+    }
+
+    fn cycle_drift_duration(&mut self, cx: &mut Context<Self>) {
+        let current = DRIFT_TEST_DURATIONS
+            .iter()
+            .position(|&d| d == self.drift_duration)
+            .unwrap_or(0);
+        self.drift_duration = DRIFT_TEST_DURATIONS[(current + 1) % DRIFT_TEST_DURATIONS.len()];
+        cx.notify();
+    }
+
+    fn start_drift_test(&mut self, cx: &mut Context<Self>) {
+        self.drift_measurement = DriftMeasurement::default();
+        self.drift_reports = self.controllers.first().map(|c| c.lock().subscribe());
+        self.drift_started = Some(Instant::now());
+        self.drift_result = None;
+        cx.notify();
+    }
+
+    fn open_stick_drift_test(&mut self, _cx: &mut Context<Self>) {
+        for c in &self.controllers {
+            if let Err(e) = c.lock().enable_standard_input() {
+                self.error_message = Some(format!("Failed to enable input: {}", e));
+                return;
+            }
+        }
+        self.stick_drift_started = None;
+        self.snapback_reports = None;
+        self.error_message = None;
+        self.set_step(CalibrationStep::StickDrift);
+    }
+
+    fn cycle_stick_drift_duration(&mut self, cx: &mut Context<Self>) {
+        let current = STICK_DRIFT_TEST_DURATIONS
+            .iter()
+            .position(|&d| d == self.stick_drift_duration)
+            .unwrap_or(0);
+        self.stick_drift_duration =
+            STICK_DRIFT_TEST_DURATIONS[(current + 1) % STICK_DRIFT_TEST_DURATIONS.len()];
+        cx.notify();
+    }
+
+    // Read what the sticks are calibrated to now, so a re-run after writing measures
+    // against the new values
+    fn start_stick_drift_test(&mut self, cx: &mut Context<Self>) {
+        let mut calibration = Vec::new();
+        for c in &self.controllers {
+            match JoycalFile::read_from(&mut c.lock()) {
+                Ok(file) => calibration.extend(
+                    file.sticks
+                        .iter()
+                        .map(|stick| (stick.side, stick.effective(), stick.parameters.deadzone)),
+                ),
+                Err(e) => {
+                    self.error_message =
+                        Some(format!("Couldn't read the stick calibration: {}", e));
+                    cx.notify();
+                    return;
+                }
+            }
+        }
+        self.stick_drift_calibration = calibration;
+        self.stick_drift_samples = Default::default();
+        if let Some(result) = self.stick_drift_result.take() {
+            self.stick_drift_previous = Some(result);
+        }
+        self.stick_drift_started = Some(Instant::now());
+        self.error_message = None;
+        cx.notify();
+    }
+
+    fn finish_stick_drift_test(&mut self) {
+        let Some(started) = self.stick_drift_started.take() else {
+            return;
+        };
+        let sticks: Vec<_> = self
+            .stick_drift_calibration
+            .iter()
+            .filter_map(|(side, cal, deadzone)| {
+                let samples = match side {
+                    StickSide::Left => &self.stick_drift_samples[0],
+                    StickSide::Right => &self.stick_drift_samples[1],
+                };
+                Some((*side, diagnostics::stick_drift(samples, cal, *deadzone)?))
+            })
+            .collect();
+        if sticks.is_empty() {
+            self.error_message =
+                Some("No stick data received. Check the controller and try again.".to_string());
+            return;
+        }
+        self.stick_drift_result = Some(StickDriftReport {
+            mac: self.session_macs(),
+            finished: unix_time(),
+            seconds: started.elapsed().as_secs_f64(),
+            sticks,
+        });
+    }
+
+    // Every connected controller's address, for naming a saved test result
+    fn session_macs(&self) -> String {
+        self.device_info
+            .iter()
+            .map(|d| d.mac.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    fn export_stick_drift(&mut self, cx: &mut Context<Self>) {
+        let Some(report) = &self.stick_drift_result else {
+            return;
+        };
+        let name = format!(
+            "{}-drift-{}.json",
+            report.mac.replace(':', "-").replace(", ", "_"),
+            report.finished
+        );
+        let dir = dirs::home_dir().unwrap_or_default();
+        let path = cx.prompt_for_new_path(&dir, Some(&name));
+        cx.spawn(async move |this, cx| {
+            let Ok(Ok(Some(path))) = path.await else {
+                return;
+            };
+            let _ = this.update(cx, |this, cx| {
+                let Some(report) = &this.stick_drift_result else {
+                    return;
+                };
+                match report.save(&path) {
+                    Ok(()) => {
+                        this.backup_status =
+                            Some(format!("Drift results exported to {}", path.display()))
+                    }
+                    Err(e) => this.error_message = Some(format!("Export failed: {}", e)),
+                }
+                cx.notify();
+            });
+        })
+        .detach();
+    }
+
+    fn stick_drift_page(&self, cx: &Context<Self>) -> Div {
+        let button = |id: &'static str, label: String, color: u32| {
+            div()
+                .id(id)
+                .p_2()
+                .bg(rgb(color))
+                .rounded_md()
+                .text_color(rgb(0xFFFFFF))
+                .cursor_pointer()
+                .child(label)
+        };
+        div()
+            .flex()
+            .flex_col()
+            .items_center()
+            .gap_4()
+            .child("Stick Drift Test")
+            .child("Put the controller down and don't touch the sticks for the whole test.")
+            .when_some(self.stick_drift_started, |el, started| {
+                el.child(format!(
+                    "Measuring… {:.0}s left",
+                    self.stick_drift_duration
+                        .saturating_sub(started.elapsed())
+                        .as_secs_f32()
+                        .ceil()
+                ))
+            })
+            .when_some(self.stick_drift_result.as_ref(), |el, report| {
+                el.child(stick_drift_summary(
+                    report,
+                    self.stick_drift_previous.as_ref(),
+                ))
+            })
+            .when(self.stick_drift_started.is_none(), |el| {
+                el.child(
+                    div()
+                        .flex()
+                        .gap_4()
+                        .child(
+                            button(
+                                "stick_drift_duration_btn",
+                                format!("Duration: {}s", self.stick_drift_duration.as_secs()),
+                                0x555555,
+                            )
+                            .on_click(
+                                cx.listener(|this, _, _, cx| this.cycle_stick_drift_duration(cx)),
+                            ),
+                        )
+                        .child(
+                            button(
+                                "start_stick_drift_btn",
+                                if self.stick_drift_result.is_some() {
+                                    "Run Again".to_string()
+                                } else {
+                                    "Start".to_string()
+                                },
+                                0x007ACC,
+                            )
+                            .on_click(
+                                cx.listener(|this, _, _, cx| this.start_stick_drift_test(cx)),
+                            ),
+                        )
+                        .when(self.stick_drift_result.is_some(), |el| {
+                            el.child(
+                                button("export_stick_drift_btn", "Export…".to_string(), 0x555555)
+                                    .on_click(
+                                        cx.listener(|this, _, _, cx| this.export_stick_drift(cx)),
+                                    ),
+                            )
+                        })
+                        .child(
+                            button("stick_drift_back_btn", "Back".to_string(), 0x555555)
+                                .on_click(cx.listener(|this, _, _, cx| this.back_to_connected(cx))),
+                        ),
+                )
+            })
+    }
+
+    fn open_snapback_test(&mut self, cx: &mut Context<Self>) {
+        for c in &self.controllers {
+            if let Err(e) = c.lock().enable_standard_input() {
+                self.error_message = Some(format!("Failed to enable input: {}", e));
+                return;
+            }
+        }
+        self.snapback_stick = if self.has_left {
+            StickSide::Left
+        } else {
+            StickSide::Right
+        };
+        self.error_message = None;
+        if self.arm_snapback() {
+            self.set_step(CalibrationStep::Snapback);
+        }
+        cx.notify();
+    }
+
+    fn toggle_snapback_stick(&mut self, cx: &mut Context<Self>) {
+        self.snapback_stick = match self.snapback_stick {
+            StickSide::Left => StickSide::Right,
+            StickSide::Right => StickSide::Left,
+        };
+        if !self.arm_snapback() {
+            self.back_to_connected(cx);
+        }
+        cx.notify();
+    }
+
+    // Start listening for flicks of the chosen stick, measured from the center the
+    // controller has stored for it
+    fn arm_snapback(&mut self) -> bool {
+        let side = self.snapback_stick;
+        let has_stick = |c: &Controller| match side {
+            StickSide::Left => c.capabilities().has_left_stick,
+            StickSide::Right => c.capabilities().has_right_stick,
+        };
+        let Some(controller) = self.controllers.iter().find(|c| has_stick(&c.lock())) else {
+            return false;
+        };
+        let mut c = controller.lock();
+        let stored = JoycalFile::read_from(&mut c)
+            .map(|file| file.sticks.into_iter().find(|stick| stick.side == side));
+        match stored {
+            Ok(Some(stick)) => {
+                let cal = stick.effective();
+                self.snapback_center = (cal.xcenter, cal.ycenter);
+            }
+            Ok(None) => return false,
+            Err(e) => {
+                self.error_message = Some(format!("Couldn't read the stick calibration: {}", e));
+                return false;
+            }
+        }
+        self.snapback_reports = Some(c.subscribe());
+        self.snapback_origin = Instant::now();
+        self.snapback_samples.clear();
+        self.snapback_results = Default::default();
+        self.snapback_latest = None;
+        true
+    }
+
+    // Take in new reports, and measure a flick once its window after release is complete
+    fn collect_snapback(&mut self) {
+        let Some(reports) = &self.snapback_reports else {
+            return;
+        };
+        let (cx, cy) = self.snapback_center;
+        for report in reports.drain() {
+            let (x, y) = match self.snapback_stick {
+                StickSide::Left => (report.data.lx, report.data.ly),
+                StickSide::Right => (report.data.rx, report.data.ry),
+            };
+            self.snapback_samples.push(TimedOffset {
+                ms: report
+                    .received
+                    .saturating_duration_since(self.snapback_origin)
+                    .as_secs_f64()
+                    * 1000.0,
+                dx: x as f64 - cx as f64,
+                dy: y as f64 - cy as f64,
+            });
+        }
+        let Some(newest) = self.snapback_samples.last().map(|s| s.ms) else {
+            return;
+        };
+        self.snapback_samples
+            .retain(|sample| newest - sample.ms <= SNAPBACK_BUFFER_MS);
+        let complete = diagnostics::release_index(&self.snapback_samples).is_some_and(|release| {
+            newest - self.snapback_samples[release].ms >= SNAPBACK_WINDOW_MS
+        });
+        if !complete {
+            return;
+        }
+        if let Some(result) = diagnostics::snapback(&self.snapback_samples) {
+            let direction = direction_index(result.angle);
+            self.snapback_results[direction] = Some(result);
+            self.snapback_latest = Some(direction);
+        }
+        self.snapback_samples.clear();
+    }
+
+    fn snapback_page(&self, cx: &Context<Self>) -> Div {
+        let stick = match self.snapback_stick {
+            StickSide::Left => "left",
+            StickSide::Right => "right",
+        };
+        let results = self
+            .snapback_results
+            .iter()
+            .enumerate()
+            .filter_map(|(direction, result)| Some((direction, result.as_ref()?)))
+            .map(|(direction, result)| {
+                div()
+                    .when(Some(direction) == self.snapback_latest, |el| {
+                        el.text_color(rgb(0x4FC3F7))
+                    })
+                    .child(format!(
+                        "Flick {}: overshoot {:.0}, {}",
+                        DIRECTION_NAMES[direction],
+                        result.overshoot,
+                        match result.settle_ms {
+                            Some(ms) => format!("settles in {:.0} ms", ms),
+                            None => format!("not settled after {:.0} ms", SNAPBACK_WINDOW_MS),
+                        }
+                    ))
+            });
+        div()
+            .flex()
+            .flex_col()
+            .items_center()
+            .gap_4()
+            .child("Snapback Test")
+            .child(format!(
+                "Push the {} stick all the way in one direction, hold it, then let go.",
+                stick
+            ))
+            .child("Repeat in other directions; each flick is measured on its own.")
+            .when(self.has_left && self.has_right, |el| {
+                el.child(
+                    div()
+                        .id("snapback_stick_btn")
+                        .p_2()
+                        .bg(rgb(0x555555))
+                        .rounded_md()
+                        .text_color(rgb(0xFFFFFF))
+                        .cursor_pointer()
+                        .child(format!("Testing the {} stick (switch)", stick))
+                        .on_click(cx.listener(|this, _, _, cx| this.toggle_snapback_stick(cx))),
+                )
+            })
+            .when_some(
+                self.snapback_latest
+                    .and_then(|direction| self.snapback_results[direction].as_ref()),
+                |el, latest| el.child(snapback_plot(&latest.trace)),
+            )
+            .children(results)
+            .child(
+                div()
+                    .id("snapback_back_btn")
+                    .p_2()
+                    .bg(rgb(0x555555))
+                    .rounded_md()
+                    .text_color(rgb(0xFFFFFF))
+                    .cursor_pointer()
+                    .child("Back")
+                    .on_click(cx.listener(|this, _, _, cx| this.back_to_connected(cx))),
+            )
+    }
+
+    fn reset_packet_stats(&self) {
+        for c in &self.controllers {
+            c.lock().reset_packet_stats();
+        }
+    }
+
+    // Note under the range sweep once enough reports went missing to matter
+    fn dropped_packets_note(&self) -> Option<String> {
+        let (mut received, mut dropped, mut duplicates) = (0, 0, 0);
+        for c in &self.controllers {
+            let stats = c.lock().packet_stats();
+            received += stats.received;
+            dropped += stats.dropped;
+            duplicates += stats.duplicates;
+        }
+        if dropped <= DROPPED_PACKETS_NOTE {
+            return None;
+        }
+        let mut note = format!("{} of {} packets dropped", dropped, received + dropped);
+        if duplicates > 0 {
+            note += &format!(", {} repeated", duplicates);
+        }
+        Some(note + ". The sweep may have gaps; consider repeating it.")
+    }
+
+    // Slowest report rate among the connected controllers, while a step is collecting data
+    fn report_rate(&self) -> Option<f32> {
+        if !matches!(
+            self.calibration_step,
+            CalibrationStep::CalibrateCenter
+                | CalibrationStep::CalibrateRange
+                | CalibrationStep::MotionCollect
+                | CalibrationStep::GyroDrift
+                | CalibrationStep::StickDrift
+                | CalibrationStep::Snapback
+        ) {
+            return None;
+        }
+        self.controllers
+            .iter()
+            .map(|c| c.lock().report_rate_hz())
+            .min_by(f32::total_cmp)
+    }
+
+    // Controller bindings for the current step, shown along the bottom of the window
+    fn button_hints(&self) -> Option<&'static str> {
+        match self.calibration_step {
+            CalibrationStep::CalibrateCenter | CalibrationStep::CalibrateRange => {
+                Some("A/SR: Next    B/SL: Back")
+            }
+            CalibrationStep::OuterDeadzoneChoice => Some("A/SR: Continue    B/SL: Back"),
+            CalibrationStep::Review | CalibrationStep::MotionReview if !self.read_only => {
+                Some("Hold A/SR for 1s: Write    B/SL: Back")
+            }
+            CalibrationStep::Review | CalibrationStep::MotionReview => Some("B/SL: Back"),
+            CalibrationStep::GyroDrift if self.drift_started.is_none() => {
+                Some("A/SR: Start    B/SL: Back")
+            }
+            CalibrationStep::GyroDrift => Some("B/SL: Back"),
+            CalibrationStep::StickDrift if self.stick_drift_started.is_none() => {
+                Some("A/SR: Start    B/SL: Back")
+            }
+            CalibrationStep::StickDrift | CalibrationStep::Snapback => Some("B/SL: Back"),
+            _ => None,
+        }
+    }
+
+    // Steps where confirm writes to flash and so needs a long press
+    fn confirm_writes(&self) -> bool {
+        matches!(
+            self.calibration_step,
+            CalibrationStep::Review | CalibrationStep::MotionReview
+        )
+    }
+
+    // Drive the wizard from the controller: A or SR confirms, B or SL goes back.
+    // SR/SL cover a single Joy-Con held sideways, where the left one has no A or B.
+    fn handle_buttons(&mut self, cx: &mut Context<Self>) {
+        let confirm = |b: &ButtonState| b.a || b.left_sr || b.right_sr;
+        let back = |b: &ButtonState| b.b || b.left_sl || b.right_sl;
+        let buttons = self.stick_data.buttons;
+        let confirm_pressed = confirm(&buttons) && !confirm(&self.prev_buttons);
+        let back_pressed = back(&buttons) && !back(&self.prev_buttons);
+        self.prev_buttons = buttons;
+        if !confirm(&buttons) {
+            self.confirm_held_since = None;
+        }
+
+        let step = self.calibration_step;
+        if confirm_pressed && self.confirm_writes() {
+            self.confirm_held_since = Some(Instant::now());
+        } else if confirm_pressed {
+            self.button_confirm(cx);
+        } else if self
+            .confirm_held_since
+            .is_some_and(|since| since.elapsed() >= WRITE_HOLD_DURATION)
+        {
+            // Once per press, however long it's held after that
+            self.confirm_held_since = None;
+            self.button_confirm(cx);
+        } else if back_pressed {
+            self.button_back(cx);
+        }
+        if self.calibration_step != step {
+            self.confirm_held_since = None;
+        }
+    }
+
+    fn button_confirm(&mut self, cx: &mut Context<Self>) {
+        match self.calibration_step {
+            CalibrationStep::CalibrateCenter | CalibrationStep::CalibrateRange => {
+                self.next_step(cx)
+            }
+            CalibrationStep::OuterDeadzoneChoice => self.finish_outer_deadzone(cx),
+            CalibrationStep::Review => self.write_calibration(false, cx),
+            CalibrationStep::MotionReview => self.write_motion_calibration(false, cx),
+            CalibrationStep::GyroDrift if self.drift_started.is_none() => self.start_drift_test(cx),
+            CalibrationStep::StickDrift if self.stick_drift_started.is_none() => {
+                self.start_stick_drift_test(cx)
+            }
+            _ => {}
+        }
+    }
+
+    fn button_back(&mut self, cx: &mut Context<Self>) {
+        match self.calibration_step {
+            CalibrationStep::CalibrateCenter => {
+                self.set_home_leds(HOME_LED_OFF);
+                self.back_to_connected(cx);
+            }
+            // Going back repeats the previous step's collection from scratch
+            CalibrationStep::CalibrateRange => {
+                self.set_step(CalibrationStep::CalibrateCenter);
+                self.calibration_data = CalibrationData::new();
+                self.set_home_leds(HOME_LED_COLLECTING);
+            }
+            CalibrationStep::OuterDeadzoneChoice => {
+                self.set_step(CalibrationStep::CalibrateRange);
+                self.start_range_collection();
+                self.reset_packet_stats();
+                self.set_home_leds(HOME_LED_COLLECTING);
+            }
+            // The range data is still there, so the choice can simply be made again
+            CalibrationStep::Review => {
+                self.set_step(CalibrationStep::OuterDeadzoneChoice);
+            }
+            CalibrationStep::MotionReview
+            | CalibrationStep::GyroDrift
+            | CalibrationStep::StickDrift
+            | CalibrationStep::Snapback => self.back_to_connected(cx),
+            _ => {}
+        }
+    }
+
+    fn finish_drift_test(&mut self) {
+        self.drift_started = None;
+        self.drift_reports = None;
+        self.drift_result = self
+            .drift_measurement
+            .result()
+            .map(|result| GyroDriftReport {
+                mac: self.session_macs(),
+                finished: unix_time(),
+                result,
+            });
+        if self.drift_result.is_none() {
+            self.error_message =
+                Some("No motion data received. Check the controller and try again.".to_string());
+        }
+    }
+
+    fn export_gyro_drift(&mut self, cx: &mut Context<Self>) {
+        let Some(report) = &self.drift_result else {
+            return;
+        };
+        let name = format!(
+            "{}-gyro-drift-{}.json",
+            report.mac.replace(':', "-").replace(", ", "_"),
+            report.finished
+        );
+        let dir = dirs::home_dir().unwrap_or_default();
+        let path = cx.prompt_for_new_path(&dir, Some(&name));
+        cx.spawn(async move |this, cx| {
+            let Ok(Ok(Some(path))) = path.await else {
+                return;
+            };
+            let _ = this.update(cx, |this, cx| {
+                let Some(report) = &this.drift_result else {
+                    return;
+                };
+                match report.save(&path) {
+                    Ok(()) => {
+                        this.backup_status =
+                            Some(format!("Gyro drift results exported to {}", path.display()))
+                    }
+                    Err(e) => this.error_message = Some(format!("Export failed: {}", e)),
+                }
+                cx.notify();
+            });
+        })
+        .detach();
+    }
+
+    // Leave a diagnostic page; the controller stays in full report mode
+    fn back_to_connected(&mut self, cx: &mut Context<Self>) {
+        self.drift_started = None;
+        self.drift_reports = None;
+        self.stick_drift_started = None;
+        self.error_message = None;
+        self.set_step(CalibrationStep::Connected);
+        cx.notify();
+    }
+
+    fn finish_motion_collection(&mut self) {
+        self.motion_result = self.motion_data.result();
+        if self.motion_result.is_none() {
+            // Restart the step rather than review an empty average
+            self.error_message =
+                Some("No motion data received. Check the controller and try again.".to_string());
+            self.motion_started = Some(Instant::now());
+            return;
+        }
+        self.set_step(CalibrationStep::MotionReview);
+    }
+
+    fn write_motion_calibration(&mut self, ignore_low_battery: bool, cx: &mut Context<Self>) {
+        if !self.can_write(ignore_low_battery) {
+            return;
+        }
+        let Some(cal) = self.motion_result else {
+            return;
+        };
+        // Nothing from an earlier stick write applies to this one
+        self.write_checks.clear();
+        self.write_backups.clear();
+        self.undo.clear();
+        let result = self.controllers[0].lock().write_imu_calibration(cal);
+        match result {
+            Err(e)
+                if e.downcast_ref::<ControllerError>() == Some(&ControllerError::Disconnected) =>
+            {
+                self.start_reconnect(0, cx);
+                return;
+            }
+            Err(e) => {
+                self.error_message = Some(write_error_message(&e));
+                return;
+            }
+            Ok(()) => {}
+        }
+        let _ = self.controllers[0].lock().blink_home_led(3);
+        self.set_step(CalibrationStep::Done);
+    }
+
+    // Flash the player LEDs for a moment, then go back to all four lit
+    fn identify(&mut self, cx: &mut Context<Self>) {
+        let controllers = self.controllers.clone();
+        for c in &controllers {
+            let _ = c.lock().set_player_leds(PLAYER_LEDS_FLASH_ALL);
+        }
+        self.identify_task = Some(cx.spawn(async move |_, cx| {
+            cx.background_executor().timer(IDENTIFY_DURATION).await;
+            for c in &controllers {
+                let _ = c.lock().set_player_leds(PLAYER_LEDS_ALL);
+            }
+        }));
+    }
+
+    // Buzz every connected controller once. Runs off the UI thread since the
+    // pulse blocks for its whole duration.
+    fn identify_rumble(&mut self, cx: &mut Context<Self>) {
+        let controllers = self.controllers.clone();
+        let pulse = cx.background_executor().spawn(async move {
+            for c in &controllers {
+                let _ = c.lock().rumble_pulse(RUMBLE_PULSE_MS);
+            }
+        });
+        self.identify_task = Some(cx.spawn(async move |_, _| pulse.await));
+    }
+
+    // Restore each controller's input mode and close it
+    // Writes finish on this thread before any step change, so by the time this runs on
+    // exit nothing is left in flight and sleeping can't cut a write short
+    fn shutdown_controllers(&mut self, sleep: bool) {
+        // The identify task holds handles too; drop it so the devices really close
+        self.identify_task = None;
+        for c in self.controllers.drain(..) {
+            let _ = c.lock().shutdown(sleep);
+        }
+    }
+
+    // Close the current device(s) and go back to the Connect step with fresh state.
+    // Stick polling runs on this thread, so once the handles are drained nothing touches them.
+    fn calibrate_another(&mut self, cx: &mut Context<Self>) {
+        // Restores simple HID mode and drops the only handles before anything is reopened
+        self.shutdown_controllers(false);
+        self.reconnect_task = None;
+        self.lost_controller = None;
+        self.asleep = false;
+        self.device_busy = false;
+
+        self.device_info.clear();
+        self.controller_type = None;
+        self.connection_kind = None;
+        self.fast_usb = false;
+        self.paired = false;
+        self.read_only = false;
+        self.has_left = false;
+        self.has_right = false;
+        self.has_imu = false;
+        self.stick_data = StickData::default();
+        self.motion_data = MotionCalibrationData::default();
+        self.motion_started = None;
+        self.motion_result = None;
+        self.drift_measurement = DriftMeasurement::default();
+        self.drift_reports = None;
+        self.drift_started = None;
+        self.drift_result = None;
+        // A new controller's first drift run has nothing to compare against
+        self.stick_drift_started = None;
+        self.stick_drift_result = None;
+        self.stick_drift_previous = None;
+        self.battery.clear();
+        self.calibration_data = CalibrationData::new();
+        self.range_coverage = Default::default();
+        self.measured_padding = [None; 2];
+        self.left_result = StickCalibration::default();
+        self.right_result = StickCalibration::default();
+        self.left_deadzone = 0;
+        self.right_deadzone = 0;
+        self.center_result = None;
+        self.padding_override = None;
+        self.padding_text.clear();
+        self.symmetric_range = false;
+        self.confirm_factory_reset = false;
+        self.operation = None;
+        self.backup_status = None;
+        self.restore = None;
+        self.raw_import = None;
+        self.imported_from = None;
+        self.import_warning = None;
+        self.color_editor = None;
+        self.write_partial = false;
+        self.calibration_details.clear();
+        self.snapshot_list = None;
+        self.write_checks.clear();
+        self.write_backups.clear();
+        self.undo.clear();
+        self.confirm_undo = false;
+        self.undone = false;
+        self.show_write_preview = false;
+        self.dry_run_writes.clear();
+        self.error_message = None;
+        self.calibration_step = CalibrationStep::Connect;
+
+        if self.device_path.is_none() && self.replay.is_none() {
+            self.rescan();
+            self.start_scanning(cx);
+        }
+        cx.notify();
+    }
+
+    // Sideways only means something for a lone Joy-Con; a pair is always held upright
+    fn can_turn_sideways(&self) -> bool {
+        !self.paired
+            && matches!(
+                self.controller_type,
+                Some(ControllerType::JoyConL | ControllerType::JoyConR)
+            )
+    }
+
+    fn toggle_orientation(&mut self, cx: &mut Context<Self>) {
+        self.orientation = match self.orientation {
+            Orientation::Upright => Orientation::Sideways,
+            Orientation::Sideways => Orientation::Upright,
+        };
+        cx.notify();
+    }
+
+    // Turn a raw stick position a quarter turn so "up" on screen is "up" in hand. A
+    // sideways Joy-Con (L) has its stick's +X pointing up, a Joy-Con (R) its -X.
+    fn display_point(&self, x: u16, y: u16) -> (u16, u16) {
+        if self.orientation != Orientation::Sideways || !self.can_turn_sideways() {
+            return (x, y);
+        }
+        if self.controller_type == Some(ControllerType::JoyConL) {
+            (0xFFF - y.min(0xFFF), x)
+        } else {
+            (y, 0xFFF - x.min(0xFFF))
+        }
+    }
+
+    // Turning swaps and flips axes, so the rotated corners need sorting back into min/max
+    fn display_range(&self, min: (u16, u16), max: (u16, u16)) -> ((u16, u16), (u16, u16)) {
+        let a = self.display_point(min.0, min.1);
+        let b = self.display_point(max.0, max.1);
+        ((a.0.min(b.0), a.1.min(b.1)), (a.0.max(b.0), a.1.max(b.1)))
+    }
+
+    fn display_stick_data(&self) -> StickData {
+        let (lx, ly) = self.display_point(self.stick_data.lx, self.stick_data.ly);
+        let (rx, ry) = self.display_point(self.stick_data.rx, self.stick_data.ry);
+        StickData {
+            lx,
+            ly,
+            rx,
+            ry,
+            ..self.stick_data
+        }
+    }
+
+    fn display_calibration_data(&self) -> CalibrationData {
+        let d = &self.calibration_data;
+        let ((min_lx, min_ly), (max_lx, max_ly)) =
+            self.display_range((d.min_lx, d.min_ly), (d.max_lx, d.max_ly));
+        let ((min_rx, min_ry), (max_rx, max_ry)) =
+            self.display_range((d.min_rx, d.min_ry), (d.max_rx, d.max_ry));
+        let (center_lx, center_ly) = self.display_point(d.center_lx, d.center_ly);
+        let (center_rx, center_ry) = self.display_point(d.center_rx, d.center_ry);
+        CalibrationData {
+            min_lx,
+            max_lx,
+            min_ly,
+            max_ly,
+            min_rx,
+            max_rx,
+            min_ry,
+            max_ry,
+            center_lx,
+            center_ly,
+            center_rx,
+            center_ry,
+            slack_l: d.slack_l,
+            slack_r: d.slack_r,
+            noise: d.noise.clone(),
+            samples: d.samples,
+            rejected: d.rejected,
+            accepted: d.accepted,
+            // Only the figures above are drawn
+            ..CalibrationData::default()
+        }
+    }
+
+    fn display_result(&self, cal: StickCalibration) -> StickCalibration {
+        let ((xmin, ymin), (xmax, ymax)) =
+            self.display_range((cal.xmin, cal.ymin), (cal.xmax, cal.ymax));
+        let (xcenter, ycenter) = self.display_point(cal.xcenter, cal.ycenter);
+        StickCalibration {
+            xmax,
+            ymax,
+            xcenter,
+            ycenter,
+            xmin,
+            ymin,
+        }
+    }
+
+    fn visual_label(&self, label: &str) -> String {
+        if self.orientation == Orientation::Sideways && self.can_turn_sideways() {
+            format!("{} (sideways)", label)
+        } else {
+            label.to_string()
+        }
+    }
+
+    fn imu_settings(&self) -> ImuSettings {
+        self.controllers
+            .first()
+            .map(|c| c.lock().imu_settings())
+            .unwrap_or_default()
+    }
+
+    fn step_imu_settings(
+        &mut self,
+        next: &dyn Fn(ImuSettings) -> ImuSettings,
+        cx: &mut Context<Self>,
+    ) {
+        let s = next(self.imu_settings());
+        for c in &self.controllers {
+            if let Err(e) = c.lock().set_imu_sensitivity(
+                s.gyro_range,
+                s.accel_range,
+                s.gyro_rate,
+                s.accel_filter,
+            ) {
+                self.error_message = Some(format!("Failed to configure motion sensor: {}", e));
+            }
+        }
+        cx.notify();
+    }
+
+    // Latest motion sample, so a stuck or dead IMU is easy to spot, with its settings
+    fn imu_section(&self, cx: &Context<Self>, imu: &ImuData) -> Div {
+        let sample = imu.samples[0];
+        let s = self.imu_settings();
+        div()
+            .flex()
+            .flex_col()
+            .items_center()
+            .gap_1()
+            .text_color(rgb(0xAAAAAA))
+            .child(format!(
+                "Accel (g): {:>6.2} {:>6.2} {:>6.2}",
+                sample.accel[0], sample.accel[1], sample.accel[2]
+            ))
+            .child(format!(
+                "Gyro (dps): {:>7.1} {:>7.1} {:>7.1}",
+                sample.gyro[0], sample.gyro[1], sample.gyro[2]
+            ))
+            .child(
+                div()
+                    .flex()
+                    .gap_2()
+                    .child(imu_setting_button(
+                        cx,
+                        "gyro_range_btn",
+                        format!("±{} dps", s.gyro_range.full_scale()),
+                        |s| ImuSettings {
+                            gyro_range: s.gyro_range.next(),
+                            ..s
+                        },
+                    ))
+                    .child(imu_setting_button(
+                        cx,
+                        "accel_range_btn",
+                        format!("±{} g", s.accel_range.full_scale()),
+                        |s| ImuSettings {
+                            accel_range: s.accel_range.next(),
+                            ..s
+                        },
+                    ))
+                    .child(imu_setting_button(
+                        cx,
+                        "gyro_rate_btn",
+                        format!("Gyro {}Hz", s.gyro_rate.hz()),
+                        |s| ImuSettings {
+                            gyro_rate: s.gyro_rate.next(),
+                            ..s
+                        },
+                    ))
+                    .child(imu_setting_button(
+                        cx,
+                        "accel_filter_btn",
+                        format!("Accel filter {}Hz", s.accel_filter.hz()),
+                        |s| ImuSettings {
+                            accel_filter: s.accel_filter.next(),
+                            ..s
+                        },
+                    )),
+            )
+    }
+
+    // Steps where full input reports are enabled and should arrive continuously
+    fn is_streaming(&self) -> bool {
+        matches!(
+            self.calibration_step,
+            CalibrationStep::CalibrateCenter
+                | CalibrationStep::CalibrateRange
+                | CalibrationStep::OuterDeadzoneChoice
+                | CalibrationStep::Review
+                | CalibrationStep::MotionCollect
+                | CalibrationStep::MotionReview
+                | CalibrationStep::GyroDrift
+                | CalibrationStep::StickDrift
+                | CalibrationStep::Snapback
+        )
+    }
+
+    fn update_stick_data(&mut self, cx: &mut Context<Self>) {
+        // The operation holds the controller; its task keeps the progress bar moving
+        if self.calibration_step == CalibrationStep::Working {
+            cx.notify();
+            return;
+        }
+        let streaming = self.is_streaming();
+        let mut updated = false;
+        let mut asleep = false;
+        for (i, c) in self.controllers.iter().enumerate() {
+            let mut c = c.lock();
+            let was_silent = c.time_since_last_report() >= SLEEP_TIMEOUT;
+            let res = c.read_stick_data();
+            if streaming && res.is_ok() && was_silent {
+                // The report mode resets when the controller wakes up. Skip this report,
+                // which is in the wrong format, and carry on with the current step.
+                let _ = c.enable_standard_input();
+                continue;
+            }
+            if streaming && res.is_err() && c.time_since_last_report() >= SLEEP_TIMEOUT {
+                asleep = true;
+            }
+            if let Ok(StickData {
+                battery: Some(status),
+                ..
+            }) = res
+            {
+                let controller_type = c.get_controller_type();
+                match self.battery.iter_mut().find(|(t, _)| *t == controller_type) {
+                    Some(entry) => entry.1 = status,
+                    None => self.battery.push((controller_type, status)),
+                }
+            }
+            match res {
+                Ok(data) if self.paired => {
+                    // Left Joy-Con feeds the left stick, right Joy-Con the right one
+                    let is_left = c.get_controller_type() == ControllerType::JoyConL;
+                    if is_left {
+                        self.stick_data.lx = data.lx;
+                        self.stick_data.ly = data.ly;
+                    } else {
+                        self.stick_data.rx = data.rx;
+                        self.stick_data.ry = data.ry;
+                    }
+                    self.stick_data.buttons.merge_half(&data.buttons, is_left);
+                    updated = true;
+                }
+                Ok(data) => {
+                    self.stick_data = data;
+                    updated = true;
+                }
+                Err(e)
+                    if e.downcast_ref::<ControllerError>()
+                        == Some(&ControllerError::Disconnected) =>
+                {
+                    drop(c);
+                    self.start_reconnect(i, cx);
+                    cx.notify();
+                    return;
+                }
+                Err(_) => {}
+            }
+        }
+
+        if asleep != self.asleep {
+            self.asleep = asleep;
+            cx.notify();
+        }
+
+        if updated {
+            self.smooth_sticks();
+            if self.calibration_step == CalibrationStep::CalibrateCenter
+                || self.calibration_step == CalibrationStep::CalibrateRange
+            {
+                let kept = self
+                    .calibration_data
+                    .update(&self.stick_data, [self.has_left, self.has_right]);
+                if self.calibration_step == CalibrationStep::CalibrateRange {
+                    let StickData { lx, ly, rx, ry, .. } = self.stick_data;
+                    if kept[0] {
+                        self.range_coverage[0].add(lx, ly);
+                    }
+                    if kept[1] {
+                        self.range_coverage[1].add(rx, ry);
+                    }
+                }
+                if self.calibration_data.samples == ENOUGH_SAMPLES {
+                    self.set_home_leds(HOME_LED_ENOUGH);
+                }
+                cx.notify();
+            } else if self.calibration_step == CalibrationStep::MotionCollect {
+                self.motion_data.update(&self.stick_data.imu);
+                if self
+                    .motion_started
+                    .is_some_and(|started| started.elapsed() >= MOTION_COLLECT_DURATION)
+                {
+                    self.finish_motion_collection();
+                }
+                cx.notify();
+            } else if self.calibration_step == CalibrationStep::GyroDrift {
+                if let Some(started) = self.drift_started {
+                    if let Some(reports) = &self.drift_reports {
+                        for report in reports.drain() {
+                            self.drift_measurement.add(&report);
+                        }
+                    }
+                    if started.elapsed() >= self.drift_duration {
+                        self.finish_drift_test();
+                    }
+                }
+                cx.notify();
+            } else if self.calibration_step == CalibrationStep::StickDrift {
+                if let Some(started) = self.stick_drift_started {
+                    let data = self.stick_data;
+                    let [left, right] = &mut self.stick_drift_samples;
+                    if self.has_left {
+                        left.push((data.lx, data.ly));
+                    }
+                    if self.has_right {
+                        right.push((data.rx, data.ry));
+                    }
+                    if started.elapsed() >= self.stick_drift_duration {
+                        self.finish_stick_drift_test();
+                    }
+                }
+                cx.notify();
+            } else if self.calibration_step == CalibrationStep::Snapback {
+                self.collect_snapback();
+                cx.notify();
+            } else if self.calibration_step == CalibrationStep::Connected
+                || self.calibration_step == CalibrationStep::Review
+                || self.calibration_step == CalibrationStep::MotionReview
+                || self.calibration_step == CalibrationStep::Done
+            {
+                cx.notify();
+            }
+            self.handle_buttons(cx);
+        }
+    }
+}
+
+// Neutral palette, used when a controller has no colors stored
+const NEUTRAL_STICK_BACKGROUND: u32 = 0x222222;
+const NEUTRAL_HEADER_BACKGROUND: u32 = 0x333333;
+
+fn rgb_hex([r, g, b]: [u8; 3]) -> u32 {
+    (r as u32) << 16 | (g as u32) << 8 | b as u32
+}
+
+// Mostly the neutral background with a quarter of the body color mixed in,
+// so the stick dot and overlays stay readable on any shell color
+fn tinted_background(color: [u8; 3]) -> u32 {
+    let neutral = (NEUTRAL_STICK_BACKGROUND & 0xFF) as u16;
+    rgb_hex(color.map(|c| ((c as u16 + 3 * neutral) / 4) as u8))
+}
+
+fn battery_indicator(battery: &[(ControllerType, BatteryStatus)]) -> Div {
+    div()
+        .flex()
+        .gap_4()
+        .children(battery.iter().map(|(controller_type, status)| {
+            let color = match status.level {
+                BatteryLevel::Empty | BatteryLevel::Critical => 0xFF0000,
+                BatteryLevel::Low => 0xFFA500,
+                BatteryLevel::Medium | BatteryLevel::Full => 0x00FF00,
+            };
+            div().text_color(rgb(color)).child(format!(
+                "{} battery: {}{}",
+                controller_type_name(*controller_type),
+                status.level,
+                if status.charging { " (charging)" } else { "" }
+            ))
+        }))
+}
+
+// Visual components
+#[allow(clippy::too_many_arguments)]
+fn stick_deadzone_visual(
+    _cx: &Context<CalibrationApp>,
+    x: u16,
+    y: u16,
+    smoothed: Option<(u16, u16)>,
+    min_x: u16,
+    max_x: u16,
+    min_y: u16,
+    max_y: u16,
+    center_x: u16,
+    center_y: u16,
+    deadzone: u16,
+    label: &str,
+    background: u32,
+) -> impl IntoElement {
+    let size = 255.0;
+    let raw_x_pct = x as f32 / 4095.0;
+    let raw_y_pct = 1.0 - (y as f32 / 4095.0);
+    let (shown_x_pct, shown_y_pct) = smoothed.map_or((raw_x_pct, raw_y_pct), |(x, y)| {
+        (x as f32 / 4095.0, 1.0 - (y as f32 / 4095.0))
+    });
+    let min_x_pct = min_x as f32 / 4095.0;
+    let max_x_pct = max_x as f32 / 4095.0;
+    let min_y_pct = min_y as f32 / 4095.0;
+    let max_y_pct = max_y as f32 / 4095.0;
+    let dz_pct = (deadzone as f32 / 4095.0) * 2.0;
+    let cx_pct = center_x as f32 / 4095.0;
+    let cy_pct = center_y as f32 / 4095.0;
+
+    div()
+        .flex()
+        .flex_col()
+        .items_center()
+        .child(label.to_string())
+        .child(
+            div()
+                .size(px(255.0))
+                .bg(rgb(background))
+                .border_0()
+                .relative()
+                // Deadzone viz
+                .child(
+                    div()
+                        .absolute()
+                        .size(px(dz_pct) * size)
+                        .left(px((cx_pct) * size) - px(dz_pct * size / 2.0))
+                        .top(px((1.0 - cy_pct) * size) - px(dz_pct * size / 2.0))
+                        .rounded_full()
+                        .bg(rgba(0xFF00FF88)),
+                )
+                // min/max viz
+                .child(
+                    div()
+                        .bg(rgba(0x0000FF88))
+                        .absolute()
+                        .w(px((max_x_pct - min_x_pct) * size))
+                        .h(px((max_y_pct - min_y_pct) * size))
+                        .left(px((cx_pct - (max_x_pct - min_x_pct) / 2.0) * size))
+                        .top(px((1.0 - cy_pct - (max_y_pct - min_y_pct) / 2.0) * size)),
+                )
+                .children(stick_dots(
+                    (raw_x_pct, raw_y_pct),
+                    smoothed.is_some().then_some((shown_x_pct, shown_y_pct)),
+                    size,
+                )),
+        )
+        .child(format!("X: {:.3}\nY: {:.3}", shown_x_pct, shown_y_pct))
+}
+
+// Visualize stick X Y range
+#[allow(clippy::too_many_arguments)]
+fn stick_range_visual(
+    _cx: &Context<CalibrationApp>,
+    x: u16,
+    y: u16,
+    smoothed: Option<(u16, u16)>,
+    min_x: u16,
+    max_x: u16,
+    min_y: u16,
+    max_y: u16,
+    ring: &[((u16, u16), bool)],
+    octagon: &[((u16, u16), bool)],
+    label: &str,
+    background: u32,
+) -> impl IntoElement {
+    let size = 255.0;
+    let to_px = |(x, y): (u16, u16)| {
+        (
+            px(x as f32 / 4095.0 * size),
+            px((1.0 - y as f32 / 4095.0) * size),
+        )
+    };
+    let outline: Vec<_> = octagon.iter().map(|&(point, _)| to_px(point)).collect();
+    let raw_x_pct = x as f32 / 4095.0;
+    let raw_y_pct = 1.0 - (y as f32 / 4095.0);
+    let smoothed = smoothed.map(|(x, y)| (x as f32 / 4095.0, 1.0 - (y as f32 / 4095.0)));
+    let raw_min_x = min_x as f32 / 4095.0;
+    let raw_min_y = min_y as f32 / 4095.0;
+    let raw_max_x = max_x as f32 / 4095.0;
+    let raw_max_y = max_y as f32 / 4095.0;
+
+    div()
+        .flex()
+        .flex_col()
+        .items_center()
+        .child(label.to_string())
+        .child(
+            div()
                 .relative()
                 .size(px(size))
-                .bg(rgb(0x222222))
+                .bg(rgb(background))
                 // Range box
                 .child(
                     div()
@@ -367,86 +5345,930 @@ fn stick_range_visual(
                         .border_color(rgba(0xFF00FF88))
                         .border(px(1.0)),
                 )
-                // Stick Dot
+                // The fitted gate octagon, with corners that fall short in orange
+                .child(
+                    canvas(
+                        |_, _, _| {},
+                        move |bounds, _, window, _| {
+                            let Some(&first) = outline.first() else {
+                                return;
+                            };
+                            let at = |(x, y)| bounds.origin + point(x, y);
+                            let mut path = PathBuilder::stroke(px(1.0));
+                            path.move_to(at(first));
+                            for &corner in &outline[1..] {
+                                path.line_to(at(corner));
+                            }
+                            path.line_to(at(first));
+                            if let Ok(path) = path.build() {
+                                window.paint_path(path, rgb(0x00BFFF));
+                            }
+                        },
+                    )
+                    .absolute()
+                    .size_full(),
+                )
+                .children(octagon.iter().map(|&(corner, short)| {
+                    let (x, y) = to_px(corner);
+                    div()
+                        .absolute()
+                        .size(px(8.0))
+                        .rounded_full()
+                        .bg(rgb(if short { 0xFFA500 } else { 0x00BFFF }))
+                        .left(x - px(4.0))
+                        .top(y - px(4.0))
+                }))
+                // Coverage ring: a marker per direction, red until the stick reaches it
+                .children(ring.iter().map(|&((x, y), covered)| {
+                    div()
+                        .absolute()
+                        .size(px(6.0))
+                        .rounded_full()
+                        .bg(rgb(if covered { 0x00AA00 } else { 0xE53935 }))
+                        .left(px(x as f32 / 4095.0 * size - 3.0))
+                        .top(px((1.0 - y as f32 / 4095.0) * size - 3.0))
+                }))
+                .children(stick_dots((raw_x_pct, raw_y_pct), smoothed, size)),
+        )
+}
+
+// The stick dot at a position given as fractions of the visual from its top left. With a
+// smoothed position, that becomes the dot and the raw reading shows faintly behind it.
+fn stick_dots(raw: (f32, f32), smoothed: Option<(f32, f32)>, size: f32) -> Vec<Div> {
+    let dot = |(x, y): (f32, f32), color| {
+        div()
+            .absolute()
+            .size(px(2.0))
+            .bg(color)
+            .rounded_full()
+            .left(px(x) * size - px(1.0))
+            .top(px(y) * size - px(1.0))
+    };
+    match smoothed {
+        Some(smoothed) => vec![dot(raw, rgba(0x00FF0066)), dot(smoothed, rgba(0x00FF00FF))],
+        None => vec![dot(raw, rgba(0x00FF00FF))],
+    }
+}
+
+// Full calibrated stick visual.
+// Takes in raw stick data, xmin, xmax, ymin, ymax, xcenter, ycenter, and deadzone,
+// and produces a calibrated visual which maps
+// [min, center-deadzone] -> [0, 0.5]
+// [center+deadzone, max] -> [0.5, 1.0]
+// just as the Switch does.
+#[allow(clippy::too_many_arguments)]
+fn calibrated_visual(
+    _cx: &Context<CalibrationApp>,
+    raw_x: u16,
+    raw_y: u16,
+    smoothed: Option<(u16, u16)>,
+    xmin: u16,
+    xmax: u16,
+    ymin: u16,
+    ymax: u16,
+    xcenter: u16,
+    ycenter: u16,
+    deadzone: u16,
+    label: &str,
+    background: u32,
+) -> impl IntoElement {
+    let size = 255.0;
+    let raw_x_pct = raw_x as f32 / 4095.0;
+    let raw_y_pct = raw_y as f32 / 4095.0;
+    let xmin_pct = xmin as f32 / 4095.0;
+    let xmax_pct = xmax as f32 / 4095.0;
+    let ymin_pct = ymin as f32 / 4095.0;
+    let ymax_pct = ymax as f32 / 4095.0;
+    let xcenter_pct = xcenter as f32 / 4095.0;
+    let ycenter_pct = ycenter as f32 / 4095.0;
+    let deadzone_pct = deadzone as f32 / 4095.0;
+
+    let remap = |(x, y): (f32, f32)| {
+        (
+            remap_calibrated_axis(x, xmin_pct, xcenter_pct, xmax_pct, deadzone_pct),
+            1.0 - remap_calibrated_axis(y, ymin_pct, ycenter_pct, ymax_pct, deadzone_pct),
+        )
+    };
+    let raw = remap((raw_x_pct, raw_y_pct));
+    let smoothed = smoothed.map(|(x, y)| remap((x as f32 / 4095.0, y as f32 / 4095.0)));
+    let (x, y) = smoothed.unwrap_or(raw);
+    let y = 1.0 - y;
+
+    (div()
+        .flex()
+        .flex_col()
+        .items_center()
+        .child(label.to_string())
+        .child(
+            div()
+                .size(px(size))
+                .bg(rgb(background))
+                .rounded_full()
+                .relative()
+                .children(stick_dots(raw, smoothed, size)),
+        ))
+    .child(format!("X: {:.3}\nY: {:.3}", x, y))
+}
+
+// Opens the first enumerated controller that answers a device info query
+fn unsupported_model(e: &anyhow::Error) -> Option<&'static str> {
+    match e.downcast_ref::<ControllerError>() {
+        Some(ControllerError::UnsupportedModel(model)) => Some(model),
+        _ => None,
+    }
+}
+
+fn is_device_busy(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<ControllerError>() == Some(&ControllerError::DeviceBusy)
+}
+
+fn connect_error_message(e: &anyhow::Error) -> String {
+    if let Some(model) = unsupported_model(e) {
+        return format!("Switch 2 controllers are not yet supported ({}).", model);
+    }
+    if is_device_busy(e) {
+        return "The controller is in use by another program. Close Steam (or turn off Steam Input) or stop joycond, then retry.".to_string();
+    }
+    format!("Failed to connect: {}", e)
+}
+
+fn find_responding_controller(
+    device_path: Option<&CStr>,
+    wanted: Option<ControllerType>,
+) -> Option<(Controller, DeviceInfo)> {
+    if let Some(path) = device_path {
+        let mut c = Controller::open_path(path).ok()?;
+        let device_info = c.get_device_info().ok()?;
+        return Some((c, device_info));
+    }
+    Controller::list().ok()?.iter().find_map(|info| {
+        if wanted.is_some_and(|t| t != info.controller_type) {
+            return None;
+        }
+        let mut c = Controller::open(info).ok()?;
+        let device_info = c.get_device_info().ok()?;
+        Some((c, device_info))
+    })
+}
+
+fn identify_button(cx: &Context<CalibrationApp>) -> impl IntoElement {
+    div()
+        .id("identify_btn")
+        .p_2()
+        .bg(rgb(0x555555))
+        .rounded_md()
+        .text_color(rgb(0xFFFFFF))
+        .cursor_pointer()
+        .child("Identify")
+        .on_click(cx.listener(|this, _, _, cx| this.identify(cx)))
+}
+
+fn identify_rumble_button(cx: &Context<CalibrationApp>) -> impl IntoElement {
+    div()
+        .id("identify_rumble_btn")
+        .p_2()
+        .bg(rgb(0x555555))
+        .rounded_md()
+        .text_color(rgb(0xFFFFFF))
+        .cursor_pointer()
+        .child("Identify (rumble)")
+        .on_click(cx.listener(|this, _, _, cx| this.identify_rumble(cx)))
+}
+
+// Opt-in: not every controller or USB stack copes with the baud rate switch
+fn fast_usb_status(cx: &Context<CalibrationApp>, enabled: bool) -> impl IntoElement {
+    if enabled {
+        return div()
+            .text_color(rgb(0xAAAAAA))
+            .child("Fast USB polling is on. The report rate is shown while calibrating.");
+    }
+    div().child(
+        div()
+            .id("fast_usb_btn")
+            .p_2()
+            .bg(rgb(0x555555))
+            .rounded_md()
+            .text_color(rgb(0xFFFFFF))
+            .cursor_pointer()
+            .child("Enable Fast USB Polling")
+            .on_click(cx.listener(|this, _, _, cx| this.enable_fast_usb(cx))),
+    )
+}
+
+fn pair_button(cx: &Context<CalibrationApp>, partner: ControllerType) -> impl IntoElement {
+    div()
+        .id("pair_btn")
+        .p_2()
+        .bg(rgb(0x555555))
+        .rounded_md()
+        .text_color(rgb(0xFFFFFF))
+        .cursor_pointer()
+        .child(format!("Also Connect {}", controller_type_name(partner)))
+        .on_click(cx.listener(|this, _, _, cx| this.pair_joycons(cx)))
+}
+
+// Connect straight to the last calibrated controller on startup when it's present
+fn auto_connect_toggle(cx: &Context<CalibrationApp>, enabled: bool) -> impl IntoElement {
+    div()
+        .id("auto_connect_toggle")
+        .cursor_pointer()
+        .child(format!(
+            "[{}] Connect automatically to the last calibrated controller",
+            if enabled { "x" } else { " " }
+        ))
+        .on_click(cx.listener(|this, _, _, cx| this.toggle_auto_connect(cx)))
+}
+
+// Where WRITE puts the stick calibration; factory overwriting stays one click away
+fn calibration_target_toggle(
+    cx: &Context<CalibrationApp>,
+    target: CalibrationTarget,
+) -> impl IntoElement {
+    div()
+        .id("calibration_target_toggle")
+        .cursor_pointer()
+        .child(match target {
+            CalibrationTarget::User => {
+                "[x] Write to user calibration (factory stick calibration kept)"
+            }
+            CalibrationTarget::Factory => {
+                "[ ] Write to user calibration (overwrites factory values)"
+            }
+        })
+        .on_click(cx.listener(|this, _, _, cx| this.toggle_calibration_target(cx)))
+}
+
+// Glitch samples dropped so far; a lot of them points at a poor Bluetooth link
+fn rejected_samples(data: &CalibrationData) -> Div {
+    if data.rejected == 0 {
+        return div();
+    }
+    let heavy = data.rejected * 20 > data.samples;
+    div()
+        .text_color(rgb(if heavy { 0xFFA500 } else { 0xAAAAAA }))
+        .child(format!("{} glitch samples ignored", data.rejected))
+        .when(heavy, |el| {
+            el.child("That's a lot; move the controller closer to the Bluetooth receiver.")
+        })
+}
+
+fn coverage_readout(coverage: &RangeCoverage) -> Div {
+    match coverage.missing() {
+        0 => div()
+            .text_color(rgb(0xAAAAAA))
+            .child("Every direction reached")
+            .child(circularity_readout(coverage)),
+        missing => div().text_color(rgb(0xFFA500)).child(format!(
+            "{} of {} directions still short of the rim",
+            missing, COVERAGE_SECTORS
+        )),
+    }
+}
+
+// Axes whose two sides differ in length by more than this share are called out
+const ASYMMETRY_LIMIT: f64 = 0.15;
+
+// How lopsided an axis is: the share of the longer side that the shorter one lacks
+fn axis_asymmetry(min: u16, center: u16, max: u16) -> f64 {
+    let below = center.saturating_sub(min) as f64;
+    let above = max.saturating_sub(center) as f64;
+    let longer = below.max(above);
+    if longer == 0.0 {
+        return 0.0;
+    }
+    (longer - below.min(above)) / longer
+}
+
+// An axis's min and max with the longer side cut to the shorter one's length
+fn symmetrize_axis(min: u16, center: u16, max: u16) -> (u16, u16) {
+    let side = center.saturating_sub(min).min(max.saturating_sub(center));
+    (center - side, center + side)
+}
+
+// Above this, uneven travel is down to the stick module rather than its calibration
+const WORN_CIRCULARITY_ERROR: f64 = 0.10;
+
+fn circularity_readout(coverage: &RangeCoverage) -> Div {
+    let Some(error) = coverage.circularity_error() else {
+        return div();
+    };
+    let worn = error > WORN_CIRCULARITY_ERROR;
+    div()
+        .text_color(rgb(if worn { 0xFFA500 } else { 0xAAAAAA }))
+        .child(format!("Circularity error: {:.1}%", error * 100.0))
+        .when(worn, |el| {
+            el.child("Above ~10% usually means a worn or dirty stick module, which calibration can't fix.")
+        })
+}
+
+// What a calibration session measured for one stick, for scoring it
+struct SessionMeasures {
+    // Rim sectors the range step never reached
+    missing_sectors: usize,
+    // Gate corners falling short of the opposite one by more than CORNER_MISMATCH
+    short_corners: usize,
+    // None when the rim wasn't reached all the way round
+    circularity_error: Option<f64>,
+    // The more lopsided of the two axes, as axis_asymmetry measures it
+    asymmetry: f64,
+    // Combined standard deviation of the resting samples, in raw units
+    noise: f64,
+    slack: CenterSlack,
+}
+
+impl SessionMeasures {
+    // One stick's measures, from the center step, the range sweep and the resulting
+    // calibration
+    fn measure(
+        center: &CenterResult,
+        coverage: &RangeCoverage,
+        result: StickCalibration,
+        stick: usize,
+    ) -> Self {
+        let (sx, sy) = center.noise[stick].std_dev();
+        SessionMeasures {
+            missing_sectors: coverage.missing(),
+            short_corners: coverage
+                .corners()
+                .map_or(0, |corners| short_corners(&corners).len()),
+            circularity_error: coverage.circularity_error(),
+            asymmetry: axis_asymmetry(result.xmin, result.xcenter, result.xmax)
+                .max(axis_asymmetry(result.ymin, result.ycenter, result.ymax)),
+            noise: sx.hypot(sy),
+            slack: center.slack[stick],
+        }
+    }
+}
+
+// Why a session lost points: a sweep that can be redone, or the stick itself
+#[derive(Clone, Copy, PartialEq)]
+enum QualityCause {
+    Sweep,
+    Hardware,
+}
+
+// Above this, center noise costs points; sticks in good shape sit well under it
+const HIGH_CENTER_NOISE: f64 = 15.0;
+// Above this, resting slack costs points; 0x0AE is a typical factory deadzone
+const LOOSE_CENTER_SLACK: u16 = 0x0C0;
+const VERY_LOOSE_CENTER_SLACK: u16 = 0x100;
+// At or above this, a score says the calibration can be written as is
+const GOOD_QUALITY_SCORE: u8 = 80;
+
+// Score a session from 100 down, with the reason and cost of every deduction. Each
+// factor deducts a fixed amount per threshold crossed, so the same measures always
+// give the same score:
+// - each missed rim sector: -3, at most -30
+// - each short gate corner: -5, at most -10
+// - circularity error over half the worn threshold: -5, over it: -20
+// - worst axis asymmetry over half ASYMMETRY_LIMIT: -5, over it: -15
+// - center noise over HIGH_CENTER_NOISE: -5, over twice that: -10
+// - radial slack over LOOSE_CENTER_SLACK: -5, over VERY_LOOSE_CENTER_SLACK: -15
+fn quality_score(m: &SessionMeasures) -> (u8, Vec<(&'static str, u8, QualityCause)>) {
+    let mut penalties = Vec::new();
+    if m.missing_sectors > 0 {
+        let cost = (m.missing_sectors * 3).min(30) as u8;
+        penalties.push(("rim coverage incomplete", cost, QualityCause::Sweep));
+    }
+    if m.short_corners > 0 {
+        let cost = (m.short_corners * 5).min(10) as u8;
+        penalties.push(("corner coverage incomplete", cost, QualityCause::Sweep));
+    }
+    match m.circularity_error {
+        Some(error) if error > WORN_CIRCULARITY_ERROR => {
+            penalties.push(("uneven travel", 20, QualityCause::Hardware))
+        }
+        Some(error) if error > WORN_CIRCULARITY_ERROR / 2.0 => {
+            penalties.push(("slightly uneven travel", 5, QualityCause::Hardware))
+        }
+        _ => {}
+    }
+    if m.asymmetry > ASYMMETRY_LIMIT {
+        penalties.push(("lopsided range", 15, QualityCause::Sweep));
+    } else if m.asymmetry > ASYMMETRY_LIMIT / 2.0 {
+        penalties.push(("slightly lopsided range", 5, QualityCause::Sweep));
+    }
+    if m.noise > HIGH_CENTER_NOISE * 2.0 {
+        penalties.push(("very high center noise", 10, QualityCause::Hardware));
+    } else if m.noise > HIGH_CENTER_NOISE {
+        penalties.push(("high center noise", 5, QualityCause::Hardware));
+    }
+    if m.slack.radial > VERY_LOOSE_CENTER_SLACK {
+        penalties.push(("very loose center", 15, QualityCause::Hardware));
+    } else if m.slack.radial > LOOSE_CENTER_SLACK {
+        penalties.push(("loose center", 5, QualityCause::Hardware));
+    }
+    let lost: u32 = penalties.iter().map(|&(_, cost, _)| cost as u32).sum();
+    (100u32.saturating_sub(lost) as u8, penalties)
+}
+
+fn short_corner_warning(coverage: &RangeCoverage) -> Div {
+    let Some(corners) = coverage.corners() else {
+        return div();
+    };
+    div()
+        .text_color(rgb(0xFFA500))
+        .children(short_corners(&corners).into_iter().map(|k| {
+        let (own, opposite) = (corners[k], corners[(k + 4) % 8]);
+        format!(
+            "The {} corner reaches {:.0}% less than the {} one; its gate corner may be collapsed.",
+            direction_name(own.angle),
+            (1.0 - own.radius / opposite.radius) * 100.0,
+            direction_name(opposite.angle)
+        )
+    }))
+}
+
+fn samples_collected(count: u32) -> Div {
+    div()
+        .text_color(rgb(if count >= MIN_SAMPLES {
+            0xAAAAAA
+        } else {
+            0xFFA500
+        }))
+        .child(format!("{} samples collected", count))
+}
+
+// Next/Finish for a collection step, greyed out until every stick has enough samples
+fn collection_button(
+    cx: &Context<CalibrationApp>,
+    id: &'static str,
+    label: &'static str,
+    enabled: bool,
+) -> impl IntoElement {
+    div()
+        .id(id)
+        .p_2()
+        .bg(rgb(if enabled { 0x007ACC } else { 0x333333 }))
+        .rounded_md()
+        .text_color(rgb(if enabled { 0xFFFFFF } else { 0x888888 }))
+        .child(label)
+        .when(enabled, |el| {
+            el.cursor_pointer()
+                .on_click(cx.listener(|this, _, _, cx| this.next_step(cx)))
+        })
+}
+
+fn slack_readout(slack: CenterSlack) -> Div {
+    div()
+        .text_color(rgb(0xAAAAAA))
+        .child(format!("Slack X {}  Y {}", slack.x, slack.y))
+}
+
+// Live spread of the resting samples, and whether it accounts for the slack so far
+fn noise_readout(noise: &CenterNoise, slack: CenterSlack) -> Div {
+    let (sx, sy) = noise.std_dev();
+    div()
+        .flex()
+        .flex_col()
+        .text_color(rgb(0xAAAAAA))
+        .child(format!(
+            "Noise σ X {:.1}  Y {:.1}  95% within {}",
+            sx,
+            sy,
+            noise.radial_p95()
+        ))
+        .when(noise.count > 0, |el| {
+            el.child(if noise.explains(slack) {
+                "Slack is mostly sensor noise"
+            } else {
+                "Slack is mostly mechanical play"
+            })
+        })
+}
+
+fn report_rate_indicator(rate: f32) -> Div {
+    let status = div()
+        .flex()
+        .flex_col()
+        .items_center()
+        .child(format!("Report rate: {:.0} Hz", rate));
+    if rate >= LOW_REPORT_RATE_HZ {
+        return status.text_color(rgb(0xAAAAAA));
+    }
+    status
+        .text_color(rgb(0xFF0000))
+        .child("Reports are arriving slowly. Move the controller closer to the Bluetooth receiver.")
+}
+
+// Live readout of the buttons held, to check they all register
+fn held_buttons(buttons: &ButtonState) -> impl IntoElement {
+    let pressed = buttons.pressed();
+    div()
+        .text_color(rgb(0xAAAAAA))
+        .child(if pressed.is_empty() {
+            "Buttons held: none".to_string()
+        } else {
+            format!("Buttons held: {}", pressed.join(", "))
+        })
+}
+
+// Only Bluetooth controllers can be told to disconnect and sleep
+fn sleep_on_exit_toggle(cx: &Context<CalibrationApp>, enabled: bool) -> impl IntoElement {
+    div()
+        .id("sleep_on_exit_toggle")
+        .cursor_pointer()
+        .child(format!(
+            "[{}] Put the controller to sleep on exit",
+            if enabled { "x" } else { " " }
+        ))
+        .on_click(cx.listener(|this, _, _, cx| this.toggle_sleep_on_exit(cx)))
+}
+
+// A write that ran out of retries says how each attempt failed. Point at the HID
+// log, which is what makes such a report actionable.
+fn write_error_message(e: &anyhow::Error) -> String {
+    if let Some(failed) = e.downcast_ref::<CalibrationWriteError>() {
+        return if failed.rolled_back() {
+            format!("Nothing changed (rolled back): {}", failed)
+        } else {
+            format!(
+                "Partially written, and the rollback also failed. Restore from the backup before using the controller: {}",
+                failed
+            )
+        };
+    }
+    if e.downcast_ref::<SpiWriteError>().is_some() {
+        format!(
+            "Failed to write: {}. Please include a log made with --hid-log when reporting this.",
+            e
+        )
+    } else {
+        format!("Failed to write: {}", e)
+    }
+}
+
+// One line per SPI write packet: offset, then the bytes in hex
+fn spi_write_list(writes: &[(u32, Vec<u8>)]) -> Div {
+    div()
+        .flex()
+        .flex_col()
+        .text_color(rgb(0xAAAAAA))
+        .children(writes.iter().map(|(offset, data)| {
+            let hex = data
+                .iter()
+                .map(|b| format!("{:02X}", b))
+                .collect::<Vec<_>>()
+                .join(" ");
+            format!("0x{:04X}: {}", offset, hex)
+        }))
+}
+
+// One stick's factory and user values side by side. Rows where the user slot differs
+// are highlighted; a blank user slot shows dashes, since the Switch then uses factory.
+fn calibration_table(stick: &StickRecord) -> Div {
+    let cell = |text: String| div().w(px(90.0)).child(text);
+    let row = |label: &str, factory: u16, user: Option<u16>| {
+        let delta = user.map(|user| user as i32 - factory as i32);
+        div()
+            .flex()
+            .when(delta.is_some_and(|d| d != 0), |el| {
+                el.text_color(rgb(0xFFA500))
+            })
+            .child(cell(label.to_string()))
+            .child(cell(factory.to_string()))
+            .child(cell(user.map_or("—".to_string(), |v| v.to_string())))
+            .child(cell(delta.map_or("—".to_string(), |d| format!("{:+}", d))))
+    };
+    let (f, u) = (&stick.factory, stick.user.as_ref());
+    let stick_name = match stick.side {
+        StickSide::Left => "Left stick",
+        StickSide::Right => "Right stick",
+    };
+    div()
+        .flex()
+        .flex_col()
+        .child(stick_name)
+        .child(
+            div()
+                .flex()
+                .text_color(rgb(0xAAAAAA))
+                .child(cell(String::new()))
+                .child(cell("Factory".to_string()))
+                .child(cell("User".to_string()))
+                .child(cell("Delta".to_string())),
+        )
+        .child(row("X center", f.xcenter, u.map(|u| u.xcenter)))
+        .child(row("X min", f.xmin, u.map(|u| u.xmin)))
+        .child(row("X max", f.xmax, u.map(|u| u.xmax)))
+        .child(row("Y center", f.ycenter, u.map(|u| u.ycenter)))
+        .child(row("Y min", f.ymin, u.map(|u| u.ymin)))
+        .child(row("Y max", f.ymax, u.map(|u| u.ymax)))
+        // Stored once per stick and applied whichever slot is in use
+        .child(row("Deadzone", stick.parameters.deadzone, None))
+        .child(row("Range ratio", stick.parameters.range_ratio, None))
+        .when(u.is_none(), |el| {
+            el.child(
+                div()
+                    .text_color(rgb(0xAAAAAA))
+                    .child("No user calibration; the Switch uses the factory values."),
+            )
+        })
+}
+
+fn operation_progress(progress: Option<Progress>) -> impl IntoElement {
+    const WIDTH: f32 = 300.0;
+    let Progress { done, total, phase } = progress.unwrap_or(Progress {
+        done: 0,
+        total: 0,
+        phase: "Starting",
+    });
+    let fraction = done as f32 / total.max(1) as f32;
+    div()
+        .flex()
+        .flex_col()
+        .items_center()
+        .gap_1()
+        .child(
+            div()
+                .w(px(WIDTH))
+                .h(px(12.0))
+                .bg(rgb(0x333333))
+                .rounded_md()
                 .child(
                     div()
-                        .absolute()
-                        .size(px(2.0))
-                        .bg(rgb(0x00FF00))
-                        .rounded_full()
-                        .left(px(raw_x_pct) * size - px(1.0))
-                        .top(px(raw_y_pct) * size - px(1.0)),
+                        .w(px(WIDTH * fraction))
+                        .h_full()
+                        .bg(rgb(0x007ACC))
+                        .rounded_md(),
                 ),
         )
+        .child(format!("{}: {} / {} bytes", phase, done, total))
 }
 
-fn remap_calibrated_axis(value: f32, min: f32, center: f32, max: f32, deadzone: f32) -> f32 {
-    // remap [min, center-deadzone] to [0, 0.5] and [center+deadzone, max] to [0.5, 1.0]
-    if value < center - deadzone {
-        (value - min) / (center - deadzone - min) / 2.0
-    } else if value > center + deadzone {
-        (value - deadzone - center) / (max - center - deadzone) / 2.0 + 0.5
-    } else {
-        0.5
-    }
-    .clamp(0.0, 1.0)
+// Seconds since the Unix epoch, for stamping a saved test result
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
 }
 
-// Full calibrated stick visual.
-// Takes in raw stick data, xmin, xmax, ymin, ymax, xcenter, ycenter, and deadzone,
-// and produces a calibrated visual which maps
-// [min, center-deadzone] -> [0, 0.5]
-// [center+deadzone, max] -> [0.5, 1.0]
-// just as the Switch does.
-fn calibrated_visual(
-    _cx: &Context<CalibrationApp>,
-    raw_x: u16,
-    raw_y: u16,
-    xmin: u16,
-    xmax: u16,
-    ymin: u16,
-    ymax: u16,
-    xcenter: u16,
-    ycenter: u16,
-    deadzone: u16,
-    label: &str,
-) -> impl IntoElement {
-    let size = 255.0;
-    let raw_x_pct = raw_x as f32 / 4095.0;
-    let raw_y_pct = raw_y as f32 / 4095.0;
-    let xmin_pct = xmin as f32 / 4095.0;
-    let xmax_pct = xmax as f32 / 4095.0;
-    let ymin_pct = ymin as f32 / 4095.0;
-    let ymax_pct = ymax as f32 / 4095.0;
-    let xcenter_pct = xcenter as f32 / 4095.0;
-    let ycenter_pct = ycenter as f32 / 4095.0;
-    let deadzone_pct = deadzone as f32 / 4095.0;
+// Per-axis outcome of a gyro drift test
+fn drift_result_summary(result: &DriftResult) -> impl IntoElement {
+    let [dx, dy, dz] = result.drift_deg_per_min;
+    let [bx, by, bz] = result.bias_dps;
+    let [rx, ry, rz] = result.bias_raw;
+    div()
+        .flex()
+        .flex_col()
+        .items_center()
+        .child(format!(
+            "Measured over {:.0}s of samples ({:.0}% of reports received)",
+            result.seconds,
+            result.coverage * 100.0
+        ))
+        .child(format!(
+            "Drift (°/min): X {:.2}  Y {:.2}  Z {:.2}",
+            dx, dy, dz
+        ))
+        .child(format!("Bias (dps): X {:.3}  Y {:.3}  Z {:.3}", bx, by, bz))
+        .child(format!("Bias (raw): X {}  Y {}  Z {}", rx, ry, rz))
+}
 
-    let x = remap_calibrated_axis(raw_x_pct, xmin_pct, xcenter_pct, xmax_pct, deadzone_pct);
-    let y = remap_calibrated_axis(raw_y_pct, ymin_pct, ycenter_pct, ymax_pct, deadzone_pct);
+// Position along the flick direction against time around the release, which sits at the
+// left edge's first tick; overshoot shows below the center line
+fn snapback_plot(trace: &[(f64, f64)]) -> impl IntoElement {
+    let (width, height) = (320.0, 140.0);
+    let (start_ms, end_ms) = (-50.0, SNAPBACK_WINDOW_MS);
+    let to_px = |&(ms, along): &(f64, f64)| {
+        (
+            px(((ms - start_ms) / (end_ms - start_ms) * width) as f32),
+            px(((0.5 - along / 4096.0) * height) as f32),
+        )
+    };
+    let points: Vec<_> = trace.iter().map(to_px).collect();
+    div()
+        .relative()
+        .w(px(width as f32))
+        .h(px(height as f32))
+        .bg(rgb(0x222222))
+        .border_1()
+        .border_color(rgb(0x888888))
+        // Center
+        .child(
+            div()
+                .absolute()
+                .w_full()
+                .h(px(1.0))
+                .top(px(height as f32 / 2.0))
+                .bg(rgb(0x555555)),
+        )
+        // Release
+        .child(
+            div()
+                .absolute()
+                .h_full()
+                .w(px(1.0))
+                .left(px((-start_ms / (end_ms - start_ms) * width) as f32))
+                .bg(rgb(0x555555)),
+        )
+        .child(
+            canvas(
+                |_, _, _| {},
+                move |bounds, _, window, _| {
+                    let Some(&first) = points.first() else {
+                        return;
+                    };
+                    let at = |(x, y)| bounds.origin + point(x, y);
+                    let mut path = PathBuilder::stroke(px(1.5));
+                    path.move_to(at(first));
+                    for &next in &points[1..] {
+                        path.line_to(at(next));
+                    }
+                    if let Ok(path) = path.build() {
+                        window.paint_path(path, rgb(0x4FC3F7));
+                    }
+                },
+            )
+            .absolute()
+            .size_full(),
+        )
+}
 
-    (div()
+// Per-stick outcome of a stick drift test, next to the previous run when there is one
+fn stick_drift_summary(
+    report: &StickDriftReport,
+    previous: Option<&StickDriftReport>,
+) -> impl IntoElement {
+    let line = |drift: &StickDrift| {
+        format!(
+            "mean offset {:.0}, peak {:.0}, peak output {:.1}%",
+            drift.mean_offset, drift.peak_offset, drift.peak_output_pct
+        )
+    };
+    div()
         .flex()
         .flex_col()
         .items_center()
-        .child(label.to_string())
-        .child(
+        .child(format!("Measured over {:.0}s", report.seconds))
+        .children(report.sticks.iter().map(|(side, drift)| {
+            let stick = match side {
+                StickSide::Left => "Left",
+                StickSide::Right => "Right",
+            };
+            let before = previous
+                .and_then(|p| p.sticks.iter().find(|(s, _)| s == side))
+                .map(|(_, drift)| drift);
             div()
-                .size(px(size))
-                .bg(rgb(0x222222))
-                .rounded_full()
-                .relative()
+                .flex()
+                .flex_col()
+                .items_center()
+                .child(format!("{} stick: {}", stick, line(drift)))
                 .child(
                     div()
-                        .absolute()
-                        .size(px(2.0))
-                        .bg(rgb(0x00FF00))
-                        .rounded_full()
-                        .left(px(x) * size - px(1.0))
-                        .top(px(1.0 - y) * size - px(1.0)),
-                ),
+                        .when(drift.visible(), |el| el.text_color(rgb(0xFFA500)))
+                        .child(if drift.visible() {
+                            "Will visibly drift in games: yes"
+                        } else {
+                            "Will visibly drift in games: no"
+                        }),
+                )
+                .when_some(before, |el, before| {
+                    el.child(
+                        div()
+                            .text_color(rgb(0xAAAAAA))
+                            .child(format!("Previous run: {}", line(before))),
+                    )
+                })
+        }))
+}
+
+// Measured origins, in raw sensor units, about to be written
+fn motion_result_summary(cal: ImuCalibration) -> impl IntoElement {
+    let [ax, ay, az] = cal.accel_origin;
+    let [gx, gy, gz] = cal.gyro_origin;
+    div()
+        .flex()
+        .flex_col()
+        .items_center()
+        .child(format!("Accelerometer offset: {} {} {}", ax, ay, az))
+        .child(format!("Gyroscope offset: {} {} {}", gx, gy, gz))
+}
+
+// One clickable IMU setting that steps to its next value
+fn imu_setting_button(
+    cx: &Context<CalibrationApp>,
+    id: &'static str,
+    label: String,
+    next: impl Fn(ImuSettings) -> ImuSettings + 'static,
+) -> impl IntoElement {
+    div()
+        .id(id)
+        .px_2()
+        .bg(rgb(0x555555))
+        .rounded_md()
+        .cursor_pointer()
+        .child(label)
+        .on_click(cx.listener(move |this, _, _, cx| this.step_imu_settings(&next, cx)))
+}
+
+// Current stage of a connect attempt, with a way out if it hangs
+fn connect_progress(cx: &Context<CalibrationApp>, stage: &'static str) -> impl IntoElement {
+    div()
+        .flex()
+        .gap_4()
+        .items_center()
+        .child(
+            div().child(stage).with_animation(
+                "connect_progress",
+                Animation::new(Duration::from_secs(2))
+                    .repeat()
+                    .with_easing(pulsating_between(0.3, 1.0)),
+                |label, delta| label.opacity(delta),
+            ),
+        )
+        .child(
+            div()
+                .id("cancel_connect_btn")
+                .p_2()
+                .bg(rgb(0x555555))
+                .rounded_md()
+                .text_color(rgb(0xFFFFFF))
+                .cursor_pointer()
+                .child("Cancel")
+                .on_click(cx.listener(|this, _, _, cx| this.cancel_connect(cx))),
+        )
+}
+
+// Switch the stick visuals between upright and sideways Joy-Con grips
+fn orientation_toggle(cx: &Context<CalibrationApp>, orientation: Orientation) -> impl IntoElement {
+    div()
+        .id("orientation_toggle")
+        .cursor_pointer()
+        .child(format!(
+            "[{}] Held sideways",
+            if orientation == Orientation::Sideways {
+                "x"
+            } else {
+                " "
+            }
         ))
-    .child(format!("X: {:.3}\nY: {:.3}", x, y))
+        .on_click(cx.listener(|this, _, _, cx| this.toggle_orientation(cx)))
+}
+
+// Abandon the current session and pick a different controller
+fn change_controller_button(cx: &Context<CalibrationApp>) -> impl IntoElement {
+    div()
+        .id("change_controller_btn")
+        .p_2()
+        .bg(rgb(0x555555))
+        .rounded_md()
+        .text_color(rgb(0xFFFFFF))
+        .cursor_pointer()
+        .child("Change Controller")
+        .on_click(cx.listener(|this, _, _, cx| this.calibrate_another(cx)))
+}
+
+// Selectable list of enumerated controllers
+fn controller_list(
+    cx: &Context<CalibrationApp>,
+    controllers: &[ControllerInfo],
+    selected: Option<usize>,
+    remembered: Option<&str>,
+) -> Div {
+    if controllers.is_empty() {
+        return div().child(
+            div().child("Waiting for controller…").with_animation(
+                "waiting_for_controller",
+                Animation::new(Duration::from_secs(2))
+                    .repeat()
+                    .with_easing(pulsating_between(0.3, 1.0)),
+                |label, delta| label.opacity(delta),
+            ),
+        );
+    }
+
+    div()
+        .flex()
+        .flex_col()
+        .gap_1()
+        .w(px(500.0))
+        .children(controllers.iter().enumerate().map(|(i, info)| {
+            // Serial numbers distinguish identical controllers; fall back to the path
+            let ident = info
+                .serial
+                .clone()
+                .unwrap_or_else(|| info.path.to_string_lossy().into_owned());
+            let last_calibrated = remembered.is_some() && info.mac.as_deref() == remembered;
+            div()
+                .id(("controller_entry", i))
+                .p_2()
+                .rounded_md()
+                .bg(if selected == Some(i) {
+                    rgb(0x007ACC)
+                } else {
+                    rgb(0x333333)
+                })
+                .cursor_pointer()
+                .child(format!(
+                    "{} ({:?}) - {}",
+                    controller_type_name(info.controller_type),
+                    info.bus,
+                    ident
+                ))
+                .when(last_calibrated, |el| el.child("★ Last calibrated"))
+                .on_click(cx.listener(move |this, _, _, cx| this.select_controller(i, cx)))
+        }))
 }
 
 impl Render for CalibrationApp {
@@ -456,6 +6278,14 @@ impl Render for CalibrationApp {
             this.update_stick_data(cx);
         });
 
+        // Stick visuals are drawn in the orientation the controller is held
+        let stick = self.display_stick_data();
+        let cal = self.display_calibration_data();
+        let enough_samples = self
+            .calibration_data
+            .has_enough_samples([self.has_left, self.has_right]);
+        let range_covered = self.range_covered();
+
         let step_content = match self.calibration_step {
             CalibrationStep::Connect => {
                 div()
@@ -468,49 +6298,171 @@ impl Render for CalibrationApp {
                         div()
                             .child("Connect your Nintendo Switch Controller (Joy-Con or Pro Controller) via Bluetooth or USB.")
                     )
-                    .child(
-                        div()
-                            .id("connect_btn")
-                            .p_2()
-                            .bg(rgb(0x007ACC))
-                            .rounded_md()
-                            .text_color(rgb(0xFFFFFF))
-                            .cursor_pointer()
-                            .child("Connect Controller")
-                            .on_click(cx.listener(|this, _, _, cx| this.connect(cx)))
-                    )
+                    .child(controller_list(cx, &self.available_controllers, self.selected_controller, self.saved_state.last_mac.as_deref()))
+                    .child(auto_connect_toggle(cx, self.saved_state.auto_connect))
+                    .when_some(self.connect_progress, |el, stage| {
+                        el.child(connect_progress(cx, stage))
+                    })
+                    .when(self.connect_progress.is_none(), |el| el.child(
+                        div().flex().gap_4()
+                        .child(
+                            div()
+                                .id("rescan_btn")
+                                .p_2()
+                                .bg(rgb(0x555555))
+                                .rounded_md()
+                                .text_color(rgb(0xFFFFFF))
+                                .cursor_pointer()
+                                .child("Rescan")
+                                .on_click(cx.listener(|this, _, _, cx| {
+                                    this.rescan();
+                                    cx.notify();
+                                }))
+                        )
+                        .child(
+                            div()
+                                .id("connect_btn")
+                                .p_2()
+                                .bg(rgb(0x007ACC))
+                                .rounded_md()
+                                .text_color(rgb(0xFFFFFF))
+                                .cursor_pointer()
+                                .child("Connect Controller")
+                                .on_click(cx.listener(|this, _, _, cx| this.connect(cx)))
+                        )
+                        .when(self.device_busy, |el| {
+                            el.child(
+                                div()
+                                    .id("retry_btn")
+                                    .p_2()
+                                    .bg(rgb(0xFFA500))
+                                    .rounded_md()
+                                    .text_color(rgb(0x000000))
+                                    .cursor_pointer()
+                                    .child("Retry")
+                                    .on_click(cx.listener(|this, _, _, cx| this.connect(cx)))
+                            )
+                        })
+                    ))
             },
             CalibrationStep::Connected => {
-                let info_text = if let Some((fw, mac)) = &self.device_info {
-                    let controllertypestring = match self.controller_type {
-                        Some(ControllerType::JoyConL) => "Switch Joy-Con (L)",
-                        Some(ControllerType::JoyConR) => "Switch Joy-Con (R)",
-                        Some(ControllerType::ProController) => "Switch Pro Controller",
-                        None => "Unknown Controller Type",
-                    };
-                    format!("Type: {}\nFirmware: {} | MAC: {}", controllertypestring, fw, mac)
-                } else {
-                    "Unknown Device".to_string()
-                };
-
                 div()
                     .flex()
                     .flex_col()
                     .items_center()
                     .gap_4()
                     .child("Controller Connected!")
-                    .child(info_text)
+                    .child(self.device_summary())
+                    .child(self.current_stick_calibration())
+                    .when(self.device_info.first().is_some_and(|d| !d.stick_cal.is_empty()), |el| {
+                        el.child(
+                            div()
+                                .id("calibration_details_btn")
+                                .p_2()
+                                .bg(rgb(0x555555))
+                                .rounded_md()
+                                .text_color(rgb(0xFFFFFF))
+                                .cursor_pointer()
+                                .child("Calibration details")
+                                .on_click(cx.listener(|this, _, _, cx| this.open_calibration_details(cx)))
+                        )
+                    })
+                    .child(self.current_deadzones())
+                    .child(self.firmware_warnings())
+                    .child(self.details_section(cx))
+                    .when(!self.has_sticks(), |el| {
+                        el.child(
+                            div()
+                                .child("This controller has no analog sticks to calibrate.")
+                                .text_color(rgb(0xFFA500))
+                        )
+                    })
+                    .when(self.has_sticks(), |el| {
+                        el.child(
+                            div()
+                                .id("start_cal_btn")
+                                .p_2()
+                                .bg(rgb(0x007ACC))
+                                .rounded_md()
+                                .text_color(rgb(0xFFFFFF))
+                                .cursor_pointer()
+                                .child("Start Calibration Wizard")
+                                .on_click(cx.listener(|this, _, _, cx| this.start_calibration(cx)))
+                        )
+                    })
+                    .when(self.has_imu && !self.paired && !self.read_only, |el| {
+                        el.child(
+                            div()
+                                .id("start_motion_cal_btn")
+                                .p_2()
+                                .bg(rgb(0x555555))
+                                .rounded_md()
+                                .text_color(rgb(0xFFFFFF))
+                                .cursor_pointer()
+                                .child("Calibrate Motion Controls")
+                                .on_click(cx.listener(|this, _, _, cx| this.start_motion_calibration(cx)))
+                        )
+                    })
+                    .when(self.has_imu && !self.paired, |el| {
+                        el.child(
+                            div()
+                                .id("drift_test_btn")
+                                .p_2()
+                                .bg(rgb(0x555555))
+                                .rounded_md()
+                                .text_color(rgb(0xFFFFFF))
+                                .cursor_pointer()
+                                .child("Gyro Drift Test")
+                                .on_click(cx.listener(|this, _, _, cx| this.open_drift_test(cx)))
+                        )
+                    })
+                    .when(self.has_sticks(), |el| {
+                        el.child(
+                            div()
+                                .id("stick_drift_test_btn")
+                                .p_2()
+                                .bg(rgb(0x555555))
+                                .rounded_md()
+                                .text_color(rgb(0xFFFFFF))
+                                .cursor_pointer()
+                                .child("Stick Drift Test")
+                                .on_click(cx.listener(|this, _, _, cx| this.open_stick_drift_test(cx)))
+                        )
+                        .child(
+                            div()
+                                .id("snapback_test_btn")
+                                .p_2()
+                                .bg(rgb(0x555555))
+                                .rounded_md()
+                                .text_color(rgb(0xFFFFFF))
+                                .cursor_pointer()
+                                .child("Snapback Test")
+                                .on_click(cx.listener(|this, _, _, cx| this.open_snapback_test(cx)))
+                        )
+                    })
+                    .when(self.has_sticks() && !self.read_only, |el| {
+                        el.child(self.factory_reset_section(cx))
+                            .child(self.undo_section(cx))
+                    })
+                    .when(!self.read_only && !self.paired, |el| el.child(self.backup_section(cx)))
                     .child(
-                        div()
-                            .id("start_cal_btn")
-                            .p_2()
-                            .bg(rgb(0x007ACC))
-                            .rounded_md()
-                            .text_color(rgb(0xFFFFFF))
-                            .cursor_pointer()
-                            .child("Start Calibration Wizard")
-                            .on_click(cx.listener(|this, _, _, cx| this.start_calibration(cx)))
+                        div().flex().gap_2()
+                            .child(identify_button(cx))
+                            .child(identify_rumble_button(cx))
                     )
+                    .when(self.connection_kind == Some(ConnectionKind::Usb), |el| {
+                        el.child(fast_usb_status(cx, self.fast_usb))
+                    })
+                    .when(self.connection_kind == Some(ConnectionKind::Bluetooth), |el| {
+                        el.child(sleep_on_exit_toggle(cx, self.saved_state.sleep_on_exit))
+                    })
+                    .when(self.can_turn_sideways(), |el| {
+                        el.child(orientation_toggle(cx, self.orientation))
+                    })
+                    .when_some(self.pair_partner_type(), |el, partner| {
+                        el.child(pair_button(cx, partner))
+                    })
+                    .child(change_controller_button(cx))
             },
             CalibrationStep::CalibrateCenter => {
                 div()
@@ -525,44 +6477,44 @@ impl Render for CalibrationApp {
                         div().flex().gap_8()
                         .child(if self.has_left {
                                 div().child(
-                                    stick_deadzone_visual(cx, self.stick_data.lx, self.stick_data.ly,
-                                    self.calibration_data.min_lx, self.calibration_data.max_lx,
-                                    self.calibration_data.min_ly, self.calibration_data.max_ly,
-                                    self.calibration_data.center_lx,
-                                    self.calibration_data.center_ly,
-                                    self.calibration_data.deadzone_l,
-                                    "Left Stick")
+                                    stick_deadzone_visual(cx, stick.lx, stick.ly,
+                                    self.display_smoothed(0),
+                                    cal.min_lx, cal.max_lx,
+                                    cal.min_ly, cal.max_ly,
+                                    cal.center_lx,
+                                    cal.center_ly,
+                                    cal.slack_l.radial,
+                                    &self.visual_label("Left Stick"), self.stick_background(false))
                                 )
+                                .child(slack_readout(cal.slack_l))
+                                .child(noise_readout(&cal.noise[0], cal.slack_l))
+                                .child(samples_collected(cal.accepted[0]))
                             } else {
                                 div()
                             }
                         )
                         .child( if self.has_right {
                                 div().child(
-                                    stick_deadzone_visual(cx, self.stick_data.rx, self.stick_data.ry,
-                                    self.calibration_data.min_rx, self.calibration_data.max_rx,
-                                    self.calibration_data.min_ry, self.calibration_data.max_ry,
-                                    self.calibration_data.center_rx,
-                                    self.calibration_data.center_ry,
-                                    self.calibration_data.deadzone_r,
-                                    "Right Stick")
+                                    stick_deadzone_visual(cx, stick.rx, stick.ry,
+                                    self.display_smoothed(1),
+                                    cal.min_rx, cal.max_rx,
+                                    cal.min_ry, cal.max_ry,
+                                    cal.center_rx,
+                                    cal.center_ry,
+                                    cal.slack_r.radial,
+                                    &self.visual_label("Right Stick"), self.stick_background(true))
                                 )
+                                .child(slack_readout(cal.slack_r))
+                                .child(noise_readout(&cal.noise[1], cal.slack_r))
+                                .child(samples_collected(cal.accepted[1]))
                             } else {
                                 div()
                             }
                         )
                     )
-                    .child(
-                        div()
-                            .id("next_btn")
-                            .p_2()
-                            .bg(rgb(0x007ACC))
-                            .rounded_md()
-                            .text_color(rgb(0xFFFFFF))
-                            .cursor_pointer()
-                            .child("Next Step")
-                            .on_click(cx.listener(|this, _, _, cx| this.next_step(cx)))
-                    )
+                    .child(self.smoothing_controls(cx))
+                    .child(rejected_samples(&self.calibration_data))
+                    .child(collection_button(cx, "next_btn", "Next Step", enough_samples))
             },
             CalibrationStep::CalibrateRange => {
                  div()
@@ -571,17 +6523,22 @@ impl Render for CalibrationApp {
                     .items_center()
                     .gap_4()
                     .child("Step 2: Range Calibration")
-                    .child("Slowly spin each stick gently around the OUTER RIM 3 times.")
+                    .child("Slowly spin each stick gently around the OUTER RIM 3 times, until every marker around it turns green.")
                      .child(
                         div().flex().gap_8()
                         .child(
                             if self.has_left {
                                 div().child(
-                                    stick_range_visual(cx, self.stick_data.lx, self.stick_data.ly,
-                                    self.calibration_data.min_lx, self.calibration_data.max_lx,
-                                    self.calibration_data.min_ly, self.calibration_data.max_ly,
-                                    "Left Stick")
+                                    stick_range_visual(cx, stick.lx, stick.ly,
+                                    self.display_smoothed(0),
+                                    cal.min_lx, cal.max_lx,
+                                    cal.min_ly, cal.max_ly,
+                                    &self.coverage_ring(0),
+                                    &self.gate_octagon(0),
+                                    &self.visual_label("Left Stick"), self.stick_background(false))
                                 )
+                                .child(samples_collected(cal.accepted[0]))
+                                .child(coverage_readout(&self.range_coverage[0]))
                             } else {
                                 div()
                             }
@@ -590,130 +6547,409 @@ impl Render for CalibrationApp {
                             if self.has_right {
                                 div()
                                     .child(
-                                        stick_range_visual(cx, self.stick_data.rx, self.stick_data.ry,
-                                        self.calibration_data.min_rx, self.calibration_data.max_rx,
-                                        self.calibration_data.min_ry, self.calibration_data.max_ry,
-                                        "Right Stick"
+                                        stick_range_visual(cx, stick.rx, stick.ry,
+                                        self.display_smoothed(1),
+                                        cal.min_rx, cal.max_rx,
+                                        cal.min_ry, cal.max_ry,
+                                        &self.coverage_ring(1),
+                                        &self.gate_octagon(1),
+                                        &self.visual_label("Right Stick"),
+                                        self.stick_background(true)
                                         )
                                     )
+                                    .child(samples_collected(cal.accepted[1]))
+                                    .child(coverage_readout(&self.range_coverage[1]))
                             } else {
                                 div()
                             }
                         )
-                    )
-                    .child(
-                        div()
-                            .id("finish_range_btn")
-                            .p_2()
-                            .bg(rgb(0x007ACC))
-                            .rounded_md()
-                            .text_color(rgb(0xFFFFFF))
-                            .cursor_pointer()
-                            .child("Finish Range Finding")
-                            .on_click(cx.listener(|this, _, _, cx| this.next_step(cx)))
-                    )
+                    )
+                    .child(self.smoothing_controls(cx))
+                    .child(rejected_samples(&self.calibration_data))
+                    .child(collection_button(cx, "finish_range_btn", "Finish Range Finding", enough_samples && range_covered))
+                    .when_some(self.dropped_packets_note(), |el, note| {
+                        el.child(div().text_color(rgb(0xAAAAAA)).child(note))
+                    })
+            },
+            CalibrationStep::OuterDeadzoneChoice => {
+                 div()
+                    .flex()
+                    .flex_col()
+                    .items_center()
+                    .gap_4()
+                    .track_focus(&self.padding_focus)
+                    .on_key_down(cx.listener(|this, event: &KeyDownEvent, _, cx| this.padding_key(event, cx)))
+                    .child("Step 3: Outer Deadzone")
+                    .child("A small outer deadzone prevents undershooting but increases error slightly.")
+                    .child("It was measured from how much the stick's reach varied between sweeps along the rim,")
+                    .child("so full deflection is still reached on the weakest one. Override it below if you like.")
+                    .child(self.padding_share())
+                    .child(self.padding_slider(cx))
+                    .child(self.padding_field(cx))
+                    .when(self.padding_override.is_some(), |el| {
+                        el.child(
+                            div()
+                                .id("measured_padding_btn")
+                                .p_2()
+                                .bg(rgb(0x555555))
+                                .rounded_md()
+                                .text_color(rgb(0xFFFFFF))
+                                .cursor_pointer()
+                                .child("Use measured padding")
+                                .on_click(cx.listener(|this, _, _, cx| this.use_measured_padding(cx)))
+                        )
+                    })
+                    .child(div().text_color(rgb(0xAAAAAA)).child("Click the field to type a value; up and down nudge it by one."))
+                    .child(self.calibrated_visuals(cx))
+                    .child(
+                        div()
+                            .id("padding_continue_btn")
+                            .p_2()
+                            .bg(rgb(0x007ACC))
+                            .rounded_md()
+                            .text_color(rgb(0xFFFFFF))
+                            .cursor_pointer()
+                            .child("Continue")
+                            .on_click(cx.listener(|this, _, _, cx| this.finish_outer_deadzone(cx)))
+                    )
+            },
+            CalibrationStep::Review => {
+                 div()
+                    .flex()
+                    .flex_col()
+                    .items_center()
+                    .gap_4()
+                    .child("Review Calibration")
+                    .when_some(self.imported_from.as_ref(), |el, path| {
+                        el.child(format!("Imported from {}", path.display()))
+                    })
+                    .when_some(self.import_warning.as_ref(), |el, warning| {
+                        el.child(div().text_color(rgb(0xFFB300)).child(warning.clone()))
+                    })
+                    .children(self.calibration_problems().into_iter().map(|problem| {
+                        div().text_color(rgb(0xFF0000)).child(problem)
+                    }))
+                    .child(self.quality_summary())
+                    .child("Check the visualized calibration below.")
+                    .child(self.current_deadzones())
+                    .child(self.deadzone_summary(cx))
+                    .child(self.range_summary())
+                    .child(self.asymmetry_summary(cx))
+                    .child(self.range_ratio_summary(cx))
+                    .child(self.range_mode(cx))
+                    .child(self.calibrated_visuals(cx))
+                    .when(self.has_imu, |el| el.child(self.imu_section(cx, &stick.imu)))
+                    .when(!self.read_only, |el| {
+                        el.child(calibration_target_toggle(cx, self.calibration_target))
+                            .children(self.device_info.iter().filter_map(|d| backup::backup_dir(&d.mac)).map(|dir| {
+                                div()
+                                    .text_color(rgb(0xAAAAAA))
+                                    .child(format!("A backup will be saved to {}", dir.display()))
+                            }))
+                    })
+                    .when(!self.read_only, |el| {
+                        el.child(
+                            div()
+                                .id("write_btn")
+                                .p_2()
+                                .bg(rgb(0xE53935))
+                                .rounded_md()
+                                .text_color(rgb(0xFFFFFF))
+                                .cursor_pointer()
+                                .child(if controller::dry_run_forced() {
+                                    "WRITE to Controller (dry run)"
+                                } else {
+                                    "WRITE to Controller"
+                                })
+                                .on_click(cx.listener(|this, _, _, cx| this.write_calibration(false, cx)))
+                        )
+                        .child(
+                            div()
+                                .id("write_preview_toggle")
+                                .cursor_pointer()
+                                .child(format!(
+                                    "{} Preview writes",
+                                    if self.show_write_preview { "▾" } else { "▸" }
+                                ))
+                                .on_click(cx.listener(|this, _, _, cx| this.toggle_write_preview(cx)))
+                        )
+                        .when(self.show_write_preview, |el| el.child(self.write_preview()))
+                    })
+                    .when(self.write_partial, |el| el.child(self.undo_section(cx)))
+                    .when(!self.read_only && self.battery_critical(), |el| {
+                        el.child(
+                            div()
+                                .id("write_anyway_btn")
+                                .p_2()
+                                .bg(rgb(0x555555))
+                                .rounded_md()
+                                .text_color(rgb(0xFFFFFF))
+                                .cursor_pointer()
+                                .child("Write Anyway (battery critical)")
+                                .on_click(cx.listener(|this, _, _, cx| this.write_calibration(true, cx)))
+                        )
+                    })
+                    .child(change_controller_button(cx))
             },
-            CalibrationStep::OuterDeadzoneChoice => {
-                 div()
+            CalibrationStep::MotionCollect => {
+                div()
                     .flex()
                     .flex_col()
                     .items_center()
                     .gap_4()
-                    .child("Step 3: Outer Deadzone")
-                    .child("Do you want to add a small outer deadzone?")
-                    .child("This prevents undershooting but increases error slightly.")
+                    .child("Motion Calibration")
+                    .child("Place the controller on a flat, level surface with the buttons facing up.")
+                    .child("Don't touch it until this step finishes.")
+                    .child(format!(
+                        "Collecting… {:.0}s left",
+                        MOTION_COLLECT_DURATION
+                            .saturating_sub(self.motion_started.map_or(Duration::ZERO, |t| t.elapsed()))
+                            .as_secs_f32()
+                            .ceil()
+                    ))
+            },
+            CalibrationStep::MotionReview => {
+                div()
+                    .flex()
+                    .flex_col()
+                    .items_center()
+                    .gap_4()
+                    .child("Review Motion Calibration")
+                    .when_some(self.motion_result, |el, cal| el.child(motion_result_summary(cal)))
+                    .child(self.imu_section(cx, &stick.imu))
                     .child(
                         div().flex().gap_4()
                         .child(
                             div()
-                                .id("yes_btn")
+                                .id("redo_motion_btn")
                                 .p_2()
-                                .bg(rgb(0x007ACC))
+                                .bg(rgb(0x555555))
                                 .rounded_md()
                                 .text_color(rgb(0xFFFFFF))
                                 .cursor_pointer()
-                                .child("Yes (Recommended)")
-                                .on_click(cx.listener(|this, _, _, cx| this.set_outer_deadzone(true, cx)))
+                                .child("Measure Again")
+                                .on_click(cx.listener(|this, _, _, cx| this.start_motion_calibration(cx)))
                         )
                         .child(
                             div()
-                                .id("no_btn")
+                                .id("write_motion_btn")
                                 .p_2()
-                                .bg(rgb(0x555555))
+                                .bg(rgb(0xE53935))
                                 .rounded_md()
                                 .text_color(rgb(0xFFFFFF))
                                 .cursor_pointer()
-                                .child("No")
-                                .on_click(cx.listener(|this, _, _, cx| this.set_outer_deadzone(false, cx)))
+                                .child("WRITE to Controller")
+                                .on_click(cx.listener(|this, _, _, cx| this.write_motion_calibration(false, cx)))
                         )
                     )
+                    .when(self.battery_critical(), |el| {
+                        el.child(
+                            div()
+                                .id("write_motion_anyway_btn")
+                                .p_2()
+                                .bg(rgb(0x555555))
+                                .rounded_md()
+                                .text_color(rgb(0xFFFFFF))
+                                .cursor_pointer()
+                                .child("Write Anyway (battery critical)")
+                                .on_click(cx.listener(|this, _, _, cx| this.write_motion_calibration(true, cx)))
+                        )
+                    })
+                    .child(change_controller_button(cx))
             },
-            CalibrationStep::Review => {
-                 div()
+            CalibrationStep::GyroDrift => {
+                div()
                     .flex()
                     .flex_col()
                     .items_center()
                     .gap_4()
-                    .child("Review Calibration")
-                    .child("Check the visualized calibration below.")
-                    .child(
-                        div().flex().gap_8()
-                        .child(
-                            if self.has_left {
-                                div().child(
-                                    calibrated_visual(cx, self.stick_data.lx, self.stick_data.ly,
-                                    self.left_result.xmin, self.left_result.xmax,
-                                    self.left_result.ymin, self.left_result.ymax,
-                                    self.left_result.xcenter, self.left_result.ycenter,
-                                    self.left_deadzone, "Left Calibrated")
-                                )
-                            } else {
+                    .child("Gyro Drift Test")
+                    .child("Leave the controller on a table, untouched, for the whole test.")
+                    .child(self.imu_section(cx, &stick.imu))
+                    .when_some(self.drift_started, |el, started| {
+                        el.child(format!(
+                            "Measuring… {:.0}s left",
+                            self.drift_duration.saturating_sub(started.elapsed()).as_secs_f32().ceil()
+                        ))
+                    })
+                    .when_some(self.drift_result.as_ref(), |el, report| el.child(drift_result_summary(&report.result)))
+                    .when(self.drift_started.is_none(), |el| {
+                        el.child(
+                            div().flex().gap_4()
+                            .child(
                                 div()
-                            }
-                        )
-                        .child(
-                            if self.has_right {
-                                div().child(
-                                    calibrated_visual(cx, self.stick_data.rx, self.stick_data.ry,
-                                    self.right_result.xmin, self.right_result.xmax,
-                                    self.right_result.ymin, self.right_result.ymax,
-                                    self.right_result.xcenter, self.right_result.ycenter,
-                                    self.right_deadzone, "Right Calibrated"))
-                            } else {
+                                    .id("drift_duration_btn")
+                                    .p_2()
+                                    .bg(rgb(0x555555))
+                                    .rounded_md()
+                                    .text_color(rgb(0xFFFFFF))
+                                    .cursor_pointer()
+                                    .child(format!("Duration: {}s", self.drift_duration.as_secs()))
+                                    .on_click(cx.listener(|this, _, _, cx| this.cycle_drift_duration(cx)))
+                            )
+                            .child(
                                 div()
-                            }
+                                    .id("start_drift_btn")
+                                    .p_2()
+                                    .bg(rgb(0x007ACC))
+                                    .rounded_md()
+                                    .text_color(rgb(0xFFFFFF))
+                                    .cursor_pointer()
+                                    .child(if self.drift_result.is_some() { "Run Again" } else { "Start" })
+                                    .on_click(cx.listener(|this, _, _, cx| this.start_drift_test(cx)))
+                            )
+                            .when(self.drift_result.is_some(), |el| {
+                                el.child(
+                                    div()
+                                        .id("export_drift_btn")
+                                        .p_2()
+                                        .bg(rgb(0x555555))
+                                        .rounded_md()
+                                        .text_color(rgb(0xFFFFFF))
+                                        .cursor_pointer()
+                                        .child("Export…")
+                                        .on_click(cx.listener(|this, _, _, cx| this.export_gyro_drift(cx)))
+                                )
+                            })
+                            .child(
+                                div()
+                                    .id("drift_back_btn")
+                                    .p_2()
+                                    .bg(rgb(0x555555))
+                                    .rounded_md()
+                                    .text_color(rgb(0xFFFFFF))
+                                    .cursor_pointer()
+                                    .child("Back")
+                                    .on_click(cx.listener(|this, _, _, cx| this.back_to_connected(cx)))
+                            )
                         )
+                    })
+            },
+             CalibrationStep::Done => {
+                div()
+                    .flex()
+                    .flex_col()
+                    .items_center()
+                    .gap_4()
+                    .child(if controller::dry_run_forced() {
+                        "Dry run complete. Nothing was written to the controller."
+                    } else {
+                        "Calibration Complete!"
+                    })
+                    .when(!self.dry_run_writes.is_empty(), |el| {
+                        el.child(spi_write_list(&self.dry_run_writes))
+                    })
+                    .children(self.write_checks.iter().map(|check| {
+                        div().text_color(rgb(0xAAAAAA)).child(format!(
+                            "{} at 0x{:04X}: {}",
+                            check.name,
+                            check.offset,
+                            if check.passed { "verified" } else { "FAILED" }
+                        ))
+                    }))
+                    .children(self.write_backups.iter().enumerate().map(|(i, path)| {
+                        let path = path.clone();
+                        div()
+                            .id(("write_backup_link", i))
+                            .text_color(rgb(0x4FC3F7))
+                            .cursor_pointer()
+                            .child(format!("Previous calibration saved to {}", path.display()))
+                            .on_click(cx.listener(move |_, _, _, cx| cx.reveal_path(&path)))
+                    }))
+                    .when(!self.undo.is_empty(), |el| el.child(self.undo_section(cx)))
+                    .child("Please disconnect and reconnect your controller to apply changes.")
+                    .child(
+                         div()
+                            .id("calibrate_another_btn")
+                            .p_2()
+                            .bg(rgb(0x555555))
+                            .rounded_md()
+                            .text_color(rgb(0xFFFFFF))
+                            .cursor_pointer()
+                            .child("Calibrate Another Controller")
+                            .on_click(cx.listener(|this, _, _, cx| this.calibrate_another(cx)))
                     )
+                    .when(self.connection_kind == Some(ConnectionKind::Bluetooth), |el| {
+                        el.child(sleep_on_exit_toggle(cx, self.saved_state.sleep_on_exit))
+                    })
                     .child(
-                        div()
-                            .id("write_btn")
+                         div()
+                            .id("exit_btn")
                             .p_2()
-                            .bg(rgb(0xE53935))
+                            .bg(rgb(0x007ACC))
                             .rounded_md()
                             .text_color(rgb(0xFFFFFF))
                             .cursor_pointer()
-                            .child("WRITE to Controller")
-                            .on_click(cx.listener(|this, _, _, cx| this.write_calibration(cx)))
+                            .child("Exit")
+                            .on_click(cx.listener(|this, _, _, cx| {
+                                this.shutdown_controllers(this.saved_state.sleep_on_exit);
+                                cx.quit();
+                            }))
                     )
             },
-             CalibrationStep::Done => {
+            CalibrationStep::CalibrationDetails => self.calibration_details_page(cx),
+            CalibrationStep::StickDrift => self.stick_drift_page(cx),
+            CalibrationStep::Snapback => self.snapback_page(cx),
+            CalibrationStep::Colors => match &self.color_editor {
+                Some(editor) => self.color_editor_page(editor, cx),
+                None => div(),
+            },
+            CalibrationStep::Working => {
                 div()
                     .flex()
                     .flex_col()
                     .items_center()
                     .gap_4()
-                    .child("Calibration Complete!")
-                    .child("Please disconnect and reconnect your controller to apply changes.")
+                    .when_some(self.operation.as_ref(), |el, view| {
+                        el.child(view.title).child(operation_progress(view.progress))
+                    })
                     .child(
-                         div()
-                            .id("exit_btn")
+                        div()
+                            .id("cancel_operation_btn")
                             .p_2()
-                            .bg(rgb(0x007ACC))
+                            .bg(rgb(0x555555))
                             .rounded_md()
                             .text_color(rgb(0xFFFFFF))
                             .cursor_pointer()
-                            .child("Exit")
-                            .on_click(cx.listener(|_, _, _, cx| cx.quit()))
+                            .child("Cancel")
+                            .on_click(cx.listener(|this, _, _, cx| this.cancel_operation(cx)))
+                    )
+            },
+            CalibrationStep::Disconnected => {
+                div()
+                    .flex()
+                    .flex_col()
+                    .items_center()
+                    .gap_4()
+                    .child(format!(
+                        "{} disconnected.",
+                        self.lost_controller.map_or("Controller", controller_type_name)
+                    ))
+                    .child("Plug it back in or press a button to wake it. Collected data is kept, and the wizard resumes once the same controller answers.")
+                    .child(
+                        div().flex().gap_4()
+                        .child(
+                            div()
+                                .id("reconnect_btn")
+                                .p_2()
+                                .bg(rgb(0x007ACC))
+                                .rounded_md()
+                                .text_color(rgb(0xFFFFFF))
+                                .cursor_pointer()
+                                .child("Reconnect")
+                                .on_click(cx.listener(|this, _, _, cx| this.reconnect(cx)))
+                        )
+                        .child(
+                            div()
+                                .id("restart_btn")
+                                .p_2()
+                                .bg(rgb(0x555555))
+                                .rounded_md()
+                                .text_color(rgb(0xFFFFFF))
+                                .cursor_pointer()
+                                .child("Restart")
+                                .on_click(cx.listener(|this, _, _, cx| this.calibrate_another(cx)))
+                        )
                     )
             },
         };
@@ -726,6 +6962,11 @@ impl Render for CalibrationApp {
             .items_center()
             .bg(rgb(0x111111))
             .text_color(rgb(0xFFFFFF))
+            .child(self.colors_header())
+            .child(battery_indicator(&self.battery))
+            .when_some(self.report_rate(), |el, rate| {
+                el.child(report_rate_indicator(rate))
+            })
             .child(if let Some(msg) = &self.error_message {
                 div()
                     .child(format!("Error: {}", msg))
@@ -733,12 +6974,159 @@ impl Render for CalibrationApp {
             } else {
                 div()
             })
+            .child(if self.asleep {
+                div()
+                    .child("Controller appears to be asleep — press any button to wake it.")
+                    .text_color(rgb(0xFFA500))
+            } else {
+                div()
+            })
+            .child(if self.read_only {
+                div()
+                    .child("This controller does not support on-device calibration.")
+                    .text_color(rgb(0xFFA500))
+            } else {
+                div()
+            })
             .child(step_content)
+            .when_some(self.button_hints(), |el, hints| {
+                el.child(
+                    div()
+                        .mt_4()
+                        .flex()
+                        .flex_col()
+                        .items_center()
+                        .text_color(rgb(0xAAAAAA))
+                        .child(hints)
+                        .child(held_buttons(&self.stick_data.buttons)),
+                )
+            })
+    }
+}
+
+struct Args {
+    // `--device <path>`: the hidapi path of the one controller to use
+    device_path: Option<CString>,
+    // `--vid <hex> --pid <hex>`: an extra protocol-compatible controller to look for
+    extra_device: Option<(u16, u16)>,
+    // `--hid-log <path>`, or RUSTJOYCAL_HID_LOG: record all HID traffic to this file
+    hid_log: Option<PathBuf>,
+    // `--replay <path>`: a HID log to play back instead of a real controller
+    replay: Option<PathBuf>,
+    // `--dry-run`: record SPI writes instead of sending them
+    dry_run: bool,
+    // `--allow-any-spi-offset`: let restores write outside the calibration regions
+    allow_any_offset: bool,
+    // `--max-stick-jump <n>`: the range step's glitch threshold, in raw stick units
+    max_stick_jump: u16,
+}
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(2);
+}
+
+fn parse_hex_id(flag: &str, value: Option<String>) -> u16 {
+    let Some(value) = value else {
+        usage_error(&format!("{} requires a hex ID", flag));
+    };
+    let digits = value.trim_start_matches("0x").trim_start_matches("0X");
+    u16::from_str_radix(digits, 16).unwrap_or_else(|_| {
+        usage_error(&format!("{} expects a 16-bit hex ID, got {}", flag, value))
+    })
+}
+
+fn parse_args() -> Args {
+    let mut device_path = None;
+    let mut vid = None;
+    let mut pid = None;
+    let mut hid_log = std::env::var_os("RUSTJOYCAL_HID_LOG").map(PathBuf::from);
+    let mut replay = None;
+    let mut dry_run = false;
+    let mut allow_any_offset = false;
+    let mut max_stick_jump = DEFAULT_MAX_STICK_JUMP;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--device" => {
+                let Some(path) = args.next() else {
+                    usage_error("--device requires a hidapi device path");
+                };
+                let Ok(path) = CString::new(path) else {
+                    usage_error("--device path must not contain NUL bytes");
+                };
+                device_path = Some(path);
+            }
+            "--vid" => vid = Some(parse_hex_id("--vid", args.next())),
+            "--pid" => pid = Some(parse_hex_id("--pid", args.next())),
+            "--hid-log" => {
+                let Some(path) = args.next() else {
+                    usage_error("--hid-log requires a file path");
+                };
+                hid_log = Some(PathBuf::from(path));
+            }
+            "--replay" => {
+                let Some(path) = args.next() else {
+                    usage_error("--replay requires a HID log path");
+                };
+                replay = Some(PathBuf::from(path));
+            }
+            "--dry-run" => dry_run = true,
+            "--allow-any-spi-offset" => allow_any_offset = true,
+            "--max-stick-jump" => {
+                let Some(Ok(jump)) = args.next().map(|n| n.parse::<u16>()) else {
+                    usage_error("--max-stick-jump requires a number of raw stick units");
+                };
+                max_stick_jump = jump;
+            }
+            _ => usage_error(&format!("Unknown argument: {}", arg)),
+        }
+    }
+
+    let extra_device = match (vid, pid) {
+        (Some(vid), Some(pid)) => Some((vid, pid)),
+        (None, None) => None,
+        _ => usage_error("--vid and --pid must be given together"),
+    };
+
+    Args {
+        device_path,
+        extra_device,
+        hid_log,
+        replay,
+        dry_run,
+        allow_any_offset,
+        max_stick_jump,
     }
 }
 
 fn main() {
-    Application::new().run(|cx: &mut App| {
+    let Args {
+        device_path,
+        extra_device,
+        hid_log,
+        replay,
+        dry_run,
+        allow_any_offset,
+        max_stick_jump,
+    } = parse_args();
+    if let Some(path) = hid_log
+        && let Err(e) = hid_log::start(&path)
+    {
+        usage_error(&format!("Can't write HID log {}: {}", path.display(), e));
+    }
+    if let Some((vid, pid)) = extra_device {
+        controller::register_extra_device(vid, pid);
+    }
+    if dry_run {
+        controller::force_dry_run();
+    }
+
+    Application::new().run(move |cx: &mut App| {
+        // Closing the window quits, which gives the app a chance to release the controller
+        cx.on_window_closed(|cx| cx.quit()).detach();
+
         let options = WindowOptions {
             window_bounds: Some(WindowBounds::Windowed(Bounds::centered(
                 None,
@@ -748,7 +7136,537 @@ fn main() {
             ..Default::default()
         };
 
-        cx.open_window(options, |_, cx| cx.new(|cx| CalibrationApp::new(cx)))
-            .unwrap();
+        cx.open_window(options, |_, cx| {
+            cx.new(|cx| {
+                CalibrationApp::new(device_path, replay, allow_any_offset, max_stick_jump, cx)
+            })
+        })
+        .unwrap();
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        ASYMMETRY_LIMIT, COVERAGE_SECTORS, CalibrationData, CenterNoise, CenterResult, CenterSlack,
+        DEFAULT_MAX_STICK_JUMP, GOOD_QUALITY_SCORE, QualityCause, RangeCoverage, SessionMeasures,
+        StickData, axis_asymmetry, circularity_error, compose_calibration, direction_index,
+        direction_name, octagon_corners, quality_score, range_extents, short_corners,
+        symmetrize_axis,
+    };
+
+    const CENTER: (u16, u16) = (0x800, 0x7F0);
+
+    // Runs resting left stick positions through the center step
+    fn center_step(points: impl IntoIterator<Item = (u16, u16)>) -> CalibrationData {
+        let mut data = CalibrationData::new();
+        for point in points {
+            assert!(keep(&mut data, point));
+        }
+        data
+    }
+
+    // Whether a left stick position was kept
+    fn keep(data: &mut CalibrationData, (lx, ly): (u16, u16)) -> bool {
+        let sample = StickData {
+            lx,
+            ly,
+            ..Default::default()
+        };
+        data.update(&sample, [true, false])[0]
+    }
+
+    // Positions around CENTER on an ellipse with the given half-widths
+    fn ellipse(rx: f64, ry: f64) -> impl Iterator<Item = (u16, u16)> {
+        (0..720).map(move |k| {
+            let angle = k as f64 / 720.0 * std::f64::consts::TAU;
+            (
+                (CENTER.0 as f64 + rx * angle.cos()).round() as u16,
+                (CENTER.1 as f64 + ry * angle.sin()).round() as u16,
+            )
+        })
+    }
+
+    #[test]
+    fn circular_slack_matches_every_sample() {
+        let data = center_step(ellipse(40.0, 40.0));
+        let exact = CenterSlack::measure(ellipse(40.0, 40.0), data.center_lx, data.center_ly);
+        assert_eq!((data.center_lx, data.center_ly), CENTER);
+        assert_eq!((data.slack_l.x, data.slack_l.y), (exact.x, exact.y));
+        assert_eq!((data.slack_l.x, data.slack_l.y), (40, 40));
+        assert!(data.slack_l.radial <= exact.radial);
+        assert!(data.slack_l.radial + 1 >= exact.radial);
+    }
+
+    #[test]
+    fn elongated_slack_keeps_the_long_axis() {
+        let data = center_step(ellipse(60.0, 15.0));
+        let exact = CenterSlack::measure(ellipse(60.0, 15.0), data.center_lx, data.center_ly);
+        assert_eq!((data.center_lx, data.center_ly), CENTER);
+        assert_eq!((data.slack_l.x, data.slack_l.y), (60, 15));
+        // The long axis decides it, where a bounding box would give 62
+        assert_eq!(data.slack_l.radial, exact.radial);
+        assert!(data.slack_l.radial <= 61);
+    }
+
+    #[test]
+    fn single_outlier_sets_the_slack() {
+        let outlier = (CENTER.0 + 30, CENTER.1 - 30);
+        let points = std::iter::repeat_n(CENTER, 500)
+            .chain([outlier])
+            .chain(std::iter::repeat_n(CENTER, 500));
+        let data = center_step(points);
+        assert_eq!((data.center_lx, data.center_ly), CENTER);
+        assert_eq!((data.slack_l.x, data.slack_l.y), (30, 30));
+        // 30 * sqrt(2) = 42.4
+        assert_eq!(data.slack_l.radial, 43);
+        assert_eq!(data.slack_r.radial, 0);
+    }
+
+    #[test]
+    fn spikes_outside_the_center_window_are_dropped() {
+        let mut data = center_step([CENTER, (0x810, 0x7E0), (0x7F0, 0x800)]);
+        assert!(!keep(&mut data, (0x000, 0x7F0)));
+        assert!(!keep(&mut data, (0xFFF, 0xFFF)));
+        assert_eq!((data.min_lx, data.max_lx), (0x7F0, 0x810));
+        assert_eq!((data.min_ly, data.max_ly), (0x7E0, 0x800));
+        assert_eq!((data.center_lx, data.center_ly), CENTER);
+        assert_eq!(data.rejected, 2);
+    }
+
+    #[test]
+    fn spikes_during_the_range_step_are_dropped() {
+        let mut data = CalibrationData::for_range(DEFAULT_MAX_STICK_JUMP);
+        for x in [0x800, 0x600, 0x400, 0x600, 0x800, 0xA00, 0xC00] {
+            assert!(keep(&mut data, (x, 0x800)));
+        }
+        assert!(!keep(&mut data, (0x000, 0x800)));
+        // Coming back from the spike is as big a jump, so that sample goes too
+        assert!(!keep(&mut data, (0xC00, 0x800)));
+        assert!(keep(&mut data, (0xC00, 0x800)));
+        assert_eq!((data.min_lx, data.max_lx), (0x400, 0xC00));
+        assert_eq!(data.rejected, 2);
+    }
+
+    #[test]
+    fn fast_sweeps_under_the_jump_limit_are_kept() {
+        let mut data = CalibrationData::for_range(DEFAULT_MAX_STICK_JUMP);
+        // Center to each edge and back in steps just under the limit
+        let step = DEFAULT_MAX_STICK_JUMP - 1;
+        let sweep = [
+            0x800,
+            0x800 + step,
+            0x800 + 2 * step,
+            0x800 + step,
+            0x800,
+            0x800 - step,
+            0x800 - 2 * step,
+        ];
+        for x in sweep {
+            assert!(keep(&mut data, (x, 0x800)));
+        }
+        assert_eq!(data.min_lx, 0x800 - 2 * step);
+        assert_eq!(data.max_lx, 0x800 + 2 * step);
+        assert_eq!(data.rejected, 0);
+    }
+
+    #[test]
+    fn streaming_noise_matches_a_batch_computation() {
+        // Deterministic jitter of a few raw units around CENTER
+        let points: Vec<(u16, u16)> = (0..1000u32)
+            .map(|i| {
+                let jitter = |seed: u32| (seed.wrapping_mul(2_654_435_761) >> 28) as u16;
+                (
+                    CENTER.0 - 8 + jitter(i),
+                    CENTER.1 - 4 + jitter(i ^ 0x5555) / 2,
+                )
+            })
+            .collect();
+        let mut noise = CenterNoise::default();
+        for &(x, y) in &points {
+            noise.add(x, y);
+        }
+        let batch = |values: Vec<f64>| {
+            let mean = values.iter().sum::<f64>() / values.len() as f64;
+            let variance =
+                values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64;
+            variance.sqrt()
+        };
+        let sx = batch(points.iter().map(|p| p.0 as f64).collect());
+        let sy = batch(points.iter().map(|p| p.1 as f64).collect());
+        let (streamed_x, streamed_y) = noise.std_dev();
+        assert!(sx > 1.0 && sy > 0.5);
+        assert!((streamed_x - sx).abs() < 1e-9, "{streamed_x} vs {sx}");
+        assert!((streamed_y - sy).abs() < 1e-9, "{streamed_y} vs {sy}");
+    }
+
+    #[test]
+    fn radial_p95_ignores_the_last_five_percent() {
+        let noise_with = |far: usize| {
+            let mut noise = CenterNoise::default();
+            for _ in 0..100 - far {
+                noise.add(CENTER.0, CENTER.1);
+            }
+            // Balanced either side, so the mean stays put
+            for i in 0..far {
+                let x = if i % 2 == 0 {
+                    CENTER.0 + 10
+                } else {
+                    CENTER.0 - 10
+                };
+                noise.add(x, CENTER.1);
+            }
+            noise
+        };
+        assert_eq!(CenterNoise::default().radial_p95(), 0);
+        assert_eq!(noise_with(5).radial_p95(), 0);
+        assert_eq!(noise_with(6).radial_p95(), 10);
+    }
+
+    #[test]
+    fn spikes_barely_move_the_mean_center() {
+        // Jitter of a couple of counts around 0x7F0, then two spikes to 0x850
+        let resting = (0..1000u16).map(|i| (0x7EE + i % 5, 0x7EE + (i / 5) % 5));
+        let data = center_step(resting.chain([(0x850, 0x850); 2]));
+        assert!(
+            data.center_lx.abs_diff(0x7F0) <= 2,
+            "0x{:X}",
+            data.center_lx
+        );
+        assert!(
+            data.center_ly.abs_diff(0x7F0) <= 2,
+            "0x{:X}",
+            data.center_ly
+        );
+        // Where the midpoint of the extremes would have put it
+        assert_eq!((data.min_lx + data.max_lx) / 2, 0x81F);
+    }
+
+    // A sweep around CENTER over the given turns, 720 samples to a full turn, at the
+    // radius `radius` gives for each angle in radians
+    fn sweep(
+        turns: std::ops::Range<f64>,
+        radius: impl Fn(f64) -> f64,
+    ) -> impl Iterator<Item = (u16, u16)> {
+        let steps = ((turns.end - turns.start) * 720.0).round() as usize;
+        (0..steps).map(move |k| {
+            let angle = (turns.start + k as f64 / 720.0) * std::f64::consts::TAU;
+            let r = radius(angle);
+            (
+                (CENTER.0 as f64 + r * angle.cos()).round() as u16,
+                (CENTER.1 as f64 + r * angle.sin()).round() as u16,
+            )
+        })
+    }
+
+    fn coverage(points: impl IntoIterator<Item = (u16, u16)>) -> RangeCoverage {
+        let mut coverage = RangeCoverage::new(CENTER);
+        for (x, y) in points {
+            coverage.add(x, y);
+        }
+        coverage
+    }
+
+    #[test]
+    fn full_sweep_covers_every_sector() {
+        let coverage = coverage(sweep(0.0..1.0, |_| 1400.0));
+        assert_eq!(coverage.missing(), 0);
+        assert!(coverage.rim_spread().is_some());
+    }
+
+    #[test]
+    fn half_sweep_leaves_the_other_half_missing() {
+        let coverage = coverage(sweep(0.0..0.5, |_| 1400.0));
+        assert_eq!(coverage.missing(), COVERAGE_SECTORS / 2);
+        assert!((0..COVERAGE_SECTORS / 2).all(|s| coverage.covered(s)));
+        assert_eq!(coverage.rim_spread(), None);
+    }
+
+    #[test]
+    fn sweep_short_of_the_rim_covers_nothing() {
+        let coverage = coverage(sweep(0.0..1.0, |_| 900.0));
+        assert_eq!(coverage.missing(), COVERAGE_SECTORS);
+    }
+
+    #[test]
+    fn cut_diagonals_are_missing() {
+        // A quick spin that never gets further than 800 within 12 degrees of a diagonal
+        let cut = |angle: f64| {
+            let from_diagonal = (angle.to_degrees() % 90.0 - 45.0).abs();
+            if from_diagonal < 12.0 { 800.0 } else { 1400.0 }
+        };
+        let coverage = coverage(sweep(0.0..1.0, cut).chain(sweep(0.0..1.0, cut)));
+        // The two sectors either side of each diagonal
+        assert_eq!(coverage.missing(), 8);
+        assert!(!coverage.covered(3) && !coverage.covered(4));
+        assert!(coverage.covered(2) && coverage.covered(5));
+    }
+
+    #[test]
+    fn circularity_of_a_circle_is_zero() {
+        assert_eq!(circularity_error(&[1400.0; COVERAGE_SECTORS]), 0.0);
+        assert_eq!(circularity_error(&[0.0; COVERAGE_SECTORS]), 0.0);
+        let coverage = coverage(sweep(0.0..1.0, |_| 1400.0));
+        assert!(coverage.circularity_error().unwrap() < 0.01);
+    }
+
+    #[test]
+    fn circularity_of_an_ellipse_is_the_axis_gap() {
+        // Alternating radii: a spread of 200 around a mean of 1300
+        let radii: Vec<f64> = (0..COVERAGE_SECTORS)
+            .map(|s| if s % 2 == 0 { 1200.0 } else { 1400.0 })
+            .collect();
+        assert!((circularity_error(&radii) - 200.0 / 1300.0).abs() < 1e-12);
+        // An ellipse 1400 wide and 1200 tall, swept for real
+        let ellipse = |angle: f64| {
+            let (a, b) = (1400.0, 1200.0);
+            a * b / (b * angle.cos()).hypot(a * angle.sin())
+        };
+        let error = coverage(sweep(0.0..1.0, ellipse))
+            .circularity_error()
+            .unwrap();
+        assert!((0.13..0.16).contains(&error), "{error}");
+        // Not until the whole rim has been reached
+        assert_eq!(coverage(sweep(0.0..0.5, ellipse)).circularity_error(), None);
+    }
+
+    // A regular octagonal gate with corners 1400 out along the axes and diagonals, with
+    // a deterministic wobble of up to 15 units on the rim
+    fn octagon(angle: f64) -> f64 {
+        let eighth = std::f64::consts::FRAC_PI_4;
+        let apothem = 1400.0 * (eighth / 2.0).cos();
+        let wobble = ((angle * 997.0).sin() * 15.0).round();
+        apothem / (angle.rem_euclid(eighth) - eighth / 2.0).cos() + wobble
+    }
+
+    #[test]
+    fn octagon_corners_are_found_on_a_noisy_gate() {
+        let coverage = coverage(sweep(0.0..1.0, octagon).chain(sweep(0.0..1.0, octagon)));
+        let corners = coverage.corners().unwrap();
+        let mut directions: Vec<usize> = corners.iter().map(|c| direction_index(c.angle)).collect();
+        directions.sort();
+        assert_eq!(directions, (0..8).collect::<Vec<_>>());
+        let sector_width = std::f64::consts::TAU / COVERAGE_SECTORS as f64;
+        for corner in corners {
+            let nearest = direction_index(corner.angle) as f64 * std::f64::consts::FRAC_PI_4;
+            let off = (corner.angle - nearest + std::f64::consts::PI)
+                .rem_euclid(std::f64::consts::TAU)
+                - std::f64::consts::PI;
+            assert!(off.abs() <= sector_width, "{}", corner.angle);
+            assert!((corner.radius - 1400.0).abs() <= 20.0, "{}", corner.radius);
+        }
+        assert!(short_corners(&corners).is_empty());
+    }
+
+    #[test]
+    fn collapsed_corner_is_short_of_its_opposite() {
+        // The gate pushed in by a fifth for about 35 degrees either side of up
+        let collapsed = |angle: f64| {
+            let from_up = (angle - std::f64::consts::FRAC_PI_2).abs();
+            octagon(angle) * if from_up < 0.6 { 0.8 } else { 1.0 }
+        };
+        let coverage = coverage(sweep(0.0..1.0, collapsed));
+        let corners = coverage.corners().unwrap();
+        let short = short_corners(&corners);
+        assert_eq!(short.len(), 1);
+        assert_eq!(direction_name(corners[short[0]].angle), "up");
+    }
+
+    #[test]
+    fn octagon_corner_ties_are_broken_the_same_way() {
+        // A perfect circle ties every sector, so each corner takes the first of its window
+        let first = octagon_corners(&[1000.0; COVERAGE_SECTORS]);
+        let second = octagon_corners(&[1000.0; COVERAGE_SECTORS]);
+        for (a, b) in first.iter().zip(&second) {
+            assert_eq!((a.angle, a.radius), (b.angle, b.radius));
+        }
+        let sector =
+            |angle: f64| (angle / std::f64::consts::TAU * COVERAGE_SECTORS as f64) as usize;
+        let sectors: Vec<usize> = first.iter().map(|c| sector(c.angle)).collect();
+        assert_eq!(sectors, [31, 3, 7, 11, 15, 19, 23, 27]);
+    }
+
+    // Per-angle and bounding box extents of a range step over the given sweeps
+    fn both_extents(points: Vec<(u16, u16)>) -> ([u16; 4], [u16; 4]) {
+        let coverage = coverage(points.iter().copied());
+        let mut data = CalibrationData::for_range(DEFAULT_MAX_STICK_JUMP);
+        for &point in &points {
+            assert!(keep(&mut data, point));
+        }
+        let bounds = ((data.min_lx, data.min_ly), (data.max_lx, data.max_ly));
+        (
+            range_extents(&coverage, bounds, CENTER, false, false),
+            range_extents(&coverage, bounds, CENTER, true, false),
+        )
+    }
+
+    #[test]
+    fn regular_octagon_extents_agree() {
+        let (per_angle, legacy) = both_extents(sweep(0.0..1.0, octagon).collect());
+        for (a, b) in per_angle.into_iter().zip(legacy) {
+            assert!(a.abs_diff(b) <= 1, "{per_angle:?} vs {legacy:?}");
+            assert!(a.abs_diff(1400) <= 20, "{per_angle:?}");
+        }
+    }
+
+    #[test]
+    fn collapsed_corner_shortens_only_the_per_angle_extent() {
+        // A wider gate with the up corner pushed in to 65% for about 35 degrees either side,
+        // still far enough out to cover every sector
+        let collapsed = |angle: f64| {
+            let from_up = (angle - std::f64::consts::FRAC_PI_2).abs();
+            octagon(angle) * 1.2 * if from_up < 0.6 { 0.65 } else { 1.0 }
+        };
+        let (per_angle, legacy) = both_extents(sweep(0.0..1.0, collapsed).collect());
+        // Straight up the stick only got to 1092
+        assert!(per_angle[1].abs_diff(1092) <= 15, "{per_angle:?}");
+        // The bounding box takes its top from the up-left and up-right corners instead
+        assert!(legacy[1] > 1250, "{legacy:?}");
+        for direction in [0, 2, 3] {
+            assert!(per_angle[direction].abs_diff(legacy[direction]) <= 1);
+        }
+    }
+
+    #[test]
+    fn shrink_pulls_the_extents_in_by_the_circularity_error() {
+        let full_sweep = coverage(sweep(0.0..1.0, octagon));
+        let bounds = ((0, 0), (0xFFF, 0xFFF));
+        let error = full_sweep.circularity_error().unwrap();
+        let full = range_extents(&full_sweep, bounds, CENTER, false, false);
+        let shrunk = range_extents(&full_sweep, bounds, CENTER, false, true);
+        for (full, shrunk) in full.into_iter().zip(shrunk) {
+            let expected = (full as f64 * (1.0 - error)).round() as u16;
+            assert!(shrunk.abs_diff(expected) <= 1);
+        }
+        // Until the whole rim has been reached, only the bounding box is known
+        let half = coverage(sweep(0.0..0.5, octagon));
+        let box_extents =
+            range_extents(&half, ((0x300, 0x300), (0xD00, 0xD00)), CENTER, false, true);
+        assert_eq!(box_extents, [0x500, 0x510, 0x500, 0x4F0]);
+    }
+
+    #[test]
+    fn asymmetry_is_the_share_the_short_side_lacks() {
+        assert_eq!(axis_asymmetry(0x200, 0x800, 0xE00), 0.0);
+        // 0x600 above and 0x500 below: a sixth short, either way round
+        let lopsided = axis_asymmetry(0x300, 0x800, 0xE00);
+        assert!((lopsided - 1.0 / 6.0).abs() < 1e-12);
+        assert_eq!(axis_asymmetry(0x200, 0x800, 0xD00), lopsided);
+        assert!(lopsided > ASYMMETRY_LIMIT);
+        // 10% short is within the limit
+        assert!(axis_asymmetry(0x260, 0x800, 0xE00) < ASYMMETRY_LIMIT);
+        // No travel at all, or none on one side
+        assert_eq!(axis_asymmetry(0x800, 0x800, 0x800), 0.0);
+        assert_eq!(axis_asymmetry(0x800, 0x800, 0xE00), 1.0);
+    }
+
+    #[test]
+    fn symmetrizing_cuts_the_longer_side() {
+        assert_eq!(symmetrize_axis(0x300, 0x800, 0xE00), (0x300, 0xD00));
+        assert_eq!(symmetrize_axis(0x200, 0x800, 0xD00), (0x300, 0xD00));
+        assert_eq!(symmetrize_axis(0x200, 0x800, 0xE00), (0x200, 0xE00));
+        let (min, max) = symmetrize_axis(0x2A1, 0x7F3, 0xE17);
+        assert_eq!(axis_asymmetry(min, 0x7F3, max), 0.0);
+        // A center outside the range leaves no travel rather than wrapping
+        assert_eq!(symmetrize_axis(0x200, 0xF00, 0xE00), (0xF00, 0xF00));
+    }
+
+    #[test]
+    fn offset_range_sweep_stays_anchored_to_the_center_step() {
+        let center = center_step(ellipse(3.0, 3.0));
+        let center = (center.center_lx, center.center_ly);
+        assert_eq!(center, CENTER);
+        // The sweeps run round a point 40 counts right of the resting center
+        let offset = |(x, y): (u16, u16)| (x + 40, y);
+        let points: Vec<(u16, u16)> = sweep(0.0..1.0, |_| 1400.0).map(offset).collect();
+        let mut range = CalibrationData::for_range(DEFAULT_MAX_STICK_JUMP);
+        let mut coverage = RangeCoverage::new(center);
+        for &(x, y) in &points {
+            assert!(keep(&mut range, (x, y)));
+            coverage.add(x, y);
+        }
+        let bounds = ((range.min_lx, range.min_ly), (range.max_lx, range.max_ly));
+        for legacy in [false, true] {
+            let extents = range_extents(&coverage, bounds, center, legacy, false);
+            let cal = compose_calibration(center, extents, 0x50);
+            assert_eq!((cal.xcenter, cal.ycenter), CENTER);
+            // 1440 to the right and 1360 to the left of it, each less the padding. Along
+            // the sector edges the per-angle reach can pick up a count from the y offset.
+            assert!(cal.xmax.abs_diff(CENTER.0 + 1440 - 0x50) <= 1);
+            assert!(cal.xmin.abs_diff(CENTER.0 - 1360 + 0x50) <= 1);
+            // Up and down, the sectors either side of each direction reach a little further
+            // round an off-center sweep
+            assert!(cal.ymax.abs_diff(CENTER.1 + 1400 - 0x50) <= 10);
+            assert!(cal.ymin.abs_diff(CENTER.1 - 1400 + 0x50) <= 10);
+        }
+    }
+
+    // What the Review step measures for a session with these resting and range step positions,
+    // through the same center step, range extents and measures it uses
+    fn session(
+        resting: impl IntoIterator<Item = (u16, u16)>,
+        range: impl IntoIterator<Item = (u16, u16)>,
+    ) -> SessionMeasures {
+        let center = CenterResult::from_center_step(&center_step(resting));
+        let center_point = center.centers[0];
+        let mut data = CalibrationData::for_range(DEFAULT_MAX_STICK_JUMP);
+        let mut coverage = RangeCoverage::new(center_point);
+        for (x, y) in range {
+            assert!(keep(&mut data, (x, y)));
+            coverage.add(x, y);
+        }
+        let bounds = ((data.min_lx, data.min_ly), (data.max_lx, data.max_ly));
+        let extents = range_extents(&coverage, bounds, center_point, false, false);
+        let result = compose_calibration(center_point, extents, 0x50);
+        SessionMeasures::measure(&center, &coverage, result, 0)
+    }
+
+    fn penalty_names(penalties: &[(&'static str, u8, QualityCause)]) -> Vec<&'static str> {
+        penalties.iter().map(|&(name, _, _)| name).collect()
+    }
+
+    #[test]
+    fn good_session_scores_well() {
+        let measures = session(ellipse(3.0, 3.0), sweep(0.0..2.0, octagon));
+        let (score, penalties) = quality_score(&measures);
+        // An octagonal gate is a little out of round by nature
+        assert_eq!(penalty_names(&penalties), ["slightly uneven travel"]);
+        assert_eq!(score, 95);
+        assert!(score >= GOOD_QUALITY_SCORE);
+        // The same measures always give the same verdict
+        assert_eq!(quality_score(&measures).0, score);
+    }
+
+    #[test]
+    fn worn_stick_loses_points_to_the_hardware() {
+        // Resting anywhere within 0x110 of center, and travel 20% short vertically
+        let resting = (1..=9).flat_map(|ring| ellipse(ring as f64 * 30.0, ring as f64 * 30.0));
+        let oval = |angle: f64| {
+            let (a, b) = (1400.0, 1120.0);
+            a * b / (b * angle.cos()).hypot(a * angle.sin())
+        };
+        let (score, penalties) = quality_score(&session(resting, sweep(0.0..2.0, oval)));
+        assert_eq!(
+            penalty_names(&penalties),
+            [
+                "uneven travel",
+                "very high center noise",
+                "very loose center"
+            ]
+        );
+        assert_eq!(score, 55);
+        assert!(penalties.iter().all(|p| p.2 == QualityCause::Hardware));
+    }
+
+    #[test]
+    fn badly_swept_session_loses_points_to_the_sweep() {
+        // Three quarters of a turn, never reaching the down-right quarter
+        let (score, penalties) =
+            quality_score(&session(ellipse(3.0, 3.0), sweep(0.0..0.75, octagon)));
+        assert_eq!(penalty_names(&penalties), ["rim coverage incomplete"]);
+        assert_eq!(penalties[0].1, 24);
+        assert_eq!(score, 76);
+        assert!(score < GOOD_QUALITY_SCORE);
+        assert!(penalties.iter().all(|p| p.2 == QualityCause::Sweep));
+    }
+}
@@ -0,0 +1,79 @@
+// How the Switch turns a raw stick axis into output, for previewing a calibration before
+// it is written. Values are fractions of full scale (raw / 4095).
+
+// Remap [min, center - deadzone] to [0, 0.5] and [center + deadzone, max] to [0.5, 1.0],
+// with everything inside the deadzone at 0.5. Each half is measured from its own edge of
+// the deadzone, so the output leaves 0.5 continuously as the stick crosses it. A half
+// with no travel left outside the deadzone jumps straight to its end.
+pub fn remap_calibrated_axis(value: f32, min: f32, center: f32, max: f32, deadzone: f32) -> f32 {
+    let low_edge = center - deadzone;
+    let high_edge = center + deadzone;
+    if value < low_edge {
+        let span = low_edge - min;
+        if span <= 0.0 {
+            return 0.0;
+        }
+        ((value - min) / span / 2.0).clamp(0.0, 0.5)
+    } else if value > high_edge {
+        let span = max - high_edge;
+        if span <= 0.0 {
+            return 1.0;
+        }
+        ((value - high_edge) / span / 2.0 + 0.5).clamp(0.5, 1.0)
+    } else {
+        0.5
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::remap_calibrated_axis;
+
+    const MIN: f32 = 0.1;
+    const CENTER: f32 = 0.5;
+    const MAX: f32 = 0.9;
+    const DEADZONE: f32 = 0.1;
+
+    fn remap(value: f32) -> f32 {
+        remap_calibrated_axis(value, MIN, CENTER, MAX, DEADZONE)
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-6,
+            "{actual} is not {expected}"
+        );
+    }
+
+    #[test]
+    fn ends_map_to_full_scale() {
+        assert_close(remap(MIN), 0.0);
+        assert_close(remap(MAX), 1.0);
+        // Beyond the calibrated range clamps
+        assert_close(remap(0.0), 0.0);
+        assert_close(remap(1.0), 1.0);
+    }
+
+    #[test]
+    fn deadzone_edges_map_to_center() {
+        assert_close(remap(CENTER - DEADZONE), 0.5);
+        assert_close(remap(CENTER), 0.5);
+        assert_close(remap(CENTER + DEADZONE), 0.5);
+    }
+
+    #[test]
+    fn half_midpoints_map_to_quarters() {
+        // Halfway between min and the low deadzone edge, and between the high edge and max
+        assert_close(remap((MIN + CENTER - DEADZONE) / 2.0), 0.25);
+        assert_close(remap((CENTER + DEADZONE + MAX) / 2.0), 0.75);
+    }
+
+    #[test]
+    fn halves_without_travel_jump_to_their_end() {
+        // The deadzone reaches min and max, so nothing is left outside it
+        let remap = |value| remap_calibrated_axis(value, 0.4, CENTER, 0.6, DEADZONE);
+        assert_close(remap(0.3), 0.0);
+        assert_close(remap(0.7), 1.0);
+        assert_close(remap(CENTER), 0.5);
+    }
+}
@@ -0,0 +1,191 @@
+use anyhow::{Result, anyhow};
+use hidapi::HidResult;
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::controller::HidBackend;
+
+// How far past a request to look for its recorded reply
+const REPLY_SEARCH_WINDOW: usize = 64;
+
+// A --hid-log recording played back as if it were the controller. Input reports
+// repeat with their original spacing, looping at the end, and each request is
+// answered with the reply recorded for the same request.
+pub struct Replay {
+    state: Mutex<ReplayState>,
+    usb: bool,
+}
+
+struct ReplayState {
+    // Recorded input reports, timed from the first one
+    inputs: Vec<(Duration, Vec<u8>)>,
+    next_input: usize,
+    started: Instant,
+    // Each recorded request with the reply that answered it
+    exchanges: Vec<(Vec<u8>, Vec<u8>)>,
+    // Replies owed for requests written since the last read
+    replies: VecDeque<Vec<u8>>,
+}
+
+fn parse_line(line: &str) -> Option<(f64, bool, Vec<u8>)> {
+    let mut fields = line.split_whitespace();
+    let seconds = fields.next()?.parse().ok()?;
+    let incoming = match fields.next()? {
+        "IN" => true,
+        "OUT" => false,
+        _ => return None,
+    };
+    let bytes = fields
+        .map(|b| u8::from_str_radix(b, 16).ok())
+        .collect::<Option<Vec<_>>>()?;
+    Some((seconds, incoming, bytes))
+}
+
+fn is_reply(report: &[u8]) -> bool {
+    matches!(report.first(), Some(0x21 | 0x81))
+}
+
+// Whether `reply` answers `request`: a subcommand's 0x21 echoes its ID at 0x0E,
+// a USB command's 0x81 echoes the command byte
+fn answers(request: &[u8], reply: &[u8]) -> bool {
+    match (request.first(), reply.first()) {
+        (Some(0x01), Some(0x21)) => request.len() > 10 && reply.get(0x0E) == Some(&request[10]),
+        (Some(0x80), Some(0x81)) => request.get(1).is_some() && reply.get(1) == request.get(1),
+        _ => false,
+    }
+}
+
+// The part of a request that decides its reply, skipping the packet counter and rumble
+fn request_key(request: &[u8]) -> &[u8] {
+    match request.first() {
+        Some(0x01) if request.len() > 10 => &request[10..],
+        _ => request,
+    }
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        let entries: Vec<_> = contents.lines().filter_map(parse_line).collect();
+        if entries.is_empty() {
+            return Err(anyhow!("{} is not a HID log", path.display()));
+        }
+
+        let first_input = entries
+            .iter()
+            .find(|(_, incoming, bytes)| *incoming && !is_reply(bytes))
+            .map_or(0.0, |(t, _, _)| *t);
+        let inputs = entries
+            .iter()
+            .filter(|(_, incoming, bytes)| *incoming && !is_reply(bytes))
+            .map(|(t, _, bytes)| {
+                (
+                    Duration::from_secs_f64((t - first_input).max(0.0)),
+                    bytes.clone(),
+                )
+            })
+            .collect();
+
+        let mut exchanges = Vec::new();
+        for (i, (_, incoming, request)) in entries.iter().enumerate() {
+            if *incoming {
+                continue;
+            }
+            let reply = entries
+                .iter()
+                .skip(i + 1)
+                .take(REPLY_SEARCH_WINDOW)
+                .find(|(_, incoming, reply)| *incoming && answers(request, reply));
+            if let Some((_, _, reply)) = reply {
+                exchanges.push((request.clone(), reply.clone()));
+            }
+        }
+        let usb = entries
+            .iter()
+            .any(|(_, incoming, bytes)| !incoming && bytes.first() == Some(&0x80));
+
+        Ok(Self {
+            state: Mutex::new(ReplayState {
+                inputs,
+                next_input: 0,
+                started: Instant::now(),
+                exchanges,
+                replies: VecDeque::new(),
+            }),
+            usb,
+        })
+    }
+
+    // Whether the recording was made over USB (it contains the USB handshake)
+    pub fn is_usb(&self) -> bool {
+        self.usb
+    }
+
+    // The type byte from the recorded device info reply, if there is one
+    pub fn device_type(&self) -> Option<u8> {
+        let state = self.state.lock();
+        state
+            .exchanges
+            .iter()
+            .find(|(request, _)| request.first() == Some(&0x01) && request.get(10) == Some(&0x02))
+            .and_then(|(_, reply)| reply.get(0x11).copied())
+    }
+}
+
+impl HidBackend for Replay {
+    fn write(&self, data: &[u8]) -> HidResult<usize> {
+        let mut state = self.state.lock();
+        let key = request_key(data);
+        // Same request if it was recorded, otherwise any reply to the same command.
+        // SPI reads echo their address, so a reply for another address is no answer.
+        let reply = state
+            .exchanges
+            .iter()
+            .find(|(request, _)| request_key(request) == key)
+            .or_else(|| {
+                state
+                    .exchanges
+                    .iter()
+                    .find(|(request, reply)| answers(data, reply) && request.get(10) != Some(&0x10))
+            })
+            .map(|(_, reply)| reply.clone());
+        if let Some(reply) = reply {
+            state.replies.push_back(reply);
+        }
+        Ok(data.len())
+    }
+
+    fn read_timeout(&self, buf: &mut [u8], timeout_ms: i32) -> HidResult<usize> {
+        let mut state = self.state.lock();
+        let report = match state.replies.pop_front() {
+            Some(reply) => reply,
+            None => {
+                if state.inputs.is_empty() {
+                    thread::sleep(Duration::from_millis(timeout_ms.max(0) as u64));
+                    return Ok(0);
+                }
+                if state.next_input == state.inputs.len() {
+                    state.next_input = 0;
+                    state.started = Instant::now();
+                }
+                let due = state.inputs[state.next_input].0;
+                let wait = due.saturating_sub(state.started.elapsed());
+                // A negative timeout blocks until the next report, like hidapi
+                if timeout_ms >= 0 && wait > Duration::from_millis(timeout_ms as u64) {
+                    thread::sleep(Duration::from_millis(timeout_ms as u64));
+                    return Ok(0);
+                }
+                thread::sleep(wait);
+                state.next_input += 1;
+                state.inputs[state.next_input - 1].1.clone()
+            }
+        };
+        let n = report.len().min(buf.len());
+        buf[..n].copy_from_slice(&report[..n]);
+        Ok(n)
+    }
+}
@@ -0,0 +1,52 @@
+use std::fs;
+use std::path::PathBuf;
+
+// Settings kept between runs, stored as key=value lines in the platform config dir
+#[derive(Default)]
+pub struct SavedState {
+    // MAC of the last controller a calibration was written to
+    pub last_mac: Option<String>,
+    pub auto_connect: bool,
+    // Disconnect Bluetooth controllers and let them sleep when the app quits
+    pub sleep_on_exit: bool,
+}
+
+fn state_path() -> Option<PathBuf> {
+    Some(dirs::config_dir()?.join("rustjoycal").join("state"))
+}
+
+// Missing or unreadable state just means defaults
+pub fn load() -> SavedState {
+    let mut state = SavedState::default();
+    let Some(contents) = state_path().and_then(|p| fs::read_to_string(p).ok()) else {
+        return state;
+    };
+    for line in contents.lines() {
+        match line.split_once('=') {
+            Some(("last_mac", mac)) if !mac.trim().is_empty() => {
+                state.last_mac = Some(mac.trim().to_string())
+            }
+            Some(("auto_connect", value)) => state.auto_connect = value.trim() == "true",
+            Some(("sleep_on_exit", value)) => state.sleep_on_exit = value.trim() == "true",
+            _ => {}
+        }
+    }
+    state
+}
+
+pub fn save(state: &SavedState) -> anyhow::Result<()> {
+    let path = state_path().ok_or_else(|| anyhow::anyhow!("No config directory"))?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(
+        path,
+        format!(
+            "last_mac={}\nauto_connect={}\nsleep_on_exit={}\n",
+            state.last_mac.as_deref().unwrap_or(""),
+            state.auto_connect,
+            state.sleep_on_exit
+        ),
+    )?;
+    Ok(())
+}